@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use riscv_emu::testing::compliance::{compare_signature, format_signature, run_test, DEFAULT_MAX_STEPS};
+
+/// Collects the `*.elf` files below `dir`
+fn find_elfs(dir: &Path, elfs: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            find_elfs(&path, elfs);
+        } else if path.extension().is_some_and(|ext| ext == "elf") {
+            elfs.push(path);
+        }
+    }
+}
+
+/// Reference signature of a test: next to the relative path of the ELF (optionally in a references/ directory) or at the top level
+fn find_reference(reference_dir: &Path, relative_dir: &Path, name: &str) -> Option<PathBuf> {
+    let file = format!("{name}.reference_output");
+    [
+        reference_dir.join(relative_dir).join(&file),
+        reference_dir.join(relative_dir).join("references").join(&file),
+        reference_dir.join(&file),
+    ].into_iter().find(|path| path.is_file())
+}
+
+fn main() {
+    env_logger::init();
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 || args.len() > 4 {
+        eprintln!("Usage: compliance <elf_dir> <reference_dir> [signature_dir]");
+        process::exit(2);
+    }
+    let elf_dir = Path::new(&args[1]);
+    let reference_dir = Path::new(&args[2]);
+    let signature_dir = Path::new(args.get(3).map_or("signatures", |dir| dir.as_str()));
+    fs::create_dir_all(signature_dir).expect("Failed to create signature directory");
+
+    let mut elfs = Vec::new();
+    find_elfs(elf_dir, &mut elfs);
+    elfs.sort();
+    if elfs.is_empty() {
+        eprintln!("No .elf files found in {}", elf_dir.display());
+        process::exit(2);
+    }
+
+    // extension (directory of the ELF) -> (passed, total)
+    let mut results: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    for elf in &elfs {
+        let relative_dir = elf.parent().and_then(|dir| dir.strip_prefix(elf_dir).ok()).unwrap_or(Path::new(""));
+        let extension = relative_dir.file_name().map_or("-".to_string(), |dir| dir.to_string_lossy().into_owned());
+        let name = elf.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+
+        let outcome = fs::read(elf).map_err(|err| err.to_string()).and_then(|binary| {
+            let run = run_test(&binary, DEFAULT_MAX_STEPS).map_err(|err| err.to_string())?;
+            let signature = format_signature(&run.signature);
+            let _ = fs::write(signature_dir.join(format!("{name}.signature")), &signature);
+            let reference = find_reference(reference_dir, relative_dir, &name).ok_or("no reference signature")?;
+            let reference = fs::read_to_string(reference).map_err(|err| err.to_string())?;
+            compare_signature(&signature, &reference).map_err(|mismatch| mismatch.to_string())
+        });
+
+        let counts = results.entry(extension.clone()).or_default();
+        counts.1 += 1;
+        match outcome {
+            Ok(()) => {
+                counts.0 += 1;
+                println!("PASS {extension}/{name}");
+            },
+            Err(err) => println!("FAIL {extension}/{name}: {err}"),
+        }
+    }
+
+    println!();
+    let mut failed = 0;
+    for (extension, (passed, total)) in &results {
+        println!("{extension:>8}: {passed}/{total} passed");
+        failed += total - passed;
+    }
+    process::exit(if failed == 0 { 0 } else { 1 });
+}
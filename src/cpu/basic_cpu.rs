@@ -1,67 +1,229 @@
-use crate::memory::dram::{DRAM_SIZE, DRAM_BASE_ADDR, DramMemory};
+use crate::memory::bus::Bus;
+use crate::cpu::compressed::{is_compressed, expand_compressed_xlen};
+use crate::cpu::commit_log::{CommitEntry, CommitLog};
+use crate::cpu::disasm;
+use crate::cpu::isa::Isa;
+use crate::cpu::registers::{RegisterFile, EMBEDDED_REGISTERS_COUNT};
+use crate::os::syscall::{SyscallHandler, SyscallOutcome};
+use crate::cpu::fpu::{self, FpFormat, RoundingMode, SignInjection};
+use crate::cpu::error::{ExecError, MemoryAccess};
+use crate::cpu::mmu::{self, MmuContext, MmuFault, Tlb, PAGE_SIZE, SATP_MODE_SHIFT};
 use log::{info, warn};
+use std::io::Write;
 
 pub const REGISTERS_COUNT: usize = 32;
 pub const CSR_COUNT: usize = 4096; // Maximum number of CSRs in RV64
-// RV64i
+// Floating-point CSRs, fflags and frm are views of the corresponding fcsr fields
+pub const CSR_FFLAGS: usize = 0x001; // Accrued exceptions, fcsr[4:0]
+pub const CSR_FRM: usize = 0x002; // Dynamic rounding mode, fcsr[7:5]
+pub const CSR_FCSR: usize = 0x003;
+// Supervisor CSRs, sstatus/sie/sip are restricted views of mstatus/mie/mip
+pub const CSR_SSTATUS: usize = 0x100;
+pub const CSR_SIE: usize = 0x104;
+pub const CSR_STVEC: usize = 0x105;
+pub const CSR_SSCRATCH: usize = 0x140;
+pub const CSR_SEPC: usize = 0x141;
+pub const CSR_SCAUSE: usize = 0x142;
+pub const CSR_STVAL: usize = 0x143;
+pub const CSR_SIP: usize = 0x144;
+pub const CSR_SATP: usize = 0x180;
+// Machine CSRs
+pub const CSR_MSTATUS: usize = 0x300;
+pub const CSR_MISA: usize = 0x301; // Read-only, the ISA is chosen with set_isa
+pub const CSR_MEDELEG: usize = 0x302; // Exceptions delegated to supervisor mode
+pub const CSR_MIDELEG: usize = 0x303; // Interrupts delegated to supervisor mode
+pub const CSR_MIE: usize = 0x304;
+pub const CSR_MTVEC: usize = 0x305; // Trap vector base address, mode in bits [1:0]
+pub const CSR_MEPC: usize = 0x341;
+pub const CSR_MCAUSE: usize = 0x342;
+pub const CSR_MTVAL: usize = 0x343;
+pub const CSR_MIP: usize = 0x344;
+
+// mstatus fields
+pub const MSTATUS_SIE: TReg = 1 << 1;
+pub const MSTATUS_MIE: TReg = 1 << 3;
+pub const MSTATUS_SPIE: TReg = 1 << 5;
+pub const MSTATUS_MPIE: TReg = 1 << 7;
+pub const MSTATUS_SPP: TReg = 1 << 8;
+pub const MSTATUS_MPP: TReg = 0b11 << 11;
+pub const MSTATUS_FS: TReg = 0b11 << 13; // Floating-point unit state: Off, Initial, Clean, Dirty
+pub const MSTATUS_FS_INITIAL: TReg = 0b01 << 13;
+pub const MSTATUS_FS_DIRTY: TReg = 0b11 << 13;
+pub const MSTATUS_MPRV: TReg = 1 << 17;
+pub const MSTATUS_SUM: TReg = 1 << 18;
+pub const MSTATUS_MXR: TReg = 1 << 19;
+pub const MSTATUS_TVM: TReg = 1 << 20;
+pub const MSTATUS_TW: TReg = 1 << 21;
+pub const MSTATUS_TSR: TReg = 1 << 22;
+pub const MSTATUS_SD: TReg = 1 << 63; // Read-only summary of the dirty state, set when FS is Dirty (bit 31 on RV32)
+// Bits of mstatus visible through sstatus (SIE, SPIE, UBE, SPP, VS, FS, XS, SUM, MXR, UXL, SD)
+pub const SSTATUS_MASK: TReg = 0x8000_0003_000D_E762;
+// Supervisor interrupt bits (SSIP, STIP, SEIP) visible through sie/sip
+pub const SIP_MASK: TReg = 0x222;
+
+pub const MCAUSE_INTERRUPT: TReg = 1 << 63; // bit 31 on RV32
+
+// Interrupt causes, mip/mie bit n corresponds to cause n
+pub const IRQ_S_SOFT: TReg = 1;
+pub const IRQ_M_SOFT: TReg = 3;
+pub const IRQ_S_TIMER: TReg = 5;
+pub const IRQ_M_TIMER: TReg = 7;
+pub const IRQ_S_EXT: TReg = 9;
+pub const IRQ_M_EXT: TReg = 11;
+pub const MIP_SSIP: TReg = 1 << IRQ_S_SOFT;
+pub const MIP_MSIP: TReg = 1 << IRQ_M_SOFT;
+pub const MIP_STIP: TReg = 1 << IRQ_S_TIMER;
+pub const MIP_MTIP: TReg = 1 << IRQ_M_TIMER;
+pub const MIP_SEIP: TReg = 1 << IRQ_S_EXT;
+pub const MIP_MEIP: TReg = 1 << IRQ_M_EXT;
+// Interrupts in the order they are taken when several are pending
+const INTERRUPT_PRIORITY: [TReg; 6] = [IRQ_M_EXT, IRQ_M_SOFT, IRQ_M_TIMER, IRQ_S_EXT, IRQ_S_SOFT, IRQ_S_TIMER];
+// Registers are 64 bits wide, RV32 keeps its 32-bit values sign-extended (like the word results of RV64)
 pub type TReg = u64;
 pub type TInstr = u32;
 pub type TImm = u64; // immediate value
 
+/// Exception raised by the instruction executed in `step`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trap {
+    pub exception: ExecError,
+    pub handled: bool, // false: no handler can make progress, the trap was not taken and the state is unchanged
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Privilege {
+    User = 0b00,
+    Supervisor = 0b01,
+    Machine = 0b11,
+}
+
+impl Privilege {
+    pub fn from_bits(bits: TReg) -> Privilege {
+        match bits & 0b11 {
+            0b00 => Privilege::User,
+            0b01 => Privilege::Supervisor,
+            _ => Privilege::Machine, // 0b10 is reserved
+        }
+    }
+}
+
 pub struct BasicCpu {
-    registers : [TReg; REGISTERS_COUNT], // General-purpose registers
+    registers : RegisterFile, // General-purpose registers, x0 is hardwired to zero
+    isa : Isa, // Enabled extensions, reported in misa
+    fregisters : [TReg; REGISTERS_COUNT], // Floating-point registers (F/D), singles are NaN-boxed
     pc : TReg, // Program Counter
-    pub mem : DramMemory, // Memory interface
+    pub bus : Bus, // System bus (DRAM and memory-mapped devices)
     csr : [TReg; CSR_COUNT], // CSR registers
+    privilege : Privilege, // Current privilege mode
+    tlb : Tlb, // Cached virtual to physical translations
+    mip_external : TReg, // mip bits driven by devices (timer, software and external interrupt lines)
+    instr_raw : TInstr, // Raw bits of the instruction being executed (before expansion of compressed instructions)
+    instr_len : TReg, // Length (in bytes) of the instruction being executed, 2 for compressed instructions
+    next_pc : TReg, // Address of the next instruction, updated by jumps and taken branches
+    commit_log : Option<CommitLog>, // Spike compatible trace of retired instructions
+    trace : Option<Box<dyn Write>>, // Disassembly of every executed instruction
+    syscall_handler : Option<Box<dyn SyscallHandler>>, // Services ECALL on the host instead of trapping (user-mode emulation)
+    semihosting_handler : Option<Box<dyn SyscallHandler>>, // Services semihosting calls (the EBREAK sequence) on the host
+    exit_code : Option<i64>, // Set when the guest exits through a syscall
+}
+
+impl Default for BasicCpu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl BasicCpu {
     
     pub fn new() -> BasicCpu{
-        BasicCpu {
-            registers: [0; REGISTERS_COUNT],
+        BasicCpu::with_bus(Bus::new())
+    }
+
+    pub fn with_bus(bus: Bus) -> BasicCpu {
+        BasicCpu::with_register_file(bus, RegisterFile::new())
+    }
+
+    /// CPU with the given register file, e.g. `RegisterFile::embedded()` for the 16 register configuration.
+    /// The FPU starts enabled (mstatus.FS = Initial), programs which do not set up mstatus can use it right away.
+    pub fn with_register_file(bus: Bus, registers: RegisterFile) -> BasicCpu {
+        let mut cpu = BasicCpu {
+            registers,
+            isa: Isa::default(),
+            fregisters: [0; REGISTERS_COUNT],
             pc: 0x0,
-            mem: DramMemory {
-                mem: vec![0; DRAM_SIZE]
-            },
-            csr: [0; CSR_COUNT] 
-        }
+            bus,
+            csr: [0; CSR_COUNT],
+            privilege: Privilege::Machine,
+            tlb: Tlb::new(),
+            mip_external: 0,
+            instr_raw: 0,
+            instr_len: 4,
+            next_pc: 0x0,
+            commit_log: None,
+            trace: None,
+            syscall_handler: None,
+            semihosting_handler: None,
+            exit_code: None,
+        };
+        cpu.csr[CSR_MSTATUS] = MSTATUS_FS_INITIAL;
+        cpu
     }   
 
     pub fn init(&mut self){
-        self.registers[0] = 0x0; // const. zero
-        self.registers[2] = (DRAM_BASE_ADDR + DRAM_SIZE) as TReg; // stack pointer
-        self.pc = DRAM_BASE_ADDR as TReg;
+        self.registers.set(2, self.sign_extend_xlen((self.bus.dram.base() + self.bus.dram.size()) as TReg)); // stack pointer
+        self.pc = self.truncate_xlen(self.bus.dram.base() as TReg);
     }
 
     pub fn print_registers(&self){
         info!("=== REGISTERS ===");
-        info!("[0:3] {:#x} {:#x} {:#x} {:#x}", self.registers[0],self.registers[1],self.registers[2],self.registers[3]);
-        info!("[4:7] {:#x} {:#x} {:#x} {:#x}", self.registers[4],self.registers[5],self.registers[6],self.registers[7]);
-        info!("[8:11] {:#x} {:#x} {:#x} {:#x}", self.registers[8],self.registers[9],self.registers[10],self.registers[11]);
-        info!("[12:15] {:#x} {:#x} {:#x} {:#x}", self.registers[12],self.registers[13],self.registers[14],self.registers[15]);
-        info!("[16:19] {:#x} {:#x} {:#x} {:#x}", self.registers[16],self.registers[17],self.registers[18],self.registers[19]);
-        info!("[20:23] {:#x} {:#x} {:#x} {:#x}", self.registers[20],self.registers[21],self.registers[22],self.registers[23]);
-        info!("[24:27] {:#x} {:#x} {:#x} {:#x}", self.registers[24],self.registers[25],self.registers[26],self.registers[27]);
-        info!("[28:31] {:#x} {:#x} {:#x} {:#x}", self.registers[28],self.registers[29],self.registers[30],self.registers[31]);
+        let registers: Vec<(usize, TReg)> = self.registers.iter().collect();
+        for group in registers.chunks(4) {
+            let line: Vec<String> = group.iter()
+                .map(|(idx, value)| format!("{}={value:#x}", RegisterFile::abi_name(*idx).unwrap_or("?")))
+                .collect();
+            info!("[{}:{}] {}", group[0].0, group[0].0 + group.len() - 1, line.join(" "));
+        }
         info!("=================");
     }
 
     pub fn get_register(&self, idx: usize) -> TReg{
-        if idx > REGISTERS_COUNT {
+        self.registers.get(idx).unwrap_or_else(|| {
             warn!("Invalid register index {idx}");
-            return 0
-        }
-        self.registers[idx]
+            0
+        })
     }
 
     pub fn set_register(&mut self, idx: usize, value: TReg) {
-        if idx > REGISTERS_COUNT {
+        let value = self.sign_extend_xlen(value);
+        if !self.registers.set(idx, value) {
             warn!("Invalid register index {idx}");
             return 
         }
         info!("Setting register {idx} to {value:#x}");
-        self.registers[idx] = value;
+        self.log_commit(CommitEntry::Register { idx, value });
+    }
+
+    pub fn registers(&self) -> &RegisterFile {
+        &self.registers
+    }
+
+    pub fn get_fregister(&self, idx: usize) -> TReg {
+        if idx >= REGISTERS_COUNT {
+            warn!("Invalid floating-point register index {idx}");
+            return 0
+        }
+        self.fregisters[idx]
+    }
+
+    pub fn set_fregister(&mut self, idx: usize, value: TReg) {
+        if idx >= REGISTERS_COUNT {
+            warn!("Invalid floating-point register index {idx}");
+            return
+        }
+        info!("Setting floating-point register {idx} to {value:#x}");
+        self.fregisters[idx] = value;
+        self.mark_fp_dirty();
+        self.log_commit(CommitEntry::FRegister { idx, value });
     }
 
     pub fn get_pc(&self) -> TReg {
@@ -70,7 +232,166 @@ impl BasicCpu {
 
     pub fn set_pc(&mut self, pc: TReg) {
         info!("Setting program counter to {:#x}", pc);
-        self.pc = pc;
+        self.pc = self.truncate_xlen(pc);
+    }
+
+    /// Enables (or disables with None) the commit log of retired instructions
+    pub fn set_commit_log(&mut self, commit_log: Option<CommitLog>) {
+        self.commit_log = commit_log;
+    }
+
+    /// Enables the base ISA and extensions of `isa`, the embedded base ISA switches to 16 integer registers.
+    /// Changing the register count or XLEN clears the integer registers.
+    pub fn set_isa(&mut self, isa: Isa) {
+        if isa.is_embedded() != (self.registers.count() == EMBEDDED_REGISTERS_COUNT) || isa.xlen() != self.isa.xlen() {
+            self.registers = if isa.is_embedded() { RegisterFile::embedded() } else { RegisterFile::new() };
+        }
+        self.isa = isa;
+        self.pc = self.truncate_xlen(self.pc);
+        // Without F there is no floating-point state to enable
+        let fs = if isa.has('f') { MSTATUS_FS_INITIAL } else { 0 };
+        self.csr[CSR_MSTATUS] = (self.csr[CSR_MSTATUS] & !MSTATUS_FS) | fs;
+    }
+
+    pub fn isa(&self) -> Isa {
+        self.isa
+    }
+
+    /// Writes the disassembly of every executed instruction to `trace` (Spike `-l` format), None stops tracing
+    pub fn set_trace(&mut self, trace: Option<Box<dyn Write>>) {
+        if let Some(mut old) = self.trace.take() {
+            let _ = old.flush();
+        }
+        self.trace = trace;
+    }
+
+    /// Installs a host implementation of the guest's system calls, ECALL no longer traps while it is set
+    pub fn set_syscall_handler(&mut self, handler: Option<Box<dyn SyscallHandler>>) {
+        self.syscall_handler = handler;
+    }
+
+    /// Installs a host implementation of semihosting, EBREAK between the semihosting marker instructions no longer traps while it is set
+    pub fn set_semihosting_handler(&mut self, handler: Option<Box<dyn SyscallHandler>>) {
+        self.semihosting_handler = handler;
+    }
+
+    /// Exit code of a guest which terminated through a syscall
+    pub fn exit_code(&self) -> Option<i64> {
+        self.exit_code
+    }
+
+    fn log_commit(&mut self, entry: CommitEntry) {
+        if let Some(commit_log) = self.commit_log.as_mut() {
+            commit_log.record(entry);
+        }
+    }
+
+    pub fn get_privilege(&self) -> Privilege {
+        self.privilege
+    }
+
+    pub fn set_privilege(&mut self, privilege: Privilege) {
+        info!("Setting privilege mode to {:?}", privilege);
+        self.privilege = privilege;
+    }
+
+    fn jump(&mut self, target: TReg) {
+        // Jumps and taken branches replace the address of the next instruction
+        let target = self.truncate_xlen(target);
+        info!("Jumping to {:#x}", target);
+        self.next_pc = target;
+    }
+
+    /// Zero-extends the lower XLEN bits of `value`, the form of addresses, the pc and CSR values
+    fn truncate_xlen(&self, value: TReg) -> TReg {
+        if self.isa.xlen() == 32 { value as u32 as TReg } else { value }
+    }
+
+    /// Sign-extends the lower XLEN bits of `value`, the form of integer register values
+    fn sign_extend_xlen(&self, value: TReg) -> TReg {
+        if self.isa.xlen() == 32 { sign_extend_word(value as u32) } else { value }
+    }
+
+    /// Most significant bit of an XLEN wide register, the interrupt bit of xcause and the SD bit of mstatus
+    fn xlen_msb(&self) -> TReg {
+        1 << (self.isa.xlen() - 1)
+    }
+
+    //
+    // Errors
+    //
+    fn illegal_instruction(&self) -> ExecError {
+        ExecError::IllegalInstruction { instr: self.instr_raw, pc: self.pc }
+    }
+
+    fn unimplemented_extension(&self, extension: &'static str) -> ExecError {
+        ExecError::UnimplementedExtension { extension, instr: self.instr_raw, pc: self.pc }
+    }
+
+    fn mmu_context(&self, access: MemoryAccess) -> MmuContext {
+        // With mstatus.MPRV set, M-mode loads and stores are translated as if in the MPP privilege mode
+        let mstatus = self.get_csr(CSR_MSTATUS);
+        let privilege = if access != MemoryAccess::Fetch && self.privilege == Privilege::Machine && mstatus & MSTATUS_MPRV != 0 {
+            Privilege::from_bits((mstatus & MSTATUS_MPP) >> 11)
+        } else {
+            self.privilege
+        };
+        MmuContext { satp: self.get_csr(CSR_SATP), privilege, sum: mstatus & MSTATUS_SUM != 0, mxr: mstatus & MSTATUS_MXR != 0 }
+    }
+
+    /// Translates the virtual address of an access of `size` bits to a physical address.
+    fn translate_access(&mut self, vaddr: TReg, size: usize, access: MemoryAccess) -> Result<TReg, ExecError> {
+        let ctx = self.mmu_context(access);
+        let pc = self.pc;
+        // Accesses crossing a page boundary could map to two unrelated physical pages
+        if ctx.translation_enabled() && (vaddr & (PAGE_SIZE - 1)) + (size / 8) as TReg > PAGE_SIZE {
+            return Err(ExecError::Misaligned { addr: vaddr, access, pc });
+        }
+        let paddr = mmu::translate(&mut self.bus, &mut self.tlb, &ctx, vaddr, access).map_err(|fault| match fault {
+            MmuFault::PageFault => ExecError::PageFault { addr: vaddr, access, pc },
+            MmuFault::AccessFault => ExecError::MemoryFault { addr: vaddr, access, pc },
+        })?;
+        if paddr != vaddr {
+            info!("Translated {vaddr:#x} to {paddr:#x} ({access:?})");
+        }
+        Ok(paddr)
+    }
+
+    // Bus accesses at physical addresses, faults report the virtual address
+    fn read_physical(&mut self, paddr: TReg, size: usize, vaddr: TReg, access: MemoryAccess) -> Result<TReg, ExecError> {
+        let value = self.bus.read(paddr, size).map_err(|_| ExecError::MemoryFault { addr: vaddr, access, pc: self.pc })?;
+        if access != MemoryAccess::Fetch {
+            self.log_commit(CommitEntry::Load { addr: vaddr });
+        }
+        Ok(value)
+    }
+
+    fn write_physical(&mut self, paddr: TReg, size: usize, value: TReg, vaddr: TReg) -> Result<(), ExecError> {
+        self.bus.write(paddr, size, value).map_err(|_| ExecError::MemoryFault { addr: vaddr, access: MemoryAccess::Store, pc: self.pc })?;
+        self.log_commit(CommitEntry::Store { addr: vaddr, size, value });
+        Ok(())
+    }
+
+    fn read_memory(&mut self, vaddr: TReg, size: usize, access: MemoryAccess) -> Result<TReg, ExecError> {
+        let vaddr = self.truncate_xlen(vaddr);
+        let paddr = self.translate_access(vaddr, size, access)?;
+        self.read_physical(paddr, size, vaddr, access)
+    }
+
+    fn write_memory(&mut self, vaddr: TReg, size: usize, value: TReg) -> Result<(), ExecError> {
+        let vaddr = self.truncate_xlen(vaddr);
+        let paddr = self.translate_access(vaddr, size, MemoryAccess::Store)?;
+        self.write_physical(paddr, size, value, vaddr)
+    }
+
+    /// Byte read for debuggers, translated like a load of the current privilege mode
+    pub fn debug_read_byte(&mut self, vaddr: TReg) -> Result<u8, ExecError> {
+        self.read_memory(vaddr, 8, MemoryAccess::Load).map(|value| value as u8)
+    }
+
+    /// Byte write for debuggers, translated like a store of the current privilege mode
+    pub fn debug_write_byte(&mut self, vaddr: TReg, value: u8) -> Result<(), ExecError> {
+        self.write_memory(vaddr, 8, value as TReg)
     }
 
     pub fn get_csr(&self, idx: usize) -> TReg {
@@ -78,7 +399,17 @@ impl BasicCpu {
             warn!("Invalid CSR index {idx}");
             return 0;
         }
-        self.csr[idx]
+        match idx {
+            CSR_FFLAGS => self.csr[CSR_FCSR] & 0x1f,
+            CSR_FRM => (self.csr[CSR_FCSR] >> 5) & 0x7,
+            CSR_MSTATUS => self.mstatus(),
+            CSR_SSTATUS => self.mstatus() & self.sstatus_mask(),
+            CSR_SIE => self.csr[CSR_MIE] & SIP_MASK,
+            CSR_SIP => (self.csr[CSR_MIP] | self.mip_external) & SIP_MASK,
+            CSR_MIP => self.csr[CSR_MIP] | self.mip_external,
+            CSR_MISA => self.isa.misa(),
+            _ => self.csr[idx],
+        }
     }
 
     pub fn set_csr(&mut self, idx: usize, value: TReg) {
@@ -86,38 +417,301 @@ impl BasicCpu {
             warn!("Invalid CSR index {idx}");
             return;
         }
+        let value = self.truncate_xlen(value);
         info!("Setting CSR {idx} to {value}");
-        self.csr[idx] = value;
+        match idx {
+            CSR_FFLAGS => {
+                self.csr[CSR_FCSR] = (self.csr[CSR_FCSR] & !0x1f) | (value & 0x1f);
+                self.mark_fp_dirty();
+            },
+            CSR_FRM => {
+                self.csr[CSR_FCSR] = (self.csr[CSR_FCSR] & !0xe0) | ((value & 0x7) << 5);
+                self.mark_fp_dirty();
+            },
+            CSR_FCSR => {
+                self.csr[CSR_FCSR] = value & 0xff;
+                self.mark_fp_dirty();
+            },
+            CSR_MSTATUS => self.csr[CSR_MSTATUS] = value & !self.xlen_msb(),
+            CSR_SSTATUS => {
+                let mask = self.sstatus_mask() & !self.xlen_msb();
+                self.csr[CSR_MSTATUS] = (self.csr[CSR_MSTATUS] & !mask) | (value & mask);
+            },
+            CSR_SIE => self.csr[CSR_MIE] = (self.csr[CSR_MIE] & !SIP_MASK) | (value & SIP_MASK),
+            CSR_SIP => self.csr[CSR_MIP] = (self.csr[CSR_MIP] & !SIP_MASK) | (value & SIP_MASK),
+            CSR_MIP => self.csr[CSR_MIP] = value & (MIP_SSIP | MIP_STIP | MIP_SEIP), // other bits are driven by devices
+            CSR_MISA => {}, // extensions can not be switched at runtime
+            CSR_SATP => {
+                // Writes selecting an unsupported translation mode are ignored, on RV32 this is everything but Bare (Sv32 is not implemented)
+                let mode = if self.isa.xlen() == 32 { value >> 31 } else { value >> SATP_MODE_SHIFT };
+                if matches!(mode, mmu::SATP_MODE_BARE | mmu::SATP_MODE_SV39 | mmu::SATP_MODE_SV48 | mmu::SATP_MODE_SV57) {
+                    self.csr[CSR_SATP] = value;
+                }
+            },
+            _ => self.csr[idx] = value,
+        }
+        self.log_commit(CommitEntry::Csr { idx, value: self.get_csr(idx) });
+    }
+
+    /// mstatus with the SD summary bit derived from FS
+    fn mstatus(&self) -> TReg {
+        let mstatus = self.csr[CSR_MSTATUS];
+        if mstatus & MSTATUS_FS == MSTATUS_FS_DIRTY { mstatus | self.xlen_msb() } else { mstatus }
+    }
+
+    /// Bits of mstatus visible through sstatus, SD moves to bit 31 on RV32
+    fn sstatus_mask(&self) -> TReg {
+        self.truncate_xlen(SSTATUS_MASK) | self.xlen_msb()
+    }
+
+    /// Records a write to the floating-point registers or fcsr in mstatus.FS
+    fn mark_fp_dirty(&mut self) {
+        self.csr[CSR_MSTATUS] |= MSTATUS_FS_DIRTY;
+    }
+
+    /// Floating-point instructions and CSRs are illegal while the FPU is off (mstatus.FS = Off)
+    fn check_fp_enabled(&self) -> Result<(), ExecError> {
+        if self.csr[CSR_MSTATUS] & MSTATUS_FS == 0 {
+            return Err(self.illegal_instruction());
+        }
+        Ok(())
     }
     //
     // Processing
     //
-    pub fn fetch_instr(&mut self) -> TInstr{
-        self.mem.dram_read(self.get_pc() as usize, 32) as TInstr // read instruction at program counter, 4bytes
+    pub fn fetch_instr(&mut self) -> Result<TInstr, ExecError> {
+        // Read the lower 16 bits first, only 32-bit instructions (lowest two bits 0b11) need the upper half
+        // The halves are translated separately, a 32-bit instruction may cross a page boundary
+        let pc = self.get_pc();
+        let instr = self.read_memory(pc, 16, MemoryAccess::Fetch)? as TInstr;
+        if is_compressed(instr) {
+            return Ok(instr); // compressed instruction, 2bytes
+        }
+        let high = self.read_memory(pc.wrapping_add(2), 16, MemoryAccess::Fetch)? as TInstr;
+        Ok((high << 16) | instr) // read instruction at program counter, 4bytes
     }
 
-    pub fn execute_instr(&mut self, instr: TInstr)  -> Result<(), String> {
+    /// An uncompressed EBREAK preceded by `slli x0, x0, 0x1f` and followed by `srai x0, x0, 7` is a semihosting call
+    fn is_semihosting_call(&mut self) -> bool {
+        if self.instr_len != 4 {
+            return false;
+        }
+        let pc = self.pc;
+        let before = self.read_memory(pc.wrapping_sub(4), 32, MemoryAccess::Fetch);
+        let after = self.read_memory(pc.wrapping_add(4), 32, MemoryAccess::Fetch);
+        matches!((before, after), (Ok(0x01f01013), Ok(0x40705013)))
+    }
+
+    pub fn execute_instr(&mut self, instr: TInstr) -> Result<(), ExecError> {
+        self.instr_raw = instr;
+        // Compressed instructions are expanded to their 32-bit equivalent
+        let (instr, instr_len) = if is_compressed(instr) {
+            match expand_compressed_xlen(instr as u16, self.isa.xlen()) {
+                Some(expanded) => {
+                    info!("Expanded compressed instruction {:#06x} to {:#010x}", instr, expanded);
+                    (expanded, 2)
+                },
+                None => return Err(self.illegal_instruction()),
+            }
+        } else {
+            (instr, 4)
+        };
+        self.instr_len = instr_len;
+        self.next_pc = self.truncate_xlen(self.pc.wrapping_add(instr_len));
+        if !self.registers.accepts(instr) || !self.isa.accepts(instr, instr_len == 2) {
+            return Err(self.illegal_instruction());
+        }
+
         let opcode: u32 = self.instr_opcode(instr);
         //let func3: u32 = self.instr_func3(instr);
         //let instr_funct7: u32 = self.instr_funct7(instr);
 
         match opcode {
-            0b0010011 => self.execute_imm(instr),
-            0b0110111 => self.execute_lui(instr),
-            0b0010111 => self.execute_auipc(instr),
-            0b1101111 => self.execute_jal(instr),
-            0b1100111 => self.execute_jalr(instr),
-            0b1100011 => self.execute_branch(instr),
-            0b0000011 => self.execute_load(instr),
-            0b0100011 => self.execute_store(instr),
-            0b0110011 => self.execute_r_type(instr),
-            0b0001111 => self.execute_fence(instr),
-            0b1110011 => self.execute_system_csr(instr), // SYSTEM instruction, e.g. ECALL, EBREAK
-            0b0011011 => self.execute_rv64i_immediate(instr), // RV64I extensions
-            0b0111011 => self.execute_rv64i_extensions(instr),
-            _ => return Err(format!("Instruction with opcode {opcode:#b} not implemented yet")),
-        }
-        return Ok(());
+            0b0010011 => self.execute_imm(instr)?,
+            0b0110111 => self.execute_lui(instr)?,
+            0b0010111 => self.execute_auipc(instr)?,
+            0b1101111 => self.execute_jal(instr)?,
+            0b1100111 => self.execute_jalr(instr)?,
+            0b1100011 => self.execute_branch(instr)?,
+            0b0000011 => self.execute_load(instr)?,
+            0b0100011 => self.execute_store(instr)?,
+            0b0110011 => self.execute_r_type(instr)?,
+            0b0001111 => self.execute_fence(instr)?,
+            0b1110011 => self.execute_system_csr(instr)?, // SYSTEM instruction, e.g. ECALL, EBREAK
+            0b0011011 => self.execute_rv64i_immediate(instr)?, // RV64I extensions
+            0b0111011 => self.execute_rv64i_extensions(instr)?,
+            0b0101111 => self.execute_atomic(instr)?, // A extension
+            0b0000111 => self.execute_load_fp(instr)?, // F/D extension
+            0b0100111 => self.execute_store_fp(instr)?,
+            0b1010011 => self.execute_op_fp(instr)?,
+            0b1000011 | 0b1000111 | 0b1001011 | 0b1001111 => self.execute_fused_multiply_add(instr)?,
+            0b1010111 => return Err(self.unimplemented_extension("V")), // OP-V
+            _ => return Err(self.illegal_instruction()),
+        }
+        // Advance to the next instruction (pc + instruction length, or the jump/branch target)
+        self.pc = self.next_pc;
+        Ok(())
+    }
+
+    /// Fetches and executes one instruction, exceptions are taken as traps into the handler at mtvec/stvec.
+    /// Returns the exception if the instruction trapped, runners stop on traps which were not handled.
+    pub fn step(&mut self) -> Option<Trap> {
+        self.bus.tick();
+        self.mip_external = self.bus.mip();
+        // Enabled interrupts are taken between instructions
+        if let Some(cause) = self.pending_interrupt() {
+            self.trap(cause, 0, self.pc, true);
+            return None;
+        }
+        let (pc, privilege) = (self.pc, self.privilege);
+        if let Some(commit_log) = self.commit_log.as_mut() {
+            commit_log.discard();
+        }
+        let result = self.fetch_instr().and_then(|instr| {
+            let xlen = self.isa.xlen();
+            info!("PC: {:#x}, Instruction: {:#x} {}", self.pc, instr, disasm::disassemble_xlen(instr, xlen));
+            if let Some(trace) = self.trace.as_mut()
+                && let Err(err) = writeln!(trace, "{}", disasm::format_trace(pc, instr, xlen)) {
+                warn!("Failed to write trace: {err}");
+            }
+            self.execute_instr(instr)
+        });
+        match result {
+            Err(err) => {
+                let handled = self.has_trap_handler(&err);
+                if handled {
+                    self.trap(err.cause(), err.tval(), err.pc(), false);
+                }
+                Some(Trap { exception: err, handled })
+            },
+            Ok(()) => {
+                // Only retired instructions are logged, like Spike does
+                if let Some(commit_log) = self.commit_log.as_mut()
+                    && let Err(err) = commit_log.commit(privilege, pc, self.instr_raw, self.instr_len) {
+                    warn!("Failed to write commit log: {err}");
+                }
+                None
+            },
+        }
+    }
+
+    /// Whether the trap for `exception` can make progress, decided from mtvec/stvec before the trap overwrites the trap CSRs.
+    /// No handler is installed if the trap vector is not mapped, a handler faulting at its own first instruction loops.
+    fn has_trap_handler(&self, exception: &ExecError) -> bool {
+        let target = self.trap_target(exception.cause(), false);
+        let handler = self.trap_vector(target) & !0b11;
+        // stvec is a virtual address if the handler runs with translation, its mapping is only known when fetching
+        let ctx = MmuContext { privilege: target, ..self.mmu_context(MemoryAccess::Fetch) };
+        handler != exception.pc() && (ctx.translation_enabled() || self.bus.is_mapped(handler, 16))
+    }
+
+    /// Highest priority interrupt which is pending, enabled and not masked by the current privilege mode.
+    fn pending_interrupt(&self) -> Option<TReg> {
+        let pending = self.get_csr(CSR_MIP) & self.get_csr(CSR_MIE);
+        if pending == 0 {
+            return None;
+        }
+        let mstatus = self.get_csr(CSR_MSTATUS);
+        let mideleg = self.get_csr(CSR_MIDELEG);
+        // Interrupts for a higher privilege mode are always enabled, for the current mode only with xIE set
+        let m_enabled = self.privilege < Privilege::Machine || mstatus & MSTATUS_MIE != 0;
+        let s_enabled = self.privilege < Privilege::Supervisor || (self.privilege == Privilege::Supervisor && mstatus & MSTATUS_SIE != 0);
+        let mut enabled = 0;
+        if m_enabled {
+            enabled |= pending & !mideleg;
+        }
+        if s_enabled {
+            enabled |= pending & mideleg;
+        }
+        INTERRUPT_PRIORITY.into_iter().find(|cause| enabled & (1 << cause) != 0)
+    }
+
+    /// Privilege mode handling a trap: delegated traps from S/U-mode go to supervisor mode.
+    fn trap_target(&self, cause: TReg, interrupt: bool) -> Privilege {
+        let deleg = self.get_csr(if interrupt { CSR_MIDELEG } else { CSR_MEDELEG });
+        if self.privilege <= Privilege::Supervisor && cause < 64 && (deleg >> cause) & 1 == 1 {
+            Privilege::Supervisor
+        } else {
+            Privilege::Machine
+        }
+    }
+
+    fn trap_vector(&self, target: Privilege) -> TReg {
+        self.get_csr(if target == Privilege::Supervisor { CSR_STVEC } else { CSR_MTVEC })
+    }
+
+    /// Takes a trap: saves pc/cause/tval, pushes the interrupt enable stack and jumps to mtvec (or stvec if delegated).
+    pub fn trap(&mut self, cause: TReg, tval: TReg, epc: TReg, interrupt: bool) {
+        let target = self.trap_target(cause, interrupt);
+        info!("Trap - cause: {cause} - tval: {tval:#x} - epc: {epc:#x} - interrupt: {interrupt} - target: {target:?}");
+        let cause_value = if interrupt { self.xlen_msb() | cause } else { cause };
+        let mstatus = self.get_csr(CSR_MSTATUS);
+        if target == Privilege::Supervisor {
+            self.set_csr(CSR_SEPC, epc & !0b1);
+            self.set_csr(CSR_SCAUSE, cause_value);
+            self.set_csr(CSR_STVAL, tval);
+            // SPIE = SIE, SIE = 0, SPP = previous privilege
+            let spie = if mstatus & MSTATUS_SIE != 0 { MSTATUS_SPIE } else { 0 };
+            let spp = if self.privilege == Privilege::Supervisor { MSTATUS_SPP } else { 0 };
+            self.set_csr(CSR_MSTATUS, (mstatus & !(MSTATUS_SIE | MSTATUS_SPIE | MSTATUS_SPP)) | spie | spp);
+        } else {
+            self.set_csr(CSR_MEPC, epc & !0b1);
+            self.set_csr(CSR_MCAUSE, cause_value);
+            self.set_csr(CSR_MTVAL, tval);
+            // MPIE = MIE, MIE = 0, MPP = previous privilege
+            let mpie = if mstatus & MSTATUS_MIE != 0 { MSTATUS_MPIE } else { 0 };
+            self.set_csr(CSR_MSTATUS, (mstatus & !(MSTATUS_MIE | MSTATUS_MPIE | MSTATUS_MPP)) | mpie | ((self.privilege as TReg) << 11));
+        }
+        self.set_privilege(target);
+        // Direct mode (0) jumps to base, vectored mode (1) jumps to base + 4 * cause for interrupts
+        let tvec = self.trap_vector(target);
+        let base = tvec & !0b11;
+        self.pc = if interrupt && tvec & 0b11 == 1 { base.wrapping_add(4 * cause) } else { base };
+        self.next_pc = self.pc;
+    }
+
+    fn execute_mret(&mut self) -> Result<(), ExecError> {
+        if self.privilege != Privilege::Machine {
+            return Err(self.illegal_instruction());
+        }
+        // MIE = MPIE, MPIE = 1, privilege = MPP, MPP = U
+        let mstatus = self.get_csr(CSR_MSTATUS);
+        let mpp = Privilege::from_bits((mstatus & MSTATUS_MPP) >> 11);
+        let mie = if mstatus & MSTATUS_MPIE != 0 { MSTATUS_MIE } else { 0 };
+        let mprv = if mpp == Privilege::Machine { mstatus & MSTATUS_MPRV } else { 0 };
+        self.set_csr(CSR_MSTATUS, (mstatus & !(MSTATUS_MIE | MSTATUS_MPP | MSTATUS_MPRV)) | mie | MSTATUS_MPIE | mprv);
+        self.set_privilege(mpp);
+        self.jump(self.get_csr(CSR_MEPC));
+        Ok(())
+    }
+
+    fn execute_sret(&mut self) -> Result<(), ExecError> {
+        // SRET is illegal in U-mode, and in S-mode when mstatus.TSR is set
+        let mstatus = self.get_csr(CSR_MSTATUS);
+        if self.privilege == Privilege::User || (self.privilege == Privilege::Supervisor && mstatus & MSTATUS_TSR != 0) {
+            return Err(self.illegal_instruction());
+        }
+        // SIE = SPIE, SPIE = 1, privilege = SPP, SPP = U
+        let spp = if mstatus & MSTATUS_SPP != 0 { Privilege::Supervisor } else { Privilege::User };
+        let sie = if mstatus & MSTATUS_SPIE != 0 { MSTATUS_SIE } else { 0 };
+        self.set_csr(CSR_MSTATUS, (mstatus & !(MSTATUS_SIE | MSTATUS_SPP | MSTATUS_MPRV)) | sie | MSTATUS_SPIE);
+        self.set_privilege(spp);
+        self.jump(self.get_csr(CSR_SEPC));
+        Ok(())
+    }
+
+    fn check_csr_access(&self, csr_addr: TInstr, write: bool) -> Result<(), ExecError> {
+        // csr[9:8] is the lowest privilege level allowed to access the CSR, csr[11:10] == 0b11 marks it read-only
+        let min_privilege = (csr_addr >> 8) & 0b11;
+        // satp is not accessible from S-mode when mstatus.TVM is set
+        let trapped_satp = csr_addr as usize == CSR_SATP && self.privilege == Privilege::Supervisor && self.get_csr(CSR_MSTATUS) & MSTATUS_TVM != 0;
+        // fflags, frm and fcsr are not accessible while the FPU is off
+        let fp_disabled = matches!(csr_addr as usize, CSR_FFLAGS | CSR_FRM | CSR_FCSR) && self.check_fp_enabled().is_err();
+        if (self.privilege as TInstr) < min_privilege || (write && (csr_addr >> 10) & 0b11 == 0b11) || trapped_satp || fp_disabled {
+            warn!("Illegal access to CSR {csr_addr:#x} in {:?} mode (write: {write})", self.privilege);
+            return Err(self.illegal_instruction());
+        }
+        Ok(())
     }
     //
     // Instruction decoding
@@ -144,6 +738,14 @@ impl BasicCpu {
         (instr >> 20) & 0x1f // 0b0111 -> bits[20:24]
     }
 
+    pub fn instr_shamt(&self, instr: TInstr) -> TInstr {
+        (instr >> 20) & 0x3f // bits[20:25], RV64 shift amounts are 6 bits wide
+    }
+
+    pub fn instr_funct6(&self, instr: TInstr) -> TInstr {
+        (instr >> 26) & 0x3f // bits[26:31], func7 without shamt[5] for the RV64 shift immediates
+    }
+
     pub fn instr_funct7(&self, instr: TInstr) -> TInstr {
         (instr >> 25) & 0x7F // 0b0111 -> bits[25:31]
     }
@@ -169,6 +771,10 @@ impl BasicCpu {
         ((instr & 0x80000000) as i32 as i64 >> 11) as TImm | (instr & 0xff000) as TImm | ((instr >> 9) & 0x800) as TImm | ((instr >> 20) & 0x7fe) as TImm
     }
 
+    pub fn instr_rs3(&self, instr: TInstr) -> TInstr {
+        (instr >> 27) & 0x1f // bits[27:31], third source register of the fused multiply-add instructions
+    }
+
     pub fn instr_csr_addr(&self, instr: TInstr) -> TInstr {
         // CSR address is in bits [20:31] of the instruction
         (instr >> 20) & 0xfff // bits[20:31]
@@ -176,13 +782,13 @@ impl BasicCpu {
     //
     // Execute instructions
     //
-    pub fn execute_imm(&mut self, instr: TInstr){
+    pub fn execute_imm(&mut self, instr: TInstr) -> Result<(), ExecError> {
         let func3: TInstr = self.instr_func3(instr);
         let rd: TInstr = self.instr_rd(instr);
         let rs1: TInstr = self.instr_rs1(instr);
         let imm: TImm = self.instr_imm_i(instr); // sign-extend immediate value
-        let rs2_shamt: TInstr = self.instr_rs2_shamt(instr);
-        let func7: TInstr = self.instr_funct7(instr);
+        let shamt: TInstr = self.instr_shamt(instr);
+        let func6: TInstr = self.instr_funct6(instr);
         info!("[execute_imm] opcode (0b0010011): func3: {func3:#x} - rd: {rd} - rs1: {rs1} - imm: {imm}");
         /*
         imm[11:0] rs1 000 rd 0010011 ADDI 
@@ -191,69 +797,77 @@ impl BasicCpu {
         imm[11:0] rs1 100 rd 0010011 XORI 
         imm[11:0] rs1 110 rd 0010011 ORI 
         imm[11:0] rs1 111 rd 0010011 ANDI
-        0000000 shamt rs1 001 rd 0010011 SLLI 
-        0000000 shamt rs1 101 rd 0010011 SRLI 
-        0100000 shamt rs1 101 rd 0010011 SRAI
+        000000 shamt rs1 001 rd 0010011 SLLI 
+        000000 shamt rs1 101 rd 0010011 SRLI 
+        010000 shamt rs1 101 rd 0010011 SRAI
+        RV64I widens shamt to 6 bits (bits[20:25]), leaving a 6-bit func6 above it.
         */
         match func3 {
             0b000 => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_add(imm)), // addi
-            0b001 => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_shl(rs2_shamt)), // slli
+            0b001 => match func6 {
+                0b000000 => self.set_register(rd as usize, self.get_register(rs1 as usize) << shamt), // slli
+                _ => return Err(self.illegal_instruction())
+            },
             0b010 => self.set_register(rd as usize, if (self.get_register(rs1 as usize) as i64) < (imm as i64) { 1 } else { 0 }), // slti
             0b011 => self.set_register(rd as usize, if self.get_register(rs1 as usize) < imm { 1 } else { 0 }), // sltiu
             0b100 => self.set_register(rd as usize, self.get_register(rs1 as usize) ^ imm), // xori
-            0b101 => match func7 {
-                0b0000000 => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_shr(rs2_shamt)), // srli - SRLI is a logical right shift (zeros are shifted into the upper bits).
-                0b0100000 => self.set_register(rd as usize, ((self.get_register(rs1 as usize) as i64).wrapping_shr(rs2_shamt)) as TReg), // srai - SRAI is an arithmetic right shift (the original sign bit is copied into the vacated upper bits).
-                _ => panic!("Function (I-Type) with code func3 {func3:#x} AND func7 {func7:#x} not found")
+            0b101 => match func6 {
+                0b000000 => self.set_register(rd as usize, self.truncate_xlen(self.get_register(rs1 as usize)) >> shamt), // srli - SRLI is a logical right shift (zeros are shifted into the upper bits).
+                0b010000 => self.set_register(rd as usize, ((self.get_register(rs1 as usize) as i64) >> shamt) as TReg), // srai - SRAI is an arithmetic right shift (the original sign bit is copied into the vacated upper bits).
+                _ => return Err(self.illegal_instruction())
             },
             0b110 => self.set_register(rd as usize, self.get_register(rs1 as usize) | imm), // ori
             0b111 => self.set_register(rd as usize, self.get_register(rs1 as usize) & imm), // andi
-            _ => panic!("Function (I-Type) with code func3 {func3:#x} not found")
+            _ => return Err(self.illegal_instruction())
         }
+        Ok(())
     }
 
-    pub fn execute_lui(&mut self, instr: TInstr){
+    pub fn execute_lui(&mut self, instr: TInstr) -> Result<(), ExecError> {
         let rd: TInstr = self.instr_rd(instr);
         let imm: TImm = self.instr_imm_u(instr); // sign-extend immediate value
         info!("[execute_lui] opcode (0b0110111): rd: {rd} - imm: {imm}");
         // imm[31:12] rd 0110111 LUI
         self.set_register(rd as usize, imm);
+        Ok(())
     }
 
-    pub fn execute_auipc(&mut self, instr: TInstr){
+    pub fn execute_auipc(&mut self, instr: TInstr) -> Result<(), ExecError> {
         let rd: TInstr = self.instr_rd(instr);
         let imm: TImm = self.instr_imm_u(instr); // sign-extend immediate value
         let pc: TReg = self.get_pc();
         info!("[execute_auipc] opcode (0b0010111): rd: {rd} - imm: {imm} (- pc: {pc})");
         // imm[31:12] rd 0010111 AUIPC
         self.set_register(rd as usize, pc.wrapping_add(imm)); // add immediate value to current pc
+        Ok(())
     }
 
-    pub fn execute_jal(&mut self, instr: TInstr){
+    pub fn execute_jal(&mut self, instr: TInstr) -> Result<(), ExecError> {
         let rd: TInstr = self.instr_rd(instr);
         let imm: TImm = self.instr_imm_j(instr); // sign-extend immediate value
         let pc: TReg = self.get_pc();
         info!("[execute_jal] opcode (0b1101111): rd: {rd} - imm: {imm} (- pc: {pc})");
         // imm[20] imm[10:1] imm[11] imm[19:12] rd 1101111 JAL
-        // self.set_register(rd as usize, pc.wrapping_add(4)); // store return address
-        self.set_register(rd as usize, pc); // store return address
+        self.set_register(rd as usize, pc.wrapping_add(self.instr_len)); // store return address
         let new_pc: TReg = pc.wrapping_add(imm); // calculate new pc
-        self.set_pc(new_pc); // jump to target address
+        self.jump(new_pc); // jump to target address
+        Ok(())
     }
 
-    pub fn execute_jalr(&mut self, instr: TInstr){
+    pub fn execute_jalr(&mut self, instr: TInstr) -> Result<(), ExecError> {
         let rd: TInstr = self.instr_rd(instr);
         let rs1: TInstr = self.instr_rs1(instr);
         let imm: TImm = self.instr_imm_i(instr); // sign-extend immediate value;
         let pc: TReg = self.get_pc();
         info!("[execute_jalr] opcode (0b1100111): rd: {rd} - rs1: {rs1} - imm: {imm} (- pc: {pc})");
         // imm[11:0] rs1 000 rd 1100111 JALR
-        // self.set_register(rd as usize, pc.wrapping_add(4)); // store return address
-        self.set_register(rd as usize, pc); // store return address
-        self.set_pc(self.get_register(rs1 as usize).wrapping_add(imm) as TReg & !1); // jump to target address (clear LSB)
+        let new_pc: TReg = self.get_register(rs1 as usize).wrapping_add(imm) & !1; // target address (clear LSB), read rs1 before rd is written (rd may equal rs1)
+        self.set_register(rd as usize, pc.wrapping_add(self.instr_len)); // store return address
+        self.jump(new_pc); // jump to target address
+        Ok(())
     }
 
-    pub fn execute_branch(&mut self, instr: TInstr){
+    pub fn execute_branch(&mut self, instr: TInstr) -> Result<(), ExecError> {
         let func3: TInstr = self.instr_func3(instr);
         let rs1: TInstr = self.instr_rs1(instr);
         let rs2: TInstr = self.instr_rs2_shamt(instr);
@@ -271,51 +885,40 @@ impl BasicCpu {
         match func3 {
             0b000 => { // BEQ
                 if self.get_register(rs1 as usize) == self.get_register(rs2 as usize) {
-                    self.set_pc(pc.wrapping_add(imm).wrapping_sub(4)); 
-                } /*else {
-                    self.set_pc(pc.wrapping_add(4));
-                }*/
+                    self.jump(pc.wrapping_add(imm));
+                }
             },
             0b001 => { // BNE
                 if self.get_register(rs1 as usize) != self.get_register(rs2 as usize) {
-                    self.set_pc(pc.wrapping_add(imm).wrapping_sub(4));
-                } /*else {
-                    self.set_pc(pc.wrapping_add(4));
-                }*/
+                    self.jump(pc.wrapping_add(imm));
+                }
             },
             0b100 => { // BLT
-                if (self.get_register(rs1 as usize) as i32) < (self.get_register(rs2 as usize) as i32) {
-                    self.set_pc(pc.wrapping_add(imm).wrapping_sub(4));
-                } /*else {
-                    self.set_pc(pc.wrapping_add(4));
-                }*/
+                if (self.get_register(rs1 as usize) as i64) < (self.get_register(rs2 as usize) as i64) {
+                    self.jump(pc.wrapping_add(imm));
+                }
             },
             0b101 => { // BGE
-                if (self.get_register(rs1 as usize) as i32) >= (self.get_register(rs2 as usize) as i32) {
-                    self.set_pc(pc.wrapping_add(imm).wrapping_sub(4));
-                } /*else {
-                    self.set_pc(pc.wrapping_add(4));
-                }*/
+                if (self.get_register(rs1 as usize) as i64) >= (self.get_register(rs2 as usize) as i64) {
+                    self.jump(pc.wrapping_add(imm));
+                }
             },
             0b110 => { // BLTU
                 if self.get_register(rs1 as usize) < self.get_register(rs2 as usize) {
-                    self.set_pc(pc.wrapping_add(imm).wrapping_sub(4));
-                } /*else {
-                    self.set_pc(pc.wrapping_add(4));
-                }*/
+                    self.jump(pc.wrapping_add(imm));
+                }
             },
             0b111 => { // BGEU
                 if self.get_register(rs1 as usize) >= self.get_register(rs2 as usize) {
-                    self.set_pc(pc.wrapping_add(imm).wrapping_sub(4));
-                } /*else {
-                    self.set_pc(pc.wrapping_add(4));
-                }*/
+                    self.jump(pc.wrapping_add(imm));
+                }
             },
-            _ => info!("Function (B-Type) with code func3 {func3:#x} not found")
+            _ => return Err(self.illegal_instruction())
         }   
+        Ok(())
     }
 
-    pub fn execute_load(&mut self, instr: TInstr){
+    pub fn execute_load(&mut self, instr: TInstr) -> Result<(), ExecError> {
         let func3: TInstr = self.instr_func3(instr);
         let rd: TInstr = self.instr_rd(instr);
         let rs1: TInstr = self.instr_rs1(instr);
@@ -328,32 +931,34 @@ impl BasicCpu {
         imm[11:0] rs1 100 rd 0000011 LBU 
         imm[11:0] rs1 101 rd 0000011 LHU
         */
-        let target_addr: usize = (self.get_register(rs1 as usize).wrapping_add(imm)) as usize;
-        if target_addr >= DRAM_BASE_ADDR && target_addr < (DRAM_BASE_ADDR + DRAM_SIZE) {
-            info!("Reading from DRAM at address {target_addr:#x}");
-        } else {
-            warn!("Attempt to read from invalid DRAM address {target_addr:#x}");
-            return;
-        }
+        let size: usize = match func3 {
+            0b000 | 0b100 => 8, // LB, LBU
+            0b001 | 0b101 => 16, // LH, LHU
+            0b010 | 0b110 => 32, // LW, LWU
+            0b011 => 64, // LD
+            _ => return Err(self.illegal_instruction())
+        };
+        let target_addr: TReg = self.get_register(rs1 as usize).wrapping_add(imm);
+        info!("Reading {size} bits from address {target_addr:#x}");
         match func3 {
             0b000 => {
-                let val = self.mem.dram_read(target_addr, 8); // LB
+                let val = self.read_memory(target_addr, 8, MemoryAccess::Load)?; // LB
                 self.set_register(rd as usize, val as i8 as i64 as TReg);
             },  
             0b001 => {
-                let val = self.mem.dram_read(target_addr, 16); // LH
+                let val = self.read_memory(target_addr, 16, MemoryAccess::Load)?; // LH
                 self.set_register(rd as usize, val as i16 as i64 as TReg);
             },
             0b010 => {
-                let val = self.mem.dram_read(target_addr, 32); // LW
+                let val = self.read_memory(target_addr, 32, MemoryAccess::Load)?; // LW
                 self.set_register(rd as usize, val as i32 as i64 as TReg);
             },
             0b100 => {
-                let val = self.mem.dram_read(target_addr, 8); // LBU
+                let val = self.read_memory(target_addr, 8, MemoryAccess::Load)?; // LBU
                 self.set_register(rd as usize, val as u8 as TReg);
             },
             0b101 => {
-                let val = self.mem.dram_read(target_addr, 16); // LHU
+                let val = self.read_memory(target_addr, 16, MemoryAccess::Load)?; // LHU
                 self.set_register(rd as usize, val as u16 as TReg);
             },
             // RV64 extensions
@@ -363,20 +968,21 @@ impl BasicCpu {
                 // LWU:
                 // The LWinstruction loads a 32-bit value from memory and sign-extends this to 64 bits before storing it in register rd for RV64I. 
                 // The LWU instruction, on the other hand, zero-extends the 32-bit value from memory for RV64I.
-                let val = self.mem.dram_read(target_addr, 32); // LWU
+                let val = self.read_memory(target_addr, 32, MemoryAccess::Load)?; // LWU
                 self.set_register(rd as usize, val as u32 as TReg);
             },
             0b011 => {
                 // LD:
                 // The LD instruction loads a 64-bit value from memory and stores it in register rd for RV64I.
-                let val = self.mem.dram_read(target_addr, 64); // LD
+                let val = self.read_memory(target_addr, 64, MemoryAccess::Load)?; // LD
                 self.set_register(rd as usize, val as i64 as TReg);
             },
-            _ => panic!("Function (Load-Type) with code func3 {func3:#x} not found")
+            _ => return Err(self.illegal_instruction())
         }
+        Ok(())
     }
 
-    pub fn execute_store(&mut self, instr: TInstr){
+    pub fn execute_store(&mut self, instr: TInstr) -> Result<(), ExecError> {
         let func3: TInstr = self.instr_func3(instr);
         let rs1: TInstr = self.instr_rs1(instr);
         let rs2: TInstr = self.instr_rs2_shamt(instr);
@@ -387,37 +993,36 @@ impl BasicCpu {
         imm[11:5] rs2 rs1 001 imm[4:0] 0100011 SH 
         imm[11:5] rs2 rs1 010 imm[4:0] 0100011 SW
         */
-        let target_addr: usize = (self.get_register(rs1 as usize).wrapping_add(imm)) as usize;
-        if target_addr >= DRAM_BASE_ADDR && target_addr < (DRAM_BASE_ADDR + DRAM_SIZE) {
-            info!("Writing to DRAM at address {target_addr}");
-        } else {
-            warn!("Attempt to write to invalid DRAM address {target_addr}");
-            return;
+        if func3 > 0b011 {
+            return Err(self.illegal_instruction());
         }
+        let target_addr: TReg = self.get_register(rs1 as usize).wrapping_add(imm);
+        info!("Writing to address {target_addr:#x}");
         match func3 {
             0b000 => {
                 let val = self.get_register(rs2 as usize) as i8 as u64; // SB
-                self.mem.dram_write(target_addr, 8, val);
+                self.write_memory(target_addr, 8, val)?;
             },
             0b001 => {
                 let val = self.get_register(rs2 as usize) as i16 as u64; // SH
-                self.mem.dram_write(target_addr, 16, val);
+                self.write_memory(target_addr, 16, val)?;
             },
             0b010 => {
                 let val = self.get_register(rs2 as usize) as i32 as u64; // SW
-                self.mem.dram_write(target_addr, 32, val);
+                self.write_memory(target_addr, 32, val)?;
             },
             0b011 =>{
                 // SD:
                 // imm[11:5] rs2 rs1 011 imm[4:0] 0100011 SD
                 let val = self.get_register(rs2 as usize) as i64 as u64; // SD
-                self.mem.dram_write(target_addr, 64, val);
+                self.write_memory(target_addr, 64, val)?;
             }
-            _ => panic!("Function (Store-Type) with code func3 {func3:#x} not found")
+            _ => return Err(self.illegal_instruction())
         }
+        Ok(())
     }
 
-    pub fn execute_r_type(&mut self, instr: TInstr){
+    pub fn execute_r_type(&mut self, instr: TInstr) -> Result<(), ExecError> {
         let func3: TInstr = self.instr_func3(instr);
         let rd: TInstr = self.instr_rd(instr);
         let rs1: TInstr = self.instr_rs1(instr);
@@ -435,48 +1040,205 @@ impl BasicCpu {
         0100000 rs2 rs1 101 rd 0110011 SRA 
         0000000 rs2 rs1 110 rd 0110011 OR 
         0000000 rs2 rs1 111 rd 0110011 AND
+        Shifts use the lower log2(XLEN) bits of rs2 as shift amount.
+        M extension:
+        0000001 rs2 rs1 000 rd 0110011 MUL 
+        0000001 rs2 rs1 001 rd 0110011 MULH 
+        0000001 rs2 rs1 010 rd 0110011 MULHSU 
+        0000001 rs2 rs1 011 rd 0110011 MULHU 
+        0000001 rs2 rs1 100 rd 0110011 DIV 
+        0000001 rs2 rs1 101 rd 0110011 DIVU 
+        0000001 rs2 rs1 110 rd 0110011 REM 
+        0000001 rs2 rs1 111 rd 0110011 REMU
         */
+        let shamt = self.get_register(rs2 as usize) as u32 & (self.isa.xlen() - 1);
         match (func3, func7) {
             (0b000, 0b0000000) => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_add(self.get_register(rs2 as usize))), // add
             (0b000, 0b0100000) => self.set_register(rd as usize, ((self.get_register(rs1 as usize) as i64).wrapping_sub(self.get_register(rs2 as usize) as i64)) as TReg), // sub
-            (0b001, 0b0000000) => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_shl(self.get_register(rs2 as usize) as u32)), // sll
+            (0b001, 0b0000000) => self.set_register(rd as usize, self.get_register(rs1 as usize) << shamt), // sll
             (0b010, 0b0000000) => self.set_register(rd as usize, if (self.get_register(rs1 as usize) as i64) < (self.get_register(rs2 as usize) as i64) { 1 } else { 0 }), // slt
             (0b011, 0b0000000) => self.set_register(rd as usize, if self.get_register(rs1 as usize) < self.get_register(rs2 as usize) { 1 } else { 0 }), // sltu
             (0b100, 0b0000000) => self.set_register(rd as usize, self.get_register(rs1 as usize) ^ self.get_register(rs2 as usize)), // xor
-            (0b101, 0b0000000) => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_shr(self.get_register(rs2 as usize) as u32)), // srl
-            (0b101, 0b0100000) => self.set_register(rd as usize, ((self.get_register(rs1 as usize) as i64).wrapping_shr(self.get_register(rs2 as usize) as u32)) as TReg), // sra
+            (0b101, 0b0000000) => self.set_register(rd as usize, self.truncate_xlen(self.get_register(rs1 as usize)) >> shamt), // srl
+            (0b101, 0b0100000) => self.set_register(rd as usize, ((self.get_register(rs1 as usize) as i64) >> shamt) as TReg), // sra
             (0b110, 0b0000000) => self.set_register(rd as usize, self.get_register(rs1 as usize) | self.get_register(rs2 as usize)), // or
             (0b111, 0b0000000) => self.set_register(rd as usize, self.get_register(rs1 as usize) & self.get_register(rs2 as usize)), // and
-            _ => panic!("Function (R-Type) with code func3 {func3:#x} AND func7 {func7:#x} not found")
+            // M extension
+            (0b000, 0b0000001) => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_mul(self.get_register(rs2 as usize))), // mul
+            // The high multiplications return the upper XLEN bits, unsigned operands are zero-extended from XLEN bits
+            (0b001, 0b0000001) => self.set_register(rd as usize, ((self.get_register(rs1 as usize) as i64 as i128 * self.get_register(rs2 as usize) as i64 as i128) >> self.isa.xlen()) as TReg), // mulh - signed x signed
+            (0b010, 0b0000001) => self.set_register(rd as usize, ((self.get_register(rs1 as usize) as i64 as i128).wrapping_mul(self.truncate_xlen(self.get_register(rs2 as usize)) as i128) >> self.isa.xlen()) as TReg), // mulhsu - signed x unsigned
+            (0b011, 0b0000001) => self.set_register(rd as usize, ((self.truncate_xlen(self.get_register(rs1 as usize)) as u128 * self.truncate_xlen(self.get_register(rs2 as usize)) as u128) >> self.isa.xlen()) as TReg), // mulhu - unsigned x unsigned
+            (0b100, 0b0000001) => self.set_register(rd as usize, div_signed(self.get_register(rs1 as usize) as i64, self.get_register(rs2 as usize) as i64) as TReg), // div
+            (0b101, 0b0000001) => self.set_register(rd as usize, div_unsigned(self.truncate_xlen(self.get_register(rs1 as usize)), self.truncate_xlen(self.get_register(rs2 as usize)))), // divu
+            (0b110, 0b0000001) => self.set_register(rd as usize, rem_signed(self.get_register(rs1 as usize) as i64, self.get_register(rs2 as usize) as i64) as TReg), // rem
+            (0b111, 0b0000001) => self.set_register(rd as usize, rem_unsigned(self.truncate_xlen(self.get_register(rs1 as usize)), self.truncate_xlen(self.get_register(rs2 as usize)))), // remu
+            _ => return Err(self.illegal_instruction())
         }   
+        Ok(())
     }
 
-    pub fn execute_fence(&mut self, _instr: TInstr){
+    pub fn execute_atomic(&mut self, instr: TInstr) -> Result<(), ExecError> {
+        let func3: TInstr = self.instr_func3(instr);
+        let rd: TInstr = self.instr_rd(instr);
+        let rs1: TInstr = self.instr_rs1(instr);
+        let rs2: TInstr = self.instr_rs2_shamt(instr);
+        let func5: TInstr = self.instr_funct7(instr) >> 2; // bits[27:31], bits[25:26] hold the aq/rl ordering bits
+        info!("[execute_atomic] opcode (0b0101111): func3: {func3:#x} - func5: {func5:#x} - rd: {rd} - rs1: {rs1} - rs2: {rs2}");
+        /*
+        00010 aq rl 00000 rs1 010 rd 0101111 LR.W 
+        00011 aq rl rs2 rs1 010 rd 0101111 SC.W 
+        00001 aq rl rs2 rs1 010 rd 0101111 AMOSWAP.W 
+        00000 aq rl rs2 rs1 010 rd 0101111 AMOADD.W 
+        00100 aq rl rs2 rs1 010 rd 0101111 AMOXOR.W 
+        01100 aq rl rs2 rs1 010 rd 0101111 AMOAND.W 
+        01000 aq rl rs2 rs1 010 rd 0101111 AMOOR.W 
+        10000 aq rl rs2 rs1 010 rd 0101111 AMOMIN.W 
+        10100 aq rl rs2 rs1 010 rd 0101111 AMOMAX.W 
+        11000 aq rl rs2 rs1 010 rd 0101111 AMOMINU.W 
+        11100 aq rl rs2 rs1 010 rd 0101111 AMOMAXU.W
+        The RV64A double word variants (LR.D, SC.D, AMO*.D) use func3 011.
+        The aq/rl bits are accepted but have no effect, this implementation executes a single hart in program order.
+        */
+        let size: usize = match func3 {
+            0b010 => 32, // .W
+            0b011 => 64, // .D
+            _ => return Err(self.illegal_instruction())
+        };
+        if !matches!(func5, 0b00010 | 0b00011 | 0b00001 | 0b00000 | 0b00100 | 0b01100 | 0b01000 | 0b10000 | 0b10100 | 0b11000 | 0b11100)
+            || (func5 == 0b00010 && rs2 != 0) {
+            return Err(self.illegal_instruction());
+        }
+        let vaddr: TReg = self.truncate_xlen(self.get_register(rs1 as usize));
+        let access = if func5 == 0b00010 { MemoryAccess::Load } else { MemoryAccess::Store }; // LR is a load, SC and AMOs are stores
+        if !vaddr.is_multiple_of(size as TReg / 8) {
+            // Atomic memory operations must be naturally aligned
+            warn!("Misaligned atomic access to address {vaddr:#x}");
+            return Err(ExecError::Misaligned { addr: vaddr, access, pc: self.pc });
+        }
+        let target_addr: TReg = self.translate_access(vaddr, size, access)?;
+        info!("Atomic access to DRAM at address {target_addr:#x}");
+        // Sign-extend loaded words to 64 bits, double words are used as is
+        let sign_extend = |val: u64| if size == 32 { val as i32 as i64 as TReg } else { val };
+
+        match func5 {
+            0b00010 => { // LR
+                let val = self.read_physical(target_addr, size, vaddr, access)?;
+                self.bus.dram.reserve(target_addr as usize);
+                self.set_register(rd as usize, sign_extend(val));
+            },
+            0b00011 => { // SC
+                if self.bus.dram.is_reserved(target_addr as usize) {
+                    self.write_physical(target_addr, size, self.get_register(rs2 as usize), vaddr)?;
+                    self.set_register(rd as usize, 0); // success
+                } else {
+                    self.set_register(rd as usize, 1); // failure
+                }
+                self.bus.dram.clear_reservation(); // SC always invalidates the reservation
+            },
+            _ => {
+                // AMOs: load the value at rs1 into rd, apply the operation to the loaded value and rs2 and store the result at rs1
+                let loaded: TReg = sign_extend(self.read_physical(target_addr, size, vaddr, access)?);
+                let src: TReg = sign_extend(self.get_register(rs2 as usize));
+                let result: TReg = match func5 {
+                    0b00001 => src, // AMOSWAP
+                    0b00000 => loaded.wrapping_add(src), // AMOADD
+                    0b00100 => loaded ^ src, // AMOXOR
+                    0b01100 => loaded & src, // AMOAND
+                    0b01000 => loaded | src, // AMOOR
+                    0b10000 => (loaded as i64).min(src as i64) as TReg, // AMOMIN
+                    0b10100 => (loaded as i64).max(src as i64) as TReg, // AMOMAX
+                    // sign-extension of both operands preserves the unsigned ordering of the 32-bit values
+                    0b11000 => loaded.min(src), // AMOMINU
+                    0b11100 => loaded.max(src), // AMOMAXU
+                    _ => return Err(self.illegal_instruction())
+                };
+                self.write_physical(target_addr, size, result, vaddr)?;
+                self.set_register(rd as usize, loaded);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn execute_fence(&mut self, _instr: TInstr) -> Result<(), ExecError> {
         // FENCE instruction is used to order memory operations
         // It does not change the state of the CPU or registers
         info!("[execute_fence] opcode (0b0001111): FENCE instruction executed");
         // No operation needed for this implementation
+        Ok(())
     }
 
-    pub fn execute_system_csr(&mut self, instr: TInstr){
+    pub fn execute_system_csr(&mut self, instr: TInstr) -> Result<(), ExecError> {
         let func3: TInstr = self.instr_func3(instr);
         let rd: TInstr = self.instr_rd(instr);
         let rs1: TInstr = self.instr_rs1(instr);
         let csr_addr: TInstr = self.instr_csr_addr(instr);
         match func3 {
-            0b000 => info!("[execute_system_csr] opcode (0b1110011): ECALL/EBREAK"), // ECALL or EBREAK instruction
+            0b000 => match instr {
+                0x00000073 => {
+                    info!("[execute_system_csr] opcode (0b1110011): ECALL");
+                    let Some(mut handler) = self.syscall_handler.take() else {
+                        return Err(ExecError::EnvironmentCall { privilege: self.privilege, pc: self.pc });
+                    };
+                    if let SyscallOutcome::Exit(code) = handler.syscall(self) {
+                        self.exit_code = Some(code);
+                    }
+                    self.syscall_handler = Some(handler);
+                },
+                0x00100073 => {
+                    info!("[execute_system_csr] opcode (0b1110011): EBREAK");
+                    if self.semihosting_handler.is_none() || !self.is_semihosting_call() {
+                        return Err(ExecError::Breakpoint { pc: self.pc });
+                    }
+                    let mut handler = self.semihosting_handler.take().unwrap();
+                    if let SyscallOutcome::Exit(code) = handler.syscall(self) {
+                        self.exit_code = Some(code);
+                    }
+                    self.semihosting_handler = Some(handler);
+                },
+                0x30200073 => {
+                    info!("[execute_system_csr] opcode (0b1110011): MRET");
+                    self.execute_mret()?;
+                },
+                0x10200073 => {
+                    info!("[execute_system_csr] opcode (0b1110011): SRET");
+                    self.execute_sret()?;
+                },
+                0x10500073 => {
+                    // WFI is a hint which may complete immediately, illegal in U-mode and in S-mode when mstatus.TW is set
+                    if self.privilege == Privilege::User
+                        || (self.privilege == Privilege::Supervisor && self.get_csr(CSR_MSTATUS) & MSTATUS_TW != 0) {
+                        return Err(self.illegal_instruction());
+                    }
+                    info!("[execute_system_csr] opcode (0b1110011): WFI");
+                },
+                _ if self.instr_funct7(instr) == 0b0001001 && rd == 0 => {
+                    // SFENCE.VMA is illegal in U-mode and in S-mode when mstatus.TVM is set
+                    if self.privilege == Privilege::User
+                        || (self.privilege == Privilege::Supervisor && self.get_csr(CSR_MSTATUS) & MSTATUS_TVM != 0) {
+                        return Err(self.illegal_instruction());
+                    }
+                    let rs2: TInstr = self.instr_rs2_shamt(instr);
+                    let vaddr = if rs1 != 0 { Some(self.get_register(rs1 as usize)) } else { None };
+                    let asid = if rs2 != 0 { Some(self.get_register(rs2 as usize) & mmu::SATP_ASID_MASK) } else { None };
+                    self.tlb.flush(vaddr, asid);
+                    info!("[execute_system_csr] opcode (0b1110011): SFENCE.VMA - rs1: {rs1} - rs2: {rs2}");
+                },
+                _ => return Err(self.illegal_instruction())
+            },
             0b001 => {
                 /*  
                 CSRRW - Read CSR and write to register
                 CSRRW reads the old value of the CSR, zero-extends the value to XLEN bits, then writes it to integer register rd. 
-                The initial value in rs1 is written to the CSR
+                The initial value in rs1 is written to the CSR, also when rs1 is x0
                 */
+                self.check_csr_access(csr_addr, true)?;
                 let csr_value = self.get_csr(csr_addr as usize);
-                self.set_register(rd as usize, csr_value);
-                if rs1 != 0 {
-                    // Write value from register to CSR
-                    self.set_csr(csr_addr as usize, self.get_register(rs1 as usize));
+                let rs1_value = self.get_register(rs1 as usize); // read before rd is written, rd may equal rs1
+                if rd != 0 {
+                    self.set_register(rd as usize, csr_value);
                 }
+                self.set_csr(csr_addr as usize, rs1_value);
                 info!("[execute_system_csr] opcode (0b1110011): CSRRW - CSR: {csr_addr} - rd: {rd} - rs1: {rs1}");
             },
             0b010 => {
@@ -486,11 +1248,13 @@ impl BasicCpu {
                 The initial value in integer register rs1 is treated as a bit mask that specifies bit positions to be set in the CSR.
                 Any bit that is high in rs1 will cause the corresponding bit to be set in the CSR, if that CSR bit is writable.
                  */
+                self.check_csr_access(csr_addr, rs1 != 0)?;
                 let csr_value = self.get_csr(csr_addr as usize);
+                let rs1_value = self.get_register(rs1 as usize);
                 self.set_register(rd as usize, csr_value);
                 if rs1 != 0 {
                     // Set bits in CSR from register
-                    self.set_csr(csr_addr as usize, csr_value | self.get_register(rs1 as usize));
+                    self.set_csr(csr_addr as usize, csr_value | rs1_value);
                 }
                 info!("[execute_system_csr] opcode (0b1110011): CSRRS - CSR: {csr_addr} - rd: {rd} - rs1: {rs1}");
             },
@@ -501,11 +1265,13 @@ impl BasicCpu {
                 The initial value in integer register rs1 is treated as a bit mask that specifies bit positions to be cleared in the CSR. 
                 Any bit that is high in rs1 will cause the corresponding bit to be cleared in the CSR, if that CSR bit is writable
                  */
+                self.check_csr_access(csr_addr, rs1 != 0)?;
                 let csr_value = self.get_csr(csr_addr as usize);
+                let rs1_value = self.get_register(rs1 as usize);
                 self.set_register(rd as usize, csr_value);
                 if rs1 != 0 {
                     // Clear bits in CSR from register
-                    self.set_csr(csr_addr as usize, csr_value & !self.get_register(rs1 as usize));
+                    self.set_csr(csr_addr as usize, csr_value & !rs1_value);
                 }
                 info!("[execute_system_csr] opcode (0b1110011): CSRRC - CSR: {csr_addr} - rd: {rd} - rs1: {rs1}");
             },
@@ -513,15 +1279,14 @@ impl BasicCpu {
                 /*
                 CSRRWI - Read CSR and write immediate value
                 CSRRWI reads the old value of the CSR, zero-extends the value to XLEN bits, then writes it to integer register rd. 
-                The immediate value is written to the CSR.
+                The immediate value is written to the CSR, also when it is 0.
                  */
+                self.check_csr_access(csr_addr, true)?;
                 let csr_value = self.get_csr(csr_addr as usize);
                 if rd != 0 {
                     self.set_register(rd as usize, csr_value);
-                    self.set_csr(csr_addr as usize, rs1 as TReg); // rs1 is used as immediate value
-                } else {
-                    warn!("CSRRWI instruction with rd = 0, no value will be stored in register");
                 }
+                self.set_csr(csr_addr as usize, rs1 as TReg); // rs1 is used as immediate value
                 info!("[execute_system_csr] opcode (0b1110011): CSRRWI - CSR: {csr_addr} - rd: {rd} - imm: {rs1}");
             },
             0b110 => {
@@ -531,6 +1296,7 @@ impl BasicCpu {
                 The immediate value is treated as a bit mask that specifies bit positions to be set in the CSR.
                 Any bit that is high in the immediate value will cause the corresponding bit to be set in the CSR, if that CSR bit is writable.
                 */
+                self.check_csr_access(csr_addr, rs1 != 0)?;
                 let csr_value = self.get_csr(csr_addr as usize);
                 self.set_register(rd as usize, csr_value);
                 if rs1 != 0 {
@@ -546,6 +1312,7 @@ impl BasicCpu {
                 The immediate value is treated as a bit mask that specifies bit positions to be cleared in the CSR.
                 Any bit that is high in the immediate value will cause the corresponding bit to be cleared in the CSR, if that CSR bit is writable.
                 */
+                self.check_csr_access(csr_addr, rs1 != 0)?;
                 let csr_value = self.get_csr(csr_addr as usize);
                 self.set_register(rd as usize, csr_value);
                 if rs1 != 0 {
@@ -554,13 +1321,223 @@ impl BasicCpu {
                 }
                 info!("[execute_system_csr] opcode (0b1110011): CSRRCI - CSR: {csr_addr} - rd: {rd} - imm: {rs1}");
             },
-            _ => panic!("Function (System-CSR) with code func3 {func3:#x} not found")
+            _ => return Err(self.illegal_instruction())
+        }
+        Ok(())
+    }
+
+    //
+    // F/D extension
+    //
+    fn fp_format(&self, fmt: TInstr) -> Result<FpFormat, ExecError> {
+        match fmt {
+            0b00 => Ok(FpFormat::Single),
+            0b01 => Ok(FpFormat::Double),
+            0b10 => Err(self.unimplemented_extension("Zfh")), // half precision
+            _ => Err(self.unimplemented_extension("Q")), // quad precision
+        }
+    }
+
+    fn fp_rounding_mode(&self, rm: TInstr) -> Result<RoundingMode, ExecError> {
+        // rm 111 selects the dynamic rounding mode in frm, reserved modes are illegal
+        let rm = if rm == 0b111 { self.get_csr(CSR_FRM) } else { rm as TReg };
+        RoundingMode::from_bits(rm).ok_or_else(|| self.illegal_instruction())
+    }
+
+    fn accrue_fflags(&mut self, flags: TReg) {
+        if flags != 0 {
+            self.set_csr(CSR_FFLAGS, self.get_csr(CSR_FFLAGS) | flags);
+        }
+    }
+
+    pub fn execute_load_fp(&mut self, instr: TInstr) -> Result<(), ExecError> {
+        self.check_fp_enabled()?;
+        let func3: TInstr = self.instr_func3(instr);
+        let rd: TInstr = self.instr_rd(instr);
+        let rs1: TInstr = self.instr_rs1(instr);
+        let imm: TImm = self.instr_imm_i(instr); // sign-extend immediate value
+        info!("[execute_load_fp] opcode (0b0000111): func3: {func3:#x} - rd: {rd} - rs1: {rs1} - imm: {imm}");
+        /*
+        imm[11:0] rs1 010 rd 0000111 FLW 
+        imm[11:0] rs1 011 rd 0000111 FLD
+        */
+        let size: usize = match func3 {
+            0b010 => 32, // FLW
+            0b011 => 64, // FLD
+            0b001 => return Err(self.unimplemented_extension("Zfh")), // FLH
+            0b100 => return Err(self.unimplemented_extension("Q")), // FLQ
+            _ => return Err(self.unimplemented_extension("V")), // vector loads share the LOAD-FP opcode
+        };
+        let target_addr: TReg = self.get_register(rs1 as usize).wrapping_add(imm);
+        info!("Reading {size} bits from address {target_addr:#x}");
+        match func3 {
+            0b010 => {
+                let val = self.read_memory(target_addr, 32, MemoryAccess::Load)?; // FLW
+                self.set_fregister(rd as usize, fpu::nan_box(val as u32));
+            },
+            0b011 => {
+                let val = self.read_memory(target_addr, 64, MemoryAccess::Load)?; // FLD
+                self.set_fregister(rd as usize, val);
+            },
+            _ => return Err(self.illegal_instruction())
+        }
+        Ok(())
+    }
+
+    pub fn execute_store_fp(&mut self, instr: TInstr) -> Result<(), ExecError> {
+        self.check_fp_enabled()?;
+        let func3: TInstr = self.instr_func3(instr);
+        let rs1: TInstr = self.instr_rs1(instr);
+        let rs2: TInstr = self.instr_rs2_shamt(instr);
+        let imm: TImm = self.instr_imm_s(instr); // sign-extend immediate value
+        info!("[execute_store_fp] opcode (0b0100111): func3: {func3:#x} - rs1: {rs1} - rs2: {rs2} - imm: {imm}");
+        /*
+        imm[11:5] rs2 rs1 010 imm[4:0] 0100111 FSW 
+        imm[11:5] rs2 rs1 011 imm[4:0] 0100111 FSD
+        */
+        let size: usize = match func3 {
+            0b010 => 32, // FSW
+            0b011 => 64, // FSD
+            0b001 => return Err(self.unimplemented_extension("Zfh")), // FSH
+            0b100 => return Err(self.unimplemented_extension("Q")), // FSQ
+            _ => return Err(self.unimplemented_extension("V")), // vector stores share the STORE-FP opcode
+        };
+        let target_addr: TReg = self.get_register(rs1 as usize).wrapping_add(imm);
+        info!("Writing {size} bits to address {target_addr:#x}");
+        match func3 {
+            0b010 => self.write_memory(target_addr, 32, self.get_fregister(rs2 as usize) as u32 as u64)?, // FSW - stores the raw lower 32 bits
+            0b011 => self.write_memory(target_addr, 64, self.get_fregister(rs2 as usize))?, // FSD
+            _ => return Err(self.illegal_instruction())
+        }
+        Ok(())
+    }
+
+    pub fn execute_op_fp(&mut self, instr: TInstr) -> Result<(), ExecError> {
+        self.check_fp_enabled()?;
+        let rm: TInstr = self.instr_func3(instr); // rounding mode, or the operation for instructions which do not round
+        let rd: TInstr = self.instr_rd(instr);
+        let rs1: TInstr = self.instr_rs1(instr);
+        let rs2: TInstr = self.instr_rs2_shamt(instr);
+        let func7: TInstr = self.instr_funct7(instr);
+        info!("[execute_op_fp] opcode (0b1010011): func7: {func7:#x} - rm: {rm:#x} - rd: {rd} - rs1: {rs1} - rs2: {rs2}");
+        /*
+        func7 = func5 fmt, fmt 00 = S (single), 01 = D (double)
+        00000 fmt rs2 rs1 rm rd 1010011 FADD 
+        00001 fmt rs2 rs1 rm rd 1010011 FSUB 
+        00010 fmt rs2 rs1 rm rd 1010011 FMUL 
+        00011 fmt rs2 rs1 rm rd 1010011 FDIV 
+        01011 fmt 00000 rs1 rm rd 1010011 FSQRT 
+        00100 fmt rs2 rs1 000 rd 1010011 FSGNJ 
+        00100 fmt rs2 rs1 001 rd 1010011 FSGNJN 
+        00100 fmt rs2 rs1 010 rd 1010011 FSGNJX 
+        00101 fmt rs2 rs1 000 rd 1010011 FMIN 
+        00101 fmt rs2 rs1 001 rd 1010011 FMAX 
+        01000 00 00001 rs1 rm rd 1010011 FCVT.S.D 
+        01000 01 00000 rs1 rm rd 1010011 FCVT.D.S 
+        10100 fmt rs2 rs1 010 rd 1010011 FEQ 
+        10100 fmt rs2 rs1 001 rd 1010011 FLT 
+        10100 fmt rs2 rs1 000 rd 1010011 FLE 
+        11100 fmt 00000 rs1 000 rd 1010011 FMV.X.W / FMV.X.D 
+        11100 fmt 00000 rs1 001 rd 1010011 FCLASS 
+        11000 fmt 0000X rs1 rm rd 1010011 FCVT.W / FCVT.WU / FCVT.L / FCVT.LU (rs2 = 0, 1, 2, 3) 
+        11010 fmt 0000X rs1 rm rd 1010011 FCVT.fmt.W / .WU / .L / .LU (rs2 = 0, 1, 2, 3) 
+        11110 fmt 00000 rs1 000 rd 1010011 FMV.W.X / FMV.D.X
+        */
+        let fmt: FpFormat = self.fp_format(func7 & 0b11)?;
+        let func5: TInstr = func7 >> 2;
+        // Unary operations select their variant with the rs2 field, the other encodings are reserved
+        let rs2_valid = match func5 {
+            0b01011 | 0b11100 | 0b11110 => rs2 == 0, // fsqrt, fmv.x / fclass, fmv.w.x
+            0b11000 | 0b11010 => rs2 <= 0b11, // integer conversions
+            0b01000 => rs2 <= 0b11 && rs2 != func7 & 0b11, // fcvt between formats, the source format differs
+            _ => true,
+        };
+        if !rs2_valid {
+            return Err(self.illegal_instruction());
+        }
+        let a: TReg = self.get_fregister(rs1 as usize);
+        let b: TReg = self.get_fregister(rs2 as usize);
+        // integer conversion: rs2 selects signed/unsigned and word/long
+        let signed: bool = rs2 & 0b1 == 0;
+        let word: bool = rs2 & 0b10 == 0;
+        match (func5, rm) {
+            (0b00000, _) => { let (res, flags) = fpu::fp_add(fmt, a, b, self.fp_rounding_mode(rm)?); self.set_fregister(rd as usize, res); self.accrue_fflags(flags) }, // fadd
+            (0b00001, _) => { let (res, flags) = fpu::fp_sub(fmt, a, b, self.fp_rounding_mode(rm)?); self.set_fregister(rd as usize, res); self.accrue_fflags(flags) }, // fsub
+            (0b00010, _) => { let (res, flags) = fpu::fp_mul(fmt, a, b, self.fp_rounding_mode(rm)?); self.set_fregister(rd as usize, res); self.accrue_fflags(flags) }, // fmul
+            (0b00011, _) => { let (res, flags) = fpu::fp_div(fmt, a, b, self.fp_rounding_mode(rm)?); self.set_fregister(rd as usize, res); self.accrue_fflags(flags) }, // fdiv
+            (0b01011, _) => { let (res, flags) = fpu::fp_sqrt(fmt, a, self.fp_rounding_mode(rm)?); self.set_fregister(rd as usize, res); self.accrue_fflags(flags) }, // fsqrt
+            (0b00100, 0b000) => self.set_fregister(rd as usize, fpu::fp_sign_inject(fmt, a, b, SignInjection::Copy)), // fsgnj
+            (0b00100, 0b001) => self.set_fregister(rd as usize, fpu::fp_sign_inject(fmt, a, b, SignInjection::Negate)), // fsgnjn
+            (0b00100, 0b010) => self.set_fregister(rd as usize, fpu::fp_sign_inject(fmt, a, b, SignInjection::Xor)), // fsgnjx
+            (0b00101, 0b000) => { let (res, flags) = fpu::fp_min(fmt, a, b); self.set_fregister(rd as usize, res); self.accrue_fflags(flags) }, // fmin
+            (0b00101, 0b001) => { let (res, flags) = fpu::fp_max(fmt, a, b); self.set_fregister(rd as usize, res); self.accrue_fflags(flags) }, // fmax
+            (0b01000, _) => {
+                // fcvt.s.d / fcvt.d.s - rs2 holds the source format
+                let (res, flags) = fpu::fp_convert(self.fp_format(rs2)?, fmt, a, self.fp_rounding_mode(rm)?);
+                self.set_fregister(rd as usize, res);
+                self.accrue_fflags(flags)
+            },
+            (0b10100, 0b010) => { let (res, flags) = fpu::fp_eq(fmt, a, b); self.set_register(rd as usize, res); self.accrue_fflags(flags) }, // feq
+            (0b10100, 0b001) => { let (res, flags) = fpu::fp_lt(fmt, a, b); self.set_register(rd as usize, res); self.accrue_fflags(flags) }, // flt
+            (0b10100, 0b000) => { let (res, flags) = fpu::fp_le(fmt, a, b); self.set_register(rd as usize, res); self.accrue_fflags(flags) }, // fle
+            (0b11100, 0b000) => match fmt {
+                FpFormat::Single => self.set_register(rd as usize, a as u32 as i32 as i64 as TReg), // fmv.x.w - raw bits, sign-extended
+                FpFormat::Double => self.set_register(rd as usize, a), // fmv.x.d
+            },
+            (0b11100, 0b001) => self.set_register(rd as usize, fpu::fp_classify(fmt, a)), // fclass
+            (0b11000, _) => {
+                // fcvt.w / fcvt.wu / fcvt.l / fcvt.lu
+                let (res, flags) = fpu::fp_to_int(fmt, a, signed, word, self.fp_rounding_mode(rm)?);
+                self.set_register(rd as usize, res);
+                self.accrue_fflags(flags)
+            },
+            (0b11010, _) => {
+                // fcvt.fmt.w / fcvt.fmt.wu / fcvt.fmt.l / fcvt.fmt.lu
+                let (res, flags) = fpu::int_to_fp(fmt, self.get_register(rs1 as usize), signed, word, self.fp_rounding_mode(rm)?);
+                self.set_fregister(rd as usize, res);
+                self.accrue_fflags(flags)
+            },
+            (0b11110, 0b000) => match fmt {
+                FpFormat::Single => self.set_fregister(rd as usize, fpu::nan_box(self.get_register(rs1 as usize) as u32)), // fmv.w.x
+                FpFormat::Double => self.set_fregister(rd as usize, self.get_register(rs1 as usize)), // fmv.d.x
+            },
+            _ => return Err(self.illegal_instruction())
         }
+        Ok(())
+    }
+
+    pub fn execute_fused_multiply_add(&mut self, instr: TInstr) -> Result<(), ExecError> {
+        self.check_fp_enabled()?;
+        let opcode: TInstr = self.instr_opcode(instr);
+        let rm: TInstr = self.instr_func3(instr);
+        let rd: TInstr = self.instr_rd(instr);
+        let rs1: TInstr = self.instr_rs1(instr);
+        let rs2: TInstr = self.instr_rs2_shamt(instr);
+        let rs3: TInstr = self.instr_rs3(instr);
+        let fmt: FpFormat = self.fp_format(self.instr_funct7(instr) & 0b11)?;
+        info!("[execute_fused_multiply_add] opcode ({opcode:#b}): rm: {rm:#x} - rd: {rd} - rs1: {rs1} - rs2: {rs2} - rs3: {rs3}");
+        /*
+        rs3 fmt rs2 rs1 rm rd 1000011 FMADD  -> (rs1 * rs2) + rs3 
+        rs3 fmt rs2 rs1 rm rd 1000111 FMSUB  -> (rs1 * rs2) - rs3 
+        rs3 fmt rs2 rs1 rm rd 1001011 FNMSUB -> -(rs1 * rs2) + rs3 
+        rs3 fmt rs2 rs1 rm rd 1001111 FNMADD -> -(rs1 * rs2) - rs3
+        */
+        let (negate_product, negate_addend) = match opcode {
+            0b1000011 => (false, false), // fmadd
+            0b1000111 => (false, true), // fmsub
+            0b1001011 => (true, false), // fnmsub
+            0b1001111 => (true, true), // fnmadd
+            _ => return Err(self.illegal_instruction())
+        };
+        let (res, flags) = fpu::fp_fma(fmt, self.get_fregister(rs1 as usize), self.get_fregister(rs2 as usize), self.get_fregister(rs3 as usize),
+            negate_product, negate_addend, self.fp_rounding_mode(rm)?);
+        self.set_fregister(rd as usize, res);
+        self.accrue_fflags(flags);
+        Ok(())
     }
 
-    pub fn execute_rv64i_extensions(&mut self, instr: TInstr) {
-        // RV64I extensions (e.g. MUL, DIV, etc.) can be implemented here
-        // For now, we will just log that this instruction is not implemented
+    pub fn execute_rv64i_extensions(&mut self, instr: TInstr) -> Result<(), ExecError> {
+        // RV64I word operations (OP-32) incl. the RV64M word multiply/divide instructions
         let func3: TInstr = self.instr_func3(instr);
         let func7: TInstr = self.instr_funct7(instr);
         let rd: TInstr = self.instr_rd(instr);
@@ -573,24 +1550,41 @@ impl BasicCpu {
         0000000 rs2 rs1 001 rd 0111011 SLLW 
         0000000 rs2 rs1 101 rd 0111011 SRLW 
         0100000 rs2 rs1 101 rd 0111011 SRAW        
+        M extension (RV64M):
+        0000001 rs2 rs1 000 rd 0111011 MULW 
+        0000001 rs2 rs1 100 rd 0111011 DIVW 
+        0000001 rs2 rs1 101 rd 0111011 DIVUW 
+        0000001 rs2 rs1 110 rd 0111011 REMW 
+        0000001 rs2 rs1 111 rd 0111011 REMUW
+        The word variants operate on the lower 32 bits and sign-extend the 32-bit result to 64 bits.
+        Shifts use the lower 5 bits of rs2 as shift amount.
         */
+        let src1: u32 = self.get_register(rs1 as usize) as u32;
+        let src2: u32 = self.get_register(rs2 as usize) as u32;
         match (func3, func7) {
-            (0b000, 0b0000000) => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_add(self.get_register(rs2 as usize))), // addw
-            (0b000, 0b0100000) => self.set_register(rd as usize, ((self.get_register(rs1 as usize) as i64).wrapping_sub(self.get_register(rs2 as usize) as i64)) as TReg), // subw
-            (0b001, 0b0000000) => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_shl(self.get_register(rs2 as usize) as u32)), // sllw
-            (0b101, 0b0000000) => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_shr(self.get_register(rs2 as usize) as u32)), // srlw
-            (0b101, 0b0100000) => self.set_register(rd as usize, ((self.get_register(rs1 as usize) as i64).wrapping_shr(self.get_register(rs2 as usize) as u32)) as TReg), // sraw
-            _ => panic!("Function (RV64I-Extensions) with code func3 {func3:#x} AND func7 {func7:#x} not found")
+            (0b000, 0b0000000) => self.set_register(rd as usize, sign_extend_word(src1.wrapping_add(src2))), // addw
+            (0b000, 0b0100000) => self.set_register(rd as usize, sign_extend_word(src1.wrapping_sub(src2))), // subw
+            (0b001, 0b0000000) => self.set_register(rd as usize, sign_extend_word(src1 << (src2 & 0x1f))), // sllw
+            (0b101, 0b0000000) => self.set_register(rd as usize, sign_extend_word(src1 >> (src2 & 0x1f))), // srlw
+            (0b101, 0b0100000) => self.set_register(rd as usize, sign_extend_word(((src1 as i32) >> (src2 & 0x1f)) as u32)), // sraw
+            // M extension
+            (0b000, 0b0000001) => self.set_register(rd as usize, (self.get_register(rs1 as usize) as i32).wrapping_mul(self.get_register(rs2 as usize) as i32) as i64 as TReg), // mulw
+            (0b100, 0b0000001) => self.set_register(rd as usize, div_signed(self.get_register(rs1 as usize) as i32 as i64, self.get_register(rs2 as usize) as i32 as i64) as i32 as i64 as TReg), // divw
+            (0b101, 0b0000001) => self.set_register(rd as usize, div_unsigned(self.get_register(rs1 as usize) as u32 as u64, self.get_register(rs2 as usize) as u32 as u64) as i32 as i64 as TReg), // divuw
+            (0b110, 0b0000001) => self.set_register(rd as usize, rem_signed(self.get_register(rs1 as usize) as i32 as i64, self.get_register(rs2 as usize) as i32 as i64) as i32 as i64 as TReg), // remw
+            (0b111, 0b0000001) => self.set_register(rd as usize, rem_unsigned(self.get_register(rs1 as usize) as u32 as u64, self.get_register(rs2 as usize) as u32 as u64) as i32 as i64 as TReg), // remuw
+            _ => return Err(self.illegal_instruction())
         }
+        Ok(())
     }
 
-    pub fn execute_rv64i_immediate(&mut self, instr: TInstr) {
-        // RV64I immediate instructions can be implemented here
-        // For now, we will just log that this instruction is not implemented
+    pub fn execute_rv64i_immediate(&mut self, instr: TInstr) -> Result<(), ExecError> {
+        // RV64I word immediate instructions (OP-IMM-32)
         let func3: TInstr = self.instr_func3(instr);
+        let func7: TInstr = self.instr_funct7(instr);
         let rd: TInstr = self.instr_rd(instr);
         let rs1: TInstr = self.instr_rs1(instr);
-        let rs2_shamt: TInstr = self.instr_rs2_shamt(instr);
+        let shamt: TInstr = self.instr_rs2_shamt(instr);
         let imm: TImm = self.instr_imm_i(instr); // sign-extend immediate value
         info!("[execute_rv64i_immediate] opcode (0b0011011): func3: {func3:#x} - rd: {rd} - rs1: {rs1} - imm: {imm}");
         /*
@@ -598,13 +1592,51 @@ impl BasicCpu {
         0000000 shamt rs1 001 rd 0011011 SLLIW 
         0000000 shamt rs1 101 rd 0011011 SRLIW 
         0100000 shamt rs1 101 rd 0011011 SRAIW
+        The shift amount is 5 bits, encodings with shamt[5] (bit 25) set are reserved.
         */
-        match func3 {
-            0b000 => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_add(imm)), // addiw
-            0b001 => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_shl(rs2_shamt)), // slliw
-            0b101 => self.set_register(rd as usize, self.get_register(rs1 as usize).wrapping_shr(rs2_shamt)), // srliw
-            0b111 => self.set_register(rd as usize, ((self.get_register(rs1 as usize) as i64).wrapping_shr(rs2_shamt)) as TReg), // sraiw
-            _ => panic!("Function (RV64I-Immediate) with code func3 {func3:#x} not found")
+        let src: u32 = self.get_register(rs1 as usize) as u32;
+        match (func3, func7) {
+            (0b000, _) => self.set_register(rd as usize, sign_extend_word(src.wrapping_add(imm as u32))), // addiw
+            (0b001, 0b0000000) => self.set_register(rd as usize, sign_extend_word(src << shamt)), // slliw
+            (0b101, 0b0000000) => self.set_register(rd as usize, sign_extend_word(src >> shamt)), // srliw
+            (0b101, 0b0100000) => self.set_register(rd as usize, sign_extend_word(((src as i32) >> shamt) as u32)), // sraiw
+            _ => return Err(self.illegal_instruction())
         }
+        Ok(())
+    }
+}
+
+/// Sign-extends the 32-bit result of a word instruction to the register width
+fn sign_extend_word(value: u32) -> TReg {
+    value as i32 as i64 as TReg
+}
+
+//
+// M extension helpers
+//
+// Division by zero and signed overflow do not trap in RISC-V, the results are defined by the spec:
+// - x / 0 = -1 (all bits set), x % 0 = x
+// - MIN / -1 = MIN, MIN % -1 = 0
+fn div_signed(dividend: i64, divisor: i64) -> i64 {
+    if divisor == 0 {
+        -1
+    } else {
+        dividend.wrapping_div(divisor) // wrapping_div handles the overflow case MIN / -1 = MIN
     }
-}
\ No newline at end of file
+}
+
+fn div_unsigned(dividend: u64, divisor: u64) -> u64 {
+    dividend.checked_div(divisor).unwrap_or(u64::MAX)
+}
+
+fn rem_signed(dividend: i64, divisor: i64) -> i64 {
+    if divisor == 0 {
+        dividend
+    } else {
+        dividend.wrapping_rem(divisor) // wrapping_rem handles the overflow case MIN % -1 = 0
+    }
+}
+
+fn rem_unsigned(dividend: u64, divisor: u64) -> u64 {
+    dividend.checked_rem(divisor).unwrap_or(dividend)
+}
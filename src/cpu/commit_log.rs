@@ -0,0 +1,126 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::cpu::basic_cpu::{Privilege, TInstr, TReg};
+use crate::cpu::disasm::csr_name;
+
+/// Architectural state change of a retired instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitEntry {
+    Register { idx: usize, value: TReg },
+    FRegister { idx: usize, value: TReg },
+    Csr { idx: usize, value: TReg },
+    Load { addr: TReg },
+    Store { addr: TReg, size: usize, value: TReg },
+}
+
+/// Formats one line of Spike's `--log-commits` output. Register writes come first (x0 is omitted), then memory accesses.
+pub fn format_commit(privilege: Privilege, pc: TReg, instr: TInstr, instr_len: TReg, entries: &[CommitEntry]) -> String {
+    let mut line = format!("core   0: {} 0x{pc:016x} ", privilege as u8);
+    if instr_len == 2 {
+        let _ = write!(line, "(0x{:04x})", instr & 0xffff);
+    } else {
+        let _ = write!(line, "(0x{instr:08x})");
+    }
+    for entry in entries {
+        let _ = match *entry {
+            CommitEntry::Register { idx: 0, .. } => Ok(()),
+            CommitEntry::Register { idx, value } => write!(line, " x{idx:<2} 0x{value:016x}"),
+            CommitEntry::FRegister { idx, value } => write!(line, " f{idx:<2} 0x{value:016x}"),
+            CommitEntry::Csr { idx, value } => write!(line, " c{idx}_{} 0x{value:016x}", csr_name(idx)),
+            _ => Ok(()),
+        };
+    }
+    for entry in entries {
+        let _ = match *entry {
+            CommitEntry::Load { addr } => write!(line, " mem 0x{addr:016x}"),
+            CommitEntry::Store { addr, size, value } => write!(line, " mem 0x{addr:016x} 0x{value:0width$x}", width = size / 4),
+            _ => Ok(()),
+        };
+    }
+    line
+}
+
+/// One parsed line of a `--log-commits` trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub privilege: Privilege,
+    pub pc: TReg,
+    pub instr: TInstr,
+    pub instr_len: TReg,
+    pub entries: Vec<CommitEntry>,
+}
+
+impl Commit {
+    /// Parses a line written by `format_commit` (or by Spike). Other lines, e.g. Spike's exception or disassembly lines, give None.
+    pub fn parse(line: &str) -> Option<Commit> {
+        let hex = |word: &str| word.strip_prefix("0x").and_then(|digits| TReg::from_str_radix(digits, 16).ok());
+        let mut words = line.split_whitespace().peekable();
+        if words.next()? != "core" || !words.next()?.ends_with(':') {
+            return None;
+        }
+        let privilege = words.next().filter(|word| word.len() == 1)?.parse::<TReg>().ok()?;
+        let pc = hex(words.next()?)?;
+        let encoding = words.next()?.strip_prefix('(')?.strip_suffix(')')?;
+        let instr = hex(encoding)? as TInstr;
+        let instr_len = if encoding.len() == 6 { 2 } else { 4 };
+
+        let mut entries = Vec::new();
+        while let Some(word) = words.next() {
+            let entry = if word == "mem" {
+                let addr = hex(words.next()?)?;
+                match words.next_if(|word| word.starts_with("0x")) {
+                    Some(value) => CommitEntry::Store { addr, size: (value.len() - 2) * 4, value: hex(value)? },
+                    None => CommitEntry::Load { addr },
+                }
+            } else {
+                let value = hex(words.next()?)?;
+                match word.split_at_checked(1)? {
+                    ("x", idx) => CommitEntry::Register { idx: idx.parse().ok()?, value },
+                    ("f", idx) => CommitEntry::FRegister { idx: idx.parse().ok()?, value },
+                    ("c", csr) => CommitEntry::Csr { idx: csr.split('_').next()?.parse().ok()?, value },
+                    _ => return None,
+                }
+            };
+            entries.push(entry);
+        }
+        Some(Commit { privilege: Privilege::from_bits(privilege), pc, instr, instr_len, entries })
+    }
+}
+
+/// Collects the state changes of the current instruction and writes a commit line once it retires.
+pub struct CommitLog {
+    output: Box<dyn Write>,
+    entries: Vec<CommitEntry>,
+}
+
+impl CommitLog {
+    pub fn new(output: Box<dyn Write>) -> CommitLog {
+        CommitLog { output, entries: Vec::new() }
+    }
+
+    /// Records a state change, a later write to the same register replaces the earlier one
+    pub fn record(&mut self, entry: CommitEntry) {
+        let same_target = |other: &CommitEntry| match (other, &entry) {
+            (CommitEntry::Register { idx: a, .. }, CommitEntry::Register { idx: b, .. }) => a == b,
+            (CommitEntry::FRegister { idx: a, .. }, CommitEntry::FRegister { idx: b, .. }) => a == b,
+            (CommitEntry::Csr { idx: a, .. }, CommitEntry::Csr { idx: b, .. }) => a == b,
+            _ => false,
+        };
+        match self.entries.iter_mut().find(|other| same_target(other)) {
+            Some(other) => *other = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Drops the changes recorded so far (e.g. of an instruction which trapped)
+    pub fn discard(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn commit(&mut self, privilege: Privilege, pc: TReg, instr: TInstr, instr_len: TReg) -> io::Result<()> {
+        let line = format_commit(privilege, pc, instr, instr_len, &self.entries);
+        self.entries.clear();
+        writeln!(self.output, "{line}")
+    }
+}
@@ -0,0 +1,233 @@
+use crate::cpu::basic_cpu::TInstr;
+
+//
+// "C" Standard Extension for Compressed Instructions (RV64C and RV32C)
+//
+// Every compressed instruction has an equivalent 32-bit instruction. Instead of executing them separately,
+// compressed instructions are expanded into their 32-bit equivalent and executed by the regular execute_* functions.
+//
+
+pub fn is_compressed(instr: TInstr) -> bool {
+    // 32-bit instructions have their lowest two bits set to 0b11, everything else is a 16-bit instruction
+    instr & 0b11 != 0b11
+}
+
+/// Expands a 16-bit compressed instruction of RV32C or RV64C (`xlen` 32 or 64) into the equivalent 32-bit instruction.
+/// RV32C has C.JAL, C.FLW(SP) and C.FSW(SP) in place of C.ADDIW, C.LD(SP) and C.SD(SP),
+/// its other encodings expand like in RV64C (the RV64-only results are rejected by `Isa::accepts`).
+pub fn expand_compressed_xlen(instr: u16, xlen: u32) -> Option<TInstr> {
+    if xlen == 64 {
+        return expand_compressed(instr);
+    }
+    let instr32: TInstr = instr as TInstr;
+    let rd = (instr32 >> 7) & 0x1f;
+    let rs2 = (instr32 >> 2) & 0x1f;
+    let rd_c = ((instr32 >> 2) & 0b111) + 8;
+    let rs1_c = ((instr32 >> 7) & 0b111) + 8;
+    match (instr32 & 0b11, (instr32 >> 13) & 0b111) {
+        (0b00, 0b011) => Some(encode_i(0b0000111, rd_c, 0b010, rs1_c, c_uimm_w(instr32))), // C.FLW -> flw rd', offset(rs1')
+        (0b00, 0b111) => Some(encode_s(0b0100111, 0b010, rs1_c, rd_c, c_uimm_w(instr32))), // C.FSW -> fsw rs2', offset(rs1')
+        (0b01, 0b001) => Some(encode_j(1, c_offset_j(instr32))), // C.JAL -> jal x1, offset
+        (0b10, 0b011) => Some(encode_i(0b0000111, rd, 0b010, 2, c_uimm_lwsp(instr32))), // C.FLWSP -> flw rd, offset(x2)
+        (0b10, 0b111) => Some(encode_s(0b0100111, 0b010, 2, rs2, c_uimm_swsp(instr32))), // C.FSWSP -> fsw rs2, offset(x2)
+        _ => expand_compressed(instr),
+    }
+}
+
+/// Expands a 16-bit compressed instruction (RV64C) into the equivalent 32-bit instruction.
+/// Returns `None` for illegal or reserved encodings.
+pub fn expand_compressed(instr: u16) -> Option<TInstr> {
+    let instr: TInstr = instr as TInstr;
+    let op = instr & 0b11;
+    let func3 = (instr >> 13) & 0b111;
+    let rd = (instr >> 7) & 0x1f; // bits[7:11], full register index (also rs1)
+    let rs2 = (instr >> 2) & 0x1f; // bits[2:6], full register index
+    let rd_c = ((instr >> 2) & 0b111) + 8; // bits[2:4], rd'/rs2' -> x8-x15
+    let rs1_c = ((instr >> 7) & 0b111) + 8; // bits[7:9], rs1'/rd' -> x8-x15
+
+    match (op, func3) {
+        //
+        // Quadrant 0
+        //
+        (0b00, 0b000) => {
+            // C.ADDI4SPN -> addi rd', x2, nzuimm
+            // nzuimm[5:4|9:6|2|3] in bits[12:5]
+            let nzuimm = ((instr >> 7) & 0x30) | ((instr >> 1) & 0x3c0) | ((instr >> 4) & 0x4) | ((instr >> 2) & 0x8);
+            if nzuimm == 0 {
+                return None; // also covers the all-zero illegal instruction
+            }
+            Some(encode_i(0b0010011, rd_c, 0b000, 2, nzuimm))
+        },
+        (0b00, 0b001) => Some(encode_i(0b0000111, rd_c, 0b011, rs1_c, c_uimm_d(instr))), // C.FLD -> fld rd', offset(rs1')
+        (0b00, 0b010) => Some(encode_i(0b0000011, rd_c, 0b010, rs1_c, c_uimm_w(instr))), // C.LW -> lw rd', offset(rs1')
+        (0b00, 0b011) => Some(encode_i(0b0000011, rd_c, 0b011, rs1_c, c_uimm_d(instr))), // C.LD -> ld rd', offset(rs1')
+        (0b00, 0b101) => Some(encode_s(0b0100111, 0b011, rs1_c, rd_c, c_uimm_d(instr))), // C.FSD -> fsd rs2', offset(rs1')
+        (0b00, 0b110) => Some(encode_s(0b0100011, 0b010, rs1_c, rd_c, c_uimm_w(instr))), // C.SW -> sw rs2', offset(rs1')
+        (0b00, 0b111) => Some(encode_s(0b0100011, 0b011, rs1_c, rd_c, c_uimm_d(instr))), // C.SD -> sd rs2', offset(rs1')
+        //
+        // Quadrant 1
+        //
+        (0b01, 0b000) => Some(encode_i(0b0010011, rd, 0b000, rd, c_imm6(instr))), // C.ADDI (C.NOP for rd = x0) -> addi rd, rd, imm
+        (0b01, 0b001) => {
+            // C.ADDIW -> addiw rd, rd, imm (RV64C, replaces C.JAL of RV32C)
+            if rd == 0 {
+                return None;
+            }
+            Some(encode_i(0b0011011, rd, 0b000, rd, c_imm6(instr)))
+        },
+        (0b01, 0b010) => Some(encode_i(0b0010011, rd, 0b000, 0, c_imm6(instr))), // C.LI -> addi rd, x0, imm
+        (0b01, 0b011) => {
+            if rd == 2 {
+                // C.ADDI16SP -> addi x2, x2, nzimm
+                // nzimm[9] in bit 12, nzimm[4|6|8:7|5] in bits[6:2]
+                let nzimm = ((((instr >> 12) & 0x1) as i32) << 31 >> 22) as TInstr
+                    | ((instr >> 2) & 0x10) | ((instr << 1) & 0x40) | ((instr << 4) & 0x180) | ((instr << 3) & 0x20);
+                if nzimm == 0 {
+                    return None;
+                }
+                Some(encode_i(0b0010011, 2, 0b000, 2, nzimm))
+            } else {
+                // C.LUI -> lui rd, nzimm
+                // nzimm[17] in bit 12, nzimm[16:12] in bits[6:2]
+                let nzimm = c_imm6(instr) << 12;
+                if nzimm == 0 {
+                    return None;
+                }
+                Some((nzimm & 0xfffff000) | (rd << 7) | 0b0110111)
+            }
+        },
+        (0b01, 0b100) => {
+            let shamt = ((instr >> 7) & 0x20) | ((instr >> 2) & 0x1f); // shamt[5] in bit 12, shamt[4:0] in bits[6:2]
+            match (instr >> 10) & 0b11 {
+                0b00 => Some(encode_i(0b0010011, rs1_c, 0b101, rs1_c, shamt)), // C.SRLI -> srli rd', rd', shamt
+                0b01 => Some(encode_i(0b0010011, rs1_c, 0b101, rs1_c, shamt | 0x400)), // C.SRAI -> srai rd', rd', shamt
+                0b10 => Some(encode_i(0b0010011, rs1_c, 0b111, rs1_c, c_imm6(instr))), // C.ANDI -> andi rd', rd', imm
+                _ => {
+                    match ((instr >> 12) & 0b1, (instr >> 5) & 0b11) {
+                        (0, 0b00) => Some(encode_r(0b0110011, rs1_c, 0b000, rs1_c, rd_c, 0b0100000)), // C.SUB -> sub rd', rd', rs2'
+                        (0, 0b01) => Some(encode_r(0b0110011, rs1_c, 0b100, rs1_c, rd_c, 0b0000000)), // C.XOR -> xor rd', rd', rs2'
+                        (0, 0b10) => Some(encode_r(0b0110011, rs1_c, 0b110, rs1_c, rd_c, 0b0000000)), // C.OR -> or rd', rd', rs2'
+                        (0, 0b11) => Some(encode_r(0b0110011, rs1_c, 0b111, rs1_c, rd_c, 0b0000000)), // C.AND -> and rd', rd', rs2'
+                        (1, 0b00) => Some(encode_r(0b0111011, rs1_c, 0b000, rs1_c, rd_c, 0b0100000)), // C.SUBW -> subw rd', rd', rs2'
+                        (1, 0b01) => Some(encode_r(0b0111011, rs1_c, 0b000, rs1_c, rd_c, 0b0000000)), // C.ADDW -> addw rd', rd', rs2'
+                        _ => None, // reserved
+                    }
+                }
+            }
+        },
+        (0b01, 0b101) => Some(encode_j(0, c_offset_j(instr))), // C.J -> jal x0, offset
+        (0b01, 0b110) | (0b01, 0b111) => {
+            // C.BEQZ -> beq rs1', x0, offset / C.BNEZ -> bne rs1', x0, offset
+            // offset[8|4:3] in bits[12:10], offset[7:6|2:1|5] in bits[6:2]
+            let offset = ((((instr >> 12) & 0x1) as i32) << 31 >> 23) as TInstr
+                | ((instr >> 7) & 0x18) | ((instr << 1) & 0xc0) | ((instr >> 2) & 0x6) | ((instr << 3) & 0x20);
+            Some(encode_b(if func3 == 0b110 { 0b000 } else { 0b001 }, rs1_c, 0, offset))
+        },
+        //
+        // Quadrant 2
+        //
+        (0b10, 0b000) => {
+            let shamt = ((instr >> 7) & 0x20) | ((instr >> 2) & 0x1f);
+            Some(encode_i(0b0010011, rd, 0b001, rd, shamt)) // C.SLLI -> slli rd, rd, shamt
+        },
+        (0b10, 0b001) => Some(encode_i(0b0000111, rd, 0b011, 2, c_uimm_ldsp(instr))), // C.FLDSP -> fld rd, offset(x2)
+        (0b10, 0b010) => {
+            // C.LWSP -> lw rd, offset(x2)
+            if rd == 0 {
+                return None;
+            }
+            Some(encode_i(0b0000011, rd, 0b010, 2, c_uimm_lwsp(instr)))
+        },
+        (0b10, 0b011) => {
+            // C.LDSP -> ld rd, offset(x2)
+            if rd == 0 {
+                return None;
+            }
+            Some(encode_i(0b0000011, rd, 0b011, 2, c_uimm_ldsp(instr)))
+        },
+        (0b10, 0b100) => {
+            match ((instr >> 12) & 0b1, rd, rs2) {
+                (0, 0, 0) => None, // reserved
+                (0, _, 0) => Some(encode_i(0b1100111, 0, 0b000, rd, 0)), // C.JR -> jalr x0, 0(rs1)
+                (0, _, _) => Some(encode_r(0b0110011, rd, 0b000, 0, rs2, 0b0000000)), // C.MV -> add rd, x0, rs2
+                (1, 0, 0) => Some(0x00100073), // C.EBREAK -> ebreak
+                (1, _, 0) => Some(encode_i(0b1100111, 1, 0b000, rd, 0)), // C.JALR -> jalr x1, 0(rs1)
+                (1, _, _) => Some(encode_r(0b0110011, rd, 0b000, rd, rs2, 0b0000000)), // C.ADD -> add rd, rd, rs2
+                _ => None,
+            }
+        },
+        (0b10, 0b101) => Some(encode_s(0b0100111, 0b011, 2, rs2, c_uimm_sdsp(instr))), // C.FSDSP -> fsd rs2, offset(x2)
+        (0b10, 0b110) => Some(encode_s(0b0100011, 0b010, 2, rs2, c_uimm_swsp(instr))), // C.SWSP -> sw rs2, offset(x2)
+        (0b10, 0b111) => Some(encode_s(0b0100011, 0b011, 2, rs2, c_uimm_sdsp(instr))), // C.SDSP -> sd rs2, offset(x2)
+        _ => None,
+    }
+}
+
+//
+// Compressed immediate decoding
+//
+fn c_imm6(instr: TInstr) -> TInstr {
+    // imm[5] in bit 12, imm[4:0] in bits[6:2], sign-extended
+    ((((instr >> 12) & 0x1) as i32) << 31 >> 26) as TInstr | ((instr >> 2) & 0x1f)
+}
+
+fn c_uimm_w(instr: TInstr) -> TInstr {
+    // C.LW/C.SW: uimm[5:3] in bits[12:10], uimm[2|6] in bits[6:5]
+    ((instr >> 7) & 0x38) | ((instr >> 4) & 0x4) | ((instr << 1) & 0x40)
+}
+
+fn c_uimm_d(instr: TInstr) -> TInstr {
+    // C.LD/C.SD/C.FLD/C.FSD: uimm[5:3] in bits[12:10], uimm[7:6] in bits[6:5]
+    ((instr >> 7) & 0x38) | ((instr << 1) & 0xc0)
+}
+
+fn c_uimm_lwsp(instr: TInstr) -> TInstr {
+    // C.LWSP/C.FLWSP: uimm[5] in bit 12, uimm[4:2|7:6] in bits[6:2]
+    ((instr >> 7) & 0x20) | ((instr >> 2) & 0x1c) | ((instr << 4) & 0xc0)
+}
+
+fn c_uimm_swsp(instr: TInstr) -> TInstr {
+    // C.SWSP/C.FSWSP: uimm[5:2|7:6] in bits[12:7]
+    ((instr >> 7) & 0x3c) | ((instr >> 1) & 0xc0)
+}
+
+fn c_offset_j(instr: TInstr) -> TInstr {
+    // C.J/C.JAL: offset[11|4|9:8|10|6|7|3:1|5] in bits[12:2], sign-extended
+    ((((instr >> 12) & 0x1) as i32) << 31 >> 20) as TInstr
+        | ((instr >> 7) & 0x10) | ((instr >> 1) & 0x300) | ((instr << 2) & 0x400)
+        | ((instr >> 1) & 0x40) | ((instr << 1) & 0x80) | ((instr >> 2) & 0xe) | ((instr << 3) & 0x20)
+}
+
+fn c_uimm_ldsp(instr: TInstr) -> TInstr {
+    // C.LDSP/C.FLDSP: uimm[5] in bit 12, uimm[4:3|8:6] in bits[6:2]
+    ((instr >> 7) & 0x20) | ((instr >> 2) & 0x18) | ((instr << 4) & 0x1c0)
+}
+
+fn c_uimm_sdsp(instr: TInstr) -> TInstr {
+    // C.SDSP/C.FSDSP: uimm[5:3|8:6] in bits[12:7]
+    ((instr >> 7) & 0x38) | ((instr >> 1) & 0x1c0)
+}
+
+//
+// 32-bit instruction encoding
+//
+fn encode_r(opcode: TInstr, rd: TInstr, func3: TInstr, rs1: TInstr, rs2: TInstr, func7: TInstr) -> TInstr {
+    (func7 << 25) | (rs2 << 20) | (rs1 << 15) | (func3 << 12) | (rd << 7) | opcode
+}
+
+fn encode_i(opcode: TInstr, rd: TInstr, func3: TInstr, rs1: TInstr, imm: TInstr) -> TInstr {
+    ((imm & 0xfff) << 20) | (rs1 << 15) | (func3 << 12) | (rd << 7) | opcode
+}
+
+fn encode_s(opcode: TInstr, func3: TInstr, rs1: TInstr, rs2: TInstr, imm: TInstr) -> TInstr {
+    ((imm & 0xfe0) << 20) | (rs2 << 20) | (rs1 << 15) | (func3 << 12) | ((imm & 0x1f) << 7) | opcode
+}
+
+fn encode_b(func3: TInstr, rs1: TInstr, rs2: TInstr, imm: TInstr) -> TInstr {
+    ((imm & 0x1000) << 19) | ((imm & 0x7e0) << 20) | (rs2 << 20) | (rs1 << 15) | (func3 << 12)
+        | ((imm & 0x1e) << 7) | ((imm & 0x800) >> 4) | 0b1100011
+}
+
+fn encode_j(rd: TInstr, imm: TInstr) -> TInstr {
+    ((imm & 0x100000) << 11) | ((imm & 0x7fe) << 20) | ((imm & 0x800) << 9) | (imm & 0xff000) | (rd << 7) | 0b1101111
+}
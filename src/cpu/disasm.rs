@@ -0,0 +1,341 @@
+use crate::cpu::basic_cpu::*;
+use crate::cpu::compressed::{expand_compressed_xlen, is_compressed};
+use crate::cpu::registers::ABI_NAMES;
+
+pub const FP_ABI_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2", "fa3", "fa4", "fa5",
+    "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9", "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+const ROUNDING_MODES: [&str; 8] = ["rne", "rtz", "rdn", "rup", "rmm", "", "", "dyn"];
+
+// Instruction fields
+fn rd(instr: TInstr) -> &'static str { ABI_NAMES[((instr >> 7) & 0x1f) as usize] }
+fn rs1(instr: TInstr) -> &'static str { ABI_NAMES[((instr >> 15) & 0x1f) as usize] }
+fn rs2(instr: TInstr) -> &'static str { ABI_NAMES[((instr >> 20) & 0x1f) as usize] }
+fn frd(instr: TInstr) -> &'static str { FP_ABI_NAMES[((instr >> 7) & 0x1f) as usize] }
+fn frs1(instr: TInstr) -> &'static str { FP_ABI_NAMES[((instr >> 15) & 0x1f) as usize] }
+fn frs2(instr: TInstr) -> &'static str { FP_ABI_NAMES[((instr >> 20) & 0x1f) as usize] }
+fn frs3(instr: TInstr) -> &'static str { FP_ABI_NAMES[(instr >> 27) as usize] }
+fn func3(instr: TInstr) -> TInstr { (instr >> 12) & 0x7 }
+fn func7(instr: TInstr) -> TInstr { instr >> 25 }
+fn imm_i(instr: TInstr) -> i64 { (instr as i32 >> 20) as i64 }
+fn imm_s(instr: TInstr) -> i64 { (((instr & 0xfe000000) as i32 >> 20) | ((instr >> 7) & 0x1f) as i32) as i64 }
+fn imm_u(instr: TInstr) -> i64 { (instr >> 12) as i64 }
+
+fn imm_b(instr: TInstr) -> i64 {
+    let imm = ((instr & 0x8000_0000) as i32 >> 19) as u32 | ((instr & 0x80) << 4) | ((instr >> 20) & 0x7e0) | ((instr >> 7) & 0x1e);
+    imm as i32 as i64
+}
+
+fn imm_j(instr: TInstr) -> i64 {
+    let imm = ((instr & 0x8000_0000) as i32 >> 11) as u32 | (instr & 0xff000) | ((instr >> 9) & 0x800) | ((instr >> 20) & 0x7fe);
+    imm as i32 as i64
+}
+
+/// CSR name, or its number for CSRs without a name here
+pub fn csr_name(csr: usize) -> String {
+    let name = match csr {
+        CSR_FFLAGS => "fflags",
+        CSR_FRM => "frm",
+        CSR_FCSR => "fcsr",
+        CSR_SSTATUS => "sstatus",
+        CSR_SIE => "sie",
+        CSR_STVEC => "stvec",
+        CSR_SSCRATCH => "sscratch",
+        CSR_SEPC => "sepc",
+        CSR_SCAUSE => "scause",
+        CSR_STVAL => "stval",
+        CSR_SIP => "sip",
+        CSR_SATP => "satp",
+        CSR_MSTATUS => "mstatus",
+        0x301 => "misa",
+        CSR_MEDELEG => "medeleg",
+        CSR_MIDELEG => "mideleg",
+        CSR_MIE => "mie",
+        CSR_MTVEC => "mtvec",
+        0x340 => "mscratch",
+        CSR_MEPC => "mepc",
+        CSR_MCAUSE => "mcause",
+        CSR_MTVAL => "mtval",
+        CSR_MIP => "mip",
+        0xc00 => "cycle",
+        0xc01 => "time",
+        0xc02 => "instret",
+        0xf14 => "mhartid",
+        _ => return format!("{csr:#x}"),
+    };
+    name.to_string()
+}
+
+/// Rounding mode operand of FP instructions, omitted for the dynamic rounding mode like binutils does
+fn rounding(instr: TInstr) -> String {
+    match func3(instr) {
+        0b111 => String::new(),
+        rm => format!(", {}", ROUNDING_MODES[rm as usize]),
+    }
+}
+
+/// Predecessor/successor set of FENCE
+fn fence_set(bits: TInstr) -> String {
+    ["i", "o", "r", "w"].iter().enumerate()
+        .filter(|(i, _)| bits & (0b1000 >> i) != 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+fn disassemble_imm(instr: TInstr) -> Option<String> {
+    let (rd_num, rs1_num, imm) = ((instr >> 7) & 0x1f, (instr >> 15) & 0x1f, imm_i(instr));
+    let shamt = (instr >> 20) & 0x3f;
+    Some(match func3(instr) {
+        0b000 if rd_num == 0 && rs1_num == 0 && imm == 0 => "nop".to_string(),
+        0b000 if rs1_num == 0 => format!("li {}, {imm}", rd(instr)),
+        0b000 if imm == 0 => format!("mv {}, {}", rd(instr), rs1(instr)),
+        0b000 => format!("addi {}, {}, {imm}", rd(instr), rs1(instr)),
+        0b010 => format!("slti {}, {}, {imm}", rd(instr), rs1(instr)),
+        0b011 => format!("sltiu {}, {}, {imm}", rd(instr), rs1(instr)),
+        0b100 => format!("xori {}, {}, {imm}", rd(instr), rs1(instr)),
+        0b110 => format!("ori {}, {}, {imm}", rd(instr), rs1(instr)),
+        0b111 => format!("andi {}, {}, {imm}", rd(instr), rs1(instr)),
+        0b001 if instr >> 26 == 0 => format!("slli {}, {}, {shamt}", rd(instr), rs1(instr)),
+        0b101 if instr >> 26 == 0 => format!("srli {}, {}, {shamt}", rd(instr), rs1(instr)),
+        0b101 if instr >> 26 == 0b010000 => format!("srai {}, {}, {shamt}", rd(instr), rs1(instr)),
+        _ => return None,
+    })
+}
+
+fn disassemble_imm_32(instr: TInstr) -> Option<String> {
+    let (imm, shamt) = (imm_i(instr), (instr >> 20) & 0x1f);
+    Some(match (func3(instr), func7(instr)) {
+        (0b000, _) if imm == 0 => format!("sext.w {}, {}", rd(instr), rs1(instr)),
+        (0b000, _) => format!("addiw {}, {}, {imm}", rd(instr), rs1(instr)),
+        (0b001, 0) => format!("slliw {}, {}, {shamt}", rd(instr), rs1(instr)),
+        (0b101, 0) => format!("srliw {}, {}, {shamt}", rd(instr), rs1(instr)),
+        (0b101, 0b0100000) => format!("sraiw {}, {}, {shamt}", rd(instr), rs1(instr)),
+        _ => return None,
+    })
+}
+
+fn disassemble_op(instr: TInstr, word: bool) -> Option<String> {
+    let mnemonic = match (func7(instr), func3(instr), word) {
+        (0b0000000, 0b000, _) => "add",
+        (0b0100000, 0b000, _) => "sub",
+        (0b0000000, 0b001, _) => "sll",
+        (0b0000000, 0b010, false) => "slt",
+        (0b0000000, 0b011, false) => "sltu",
+        (0b0000000, 0b100, false) => "xor",
+        (0b0000000, 0b101, _) => "srl",
+        (0b0100000, 0b101, _) => "sra",
+        (0b0000000, 0b110, false) => "or",
+        (0b0000000, 0b111, false) => "and",
+        (0b0000001, 0b000, _) => "mul",
+        (0b0000001, 0b001, false) => "mulh",
+        (0b0000001, 0b010, false) => "mulhsu",
+        (0b0000001, 0b011, false) => "mulhu",
+        (0b0000001, 0b100, _) => "div",
+        (0b0000001, 0b101, _) => "divu",
+        (0b0000001, 0b110, _) => "rem",
+        (0b0000001, 0b111, _) => "remu",
+        _ => return None,
+    };
+    let suffix = if word { "w" } else { "" };
+    if !word && mnemonic == "add" && (instr >> 15) & 0x1f == 0 {
+        return Some(format!("mv {}, {}", rd(instr), rs2(instr))); // expansion of c.mv
+    }
+    Some(format!("{mnemonic}{suffix} {}, {}, {}", rd(instr), rs1(instr), rs2(instr)))
+}
+
+fn disassemble_atomic(instr: TInstr) -> Option<String> {
+    let width = match func3(instr) {
+        0b010 => "w",
+        0b011 => "d",
+        _ => return None,
+    };
+    let ordering = match (instr >> 25) & 0b11 {
+        0b00 => "",
+        0b01 => ".rl",
+        0b10 => ".aq",
+        _ => ".aqrl",
+    };
+    let mnemonic = match instr >> 27 {
+        0b00010 if (instr >> 20) & 0x1f == 0 => return Some(format!("lr.{width}{ordering} {}, ({})", rd(instr), rs1(instr))),
+        0b00011 => "sc",
+        0b00001 => "amoswap",
+        0b00000 => "amoadd",
+        0b00100 => "amoxor",
+        0b01100 => "amoand",
+        0b01000 => "amoor",
+        0b10000 => "amomin",
+        0b10100 => "amomax",
+        0b11000 => "amominu",
+        0b11100 => "amomaxu",
+        _ => return None,
+    };
+    Some(format!("{mnemonic}.{width}{ordering} {}, {}, ({})", rd(instr), rs2(instr), rs1(instr)))
+}
+
+fn disassemble_system(instr: TInstr) -> Option<String> {
+    let csr = csr_name((instr >> 20) as usize);
+    let uimm = (instr >> 15) & 0x1f;
+    Some(match func3(instr) {
+        0b000 => match instr {
+            0x00000073 => "ecall".to_string(),
+            0x00100073 => "ebreak".to_string(),
+            0x30200073 => "mret".to_string(),
+            0x10200073 => "sret".to_string(),
+            0x10500073 => "wfi".to_string(),
+            _ if func7(instr) == 0b0001001 && (instr >> 7) & 0x1f == 0 => match ((instr >> 15) & 0x1f, (instr >> 20) & 0x1f) {
+                (0, 0) => "sfence.vma".to_string(),
+                (_, 0) => format!("sfence.vma {}", rs1(instr)),
+                _ => format!("sfence.vma {}, {}", rs1(instr), rs2(instr)),
+            },
+            _ => return None,
+        },
+        0b001 if (instr >> 7) & 0x1f == 0 => format!("csrw {csr}, {}", rs1(instr)),
+        0b001 => format!("csrrw {}, {csr}, {}", rd(instr), rs1(instr)),
+        0b010 if uimm == 0 => format!("csrr {}, {csr}", rd(instr)),
+        0b010 => format!("csrrs {}, {csr}, {}", rd(instr), rs1(instr)),
+        0b011 => format!("csrrc {}, {csr}, {}", rd(instr), rs1(instr)),
+        0b101 => format!("csrrwi {}, {csr}, {uimm}", rd(instr)),
+        0b110 => format!("csrrsi {}, {csr}, {uimm}", rd(instr)),
+        0b111 => format!("csrrci {}, {csr}, {uimm}", rd(instr)),
+        _ => return None,
+    })
+}
+
+fn disassemble_op_fp(instr: TInstr) -> Option<String> {
+    let fmt = match func7(instr) & 0b11 {
+        0b00 => "s",
+        0b01 => "d",
+        _ => return None,
+    };
+    let int_type = ["w", "wu", "l", "lu"].get(((instr >> 20) & 0x1f) as usize);
+    let arith = |name: &str| format!("{name}.{fmt} {}, {}, {}{}", frd(instr), frs1(instr), frs2(instr), rounding(instr));
+    let sign = |name: &str| format!("{name}.{fmt} {}, {}, {}", frd(instr), frs1(instr), frs2(instr));
+    let compare = |name: &str| format!("{name}.{fmt} {}, {}, {}", rd(instr), frs1(instr), frs2(instr));
+    Some(match (func7(instr) >> 2, func3(instr)) {
+        (0b00000, _) => arith("fadd"),
+        (0b00001, _) => arith("fsub"),
+        (0b00010, _) => arith("fmul"),
+        (0b00011, _) => arith("fdiv"),
+        (0b01011, _) => format!("fsqrt.{fmt} {}, {}{}", frd(instr), frs1(instr), rounding(instr)),
+        (0b00100, 0b000) if (instr >> 15) & 0x1f == (instr >> 20) & 0x1f => format!("fmv.{fmt} {}, {}", frd(instr), frs1(instr)),
+        (0b00100, 0b000) => sign("fsgnj"),
+        (0b00100, 0b001) => sign("fsgnjn"),
+        (0b00100, 0b010) => sign("fsgnjx"),
+        (0b00101, 0b000) => sign("fmin"),
+        (0b00101, 0b001) => sign("fmax"),
+        (0b01000, _) => {
+            let src = if (instr >> 20) & 0x1f == 1 { "d" } else { "s" };
+            format!("fcvt.{fmt}.{src} {}, {}{}", frd(instr), frs1(instr), rounding(instr))
+        },
+        (0b10100, 0b010) => compare("feq"),
+        (0b10100, 0b001) => compare("flt"),
+        (0b10100, 0b000) => compare("fle"),
+        (0b11100, 0b000) => format!("fmv.x.{} {}, {}", if fmt == "s" { "w" } else { "d" }, rd(instr), frs1(instr)),
+        (0b11100, 0b001) => format!("fclass.{fmt} {}, {}", rd(instr), frs1(instr)),
+        (0b11000, _) => format!("fcvt.{}.{fmt} {}, {}{}", int_type?, rd(instr), frs1(instr), rounding(instr)),
+        (0b11010, _) => format!("fcvt.{fmt}.{} {}, {}{}", int_type?, frd(instr), rs1(instr), rounding(instr)),
+        (0b11110, 0b000) => format!("fmv.{}.x {}, {}", if fmt == "s" { "w" } else { "d" }, frd(instr), rs1(instr)),
+        _ => return None,
+    })
+}
+
+fn disassemble_32(instr: TInstr) -> Option<String> {
+    let (rd_num, rs1_num) = ((instr >> 7) & 0x1f, (instr >> 15) & 0x1f);
+    Some(match instr & 0x7f {
+        0b0010011 => disassemble_imm(instr)?,
+        0b0011011 => disassemble_imm_32(instr)?,
+        0b0110111 => format!("lui {}, {:#x}", rd(instr), imm_u(instr)),
+        0b0010111 => format!("auipc {}, {:#x}", rd(instr), imm_u(instr)),
+        0b1101111 if rd_num == 0 => format!("j {}", imm_j(instr)),
+        0b1101111 if rd_num == 1 => format!("jal {}", imm_j(instr)),
+        0b1101111 => format!("jal {}, {}", rd(instr), imm_j(instr)),
+        0b1100111 if func3(instr) != 0 => return None,
+        0b1100111 if rd_num == 0 && rs1_num == 1 && imm_i(instr) == 0 => "ret".to_string(),
+        0b1100111 if rd_num == 0 && imm_i(instr) == 0 => format!("jr {}", rs1(instr)),
+        0b1100111 => format!("jalr {}, {}({})", rd(instr), imm_i(instr), rs1(instr)),
+        0b1100011 => {
+            let mnemonic = match func3(instr) {
+                0b000 => "beq",
+                0b001 => "bne",
+                0b100 => "blt",
+                0b101 => "bge",
+                0b110 => "bltu",
+                0b111 => "bgeu",
+                _ => return None,
+            };
+            format!("{mnemonic} {}, {}, {}", rs1(instr), rs2(instr), imm_b(instr))
+        },
+        0b0000011 => {
+            let mnemonic = ["lb", "lh", "lw", "ld", "lbu", "lhu", "lwu"].get(func3(instr) as usize)?;
+            format!("{mnemonic} {}, {}({})", rd(instr), imm_i(instr), rs1(instr))
+        },
+        0b0100011 => {
+            let mnemonic = ["sb", "sh", "sw", "sd"].get(func3(instr) as usize)?;
+            format!("{mnemonic} {}, {}({})", rs2(instr), imm_s(instr), rs1(instr))
+        },
+        0b0110011 => disassemble_op(instr, false)?,
+        0b0111011 => disassemble_op(instr, true)?,
+        0b0001111 => match func3(instr) {
+            0b000 if instr >> 28 == 0b1000 && (instr >> 20) & 0xff == 0x33 => "fence.tso".to_string(),
+            0b000 => format!("fence {}, {}", fence_set((instr >> 24) & 0xf), fence_set((instr >> 20) & 0xf)),
+            0b001 => "fence.i".to_string(),
+            _ => return None,
+        },
+        0b1110011 => disassemble_system(instr)?,
+        0b0101111 => disassemble_atomic(instr)?,
+        0b0000111 => match func3(instr) {
+            0b010 => format!("flw {}, {}({})", frd(instr), imm_i(instr), rs1(instr)),
+            0b011 => format!("fld {}, {}({})", frd(instr), imm_i(instr), rs1(instr)),
+            _ => return None,
+        },
+        0b0100111 => match func3(instr) {
+            0b010 => format!("fsw {}, {}({})", frs2(instr), imm_s(instr), rs1(instr)),
+            0b011 => format!("fsd {}, {}({})", frs2(instr), imm_s(instr), rs1(instr)),
+            _ => return None,
+        },
+        0b1010011 => disassemble_op_fp(instr)?,
+        opcode @ (0b1000011 | 0b1000111 | 0b1001011 | 0b1001111) => {
+            let fmt = match (instr >> 25) & 0b11 {
+                0b00 => "s",
+                0b01 => "d",
+                _ => return None,
+            };
+            let mnemonic = match opcode {
+                0b1000011 => "fmadd",
+                0b1000111 => "fmsub",
+                0b1001011 => "fnmsub",
+                _ => "fnmadd",
+            };
+            format!("{mnemonic}.{fmt} {}, {}, {}, {}{}", frd(instr), frs1(instr), frs2(instr), frs3(instr), rounding(instr))
+        },
+        _ => return None,
+    })
+}
+
+/// Disassembles an instruction (compressed instructions in the lower 16 bits) to assembly with ABI register names.
+/// Branch and jump targets are shown as offsets relative to the instruction.
+pub fn disassemble(instr: TInstr) -> String {
+    disassemble_xlen(instr, 64)
+}
+
+/// Like `disassemble`, compressed instructions are decoded as RV32C or RV64C depending on `xlen`
+pub fn disassemble_xlen(instr: TInstr, xlen: u32) -> String {
+    let expanded = if is_compressed(instr) { expand_compressed_xlen(instr as u16, xlen) } else { Some(instr) };
+    match expanded.and_then(disassemble_32) {
+        Some(text) => text,
+        None if is_compressed(instr) => format!("unknown {:#06x}", instr & 0xffff),
+        None => format!("unknown {instr:#010x}"),
+    }
+}
+
+/// One line of Spike's `-l` instruction log, the pc is printed with `xlen` bits
+pub fn format_trace(pc: TReg, instr: TInstr, xlen: u32) -> String {
+    let width = xlen as usize / 4;
+    if is_compressed(instr) {
+        format!("core   0: 0x{pc:0width$x} (0x{:04x}) {}", instr & 0xffff, disassemble_xlen(instr, xlen))
+    } else {
+        format!("core   0: 0x{pc:0width$x} (0x{instr:08x}) {}", disassemble_xlen(instr, xlen))
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+
+use crate::cpu::basic_cpu::{Privilege, TInstr, TReg};
+
+// Exception codes written to mcause
+pub const EXC_INSTR_MISALIGNED: TReg = 0;
+pub const EXC_INSTR_ACCESS_FAULT: TReg = 1;
+pub const EXC_ILLEGAL_INSTRUCTION: TReg = 2;
+pub const EXC_BREAKPOINT: TReg = 3;
+pub const EXC_LOAD_MISALIGNED: TReg = 4;
+pub const EXC_LOAD_ACCESS_FAULT: TReg = 5;
+pub const EXC_STORE_MISALIGNED: TReg = 6;
+pub const EXC_STORE_ACCESS_FAULT: TReg = 7;
+pub const EXC_ECALL_FROM_U: TReg = 8;
+pub const EXC_ECALL_FROM_S: TReg = 9;
+pub const EXC_ECALL_FROM_M: TReg = 11;
+pub const EXC_INSTR_PAGE_FAULT: TReg = 12;
+pub const EXC_LOAD_PAGE_FAULT: TReg = 13;
+pub const EXC_STORE_PAGE_FAULT: TReg = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAccess {
+    Fetch,
+    Load,
+    Store, // also used for AMOs and SC
+}
+
+/// Errors raised while fetching, decoding or executing an instruction.
+/// `pc` is always the address of the instruction which caused the error, the program counter is not advanced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecError {
+    /// Encoding which is not a valid instruction (unknown func3/func7 combination, reserved encoding, ...)
+    IllegalInstruction { instr: TInstr, pc: TReg },
+    /// Valid encoding of an extension this CPU does not implement (e.g. "V" or "Q")
+    UnimplementedExtension { extension: &'static str, instr: TInstr, pc: TReg },
+    /// Access to an address outside of the memory
+    MemoryFault { addr: TReg, access: MemoryAccess, pc: TReg },
+    /// Virtual address without a valid mapping or with insufficient permissions
+    PageFault { addr: TReg, access: MemoryAccess, pc: TReg },
+    /// Access which has to be naturally aligned but is not (AMOs, LR/SC, accesses crossing a page)
+    Misaligned { addr: TReg, access: MemoryAccess, pc: TReg },
+    /// ECALL instruction, the cause depends on the privilege mode it was executed in
+    EnvironmentCall { privilege: Privilege, pc: TReg },
+    /// EBREAK instruction
+    Breakpoint { pc: TReg },
+}
+
+impl ExecError {
+    pub fn pc(&self) -> TReg {
+        match self {
+            ExecError::IllegalInstruction { pc, .. } => *pc,
+            ExecError::UnimplementedExtension { pc, .. } => *pc,
+            ExecError::MemoryFault { pc, .. } => *pc,
+            ExecError::PageFault { pc, .. } => *pc,
+            ExecError::Misaligned { pc, .. } => *pc,
+            ExecError::EnvironmentCall { pc, .. } => *pc,
+            ExecError::Breakpoint { pc } => *pc,
+        }
+    }
+
+    /// Exception code of the trap raised by this error (mcause)
+    pub fn cause(&self) -> TReg {
+        match self {
+            // Unimplemented extensions are illegal instructions for the running program
+            ExecError::IllegalInstruction { .. } | ExecError::UnimplementedExtension { .. } => EXC_ILLEGAL_INSTRUCTION,
+            ExecError::MemoryFault { access: MemoryAccess::Fetch, .. } => EXC_INSTR_ACCESS_FAULT,
+            ExecError::MemoryFault { access: MemoryAccess::Load, .. } => EXC_LOAD_ACCESS_FAULT,
+            ExecError::MemoryFault { access: MemoryAccess::Store, .. } => EXC_STORE_ACCESS_FAULT,
+            ExecError::PageFault { access: MemoryAccess::Fetch, .. } => EXC_INSTR_PAGE_FAULT,
+            ExecError::PageFault { access: MemoryAccess::Load, .. } => EXC_LOAD_PAGE_FAULT,
+            ExecError::PageFault { access: MemoryAccess::Store, .. } => EXC_STORE_PAGE_FAULT,
+            ExecError::Misaligned { access: MemoryAccess::Fetch, .. } => EXC_INSTR_MISALIGNED,
+            ExecError::Misaligned { access: MemoryAccess::Load, .. } => EXC_LOAD_MISALIGNED,
+            ExecError::Misaligned { access: MemoryAccess::Store, .. } => EXC_STORE_MISALIGNED,
+            ExecError::EnvironmentCall { privilege: Privilege::User, .. } => EXC_ECALL_FROM_U,
+            ExecError::EnvironmentCall { privilege: Privilege::Supervisor, .. } => EXC_ECALL_FROM_S,
+            ExecError::EnvironmentCall { privilege: Privilege::Machine, .. } => EXC_ECALL_FROM_M,
+            ExecError::Breakpoint { .. } => EXC_BREAKPOINT,
+        }
+    }
+
+    /// Trap value (mtval): faulting address, instruction bits or zero
+    pub fn tval(&self) -> TReg {
+        match self {
+            ExecError::IllegalInstruction { instr, .. } => *instr as TReg,
+            ExecError::UnimplementedExtension { instr, .. } => *instr as TReg,
+            ExecError::MemoryFault { addr, .. } => *addr,
+            ExecError::PageFault { addr, .. } => *addr,
+            ExecError::Misaligned { addr, .. } => *addr,
+            ExecError::EnvironmentCall { .. } => 0,
+            ExecError::Breakpoint { pc } => *pc,
+        }
+    }
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::IllegalInstruction { instr, pc } => {
+                write!(f, "illegal instruction {instr:#010x} at PC {pc:#x}")
+            },
+            ExecError::UnimplementedExtension { extension, instr, pc } => {
+                write!(f, "instruction {instr:#010x} at PC {pc:#x} requires the unimplemented \"{extension}\" extension")
+            },
+            ExecError::MemoryFault { addr, access, pc } => {
+                write!(f, "memory fault ({access:?}) at address {addr:#x} (PC {pc:#x})")
+            },
+            ExecError::PageFault { addr, access, pc } => {
+                write!(f, "page fault ({access:?}) at address {addr:#x} (PC {pc:#x})")
+            },
+            ExecError::Misaligned { addr, access, pc } => {
+                write!(f, "misaligned access ({access:?}) at address {addr:#x} (PC {pc:#x})")
+            },
+            ExecError::EnvironmentCall { privilege, pc } => write!(f, "environment call from {privilege:?} mode at PC {pc:#x}"),
+            ExecError::Breakpoint { pc } => write!(f, "breakpoint at PC {pc:#x}"),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
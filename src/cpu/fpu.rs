@@ -0,0 +1,493 @@
+use std::cmp::Ordering;
+
+//
+// Floating-point helpers for the "F" and "D" extensions
+//
+// Values are kept in their raw register representation (u64): doubles use all 64 bits, singles are NaN-boxed
+// (upper 32 bits all ones). All operations return the raw result together with the exception flags they raised,
+// the flags are accumulated into fcsr.fflags by the CPU.
+//
+// Arithmetic is carried out with the host double precision operations (round to nearest, ties to even) together with
+// the sign of the rounding error. The sign of the error is enough to implement the directed rounding modes
+// (RTZ, RDN, RUP) and the inexact flag, and to round double results of single precision operations correctly.
+// Known limitation: RMM (ties to max magnitude) behaves like RNE for exact ties of double precision results.
+//
+
+pub const FFLAGS_NX: u64 = 1 << 0; // Inexact
+pub const FFLAGS_UF: u64 = 1 << 1; // Underflow
+pub const FFLAGS_OF: u64 = 1 << 2; // Overflow
+pub const FFLAGS_DZ: u64 = 1 << 3; // Divide by zero
+pub const FFLAGS_NV: u64 = 1 << 4; // Invalid operation
+
+pub const CANONICAL_NAN_F32: u32 = 0x7fc0_0000;
+pub const CANONICAL_NAN_F64: u64 = 0x7ff8_0000_0000_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FpFormat {
+    Single, // fmt 00
+    Double, // fmt 01
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    Rne, // 000 - round to nearest, ties to even
+    Rtz, // 001 - round towards zero
+    Rdn, // 010 - round down (towards -inf)
+    Rup, // 011 - round up (towards +inf)
+    Rmm, // 100 - round to nearest, ties to max magnitude
+}
+
+impl RoundingMode {
+    pub fn from_bits(bits: u64) -> Option<RoundingMode> {
+        // 101 and 110 are reserved, 111 (dynamic) has to be resolved through frm by the caller
+        match bits {
+            0b000 => Some(RoundingMode::Rne),
+            0b001 => Some(RoundingMode::Rtz),
+            0b010 => Some(RoundingMode::Rdn),
+            0b011 => Some(RoundingMode::Rup),
+            0b100 => Some(RoundingMode::Rmm),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignInjection {
+    Copy,   // FSGNJ
+    Negate, // FSGNJN
+    Xor,    // FSGNJX
+}
+
+//
+// Register representation
+//
+pub fn nan_box(bits: u32) -> u64 {
+    0xffff_ffff_0000_0000 | bits as u64
+}
+
+pub fn unbox(reg: u64) -> u32 {
+    // Singles which are not properly NaN-boxed are treated as the canonical NaN
+    if reg >> 32 == 0xffff_ffff { reg as u32 } else { CANONICAL_NAN_F32 }
+}
+
+pub fn canonical_nan(fmt: FpFormat) -> u64 {
+    match fmt {
+        FpFormat::Single => nan_box(CANONICAL_NAN_F32),
+        FpFormat::Double => CANONICAL_NAN_F64,
+    }
+}
+
+pub fn fp_value(reg: u64, fmt: FpFormat) -> f64 {
+    match fmt {
+        FpFormat::Single => f32::from_bits(unbox(reg)) as f64,
+        FpFormat::Double => f64::from_bits(reg),
+    }
+}
+
+fn fp_bits(value: f64, fmt: FpFormat) -> u64 {
+    // only used for values which are exactly representable in the target format
+    match fmt {
+        FpFormat::Single => nan_box((value as f32).to_bits()),
+        FpFormat::Double => value.to_bits(),
+    }
+}
+
+pub fn is_snan(reg: u64, fmt: FpFormat) -> bool {
+    match fmt {
+        FpFormat::Single => {
+            let bits = unbox(reg);
+            (bits & 0x7f80_0000) == 0x7f80_0000 && (bits & 0x003f_ffff) != 0 && (bits & 0x0040_0000) == 0
+        },
+        FpFormat::Double => {
+            (reg & 0x7ff0_0000_0000_0000) == 0x7ff0_0000_0000_0000 && (reg & 0x0007_ffff_ffff_ffff) != 0 && (reg & 0x0008_0000_0000_0000) == 0
+        },
+    }
+}
+
+//
+// Rounding
+//
+
+// Result computed in double precision: the value rounded to nearest and the sign of the rounding error (exact - value)
+#[derive(Debug, Clone, Copy)]
+struct Approx {
+    value: f64,
+    err: Ordering,
+}
+
+fn sign_of(x: f64) -> Ordering {
+    x.partial_cmp(&0.0).unwrap_or(Ordering::Equal)
+}
+
+fn special(value: f64, finite_inputs: bool) -> Approx {
+    // An infinite result from finite operands is an overflow, the exact result is finite and lies below/above the infinity
+    let err = if value.is_infinite() && finite_inputs {
+        if value > 0.0 { Ordering::Less } else { Ordering::Greater }
+    } else {
+        Ordering::Equal
+    };
+    Approx { value, err }
+}
+
+fn apply_rounding_mode<T: Copy + PartialOrd + Default>(value: T, err: Ordering, rm: RoundingMode, next_up: fn(T) -> T, next_down: fn(T) -> T) -> T {
+    // value is the round-to-nearest result, move it to the neighbour required by the directed rounding modes
+    let zero = T::default();
+    match rm {
+        RoundingMode::Rne | RoundingMode::Rmm => value,
+        RoundingMode::Rtz => {
+            if value > zero && err == Ordering::Less {
+                next_down(value)
+            } else if value < zero && err == Ordering::Greater {
+                next_up(value)
+            } else {
+                value
+            }
+        },
+        RoundingMode::Rdn => if err == Ordering::Less { next_down(value) } else { value },
+        RoundingMode::Rup => if err == Ordering::Greater { next_up(value) } else { value },
+    }
+}
+
+fn round_f64(approx: Approx, rm: RoundingMode) -> (f64, u64) {
+    if approx.err == Ordering::Equal {
+        return (approx.value, 0);
+    }
+    let mut flags = FFLAGS_NX;
+    if approx.value.is_infinite() {
+        flags |= FFLAGS_OF;
+    }
+    let result = apply_rounding_mode(approx.value, approx.err, rm, f64::next_up, f64::next_down);
+    if result.abs() < f64::MIN_POSITIVE {
+        flags |= FFLAGS_UF;
+    }
+    (result, flags)
+}
+
+fn round_f32(approx: Approx, rm: RoundingMode) -> (f32, u64) {
+    let value = approx.value;
+    let nearest = value as f32; // round to nearest, ties to even
+    let (nearest, err) = if nearest.is_infinite() && value.is_finite() {
+        (nearest, if value > 0.0 { Ordering::Less } else { Ordering::Greater })
+    } else {
+        let diff = value - nearest as f64; // exact
+        if diff == 0.0 {
+            (nearest, approx.err)
+        } else {
+            let other = if diff > 0.0 { nearest.next_up() } else { nearest.next_down() };
+            let half = (other as f64 - nearest as f64) / 2.0;
+            if diff == half && approx.err == sign_of(diff) {
+                // value is exactly halfway, but the exact result lies beyond the midpoint
+                (other, sign_of(-diff))
+            } else if diff == half && approx.err == Ordering::Equal && rm == RoundingMode::Rmm && other.abs() > nearest.abs() {
+                (other, sign_of(-diff))
+            } else {
+                (nearest, sign_of(diff))
+            }
+        }
+    };
+    if err == Ordering::Equal {
+        return (nearest, 0);
+    }
+    let mut flags = FFLAGS_NX;
+    if nearest.is_infinite() {
+        flags |= FFLAGS_OF;
+    }
+    let result = apply_rounding_mode(nearest, err, rm, f32::next_up, f32::next_down);
+    if result.abs() < f32::MIN_POSITIVE {
+        flags |= FFLAGS_UF;
+    }
+    (result, flags)
+}
+
+fn round_to(approx: Approx, fmt: FpFormat, rm: RoundingMode) -> (u64, u64) {
+    match fmt {
+        FpFormat::Single => {
+            let (result, flags) = round_f32(approx, rm);
+            (nan_box(result.to_bits()), flags)
+        },
+        FpFormat::Double => {
+            let (result, flags) = round_f64(approx, rm);
+            (result.to_bits(), flags)
+        },
+    }
+}
+
+//
+// Exact operations (value rounded to nearest + sign of the error)
+//
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+fn add_approx(a: f64, b: f64, rm: RoundingMode) -> Approx {
+    let (s, err) = two_sum(a, b);
+    if !s.is_finite() {
+        return special(s, a.is_finite() && b.is_finite());
+    }
+    if s == 0.0 && err == 0.0 && !(a == 0.0 && b == 0.0 && a.is_sign_negative() == b.is_sign_negative()) {
+        // An exact zero sum of operands with opposite signs is +0, except when rounding down
+        return Approx { value: if rm == RoundingMode::Rdn { -0.0 } else { 0.0 }, err: Ordering::Equal };
+    }
+    Approx { value: s, err: sign_of(err) }
+}
+
+fn mul_approx(a: f64, b: f64) -> Approx {
+    let p = a * b;
+    if !p.is_finite() {
+        return special(p, a.is_finite() && b.is_finite());
+    }
+    Approx { value: p, err: sign_of(a.mul_add(b, -p)) }
+}
+
+fn div_approx(a: f64, b: f64) -> Approx {
+    let q = a / b;
+    if !q.is_finite() {
+        return special(q, a.is_finite() && b.is_finite() && b != 0.0);
+    }
+    if q == 0.0 && a == 0.0 {
+        return Approx { value: q, err: Ordering::Equal };
+    }
+    // exact - q = (a - q * b) / b
+    let remainder = (-q).mul_add(b, a);
+    let err = if b > 0.0 { sign_of(remainder) } else { sign_of(-remainder) };
+    Approx { value: q, err }
+}
+
+fn sqrt_approx(a: f64) -> Approx {
+    let r = a.sqrt();
+    if !r.is_finite() || r == 0.0 {
+        return Approx { value: r, err: Ordering::Equal };
+    }
+    Approx { value: r, err: sign_of((-r).mul_add(r, a)) }
+}
+
+fn fma_approx(a: f64, b: f64, c: f64) -> Approx {
+    let r = a.mul_add(b, c);
+    if !r.is_finite() {
+        return special(r, a.is_finite() && b.is_finite() && c.is_finite());
+    }
+    // a * b = p + pe exactly, the error of the fused result is (p + pe + c) - r
+    let p = a * b;
+    let pe = a.mul_add(b, -p);
+    let (s, t) = two_sum(p, c);
+    Approx { value: r, err: sign_of(((s - r) + t) + pe) }
+}
+
+// Common NaN handling for the arithmetic operations
+fn compute(fmt: FpFormat, operands: &[u64], rm: RoundingMode, op: impl Fn(&[f64]) -> Approx) -> (u64, u64) {
+    let mut flags = 0;
+    if operands.iter().any(|&reg| is_snan(reg, fmt)) {
+        flags |= FFLAGS_NV;
+    }
+    let values: Vec<f64> = operands.iter().map(|&reg| fp_value(reg, fmt)).collect();
+    let approx = op(&values);
+    if approx.value.is_nan() {
+        // Invalid operation (e.g. inf - inf, 0 * inf, 0 / 0, sqrt(-1)) if none of the operands was a NaN already
+        if !values.iter().any(|v| v.is_nan()) {
+            flags |= FFLAGS_NV;
+        }
+        return (canonical_nan(fmt), flags);
+    }
+    let (result, round_flags) = round_to(approx, fmt, rm);
+    (result, flags | round_flags)
+}
+
+//
+// Arithmetic
+//
+pub fn fp_add(fmt: FpFormat, a: u64, b: u64, rm: RoundingMode) -> (u64, u64) {
+    compute(fmt, &[a, b], rm, |v| add_approx(v[0], v[1], rm))
+}
+
+pub fn fp_sub(fmt: FpFormat, a: u64, b: u64, rm: RoundingMode) -> (u64, u64) {
+    compute(fmt, &[a, b], rm, |v| add_approx(v[0], -v[1], rm))
+}
+
+pub fn fp_mul(fmt: FpFormat, a: u64, b: u64, rm: RoundingMode) -> (u64, u64) {
+    compute(fmt, &[a, b], rm, |v| mul_approx(v[0], v[1]))
+}
+
+pub fn fp_div(fmt: FpFormat, a: u64, b: u64, rm: RoundingMode) -> (u64, u64) {
+    let (x, y) = (fp_value(a, fmt), fp_value(b, fmt));
+    let mut flags = 0;
+    if y == 0.0 && x.is_finite() && x != 0.0 {
+        flags |= FFLAGS_DZ;
+    }
+    let (result, op_flags) = compute(fmt, &[a, b], rm, |v| div_approx(v[0], v[1]));
+    (result, flags | op_flags)
+}
+
+pub fn fp_sqrt(fmt: FpFormat, a: u64, rm: RoundingMode) -> (u64, u64) {
+    compute(fmt, &[a], rm, |v| sqrt_approx(v[0]))
+}
+
+/// Fused multiply-add: (+/-)(a * b) (+/-) c with a single rounding
+pub fn fp_fma(fmt: FpFormat, a: u64, b: u64, c: u64, negate_product: bool, negate_addend: bool, rm: RoundingMode) -> (u64, u64) {
+    let (x, y) = (fp_value(a, fmt), fp_value(b, fmt));
+    let mut flags = 0;
+    if (x.is_infinite() && y == 0.0) || (x == 0.0 && y.is_infinite()) {
+        flags |= FFLAGS_NV; // invalid even if c is a quiet NaN
+    }
+    let (result, op_flags) = compute(fmt, &[a, b, c], rm, |v| {
+        let x = if negate_product { -v[0] } else { v[0] };
+        let z = if negate_addend { -v[2] } else { v[2] };
+        fma_approx(x, v[1], z)
+    });
+    (result, flags | op_flags)
+}
+
+pub fn fp_min(fmt: FpFormat, a: u64, b: u64) -> (u64, u64) {
+    fp_min_max(fmt, a, b, true)
+}
+
+pub fn fp_max(fmt: FpFormat, a: u64, b: u64) -> (u64, u64) {
+    fp_min_max(fmt, a, b, false)
+}
+
+fn fp_min_max(fmt: FpFormat, a: u64, b: u64, min: bool) -> (u64, u64) {
+    let flags = if is_snan(a, fmt) || is_snan(b, fmt) { FFLAGS_NV } else { 0 };
+    let (x, y) = (fp_value(a, fmt), fp_value(b, fmt));
+    let result = match (x.is_nan(), y.is_nan()) {
+        (true, true) => return (canonical_nan(fmt), flags),
+        (true, false) => y,
+        (false, true) => x,
+        // -0.0 is considered less than +0.0
+        _ if x == y => if x.is_sign_negative() == min { x } else { y },
+        _ => if (x < y) == min { x } else { y },
+    };
+    (fp_bits(result, fmt), flags)
+}
+
+pub fn fp_sign_inject(fmt: FpFormat, a: u64, b: u64, mode: SignInjection) -> u64 {
+    let (sign_bit, x, y) = match fmt {
+        FpFormat::Single => (1u64 << 31, unbox(a) as u64, unbox(b) as u64),
+        FpFormat::Double => (1u64 << 63, a, b),
+    };
+    let sign = match mode {
+        SignInjection::Copy => y & sign_bit,
+        SignInjection::Negate => !y & sign_bit,
+        SignInjection::Xor => (x ^ y) & sign_bit,
+    };
+    let result = (x & !sign_bit) | sign;
+    match fmt {
+        FpFormat::Single => nan_box(result as u32),
+        FpFormat::Double => result,
+    }
+}
+
+//
+// Comparisons
+//
+pub fn fp_eq(fmt: FpFormat, a: u64, b: u64) -> (u64, u64) {
+    // quiet comparison, only signaling NaNs raise the invalid flag
+    let flags = if is_snan(a, fmt) || is_snan(b, fmt) { FFLAGS_NV } else { 0 };
+    ((fp_value(a, fmt) == fp_value(b, fmt)) as u64, flags)
+}
+
+pub fn fp_lt(fmt: FpFormat, a: u64, b: u64) -> (u64, u64) {
+    // signaling comparison, any NaN raises the invalid flag
+    let (x, y) = (fp_value(a, fmt), fp_value(b, fmt));
+    let flags = if x.is_nan() || y.is_nan() { FFLAGS_NV } else { 0 };
+    ((x < y) as u64, flags)
+}
+
+pub fn fp_le(fmt: FpFormat, a: u64, b: u64) -> (u64, u64) {
+    let (x, y) = (fp_value(a, fmt), fp_value(b, fmt));
+    let flags = if x.is_nan() || y.is_nan() { FFLAGS_NV } else { 0 };
+    ((x <= y) as u64, flags)
+}
+
+pub fn fp_classify(fmt: FpFormat, a: u64) -> u64 {
+    /*
+    bit 0: -inf, 1: negative normal, 2: negative subnormal, 3: -0
+    bit 4: +0, 5: positive subnormal, 6: positive normal, 7: +inf
+    bit 8: signaling NaN, 9: quiet NaN
+    */
+    let (negative, class) = match fmt {
+        FpFormat::Single => {
+            let x = f32::from_bits(unbox(a));
+            (x.is_sign_negative(), x.classify())
+        },
+        FpFormat::Double => {
+            let x = f64::from_bits(a);
+            (x.is_sign_negative(), x.classify())
+        },
+    };
+    let bit = match (class, negative) {
+        (std::num::FpCategory::Nan, _) => if is_snan(a, fmt) { 8 } else { 9 },
+        (std::num::FpCategory::Infinite, true) => 0,
+        (std::num::FpCategory::Normal, true) => 1,
+        (std::num::FpCategory::Subnormal, true) => 2,
+        (std::num::FpCategory::Zero, true) => 3,
+        (std::num::FpCategory::Zero, false) => 4,
+        (std::num::FpCategory::Subnormal, false) => 5,
+        (std::num::FpCategory::Normal, false) => 6,
+        (std::num::FpCategory::Infinite, false) => 7,
+    };
+    1 << bit
+}
+
+//
+// Conversions
+//
+fn round_integral(x: f64, rm: RoundingMode) -> f64 {
+    match rm {
+        RoundingMode::Rne => x.round_ties_even(),
+        RoundingMode::Rtz => x.trunc(),
+        RoundingMode::Rdn => x.floor(),
+        RoundingMode::Rup => x.ceil(),
+        RoundingMode::Rmm => x.round(),
+    }
+}
+
+/// FCVT.{W,WU,L,LU}.{S,D}: converts to a signed/unsigned 32-bit (`word`) or 64-bit integer.
+/// 32-bit results are sign-extended to 64 bits, out of range inputs saturate and raise the invalid flag.
+pub fn fp_to_int(fmt: FpFormat, a: u64, signed: bool, word: bool, rm: RoundingMode) -> (u64, u64) {
+    let (min, max): (i128, i128) = match (signed, word) {
+        (true, true) => (i32::MIN as i128, i32::MAX as i128),
+        (false, true) => (0, u32::MAX as i128),
+        (true, false) => (i64::MIN as i128, i64::MAX as i128),
+        (false, false) => (0, u64::MAX as i128),
+    };
+    let x = fp_value(a, fmt);
+    let (value, flags) = if x.is_nan() {
+        (max, FFLAGS_NV)
+    } else {
+        let rounded = round_integral(x, rm);
+        if rounded < min as f64 {
+            (min, FFLAGS_NV)
+        } else if rounded >= max as f64 + 1.0 {
+            (max, FFLAGS_NV)
+        } else {
+            (rounded as i128, if rounded != x { FFLAGS_NX } else { 0 })
+        }
+    };
+    let result = if word { value as i32 as i64 as u64 } else { value as u64 };
+    (result, flags)
+}
+
+/// FCVT.{S,D}.{W,WU,L,LU}: converts the signed/unsigned 32-bit (`word`) or 64-bit integer in `value`.
+pub fn int_to_fp(fmt: FpFormat, value: u64, signed: bool, word: bool, rm: RoundingMode) -> (u64, u64) {
+    let int: i128 = match (signed, word) {
+        (true, true) => value as i32 as i128,
+        (false, true) => value as u32 as i128,
+        (true, false) => value as i64 as i128,
+        (false, false) => value as i128,
+    };
+    let nearest = int as f64;
+    let approx = Approx { value: nearest, err: (int - nearest as i128).cmp(&0) };
+    round_to(approx, fmt, rm)
+}
+
+/// FCVT.S.D / FCVT.D.S
+pub fn fp_convert(from: FpFormat, to: FpFormat, a: u64, rm: RoundingMode) -> (u64, u64) {
+    let flags = if is_snan(a, from) { FFLAGS_NV } else { 0 };
+    let x = fp_value(a, from);
+    if x.is_nan() {
+        return (canonical_nan(to), flags);
+    }
+    let (result, round_flags) = round_to(Approx { value: x, err: Ordering::Equal }, to, rm);
+    (result, flags | round_flags)
+}
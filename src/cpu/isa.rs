@@ -0,0 +1,140 @@
+use std::fmt;
+
+use crate::cpu::basic_cpu::{TInstr, TReg};
+
+/// Extensions implemented by BasicCpu, in canonical order
+pub const SUPPORTED_EXTENSIONS: &str = "imafdc";
+/// Multi-letter extensions which are always implemented
+const ALWAYS_IMPLEMENTED: [&str; 2] = ["zicsr", "zifencei"];
+const MISA_MXL_32: TReg = 1 << 30;
+const MISA_MXL_64: TReg = 2 << 62;
+
+fn extension_bit(letter: char) -> u32 {
+    1 << (letter as u32 - 'a' as u32)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IsaError {
+    pub isa: String,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for IsaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid ISA string {:?}: {}", self.isa, self.reason)
+    }
+}
+
+impl std::error::Error for IsaError {}
+
+/// Base ISA and single-letter extensions of the CPU (XLEN and the extension bits of misa).
+/// Instructions of disabled extensions are illegal, so are the RV64-only instructions on RV32.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Isa {
+    xlen: u32,
+    extensions: u32,
+}
+
+impl Default for Isa {
+    fn default() -> Self {
+        Isa::parse("rv64imafdc").unwrap()
+    }
+}
+
+impl Isa {
+    /// Parses an ISA string like "rv64imac", "rv64gc", "rv32emc" or "rv64i_zicsr_zifencei"
+    pub fn parse(isa: &str) -> Result<Isa, IsaError> {
+        let error = |reason| IsaError { isa: isa.to_string(), reason };
+        let lower = isa.to_ascii_lowercase();
+        let (xlen, rest) = match (lower.strip_prefix("rv64"), lower.strip_prefix("rv32")) {
+            (Some(rest), _) => (64, rest),
+            (_, Some(rest)) => (32, rest),
+            _ => return Err(error("expected rv32 or rv64 prefix")),
+        };
+        let mut parts = rest.split('_');
+        let letters = parts.next().unwrap_or_default();
+        let mut extensions = match letters.chars().next() {
+            Some('i') => extension_bit('i'),
+            Some('e') => extension_bit('e'),
+            Some('g') => "imafd".chars().map(extension_bit).sum(),
+            _ => return Err(error("expected base ISA i, e or g")),
+        };
+        for letter in letters.chars().skip(1) {
+            if !SUPPORTED_EXTENSIONS.contains(letter) || letter == 'i' {
+                return Err(error("unsupported extension"));
+            }
+            if extensions & extension_bit(letter) != 0 {
+                return Err(error("duplicate extension"));
+            }
+            extensions |= extension_bit(letter);
+        }
+        if parts.any(|extension| !ALWAYS_IMPLEMENTED.contains(&extension)) {
+            return Err(error("unsupported extension"));
+        }
+        let isa = Isa { xlen, extensions };
+        if isa.has('d') && !isa.has('f') {
+            return Err(error("D requires F"));
+        }
+        Ok(isa)
+    }
+
+    pub fn has(&self, extension: char) -> bool {
+        extension.is_ascii_lowercase() && self.extensions & extension_bit(extension) != 0
+    }
+
+    pub fn is_embedded(&self) -> bool {
+        self.has('e')
+    }
+
+    /// Width of the integer registers and addresses, 32 or 64
+    pub fn xlen(&self) -> u32 {
+        self.xlen
+    }
+
+    /// Value of the misa CSR, S and U mode are always implemented
+    pub fn misa(&self) -> TReg {
+        let mxl = if self.xlen == 32 { MISA_MXL_32 } else { MISA_MXL_64 };
+        mxl | TReg::from(self.extensions | extension_bit('s') | extension_bit('u'))
+    }
+
+    /// Whether the instruction (expanded if `compressed`) belongs to an enabled extension
+    pub fn accepts(&self, instr: TInstr, compressed: bool) -> bool {
+        if (compressed && !self.has('c')) || (self.xlen == 32 && is_rv64_only(instr)) {
+            return false;
+        }
+        let fmt = (instr >> 25) & 0b11;
+        match instr & 0x7f {
+            0b0110011 | 0b0111011 if instr >> 25 == 0b0000001 => self.has('m'), // MUL/DIV(W)
+            0b0101111 => self.has('a'),
+            0b0000111 | 0b0100111 => self.has('f') && ((instr >> 12) & 0b111 != 0b011 || self.has('d')), // FLW/FSW, FLD/FSD
+            0b1000011 | 0b1000111 | 0b1001011 | 0b1001111 => self.has('f') && (fmt != 0b01 || self.has('d')),
+            // FCVT.S.D (funct7 0100000, rs2 1) converts from double with the single precision format field
+            0b1010011 => self.has('f') && (fmt != 0b01 || self.has('d')) && (instr >> 20 != 0x401 || self.has('d')),
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Isa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let base = if self.is_embedded() { "e" } else { "i" };
+        let extensions: String = SUPPORTED_EXTENSIONS.chars().skip(1).filter(|letter| self.has(*letter)).collect();
+        write!(f, "rv{}{base}{extensions}", self.xlen)
+    }
+}
+
+/// Whether the instruction only exists in RV64, its encoding is reserved in RV32
+fn is_rv64_only(instr: TInstr) -> bool {
+    let func3 = (instr >> 12) & 0b111;
+    match instr & 0x7f {
+        0b0011011 | 0b0111011 => true, // OP-IMM-32, OP-32
+        0b0000011 => func3 == 0b011 || func3 == 0b110, // LD, LWU
+        0b0100011 => func3 == 0b011, // SD
+        0b0101111 => func3 == 0b011, // LR.D, SC.D, AMO*.D
+        0b0010011 => func3 & 0b011 == 0b001 && instr & (1 << 25) != 0, // SLLI/SRLI/SRAI with shamt[5] set
+        // FCVT.L[U].fmt and FCVT.fmt.L[U] (rs2 = 2, 3), FMV.X.D and FMV.D.X
+        0b1010011 => (matches!(instr >> 27, 0b11000 | 0b11010) && instr & (1 << 21) != 0)
+            || (func3 == 0b000 && matches!(instr >> 20, 0xe20 | 0xf20)),
+        _ => false,
+    }
+}
@@ -0,0 +1,201 @@
+use crate::cpu::basic_cpu::{Privilege, TReg};
+use crate::cpu::error::MemoryAccess;
+use crate::memory::bus::Bus;
+use log::info;
+
+pub const PAGE_SIZE: TReg = 4096;
+pub const PAGE_SHIFT: TReg = 12;
+pub const TLB_SIZE: usize = 64;
+
+// satp fields
+pub const SATP_MODE_SHIFT: TReg = 60;
+pub const SATP_MODE_BARE: TReg = 0;
+pub const SATP_MODE_SV39: TReg = 8;
+pub const SATP_MODE_SV48: TReg = 9;
+pub const SATP_MODE_SV57: TReg = 10;
+pub const SATP_ASID_SHIFT: TReg = 44;
+pub const SATP_ASID_MASK: TReg = 0xffff;
+pub const SATP_PPN_MASK: TReg = (1 << 44) - 1;
+
+// Page table entry fields
+pub const PTE_V: TReg = 1 << 0;
+pub const PTE_R: TReg = 1 << 1;
+pub const PTE_W: TReg = 1 << 2;
+pub const PTE_X: TReg = 1 << 3;
+pub const PTE_U: TReg = 1 << 4;
+pub const PTE_G: TReg = 1 << 5;
+pub const PTE_A: TReg = 1 << 6;
+pub const PTE_D: TReg = 1 << 7;
+const PTE_PPN_SHIFT: TReg = 10;
+const PTE_PPN_MASK: TReg = (1 << 44) - 1;
+const PTE_RESERVED_MASK: TReg = !((1 << 54) - 1); // Svpbmt/Svnapot bits (63:54) are not supported and must be zero
+
+/// Reasons a translation fails, the CPU turns them into page faults or access faults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmuFault {
+    PageFault,
+    AccessFault, // page table entry outside of the memory
+}
+
+/// State of the translation context which the permission checks depend on.
+#[derive(Debug, Clone, Copy)]
+pub struct MmuContext {
+    pub satp: TReg,
+    pub privilege: Privilege, // effective privilege (mstatus.MPRV applied for loads and stores)
+    pub sum: bool, // S-mode may access U pages
+    pub mxr: bool, // loads from executable pages are allowed
+}
+
+impl MmuContext {
+    pub fn mode(&self) -> TReg {
+        self.satp >> SATP_MODE_SHIFT
+    }
+
+    pub fn asid(&self) -> TReg {
+        (self.satp >> SATP_ASID_SHIFT) & SATP_ASID_MASK
+    }
+
+    /// Number of page table levels, 0 if translation is disabled.
+    fn levels(&self) -> u32 {
+        match self.mode() {
+            SATP_MODE_SV39 => 3,
+            SATP_MODE_SV48 => 4,
+            SATP_MODE_SV57 => 5,
+            _ => 0,
+        }
+    }
+
+    pub fn translation_enabled(&self) -> bool {
+        self.privilege != Privilege::Machine && self.levels() != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TlbEntry {
+    vpn: TReg, // virtual page number of the 4 KiB page (vaddr >> 12 including the sign-extended upper bits)
+    ppn: TReg, // physical page number of the 4 KiB page (superpages are split)
+    asid: TReg,
+    pte: TReg, // leaf PTE flags, A/D as seen at the time of the walk
+}
+
+/// Direct-mapped software TLB caching leaf translations of 4 KiB pages.
+pub struct Tlb {
+    entries: [Option<TlbEntry>; TLB_SIZE],
+}
+
+impl Default for Tlb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tlb {
+    pub fn new() -> Tlb {
+        Tlb { entries: [None; TLB_SIZE] }
+    }
+
+    fn lookup(&self, vpn: TReg, asid: TReg) -> Option<TlbEntry> {
+        self.entries[vpn as usize % TLB_SIZE].filter(|entry| entry.vpn == vpn && (entry.asid == asid || entry.pte & PTE_G != 0))
+    }
+
+    fn insert(&mut self, entry: TlbEntry) {
+        self.entries[entry.vpn as usize % TLB_SIZE] = Some(entry);
+    }
+
+    /// SFENCE.VMA: flush entries matching the virtual address and/or ASID, global mappings are kept when only an ASID is given.
+    pub fn flush(&mut self, vaddr: Option<TReg>, asid: Option<TReg>) {
+        info!("TLB flush - vaddr: {vaddr:x?} - asid: {asid:?}");
+        for slot in self.entries.iter_mut() {
+            if let Some(entry) = slot {
+                let vaddr_match = vaddr.is_none_or(|addr| entry.vpn == addr >> PAGE_SHIFT);
+                let asid_match = asid.is_none_or(|asid| entry.asid == asid && entry.pte & PTE_G == 0);
+                if vaddr_match && asid_match {
+                    *slot = None;
+                }
+            }
+        }
+    }
+}
+
+fn read_pte(bus: &mut Bus, addr: TReg) -> Result<TReg, MmuFault> {
+    bus.read(addr, 64).map_err(|_| MmuFault::AccessFault)
+}
+
+fn check_permissions(ctx: &MmuContext, pte: TReg, access: MemoryAccess) -> Result<(), MmuFault> {
+    // U pages are only accessible from U-mode, and from S-mode with SUM (never for fetches)
+    let user_page = pte & PTE_U != 0;
+    match ctx.privilege {
+        Privilege::User if !user_page => return Err(MmuFault::PageFault),
+        Privilege::Supervisor if user_page && (access == MemoryAccess::Fetch || !ctx.sum) => return Err(MmuFault::PageFault),
+        _ => {},
+    }
+    let allowed = match access {
+        MemoryAccess::Fetch => pte & PTE_X != 0,
+        MemoryAccess::Load => pte & PTE_R != 0 || (ctx.mxr && pte & PTE_X != 0),
+        MemoryAccess::Store => pte & PTE_W != 0,
+    };
+    if allowed { Ok(()) } else { Err(MmuFault::PageFault) }
+}
+
+/// Translates a virtual address, walking the page table on a TLB miss.
+/// Accessed/dirty bits are updated in the page table by the walker.
+pub fn translate(bus: &mut Bus, tlb: &mut Tlb, ctx: &MmuContext, vaddr: TReg, access: MemoryAccess) -> Result<TReg, MmuFault> {
+    if !ctx.translation_enabled() {
+        return Ok(vaddr);
+    }
+    let levels = ctx.levels();
+    // Upper address bits must be a sign extension of the highest virtual address bit
+    let va_bits = PAGE_SHIFT as u32 + 9 * levels;
+    let upper = (vaddr as i64) >> (va_bits - 1);
+    if upper != 0 && upper != -1 {
+        return Err(MmuFault::PageFault);
+    }
+    let vpn = (vaddr >> PAGE_SHIFT) & ((1 << (9 * levels)) - 1);
+    let offset = vaddr & (PAGE_SIZE - 1);
+
+    // A cached entry is only used if the access does not need to set the A/D bits
+    if let Some(entry) = tlb.lookup(vaddr >> PAGE_SHIFT, ctx.asid())
+        && (access != MemoryAccess::Store || entry.pte & PTE_D != 0) {
+        check_permissions(ctx, entry.pte, access)?;
+        return Ok((entry.ppn << PAGE_SHIFT) | offset);
+    }
+
+    let mut table = (ctx.satp & SATP_PPN_MASK) << PAGE_SHIFT;
+    let mut level = levels - 1;
+    let (pte_addr, mut pte) = loop {
+        let vpn_i = (vpn >> (9 * level)) & 0x1ff;
+        let pte_addr = table + vpn_i * 8;
+        let pte = read_pte(bus, pte_addr)?;
+        if pte & PTE_V == 0 || (pte & PTE_R == 0 && pte & PTE_W != 0) || pte & PTE_RESERVED_MASK != 0 {
+            return Err(MmuFault::PageFault);
+        }
+        if pte & (PTE_R | PTE_X) != 0 {
+            break (pte_addr, pte); // leaf
+        }
+        if level == 0 {
+            return Err(MmuFault::PageFault);
+        }
+        level -= 1;
+        table = ((pte >> PTE_PPN_SHIFT) & PTE_PPN_MASK) << PAGE_SHIFT;
+    };
+    check_permissions(ctx, pte, access)?;
+
+    // Superpages need the lower PPN fields to be zero
+    let ppn = (pte >> PTE_PPN_SHIFT) & PTE_PPN_MASK;
+    let low_mask = (1 << (9 * level)) - 1;
+    if ppn & low_mask != 0 {
+        return Err(MmuFault::PageFault);
+    }
+
+    // Set accessed (and dirty for stores) bits
+    let updated = pte | PTE_A | if access == MemoryAccess::Store { PTE_D } else { 0 };
+    if updated != pte {
+        info!("Updating PTE at {pte_addr:#x}: {pte:#x} -> {updated:#x}");
+        bus.write(pte_addr, 64, updated).map_err(|_| MmuFault::AccessFault)?;
+        pte = updated;
+    }
+
+    let page_ppn = ppn | (vpn & low_mask);
+    tlb.insert(TlbEntry { vpn: vaddr >> PAGE_SHIFT, ppn: page_ppn, asid: ctx.asid(), pte });
+    Ok((page_ppn << PAGE_SHIFT) | offset)
+}
@@ -0,0 +1,108 @@
+use crate::cpu::basic_cpu::{TInstr, TReg, REGISTERS_COUNT};
+
+/// Number of general purpose registers of the embedded base ISAs RV32E and RV64E.
+pub const EMBEDDED_REGISTERS_COUNT: usize = 16;
+
+pub const ABI_NAMES: [&str; REGISTERS_COUNT] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+/// Integer register file. x0 is hardwired to zero, registers above `count` do not exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisterFile {
+    registers: [TReg; REGISTERS_COUNT],
+    count: usize,
+}
+
+impl Default for RegisterFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegisterFile {
+    pub fn new() -> RegisterFile {
+        RegisterFile { registers: [0; REGISTERS_COUNT], count: REGISTERS_COUNT }
+    }
+
+    /// Register file of the embedded base ISA with x0-x15 only
+    pub fn embedded() -> RegisterFile {
+        RegisterFile { registers: [0; REGISTERS_COUNT], count: EMBEDDED_REGISTERS_COUNT }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_valid(&self, idx: usize) -> bool {
+        idx < self.count
+    }
+
+    /// Value of x`idx`, None if the register does not exist
+    pub fn get(&self, idx: usize) -> Option<TReg> {
+        self.is_valid(idx).then(|| self.registers[idx])
+    }
+
+    /// Writes x`idx`, writes to x0 are discarded. Returns false if the register does not exist.
+    pub fn set(&mut self, idx: usize, value: TReg) -> bool {
+        if !self.is_valid(idx) {
+            return false;
+        }
+        if idx != 0 {
+            self.registers[idx] = value;
+        }
+        true
+    }
+
+    /// Index and value of every register
+    pub fn iter(&self) -> impl Iterator<Item = (usize, TReg)> + '_ {
+        self.registers[..self.count].iter().copied().enumerate()
+    }
+
+    pub fn abi_name(idx: usize) -> Option<&'static str> {
+        ABI_NAMES.get(idx).copied()
+    }
+
+    /// Register index of an ABI name (incl. "fp" for s0) or of an architectural name ("x5")
+    pub fn lookup(name: &str) -> Option<usize> {
+        if name == "fp" {
+            return Some(8);
+        }
+        if let Some(idx) = ABI_NAMES.iter().position(|abi_name| *abi_name == name) {
+            return Some(idx);
+        }
+        name.strip_prefix('x')
+            .filter(|digits| !digits.starts_with('0') || *digits == "0")
+            .and_then(|digits| digits.parse::<usize>().ok())
+            .filter(|idx| *idx < REGISTERS_COUNT)
+    }
+
+    /// Checks the integer register fields of an (uncompressed) instruction against the register count,
+    /// the embedded ISA reserves encodings which name x16-x31.
+    pub fn accepts(&self, instr: TInstr) -> bool {
+        let rd = ((instr >> 7) & 0x1f) as usize;
+        let rs1 = ((instr >> 15) & 0x1f) as usize;
+        let rs2 = ((instr >> 20) & 0x1f) as usize;
+        let func3 = (instr >> 12) & 0x7;
+        let func7 = instr >> 25;
+        let fields: &[usize] = match instr & 0x7f {
+            0b0110111 | 0b0010111 | 0b1101111 => &[rd], // LUI, AUIPC, JAL
+            0b1100111 | 0b0000011 | 0b0010011 | 0b0011011 => &[rd, rs1], // JALR, loads, OP-IMM(-32)
+            0b0100011 | 0b1100011 => &[rs1, rs2], // stores, branches
+            0b0110011 | 0b0111011 | 0b0101111 => &[rd, rs1, rs2], // OP(-32), AMO
+            0b0000111 | 0b0100111 => &[rs1], // FP loads and stores
+            // OP-FP: only comparisons, moves and conversions between the register files use integer registers
+            0b1010011 => match func7 >> 2 {
+                0b10100 | 0b11100 | 0b11000 => &[rd], // FEQ/FLT/FLE, FMV.X.W/FCLASS, FCVT.W[U]/L[U]
+                0b11010 | 0b11110 => &[rs1], // FCVT.fmt.W[U]/L[U], FMV.W.X
+                _ => &[],
+            },
+            0b1110011 if func3 == 0 && func7 == 0b0001001 => &[rs1, rs2], // SFENCE.VMA, rs2 holds the ASID
+            0b1110011 if func3 & 0b100 != 0 => &[rd], // CSR immediate forms, rs1 holds the immediate
+            0b1110011 => &[rd, rs1],
+            _ => &[], // FMA only uses floating-point registers
+        };
+        fields.iter().all(|idx| self.is_valid(*idx))
+    }
+}
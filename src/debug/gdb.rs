@@ -0,0 +1,394 @@
+use std::collections::BTreeSet;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::cpu::basic_cpu::*;
+use crate::cpu::error::ExecError;
+use crate::devices::htif::Htif;
+use log::{info, warn};
+
+// GDB register numbers of the RISC-V target
+pub const GDB_REG_PC: usize = 32;
+pub const GDB_REG_F0: usize = 33;
+pub const GDB_REG_CSR0: usize = 65; // CSR n is register 65 + n
+pub const GDB_REG_PRIV: usize = GDB_REG_CSR0 + CSR_COUNT;
+
+// Stop signals
+pub const SIGINT: u8 = 2;
+pub const SIGILL: u8 = 4;
+pub const SIGTRAP: u8 = 5;
+pub const SIGBUS: u8 = 7;
+pub const SIGSEGV: u8 = 11;
+
+/// Steps between two checks for a Ctrl-C from the debugger while continuing
+const INTERRUPT_POLL_STEPS: u64 = 1024;
+
+// CSRs described in the target XML (name, number)
+const GDB_CSRS: [(&str, usize); 18] = [
+    ("sstatus", CSR_SSTATUS), ("sie", CSR_SIE), ("stvec", CSR_STVEC), ("sscratch", CSR_SSCRATCH),
+    ("sepc", CSR_SEPC), ("scause", CSR_SCAUSE), ("stval", CSR_STVAL), ("sip", CSR_SIP), ("satp", CSR_SATP),
+    ("mstatus", CSR_MSTATUS), ("medeleg", CSR_MEDELEG), ("mideleg", CSR_MIDELEG), ("mie", CSR_MIE),
+    ("mtvec", CSR_MTVEC), ("mepc", CSR_MEPC), ("mcause", CSR_MCAUSE), ("mtval", CSR_MTVAL), ("mip", CSR_MIP),
+];
+
+const GPR_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "fp", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+const FPR_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2", "fa3", "fa4", "fa5",
+    "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9", "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+/// Target description sent to GDB: general purpose registers, FPU, the CSRs above and the privilege mode.
+pub fn target_xml() -> String {
+    let reg = |name: &str, regnum: usize, kind: &str| format!("<reg name=\"{name}\" bitsize=\"64\" regnum=\"{regnum}\" type=\"{kind}\"/>");
+    let mut xml = String::from("<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\"><target><architecture>riscv:rv64</architecture>");
+    xml += "<feature name=\"org.gnu.gdb.riscv.cpu\">";
+    for (i, name) in GPR_NAMES.iter().enumerate() {
+        xml += &reg(name, i, if i == 1 { "code_ptr" } else if i == 2 { "data_ptr" } else { "int" });
+    }
+    xml += &reg("pc", GDB_REG_PC, "code_ptr");
+    xml += "</feature><feature name=\"org.gnu.gdb.riscv.fpu\">";
+    for (i, name) in FPR_NAMES.iter().enumerate() {
+        xml += &reg(name, GDB_REG_F0 + i, "ieee_double");
+    }
+    for (name, csr) in [("fflags", CSR_FFLAGS), ("frm", CSR_FRM), ("fcsr", CSR_FCSR)] {
+        xml += &reg(name, GDB_REG_CSR0 + csr, "int");
+    }
+    xml += "</feature><feature name=\"org.gnu.gdb.riscv.csr\">";
+    for (name, csr) in GDB_CSRS {
+        xml += &reg(name, GDB_REG_CSR0 + csr, "int");
+    }
+    xml += "</feature><feature name=\"org.gnu.gdb.riscv.virtual\">";
+    xml += &reg("priv", GDB_REG_PRIV, "int");
+    xml += "</feature></target>";
+    xml
+}
+
+/// Modulo 256 sum of the packet data
+pub fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+pub fn encode_packet(data: &str) -> String {
+    format!("${data}#{:02x}", checksum(data))
+}
+
+// Registers are transferred as little-endian hex
+fn encode_reg(value: TReg) -> String {
+    format!("{:016x}", value.swap_bytes())
+}
+
+fn decode_reg(hex: &str) -> Option<TReg> {
+    if hex.len() != 16 {
+        return None;
+    }
+    u64::from_str_radix(hex, 16).ok().map(u64::swap_bytes)
+}
+
+fn decode_hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    // Slicing at byte offsets needs ASCII, packets are decoded lossily and may contain multi-byte characters
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
+
+/// "addr,len" with both numbers in hex
+fn parse_addr_len(args: &str) -> Option<(u64, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((u64::from_str_radix(addr, 16).ok()?, usize::from_str_radix(len, 16).ok()?))
+}
+
+fn signal_for(err: &ExecError) -> u8 {
+    match err {
+        ExecError::IllegalInstruction { .. } | ExecError::UnimplementedExtension { .. } => SIGILL,
+        ExecError::MemoryFault { .. } | ExecError::PageFault { .. } => SIGSEGV,
+        ExecError::Misaligned { .. } => SIGBUS,
+        ExecError::EnvironmentCall { .. } | ExecError::Breakpoint { .. } => SIGTRAP,
+    }
+}
+
+/// GDB remote serial protocol stub controlling a `BasicCpu`.
+pub struct GdbStub {
+    breakpoints: BTreeSet<TReg>,
+    /// Lets the stub report the exit of programs using HTIF
+    pub htif: Option<Htif>,
+    /// Exit code of the program once it exited through HTIF or a syscall
+    pub exit_code: Option<i64>,
+}
+
+impl Default for GdbStub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GdbStub {
+    pub fn new() -> GdbStub {
+        GdbStub { breakpoints: BTreeSet::new(), htif: None, exit_code: None }
+    }
+
+    fn read_register(&self, cpu: &BasicCpu, regnum: usize) -> Option<TReg> {
+        match regnum {
+            0..GDB_REG_PC => Some(cpu.get_register(regnum)),
+            GDB_REG_PC => Some(cpu.get_pc()),
+            GDB_REG_F0..GDB_REG_CSR0 => Some(cpu.get_fregister(regnum - GDB_REG_F0)),
+            GDB_REG_CSR0..GDB_REG_PRIV => Some(cpu.get_csr(regnum - GDB_REG_CSR0)),
+            GDB_REG_PRIV => Some(cpu.get_privilege() as TReg),
+            _ => None,
+        }
+    }
+
+    fn write_register(&self, cpu: &mut BasicCpu, regnum: usize, value: TReg) -> bool {
+        match regnum {
+            0 => {}, // x0 is hardwired to zero
+            1..GDB_REG_PC => cpu.set_register(regnum, value),
+            GDB_REG_PC => cpu.set_pc(value),
+            GDB_REG_F0..GDB_REG_CSR0 => cpu.set_fregister(regnum - GDB_REG_F0, value),
+            GDB_REG_CSR0..GDB_REG_PRIV => cpu.set_csr(regnum - GDB_REG_CSR0, value),
+            GDB_REG_PRIV => cpu.set_privilege(Privilege::from_bits(value)),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Executes one instruction (or takes one trap), returns the stop reply if execution has to stop.
+    fn step_once(&mut self, cpu: &mut BasicCpu) -> Option<String> {
+        if let Some(trap) = cpu.step()
+            && !trap.handled {
+            info!("GDB: stopped by {}", trap.exception);
+            return Some(format!("S{:02x}", signal_for(&trap.exception)));
+        }
+        let htif_code = self.htif.as_mut().and_then(|htif| htif.poll(&mut cpu.bus.dram));
+        let code = cpu.exit_code().or(htif_code.map(|code| code as i64))?;
+        self.exit_code = Some(code);
+        Some(format!("W{:02x}", code as u8))
+    }
+
+    fn resume(&mut self, cpu: &mut BasicCpu, args: &str, single_step: bool, interrupted: &mut dyn FnMut() -> bool) -> String {
+        if let Ok(addr) = u64::from_str_radix(args, 16) {
+            cpu.set_pc(addr);
+        }
+        // The first instruction is executed even if there is a breakpoint at the current PC
+        if let Some(reply) = self.step_once(cpu) {
+            return reply;
+        }
+        if single_step {
+            return format!("S{SIGTRAP:02x}");
+        }
+        let mut steps = 0u64;
+        loop {
+            if self.breakpoints.contains(&cpu.get_pc()) {
+                info!("GDB: breakpoint at {:#x}", cpu.get_pc());
+                return format!("T{SIGTRAP:02x}swbreak:;");
+            }
+            steps += 1;
+            if steps.is_multiple_of(INTERRUPT_POLL_STEPS) && interrupted() {
+                return format!("S{SIGINT:02x}");
+            }
+            if let Some(reply) = self.step_once(cpu) {
+                return reply;
+            }
+        }
+    }
+
+    fn read_memory(&self, cpu: &mut BasicCpu, args: &str) -> String {
+        let Some((addr, len)) = parse_addr_len(args) else {
+            return "E01".to_string();
+        };
+        let mut data = String::new();
+        for i in 0..len as u64 {
+            match cpu.debug_read_byte(addr.wrapping_add(i)) {
+                Ok(byte) => data += &format!("{byte:02x}"),
+                Err(_) => break,
+            }
+        }
+        // Partial reads are allowed, an error is only reported if nothing could be read
+        if data.is_empty() && len > 0 { "E14".to_string() } else { data }
+    }
+
+    fn write_memory(&self, cpu: &mut BasicCpu, args: &str) -> String {
+        let Some((range, hex)) = args.split_once(':') else {
+            return "E01".to_string();
+        };
+        let (Some((addr, len)), Some(bytes)) = (parse_addr_len(range), decode_hex_bytes(hex)) else {
+            return "E01".to_string();
+        };
+        if bytes.len() != len {
+            return "E01".to_string();
+        }
+        for (i, byte) in bytes.iter().enumerate() {
+            if cpu.debug_write_byte(addr.wrapping_add(i as u64), *byte).is_err() {
+                return "E14".to_string();
+            }
+        }
+        "OK".to_string()
+    }
+
+    fn breakpoint(&mut self, args: &str, insert: bool) -> String {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(addr)) = (fields.next(), fields.next().and_then(|addr| u64::from_str_radix(addr, 16).ok())) else {
+            return "E01".to_string();
+        };
+        // Software and hardware breakpoints are handled the same way, watchpoints are not supported
+        if kind != "0" && kind != "1" {
+            return String::new();
+        }
+        if insert {
+            self.breakpoints.insert(addr);
+        } else {
+            self.breakpoints.remove(&addr);
+        }
+        "OK".to_string()
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return "PacketSize=4000;qXfer:features:read+;swbreak+".to_string();
+        }
+        if let Some(args) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, len)) = parse_addr_len(args) else {
+                return "E01".to_string();
+            };
+            let xml = target_xml();
+            let chunk = xml.get(offset.min(xml.len() as u64) as usize..).unwrap_or("");
+            return if chunk.len() > len { format!("m{}", &chunk[..len]) } else { format!("l{chunk}") };
+        }
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Handles the data of one packet and returns the reply data (empty for unsupported commands).
+    /// `interrupted` is polled while continuing and returns true when the debugger asked to stop.
+    pub fn handle_command(&mut self, cpu: &mut BasicCpu, packet: &str, interrupted: &mut dyn FnMut() -> bool) -> String {
+        info!("GDB: <- {packet}");
+        let Some(command) = packet.chars().next() else {
+            return String::new();
+        };
+        let args = &packet[command.len_utf8()..];
+        match command {
+            '?' => format!("S{SIGTRAP:02x}"),
+            'g' => (0..=GDB_REG_PC).map(|regnum| encode_reg(self.read_register(cpu, regnum).unwrap_or(0))).collect(),
+            'G' => {
+                for regnum in 0..=GDB_REG_PC {
+                    match args.get(regnum * 16..regnum * 16 + 16).and_then(decode_reg) {
+                        Some(value) => { self.write_register(cpu, regnum, value); },
+                        None => break,
+                    }
+                }
+                "OK".to_string()
+            },
+            'p' => match usize::from_str_radix(args, 16).ok().and_then(|regnum| self.read_register(cpu, regnum)) {
+                Some(value) => encode_reg(value),
+                None => "E01".to_string(),
+            },
+            'P' => {
+                let register = args.split_once('=')
+                    .and_then(|(regnum, value)| Some((usize::from_str_radix(regnum, 16).ok()?, decode_reg(value)?)));
+                match register {
+                    Some((regnum, value)) if self.write_register(cpu, regnum, value) => "OK".to_string(),
+                    _ => "E01".to_string(),
+                }
+            },
+            'm' => self.read_memory(cpu, args),
+            'M' => self.write_memory(cpu, args),
+            'Z' => self.breakpoint(args, true),
+            'z' => self.breakpoint(args, false),
+            'c' => self.resume(cpu, args, false, interrupted),
+            's' => self.resume(cpu, args, true, interrupted),
+            'H' => "OK".to_string(),
+            'T' => "OK".to_string(), // the only thread is always alive
+            'D' => "OK".to_string(),
+            'q' => self.query(args),
+            _ => String::new(),
+        }
+    }
+
+    /// Serves one debugger connection until it detaches, kills the target or disconnects.
+    pub fn run(&mut self, cpu: &mut BasicCpu, mut stream: TcpStream) -> io::Result<()> {
+        let _ = stream.set_nodelay(true);
+        while let Some(packet) = read_packet(&mut stream)? {
+            if packet == "k" {
+                info!("GDB: kill request");
+                break;
+            }
+            let poll_stream = stream.try_clone()?;
+            let reply = self.handle_command(cpu, &packet, &mut || interrupt_requested(&poll_stream));
+            info!("GDB: -> {reply}");
+            stream.write_all(encode_packet(&reply).as_bytes())?;
+            if packet == "D" {
+                info!("GDB: debugger detached");
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks without blocking whether the debugger sent a Ctrl-C (0x03)
+fn interrupt_requested(mut stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let mut byte = [0u8];
+    let interrupted = matches!(stream.read(&mut byte), Ok(1) if byte[0] == 0x03);
+    let _ = stream.set_nonblocking(false);
+    interrupted
+}
+
+/// Reads the next "$data#cs" packet and acknowledges it, None once the connection is closed.
+fn read_packet(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    let mut byte = [0u8];
+    loop {
+        // Skip acknowledgements and interrupts received while halted
+        loop {
+            match stream.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) if byte[0] == b'$' => break,
+                Ok(_) => continue,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        let mut data = Vec::new();
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut sum = [0u8; 2];
+        stream.read_exact(&mut sum)?;
+        let data = String::from_utf8_lossy(&data).into_owned();
+        let expected = std::str::from_utf8(&sum).ok().and_then(|sum| u8::from_str_radix(sum, 16).ok());
+        if expected == Some(checksum(&data)) {
+            stream.write_all(b"+")?;
+            return Ok(Some(data));
+        }
+        warn!("GDB: bad checksum for packet {data}");
+        stream.write_all(b"-")?;
+    }
+}
+
+/// Waits for a debugger on localhost:`port` and serves it, returns the exit code if the program exited.
+pub fn serve(cpu: &mut BasicCpu, port: u16, htif: Option<Htif>) -> io::Result<Option<i64>> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("Waiting for GDB on localhost:{port}...");
+    let (stream, peer) = listener.accept()?;
+    info!("GDB: connection from {peer}");
+    let mut stub = GdbStub::new();
+    stub.htif = htif;
+    stub.run(cpu, stream)?;
+    Ok(stub.exit_code)
+}
@@ -0,0 +1,91 @@
+use crate::cpu::basic_cpu::{MIP_MSIP, MIP_MTIP, TReg};
+use crate::memory::bus::{AccessFault, Device};
+use log::warn;
+
+pub const CLINT_BASE: u64 = 0x0200_0000;
+pub const CLINT_SIZE: u64 = 0x10000;
+
+// Register offsets (single hart)
+pub const CLINT_MSIP: u64 = 0x0000;
+pub const CLINT_MTIMECMP: u64 = 0x4000;
+pub const CLINT_MTIME: u64 = 0xbff8;
+
+/// Core-local interruptor: software interrupt (msip) and machine timer (mtime/mtimecmp) of hart 0.
+/// mtime advances by one every CPU step.
+pub struct Clint {
+    pub msip: u32,
+    pub mtimecmp: u64,
+    pub mtime: u64,
+}
+
+impl Default for Clint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clint {
+    pub fn new() -> Clint {
+        Clint {
+            msip: 0,
+            mtimecmp: u64::MAX, // no timer interrupt until software programs mtimecmp
+            mtime: 0,
+        }
+    }
+}
+
+// 64-bit registers can be accessed as a whole or as two 32-bit halves
+fn read_half(value: u64, offset: u64, size: usize) -> Result<u64, AccessFault> {
+    match (offset, size) {
+        (0, 64) => Ok(value),
+        (0, 32) => Ok(value & 0xffff_ffff),
+        (4, 32) => Ok(value >> 32),
+        _ => Err(AccessFault),
+    }
+}
+
+fn write_half(current: u64, offset: u64, size: usize, value: u64) -> Result<u64, AccessFault> {
+    match (offset, size) {
+        (0, 64) => Ok(value),
+        (0, 32) => Ok((current & !0xffff_ffff) | (value & 0xffff_ffff)),
+        (4, 32) => Ok((current & 0xffff_ffff) | (value << 32)),
+        _ => Err(AccessFault),
+    }
+}
+
+impl Device for Clint {
+    fn read(&mut self, offset: u64, size: usize) -> Result<u64, AccessFault> {
+        match offset {
+            CLINT_MSIP if size == 32 => Ok(self.msip as u64),
+            CLINT_MTIMECMP..=0x4007 => read_half(self.mtimecmp, offset - CLINT_MTIMECMP, size),
+            CLINT_MTIME..=0xbfff => read_half(self.mtime, offset - CLINT_MTIME, size),
+            _ => {
+                warn!("Invalid {size} bit CLINT read at offset {offset:#x}");
+                Err(AccessFault)
+            },
+        }
+    }
+
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> Result<(), AccessFault> {
+        match offset {
+            CLINT_MSIP if size == 32 => self.msip = value as u32 & 1,
+            CLINT_MTIMECMP..=0x4007 => self.mtimecmp = write_half(self.mtimecmp, offset - CLINT_MTIMECMP, size, value)?,
+            CLINT_MTIME..=0xbfff => self.mtime = write_half(self.mtime, offset - CLINT_MTIME, size, value)?,
+            _ => {
+                warn!("Invalid {size} bit CLINT write at offset {offset:#x}");
+                return Err(AccessFault);
+            },
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.mtime = self.mtime.wrapping_add(1);
+    }
+
+    fn mip(&self) -> TReg {
+        let msip = if self.msip & 1 != 0 { MIP_MSIP } else { 0 };
+        let mtip = if self.mtime >= self.mtimecmp { MIP_MTIP } else { 0 };
+        msip | mtip
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::{self, Write};
+
+use crate::memory::dram::DramMemory;
+use log::{info, warn};
+
+// tohost command layout: device [63:56], command [55:48], payload [47:0]
+pub const HTIF_DEV_SYSCALL: u64 = 0;
+pub const HTIF_DEV_CONSOLE: u64 = 1;
+pub const HTIF_CONSOLE_PUTCHAR: u64 = 1;
+
+// Proxied syscalls (riscv-pk numbering)
+pub const HTIF_SYS_WRITE: u64 = 64;
+pub const HTIF_SYS_EXIT: u64 = 93;
+const EBADF: i64 = 9;
+const ENOSYS: i64 = 38;
+
+/// Host-Target Interface of Spike: the guest writes commands to the `tohost` word in memory,
+/// the host answers through `fromhost`. Used by riscv-tests to report the test result.
+pub struct Htif {
+    tohost: usize,
+    fromhost: Option<usize>,
+    output: Box<dyn Write>,
+    exit_code: Option<u64>,
+}
+
+impl Htif {
+    /// `tohost` and `fromhost` are the physical addresses of the symbols with the same names
+    pub fn new(tohost: u64, fromhost: Option<u64>) -> Htif {
+        Htif::with_output(tohost, fromhost, Box::new(io::stdout()))
+    }
+
+    pub fn with_output(tohost: u64, fromhost: Option<u64>, output: Box<dyn Write>) -> Htif {
+        info!("HTIF: tohost at {:#x}, fromhost at {:#x?}", tohost, fromhost);
+        Htif {
+            tohost: tohost as usize,
+            fromhost: fromhost.map(|addr| addr as usize),
+            output,
+            exit_code: None,
+        }
+    }
+
+    /// Exit code of the guest once it has requested to terminate
+    pub fn exit_code(&self) -> Option<u64> {
+        self.exit_code
+    }
+
+    /// Handles a pending tohost command, to be called after every step. Returns the exit code once the guest exits.
+    pub fn poll(&mut self, dram: &mut DramMemory) -> Option<u64> {
+        if self.exit_code.is_some() || !dram.contains(self.tohost, 64) {
+            return self.exit_code;
+        }
+        let tohost = dram.dram_read(self.tohost, 64);
+        if tohost == 0 {
+            return None;
+        }
+        dram.dram_write(self.tohost, 64, 0);
+
+        let device = tohost >> 56;
+        let command = (tohost >> 48) & 0xff;
+        let payload = tohost & 0xffff_ffff_ffff;
+        info!("HTIF: device {device} command {command} payload {payload:#x}");
+        match (device, command) {
+            (HTIF_DEV_SYSCALL, 0) if payload & 1 == 1 => {
+                self.exit_code = Some(payload >> 1);
+                return self.exit_code;
+            },
+            (HTIF_DEV_SYSCALL, 0) => {
+                self.syscall(dram, payload as usize);
+                if self.exit_code.is_none() {
+                    self.respond(dram, 1);
+                }
+                return self.exit_code;
+            },
+            (HTIF_DEV_CONSOLE, HTIF_CONSOLE_PUTCHAR) => {
+                let _ = self.output.write_all(&[payload as u8]);
+                let _ = self.output.flush();
+            },
+            _ => warn!("HTIF: unsupported command {command} for device {device}"),
+        }
+        self.respond(dram, (device << 56) | (command << 48));
+        None
+    }
+
+    fn respond(&mut self, dram: &mut DramMemory, value: u64) {
+        if let Some(fromhost) = self.fromhost.filter(|addr| dram.contains(*addr, 64)) {
+            dram.dram_write(fromhost, 64, value);
+        }
+    }
+
+    /// Executes the syscall described by the `magic_mem` array at `addr` ([number, arg0, arg1, ...]),
+    /// the return value replaces the syscall number.
+    fn syscall(&mut self, dram: &mut DramMemory, addr: usize) {
+        if !dram.contains(addr, 64 * 4) {
+            warn!("HTIF: syscall arguments at {addr:#x} are outside of DRAM");
+            return;
+        }
+        let arg = |i: usize| dram.dram_read(addr + 8 * i, 64);
+        let (number, args) = (arg(0), [arg(1), arg(2), arg(3)]);
+        info!("HTIF: syscall {number} {args:x?}");
+        let result = match number {
+            HTIF_SYS_WRITE => {
+                let (buf, len) = (args[1] as usize, args[2] as usize);
+                if (args[0] == 1 || args[0] == 2) && dram.contains(buf, len.saturating_mul(8)) {
+                    let bytes: Vec<u8> = (0..len).map(|i| dram.dram_read(buf + i, 8) as u8).collect();
+                    let _ = self.output.write_all(&bytes);
+                    let _ = self.output.flush();
+                    len as i64
+                } else {
+                    -EBADF
+                }
+            },
+            HTIF_SYS_EXIT => {
+                self.exit_code = Some(args[0]);
+                0
+            },
+            _ => {
+                warn!("HTIF: unsupported syscall {number}");
+                -ENOSYS
+            },
+        };
+        dram.dram_write(addr, 64, result as u64);
+    }
+}
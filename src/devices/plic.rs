@@ -0,0 +1,159 @@
+use crate::cpu::basic_cpu::{MIP_MEIP, MIP_SEIP, TReg};
+use crate::memory::bus::{AccessFault, Device};
+use log::{info, warn};
+
+pub const PLIC_BASE: u64 = 0x0c00_0000;
+pub const PLIC_SIZE: u64 = 0x0400_0000;
+pub const PLIC_SOURCES: usize = 32; // source 0 is reserved, 1..=31 are usable
+pub const PLIC_CONTEXTS: usize = 2; // hart 0 M-mode and S-mode
+
+// Register windows (SiFive layout)
+pub const PLIC_PRIORITY: u64 = 0x00_0000; // 4 bytes per source
+pub const PLIC_PENDING: u64 = 0x00_1000; // 1 bit per source
+pub const PLIC_ENABLE: u64 = 0x00_2000; // 0x80 bytes per context
+pub const PLIC_ENABLE_STRIDE: u64 = 0x80;
+pub const PLIC_CONTEXT: u64 = 0x20_0000; // threshold at +0, claim/complete at +4, 0x1000 bytes per context
+pub const PLIC_CONTEXT_STRIDE: u64 = 0x1000;
+pub const PLIC_PRIORITY_MASK: u32 = 0x7;
+
+/// Platform-level interrupt controller with level-triggered gateways.
+pub struct Plic {
+    priority: [u32; PLIC_SOURCES],
+    pending: u32, // one bit per source
+    in_flight: u32, // claimed and not completed yet
+    lines: u32, // current level of the source interrupt lines
+    enable: [u32; PLIC_CONTEXTS],
+    threshold: [u32; PLIC_CONTEXTS],
+}
+
+impl Default for Plic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plic {
+    pub fn new() -> Plic {
+        Plic {
+            priority: [0; PLIC_SOURCES],
+            pending: 0,
+            in_flight: 0,
+            lines: 0,
+            enable: [0; PLIC_CONTEXTS],
+            threshold: [0; PLIC_CONTEXTS],
+        }
+    }
+
+    /// Highest priority source which is pending, enabled for the context and above its threshold (ties go to the lowest id).
+    fn best_source(&self, context: usize) -> Option<usize> {
+        let candidates = self.pending & self.enable[context];
+        (1..PLIC_SOURCES)
+            .filter(|source| candidates & (1 << source) != 0 && self.priority[*source] > self.threshold[context])
+            .max_by_key(|source| (self.priority[*source], std::cmp::Reverse(*source)))
+    }
+
+    fn claim(&mut self, context: usize) -> u32 {
+        match self.best_source(context) {
+            Some(source) => {
+                info!("PLIC context {context} claims source {source}");
+                self.pending &= !(1 << source);
+                self.in_flight |= 1 << source;
+                source as u32
+            },
+            None => 0,
+        }
+    }
+
+    fn complete(&mut self, context: usize, source: u32) {
+        let source = source as usize;
+        if (1..PLIC_SOURCES).contains(&source) && self.enable[context] & (1 << source) != 0 {
+            info!("PLIC context {context} completes source {source}");
+            self.in_flight &= !(1 << source);
+            self.update_pending();
+        }
+    }
+
+    fn update_pending(&mut self) {
+        // Level-triggered gateways: a source is pending while its line is high and no request of it is in flight
+        self.pending = self.lines & !self.in_flight & !1;
+    }
+}
+
+impl Device for Plic {
+    fn read(&mut self, offset: u64, size: usize) -> Result<u64, AccessFault> {
+        if size != 32 {
+            warn!("Unsupported {size} bit PLIC read at offset {offset:#x}");
+            return Err(AccessFault);
+        }
+        let value = match offset {
+            PLIC_PRIORITY..PLIC_PENDING => {
+                let source = ((offset - PLIC_PRIORITY) / 4) as usize;
+                self.priority.get(source).copied().unwrap_or(0)
+            },
+            PLIC_PENDING => self.pending,
+            PLIC_ENABLE..PLIC_CONTEXT => {
+                let context = ((offset - PLIC_ENABLE) / PLIC_ENABLE_STRIDE) as usize;
+                if (offset - PLIC_ENABLE).is_multiple_of(PLIC_ENABLE_STRIDE) { self.enable.get(context).copied().unwrap_or(0) } else { 0 }
+            },
+            PLIC_CONTEXT.. => {
+                let context = ((offset - PLIC_CONTEXT) / PLIC_CONTEXT_STRIDE) as usize;
+                if context >= PLIC_CONTEXTS {
+                    return Err(AccessFault);
+                }
+                match (offset - PLIC_CONTEXT) % PLIC_CONTEXT_STRIDE {
+                    0 => self.threshold[context],
+                    4 => self.claim(context),
+                    _ => 0,
+                }
+            },
+            _ => 0,
+        };
+        Ok(value as u64)
+    }
+
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> Result<(), AccessFault> {
+        if size != 32 {
+            warn!("Unsupported {size} bit PLIC write at offset {offset:#x}");
+            return Err(AccessFault);
+        }
+        let value = value as u32;
+        match offset {
+            PLIC_PRIORITY..PLIC_PENDING => {
+                let source = ((offset - PLIC_PRIORITY) / 4) as usize;
+                if (1..PLIC_SOURCES).contains(&source) {
+                    self.priority[source] = value & PLIC_PRIORITY_MASK;
+                }
+            },
+            PLIC_ENABLE..PLIC_CONTEXT => {
+                let context = ((offset - PLIC_ENABLE) / PLIC_ENABLE_STRIDE) as usize;
+                if context < PLIC_CONTEXTS && (offset - PLIC_ENABLE).is_multiple_of(PLIC_ENABLE_STRIDE) {
+                    self.enable[context] = value & !1; // source 0 does not exist
+                }
+            },
+            PLIC_CONTEXT.. => {
+                let context = ((offset - PLIC_CONTEXT) / PLIC_CONTEXT_STRIDE) as usize;
+                if context >= PLIC_CONTEXTS {
+                    return Err(AccessFault);
+                }
+                match (offset - PLIC_CONTEXT) % PLIC_CONTEXT_STRIDE {
+                    0 => self.threshold[context] = value & PLIC_PRIORITY_MASK,
+                    4 => self.complete(context, value),
+                    _ => {},
+                }
+            },
+            _ => info!("Ignoring write of {value:#x} to PLIC offset {offset:#x}"), // pending bits are read-only
+        }
+        Ok(())
+    }
+
+    fn set_irq_lines(&mut self, lines: u64) {
+        self.lines = lines as u32;
+        self.update_pending();
+    }
+
+    fn mip(&self) -> TReg {
+        let meip = if self.best_source(0).is_some() { MIP_MEIP } else { 0 };
+        let seip = if self.best_source(1).is_some() { MIP_SEIP } else { 0 };
+        meip | seip
+    }
+}
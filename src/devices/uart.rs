@@ -0,0 +1,237 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::memory::bus::{AccessFault, Device};
+use log::{info, warn};
+
+pub const UART_BASE: u64 = 0x1000_0000;
+pub const UART_SIZE: u64 = 0x100;
+pub const UART_IRQ: u32 = 10; // PLIC source of the conventional (QEMU virt) memory map
+pub const UART_FIFO_SIZE: usize = 16;
+
+// Register offsets, RBR/THR/IER are replaced by the divisor latch when LCR.DLAB is set
+pub const UART_RBR: u64 = 0; // Receiver buffer (read)
+pub const UART_THR: u64 = 0; // Transmitter holding (write)
+pub const UART_IER: u64 = 1; // Interrupt enable
+pub const UART_IIR: u64 = 2; // Interrupt identification (read)
+pub const UART_FCR: u64 = 2; // FIFO control (write)
+pub const UART_LCR: u64 = 3; // Line control
+pub const UART_MCR: u64 = 4; // Modem control
+pub const UART_LSR: u64 = 5; // Line status
+pub const UART_MSR: u64 = 6; // Modem status
+pub const UART_SCR: u64 = 7; // Scratch
+
+pub const IER_RDI: u8 = 1 << 0; // Received data available
+pub const IER_THRI: u8 = 1 << 1; // Transmitter holding register empty
+pub const IIR_NO_INT: u8 = 0x01;
+pub const IIR_THRI: u8 = 0x02;
+pub const IIR_RDI: u8 = 0x04;
+pub const IIR_FIFO_ENABLED: u8 = 0xc0;
+pub const FCR_ENABLE: u8 = 1 << 0;
+pub const FCR_CLEAR_RX: u8 = 1 << 1;
+pub const LCR_DLAB: u8 = 1 << 7;
+pub const LSR_DR: u8 = 1 << 0; // Data ready
+pub const LSR_THRE: u8 = 1 << 5; // THR empty
+pub const LSR_TEMT: u8 = 1 << 6; // Transmitter empty
+
+/// Host side of the serial line.
+pub trait UartBackend {
+    fn write_byte(&mut self, byte: u8);
+    /// Returns the next received byte without blocking
+    fn read_byte(&mut self) -> Option<u8>;
+}
+
+/// Backend writing to the host stdout and reading stdin from a background thread.
+/// The thread is started on the first read, so stdin stays untouched while the guest does not use the receiver.
+pub struct StdioBackend {
+    input: Option<Receiver<u8>>,
+}
+
+impl Default for StdioBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StdioBackend {
+    pub fn new() -> StdioBackend {
+        StdioBackend { input: None }
+    }
+
+    fn spawn_reader() -> Receiver<u8> {
+        let (sender, input) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 64];
+            loop {
+                match io::stdin().read(&mut buffer) {
+                    Ok(0) | Err(_) => break, // stdin closed
+                    Ok(len) => {
+                        if buffer[..len].iter().any(|byte| sender.send(*byte).is_err()) {
+                            break; // emulator gone
+                        }
+                    },
+                }
+            }
+        });
+        input
+    }
+}
+
+impl UartBackend for StdioBackend {
+    fn write_byte(&mut self, byte: u8) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(&[byte]);
+        let _ = stdout.flush();
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        self.input.get_or_insert_with(Self::spawn_reader).try_recv().ok()
+    }
+}
+
+/// NS16550A compatible UART. Transmission completes immediately, received bytes are buffered in the RX FIFO.
+pub struct Uart {
+    backend: Box<dyn UartBackend>,
+    rx_fifo: VecDeque<u8>,
+    ier: u8,
+    fcr: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+    dll: u8,
+    dlm: u8,
+    thre_pending: bool, // THR empty interrupt, cleared by reading IIR or writing THR
+    rx_active: bool,    // set on the first RBR/LSR read or RDI enable, the backend is not polled before
+}
+
+impl Uart {
+    pub fn new(backend: Box<dyn UartBackend>) -> Uart {
+        Uart {
+            backend,
+            rx_fifo: VecDeque::new(),
+            ier: 0,
+            fcr: 0,
+            lcr: 0,
+            mcr: 0,
+            scr: 0,
+            dll: 0,
+            dlm: 0,
+            thre_pending: false,
+            rx_active: false,
+        }
+    }
+
+    fn dlab(&self) -> bool {
+        self.lcr & LCR_DLAB != 0
+    }
+
+    fn iir(&self) -> u8 {
+        // Received data has priority over THR empty
+        let fifo = if self.fcr & FCR_ENABLE != 0 { IIR_FIFO_ENABLED } else { 0 };
+        let id = if self.ier & IER_RDI != 0 && !self.rx_fifo.is_empty() {
+            IIR_RDI
+        } else if self.ier & IER_THRI != 0 && self.thre_pending {
+            IIR_THRI
+        } else {
+            IIR_NO_INT
+        };
+        fifo | id
+    }
+
+    fn read_register(&mut self, offset: u64) -> u8 {
+        if matches!(offset, UART_RBR | UART_LSR) && !self.dlab() && !self.rx_active {
+            self.rx_active = true;
+            self.poll_input();
+        }
+        match offset {
+            UART_RBR if self.dlab() => self.dll,
+            UART_RBR => {
+                let byte = self.rx_fifo.pop_front().unwrap_or(0);
+                self.poll_input(); // refill as soon as there is space
+                byte
+            },
+            UART_IER if self.dlab() => self.dlm,
+            UART_IER => self.ier,
+            UART_IIR => {
+                let iir = self.iir();
+                if iir & 0x0f == IIR_THRI {
+                    self.thre_pending = false;
+                }
+                iir
+            },
+            UART_LCR => self.lcr,
+            UART_MCR => self.mcr,
+            UART_LSR => LSR_THRE | LSR_TEMT | if self.rx_fifo.is_empty() { 0 } else { LSR_DR },
+            UART_MSR => 0xb0, // DCD, DSR, CTS asserted
+            UART_SCR => self.scr,
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, offset: u64, value: u8) {
+        match offset {
+            UART_THR if self.dlab() => self.dll = value,
+            UART_THR => {
+                self.backend.write_byte(value);
+                self.thre_pending = true; // transmitted immediately, THR is empty again
+            },
+            UART_IER if self.dlab() => self.dlm = value,
+            UART_IER => {
+                // Enabling the THR empty interrupt raises it right away since THR is always empty
+                if value & IER_THRI != 0 && self.ier & IER_THRI == 0 {
+                    self.thre_pending = true;
+                }
+                self.ier = value & 0x0f;
+                self.rx_active |= value & IER_RDI != 0;
+            },
+            UART_FCR => {
+                if value & FCR_CLEAR_RX != 0 {
+                    self.rx_fifo.clear();
+                }
+                self.fcr = value;
+            },
+            UART_LCR => self.lcr = value,
+            UART_MCR => self.mcr = value,
+            UART_SCR => self.scr = value,
+            _ => info!("Ignoring write of {value:#x} to UART register {offset}"),
+        }
+    }
+
+    fn poll_input(&mut self) {
+        while self.rx_active && self.rx_fifo.len() < UART_FIFO_SIZE {
+            match self.backend.read_byte() {
+                Some(byte) => self.rx_fifo.push_back(byte),
+                None => break,
+            }
+        }
+    }
+}
+
+impl Device for Uart {
+    fn read(&mut self, offset: u64, size: usize) -> Result<u64, AccessFault> {
+        if size != 8 {
+            warn!("Unsupported {size} bit UART read at offset {offset}");
+            return Err(AccessFault);
+        }
+        Ok(self.read_register(offset) as u64)
+    }
+
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> Result<(), AccessFault> {
+        if size != 8 {
+            warn!("Unsupported {size} bit UART write at offset {offset}");
+            return Err(AccessFault);
+        }
+        self.write_register(offset, value as u8);
+        Ok(())
+    }
+
+    fn tick(&mut self) {
+        self.poll_input();
+    }
+
+    fn irq(&self) -> bool {
+        self.iir() & 0x0f != IIR_NO_INT
+    }
+}
@@ -1,6 +1,40 @@
 pub mod memory {
+    pub mod bus;
     pub mod dram;
+    pub mod rom;
 }
 pub mod cpu {
     pub mod basic_cpu;
-}
\ No newline at end of file
+    pub mod commit_log;
+    pub mod compressed;
+    pub mod disasm;
+    pub mod error;
+    pub mod fpu;
+    pub mod isa;
+    pub mod mmu;
+    pub mod registers;
+}
+pub mod devices {
+    pub mod clint;
+    pub mod htif;
+    pub mod plic;
+    pub mod uart;
+}
+pub mod loader {
+    pub mod elf;
+    pub mod fdt;
+}
+pub mod testing {
+    pub mod compliance;
+    pub mod cosim;
+}
+pub mod debug {
+    pub mod gdb;
+}
+pub mod os {
+    pub mod host_files;
+    pub mod linux;
+    pub mod pk;
+    pub mod semihosting;
+    pub mod syscall;
+}
@@ -0,0 +1,234 @@
+use std::fmt;
+
+use crate::memory::dram::DramMemory;
+use log::info;
+
+// ELF header constants
+pub const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+pub const ELFCLASS32: u8 = 1;
+pub const ELFCLASS64: u8 = 2;
+pub const ELFDATA2LSB: u8 = 1;
+pub const ET_EXEC: u16 = 2;
+pub const EM_RISCV: u16 = 243;
+pub const PT_LOAD: u32 = 1;
+pub const SHT_SYMTAB: u32 = 2;
+
+const ELF32_EHDR_SIZE: usize = 52;
+
+/// Field offsets and sizes of the ELF32 or ELF64 headers, addresses and offsets are 4 or 8 bytes wide.
+struct Layout {
+    word_size: usize,
+    entry: usize,
+    phoff: usize,
+    shoff: usize,
+    phentsize: usize,
+    phnum: usize,
+    shentsize: usize,
+    shnum: usize,
+    phdr_size: usize,
+    // p_offset, p_vaddr, p_paddr, p_filesz, p_memsz
+    phdr_fields: [usize; 5],
+    shdr_size: usize,
+    sh_offset: usize,
+    sh_size: usize,
+    sh_link: usize,
+    sym_size: usize,
+    st_value: usize,
+}
+
+const ELF32: Layout = Layout {
+    word_size: 4, entry: 24, phoff: 28, shoff: 32, phentsize: 42, phnum: 44, shentsize: 46, shnum: 48,
+    phdr_size: 32, phdr_fields: [4, 8, 12, 16, 20],
+    shdr_size: 40, sh_offset: 16, sh_size: 20, sh_link: 24,
+    sym_size: 16, st_value: 4,
+};
+
+const ELF64: Layout = Layout {
+    word_size: 8, entry: 24, phoff: 32, shoff: 40, phentsize: 54, phnum: 56, shentsize: 58, shnum: 60,
+    phdr_size: 56, phdr_fields: [8, 16, 24, 32, 40],
+    shdr_size: 64, sh_offset: 24, sh_size: 32, sh_link: 40,
+    sym_size: 24, st_value: 8,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElfError {
+    /// File ends before a header or segment it describes
+    Truncated,
+    /// Missing "\x7fELF" magic
+    BadMagic,
+    /// Not a 32 or 64-bit little-endian file
+    UnsupportedFormat { class: u8, data: u8 },
+    /// Not an executable (relocatable objects and shared libraries can not be loaded)
+    NotExecutable { e_type: u16 },
+    /// Not built for RISC-V
+    WrongMachine { e_machine: u16 },
+    /// PT_LOAD segment which does not fit into DRAM
+    SegmentOutOfMemory { paddr: u64, memsz: u64 },
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElfError::Truncated => write!(f, "truncated ELF file"),
+            ElfError::BadMagic => write!(f, "not an ELF file"),
+            ElfError::UnsupportedFormat { class, data } => write!(f, "unsupported ELF format (class {class}, data {data}), expected ELF32 or ELF64 little-endian"),
+            ElfError::NotExecutable { e_type } => write!(f, "ELF type {e_type} is not an executable"),
+            ElfError::WrongMachine { e_machine } => write!(f, "ELF machine {e_machine} is not RISC-V"),
+            ElfError::SegmentOutOfMemory { paddr, memsz } => write!(f, "segment at {paddr:#x} ({memsz:#x} bytes) does not fit into DRAM"),
+        }
+    }
+}
+
+impl std::error::Error for ElfError {}
+
+/// PT_LOAD program header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub paddr: u64,
+    pub vaddr: u64,
+    pub offset: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+}
+
+/// Parsed ELF32 or ELF64 RISC-V executable.
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+    layout: &'static Layout,
+    /// 32 for ELF32 files (RV32 programs), 64 for ELF64
+    pub xlen: u32,
+    pub entry: u64,
+    pub segments: Vec<Segment>,
+    /// File offset, entry size and number of the program headers (AT_PHDR/AT_PHENT/AT_PHNUM of a Linux process)
+    pub phoff: u64,
+    pub phentsize: u16,
+    pub phnum: u16,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ElfError> {
+    let bytes = data.get(offset..offset.saturating_add(2)).ok_or(ElfError::Truncated)?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ElfError> {
+    let bytes = data.get(offset..offset.saturating_add(4)).ok_or(ElfError::Truncated)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, ElfError> {
+    let bytes = data.get(offset..offset.saturating_add(8)).ok_or(ElfError::Truncated)?;
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Address or offset field, 4 bytes in ELF32 and 8 bytes in ELF64
+fn read_word(data: &[u8], offset: usize, layout: &Layout) -> Result<u64, ElfError> {
+    if layout.word_size == 4 { read_u32(data, offset).map(u64::from) } else { read_u64(data, offset) }
+}
+
+/// Bytes from the start of the section header `index` on, None if it lies beyond the end of the file.
+fn section_header(data: &[u8], table_offset: usize, index: usize, entry_size: usize) -> Option<&[u8]> {
+    let offset = index.checked_mul(entry_size).and_then(|offset| offset.checked_add(table_offset))?;
+    data.get(offset..)
+}
+
+impl<'a> ElfFile<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<ElfFile<'a>, ElfError> {
+        if data.len() < ELF32_EHDR_SIZE {
+            return Err(if data.starts_with(&ELF_MAGIC) { ElfError::Truncated } else { ElfError::BadMagic });
+        }
+        if data[0..4] != ELF_MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        let (layout, xlen) = match (data[4], data[5]) {
+            (ELFCLASS32, ELFDATA2LSB) => (&ELF32, 32),
+            (ELFCLASS64, ELFDATA2LSB) => (&ELF64, 64),
+            (class, data) => return Err(ElfError::UnsupportedFormat { class, data }),
+        };
+        let e_type = read_u16(data, 16)?;
+        if e_type != ET_EXEC {
+            return Err(ElfError::NotExecutable { e_type });
+        }
+        let e_machine = read_u16(data, 18)?;
+        if e_machine != EM_RISCV {
+            return Err(ElfError::WrongMachine { e_machine });
+        }
+        let entry = read_word(data, layout.entry, layout)?;
+        let phoff = read_word(data, layout.phoff, layout)? as usize;
+        let phentsize = read_u16(data, layout.phentsize)? as usize;
+        let phnum = read_u16(data, layout.phnum)? as usize;
+
+        let mut segments = Vec::new();
+        for i in 0..phnum {
+            let phdr = phoff.saturating_add(i * phentsize.max(layout.phdr_size));
+            if read_u32(data, phdr)? != PT_LOAD {
+                continue;
+            }
+            let [offset, vaddr, paddr, filesz, memsz] = layout.phdr_fields;
+            let segment = Segment {
+                offset: read_word(data, phdr + offset, layout)?,
+                vaddr: read_word(data, phdr + vaddr, layout)?,
+                paddr: read_word(data, phdr + paddr, layout)?,
+                filesz: read_word(data, phdr + filesz, layout)?,
+                memsz: read_word(data, phdr + memsz, layout)?,
+            };
+            if segment.offset.checked_add(segment.filesz).is_none_or(|end| end > data.len() as u64) {
+                return Err(ElfError::Truncated);
+            }
+            segments.push(segment);
+        }
+        info!("ELF{xlen}: entry {:#x}, {} loadable segments", entry, segments.len());
+        Ok(ElfFile { data, layout, xlen, entry, segments, phoff: phoff as u64, phentsize: phentsize as u16, phnum: phnum as u16 })
+    }
+
+    /// Value of the symbol `name` from the symbol table, None if the file is stripped or does not define it.
+    pub fn symbol(&self, name: &str) -> Option<u64> {
+        let (data, layout) = (self.data, self.layout);
+        let shoff = read_word(data, layout.shoff, layout).ok()? as usize;
+        let shentsize = (read_u16(data, layout.shentsize).ok()? as usize).max(layout.shdr_size);
+        let shnum = read_u16(data, layout.shnum).ok()? as usize;
+        for i in 0..shnum {
+            let shdr = section_header(data, shoff, i, shentsize)?;
+            if read_u32(shdr, 4).ok()? != SHT_SYMTAB {
+                continue;
+            }
+            let symtab = read_word(shdr, layout.sh_offset, layout).ok()? as usize;
+            let symtab_end = symtab.checked_add(read_word(shdr, layout.sh_size, layout).ok()? as usize)?;
+            let strtab_shdr = section_header(data, shoff, read_u32(shdr, layout.sh_link).ok()? as usize, shentsize)?;
+            let strtab = data.get(read_word(strtab_shdr, layout.sh_offset, layout).ok()? as usize..)?;
+            for offset in (symtab..symtab_end).step_by(layout.sym_size) {
+                let sym = data.get(offset..)?;
+                let name_offset = read_u32(sym, 0).ok()? as usize;
+                let sym_name = strtab.get(name_offset..)?.split(|byte| *byte == 0).next()?;
+                if sym_name == name.as_bytes() {
+                    return read_word(sym, layout.st_value, layout).ok();
+                }
+            }
+        }
+        None
+    }
+
+    /// Copies every PT_LOAD segment to its physical address and zeroes the rest of it (BSS).
+    pub fn load(&self, dram: &mut DramMemory) -> Result<(), ElfError> {
+        for segment in &self.segments {
+            let out_of_memory = ElfError::SegmentOutOfMemory { paddr: segment.paddr, memsz: segment.memsz };
+            if segment.memsz == 0 {
+                continue;
+            }
+            let paddr = usize::try_from(segment.paddr).map_err(|_| out_of_memory.clone())?;
+            let bits = usize::try_from(segment.memsz).ok().and_then(|memsz| memsz.checked_mul(8));
+            if bits.is_none_or(|bits| !dram.contains(paddr, bits)) {
+                return Err(out_of_memory);
+            }
+            info!("ELF: loading segment at {:#x} ({:#x} bytes, {:#x} from file)", segment.paddr, segment.memsz, segment.filesz);
+            let filesz = segment.filesz.min(segment.memsz) as usize;
+            let bytes = &self.data[segment.offset as usize..segment.offset as usize + filesz];
+            for (i, byte) in bytes.iter().enumerate() {
+                dram.dram_write(paddr + i, 8, *byte as u64);
+            }
+            for i in filesz..segment.memsz as usize {
+                dram.dram_write(paddr + i, 8, 0);
+            }
+        }
+        Ok(())
+    }
+}
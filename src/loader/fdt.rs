@@ -0,0 +1,217 @@
+use crate::cpu::isa::Isa;
+use crate::devices::clint::{CLINT_BASE, CLINT_SIZE};
+use crate::devices::plic::{PLIC_BASE, PLIC_SIZE, PLIC_SOURCES};
+use crate::devices::uart::{UART_BASE, UART_IRQ, UART_SIZE};
+
+/// Address of the device tree blob handed to the program in a1
+pub const FDT_BASE: u64 = 0x1000;
+/// Room reserved for the device tree blob in the memory map
+pub const FDT_MAX_SIZE: u64 = 0xf000;
+/// Frequency of the CLINT timer advertised to the guest
+pub const TIMEBASE_FREQUENCY: u32 = 10_000_000;
+const UART_CLOCK_FREQUENCY: u32 = 3_686_400;
+
+const FDT_MAGIC: u32 = 0xd00dfeed;
+const FDT_VERSION: u32 = 17;
+const FDT_LAST_COMPATIBLE_VERSION: u32 = 16;
+const FDT_HEADER_SIZE: usize = 40;
+const FDT_RESERVE_MAP_SIZE: usize = 16; // only the terminating entry
+pub const FDT_BEGIN_NODE: u32 = 1;
+pub const FDT_END_NODE: u32 = 2;
+pub const FDT_PROP: u32 = 3;
+pub const FDT_NOP: u32 = 4;
+pub const FDT_END: u32 = 9;
+
+// Phandles of the interrupt controllers
+const CPU_INTC_PHANDLE: u32 = 1;
+const PLIC_PHANDLE: u32 = 2;
+// Local interrupt numbers
+const IRQ_M_SOFT: u32 = 3;
+const IRQ_M_TIMER: u32 = 7;
+const IRQ_S_EXT: u32 = 9;
+const IRQ_M_EXT: u32 = 11;
+
+fn pad(buffer: &mut Vec<u8>) {
+    buffer.resize(buffer.len().next_multiple_of(4), 0);
+}
+
+/// Builder of a flattened device tree blob (DTB). Nodes must be closed in the order they were opened.
+#[derive(Default)]
+pub struct FdtBuilder {
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+    depth: usize,
+}
+
+impl FdtBuilder {
+    pub fn new() -> FdtBuilder {
+        FdtBuilder::default()
+    }
+
+    fn token(&mut self, token: u32) {
+        self.structure.extend_from_slice(&token.to_be_bytes());
+    }
+
+    /// Offset of a property name in the strings block, names are stored once
+    fn string_offset(&mut self, name: &str) -> u32 {
+        let mut offset = 0;
+        for existing in self.strings.split(|byte| *byte == 0) {
+            if existing == name.as_bytes() && offset < self.strings.len() {
+                return offset as u32;
+            }
+            offset += existing.len() + 1;
+        }
+        let offset = self.strings.len();
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        offset as u32
+    }
+
+    pub fn begin_node(&mut self, name: &str) {
+        self.token(FDT_BEGIN_NODE);
+        self.structure.extend_from_slice(name.as_bytes());
+        self.structure.push(0);
+        pad(&mut self.structure);
+        self.depth += 1;
+    }
+
+    pub fn end_node(&mut self) {
+        assert!(self.depth > 0, "end_node without begin_node");
+        self.token(FDT_END_NODE);
+        self.depth -= 1;
+    }
+
+    pub fn property(&mut self, name: &str, value: &[u8]) {
+        let name_offset = self.string_offset(name);
+        self.token(FDT_PROP);
+        self.token(value.len() as u32);
+        self.token(name_offset);
+        self.structure.extend_from_slice(value);
+        pad(&mut self.structure);
+    }
+
+    pub fn property_empty(&mut self, name: &str) {
+        self.property(name, &[]);
+    }
+
+    pub fn property_u32(&mut self, name: &str, value: u32) {
+        self.property_cells(name, &[value]);
+    }
+
+    pub fn property_cells(&mut self, name: &str, cells: &[u32]) {
+        let value: Vec<u8> = cells.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+        self.property(name, &value);
+    }
+
+    /// 64-bit values as pairs of cells, e.g. `reg` with #address-cells and #size-cells of 2
+    pub fn property_u64s(&mut self, name: &str, values: &[u64]) {
+        let value: Vec<u8> = values.iter().flat_map(|value| value.to_be_bytes()).collect();
+        self.property(name, &value);
+    }
+
+    pub fn property_string(&mut self, name: &str, value: &str) {
+        self.property(name, &[value.as_bytes(), &[0]].concat());
+    }
+
+    /// The blob: header, empty memory reservation map, structure and strings blocks
+    pub fn finish(mut self) -> Vec<u8> {
+        assert_eq!(self.depth, 0, "unclosed device tree node");
+        self.token(FDT_END);
+        let structure_offset = FDT_HEADER_SIZE + FDT_RESERVE_MAP_SIZE;
+        let strings_offset = structure_offset + self.structure.len();
+        let total_size = strings_offset + self.strings.len();
+        let header = [
+            FDT_MAGIC,
+            total_size as u32,
+            structure_offset as u32,
+            strings_offset as u32,
+            FDT_HEADER_SIZE as u32, // off_mem_rsvmap
+            FDT_VERSION,
+            FDT_LAST_COMPATIBLE_VERSION,
+            0, // boot_cpuid_phys
+            self.strings.len() as u32,
+            self.structure.len() as u32,
+        ];
+        let mut blob: Vec<u8> = header.iter().flat_map(|field| field.to_be_bytes()).collect();
+        blob.resize(structure_offset, 0);
+        blob.extend(self.structure);
+        blob.extend(self.strings);
+        blob
+    }
+}
+
+/// Device tree of the system-mode machine: one hart with `isa`, DRAM, CLINT, PLIC and UART.
+/// `bootargs` is the kernel command line in /chosen.
+pub fn machine_fdt(dram_base: u64, dram_size: u64, isa: &Isa, bootargs: &str) -> Vec<u8> {
+    let mut fdt = FdtBuilder::new();
+    fdt.begin_node("");
+    fdt.property_u32("#address-cells", 2);
+    fdt.property_u32("#size-cells", 2);
+    fdt.property_string("compatible", "riscv-emu");
+    fdt.property_string("model", "riscv-emu");
+
+    fdt.begin_node("chosen");
+    fdt.property_string("bootargs", bootargs);
+    fdt.property_string("stdout-path", &format!("/soc/serial@{UART_BASE:x}"));
+    fdt.end_node();
+
+    fdt.begin_node("cpus");
+    fdt.property_u32("#address-cells", 1);
+    fdt.property_u32("#size-cells", 0);
+    fdt.property_u32("timebase-frequency", TIMEBASE_FREQUENCY);
+    fdt.begin_node("cpu@0");
+    fdt.property_string("device_type", "cpu");
+    fdt.property_u32("reg", 0);
+    fdt.property_string("status", "okay");
+    fdt.property_string("compatible", "riscv");
+    fdt.property_string("riscv,isa", &isa.to_string());
+    // RV32 only implements Bare translation, Sv32 is not supported
+    fdt.property_string("mmu-type", if isa.xlen() == 32 { "riscv,none" } else { "riscv,sv57" });
+    fdt.begin_node("interrupt-controller");
+    fdt.property_u32("#interrupt-cells", 1);
+    fdt.property_empty("interrupt-controller");
+    fdt.property_string("compatible", "riscv,cpu-intc");
+    fdt.property_u32("phandle", CPU_INTC_PHANDLE);
+    fdt.end_node();
+    fdt.end_node();
+    fdt.end_node();
+
+    fdt.begin_node(&format!("memory@{dram_base:x}"));
+    fdt.property_string("device_type", "memory");
+    fdt.property_u64s("reg", &[dram_base, dram_size]);
+    fdt.end_node();
+
+    fdt.begin_node("soc");
+    fdt.property_u32("#address-cells", 2);
+    fdt.property_u32("#size-cells", 2);
+    fdt.property_string("compatible", "simple-bus");
+    fdt.property_empty("ranges");
+
+    fdt.begin_node(&format!("clint@{CLINT_BASE:x}"));
+    fdt.property_string("compatible", "riscv,clint0");
+    fdt.property_u64s("reg", &[CLINT_BASE, CLINT_SIZE]);
+    fdt.property_cells("interrupts-extended", &[CPU_INTC_PHANDLE, IRQ_M_SOFT, CPU_INTC_PHANDLE, IRQ_M_TIMER]);
+    fdt.end_node();
+
+    fdt.begin_node(&format!("plic@{PLIC_BASE:x}"));
+    fdt.property_string("compatible", "riscv,plic0");
+    fdt.property_u64s("reg", &[PLIC_BASE, PLIC_SIZE]);
+    fdt.property_u32("#interrupt-cells", 1);
+    fdt.property_empty("interrupt-controller");
+    fdt.property_u32("riscv,ndev", PLIC_SOURCES as u32 - 1);
+    fdt.property_cells("interrupts-extended", &[CPU_INTC_PHANDLE, IRQ_M_EXT, CPU_INTC_PHANDLE, IRQ_S_EXT]);
+    fdt.property_u32("phandle", PLIC_PHANDLE);
+    fdt.end_node();
+
+    fdt.begin_node(&format!("serial@{UART_BASE:x}"));
+    fdt.property_string("compatible", "ns16550a");
+    fdt.property_u64s("reg", &[UART_BASE, UART_SIZE]);
+    fdt.property_u32("clock-frequency", UART_CLOCK_FREQUENCY);
+    fdt.property_u32("interrupts", UART_IRQ);
+    fdt.property_u32("interrupt-parent", PLIC_PHANDLE);
+    fdt.end_node();
+
+    fdt.end_node();
+    fdt.end_node();
+    fdt.finish()
+}
@@ -1,76 +1,445 @@
-use std::fs::File;
-use std::io::prelude::*;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
 use std::env;
+use std::path::PathBuf;
+use std::process;
 use log::{info, warn};
-use env_logger;
 
-mod memory {
-    pub mod dram;
+use riscv_emu::cpu::basic_cpu::{self, BasicCpu};
+use riscv_emu::cpu::commit_log::CommitLog;
+use riscv_emu::cpu::isa::Isa;
+use riscv_emu::cpu::registers::RegisterFile;
+use riscv_emu::debug::gdb;
+use riscv_emu::devices::clint::{Clint, CLINT_BASE, CLINT_SIZE};
+use riscv_emu::devices::htif::Htif;
+use riscv_emu::devices::plic::{Plic, PLIC_BASE, PLIC_SIZE};
+use riscv_emu::devices::uart::{StdioBackend, Uart, UART_BASE, UART_IRQ, UART_SIZE};
+use riscv_emu::loader::elf::ElfFile;
+use riscv_emu::loader::fdt::{self, FDT_BASE, FDT_MAX_SIZE};
+use riscv_emu::memory::bus::{Bus, Device};
+use riscv_emu::memory::dram::{DramMemory, DRAM_BASE_ADDR, DRAM_SIZE};
+use riscv_emu::memory::rom::Rom;
+use riscv_emu::os::linux::{self, LinuxProcess};
+use riscv_emu::os::pk::ProxyKernel;
+use riscv_emu::os::semihosting::Semihosting;
+use riscv_emu::os::syscall::ExitOnEcall;
+use riscv_emu::testing::cosim;
+
+// Exit status of the emulator when the guest did not report one
+const EXIT_FAILURE: i32 = 1; // unhandled exception, unreadable or invalid program
+const EXIT_USAGE: i32 = 2;
+const EXIT_TIMEOUT: i32 = 124; // instruction limit reached, like timeout(1)
+
+const USAGE: &str = "\
+Usage: riscv-emu [options] <binary_filename> [guest_args...]
+
+Loading:
+  --format <elf|raw>      Format of the binary, raw binaries are loaded at the start of DRAM (default: elf)
+  --raw                   Same as --format raw
+  --mem-base <addr>       DRAM base address (default: 0x80000000)
+  --mem-size <size>       DRAM size in bytes, K, M and G suffixes are accepted (default: 8M)
+  --entry <addr>          Start address instead of the ELF entry point or the start of DRAM
+  --isa <isa>             Base ISA and extensions, e.g. rv64imac, rv64e or rv32imc (default: rv64imafdc)
+  --dtb <file>            Device tree blob passed in a1 instead of the generated one
+  --bootargs <args>       Kernel command line of the generated device tree
+Execution:
+  --max-instructions <n>  Stop after n instructions
+  --exit-on-ecall         Stop at the first ECALL, a0 is the exit code
+  --pk                    Service ECALLs of newlib programs like riscv-pk
+  --semihosting           Service semihosting calls, guest_args form the command line
+  --sandbox <dir>         Root directory of the files opened by --pk and --semihosting (default: the current directory)
+  --user                  Run a statically linked Linux executable in user mode with guest_args
+  --gdb <port>            Wait for a debugger on the given port instead of running
+Tracing:
+  --trace <file>          Write the disassembly of every executed instruction (Spike -l format)
+  --log-commits <file>    Write a Spike compatible trace of the retired instructions
+  --cosim <trace>         Run in lockstep with a reference commit trace, stop at the first divergence
+  -h, --help              Show this help
+
+Exit status: the exit code of the program (HTIF tohost, exit syscall, semihosting SYS_EXIT or
+--exit-on-ecall), 0 when it returns to PC 0 or runs off the end of DRAM, 1 on an unhandled
+exception or a program that can not be loaded, 2 on invalid arguments and 124 when the
+instruction limit is reached.";
+
+struct Options {
+    help: bool,
+    filename: String,
+    guest_args: Vec<String>,
+    raw: bool,
+    mem_base: usize,
+    mem_size: usize,
+    entry: Option<u64>,
+    isa: Isa,
+    dtb: Option<String>,
+    bootargs: String,
+    max_instructions: Option<u64>,
+    exit_on_ecall: bool,
+    pk: bool,
+    semihosting: bool,
+    sandbox: PathBuf,
+    user: bool,
+    gdb_port: Option<u16>,
+    trace: Option<String>,
+    commit_log: Option<String>,
+    cosim: Option<String>,
 }
-use memory::dram;
-mod cpu {
-    pub mod basic_cpu;
+
+// Fixed part of the memory map, DRAM must not overlap it
+const DEVICE_REGIONS: [(&str, u64, u64); 4] = [
+    ("device tree", FDT_BASE, FDT_MAX_SIZE),
+    ("CLINT", CLINT_BASE, CLINT_SIZE),
+    ("PLIC", PLIC_BASE, PLIC_SIZE),
+    ("UART", UART_BASE, UART_SIZE),
+];
+
+/// Decimal or 0x prefixed hexadecimal number
+fn parse_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
 }
-use cpu::basic_cpu;
 
-fn main() {
-    env_logger::init();
-    info!("Starting RISC-V Emulator...");
-    let args: Vec<String> = env::args().collect();
+/// Number with an optional K, M or G suffix
+fn parse_size(text: &str) -> Option<u64> {
+    let (number, shift) = match text.to_ascii_uppercase().chars().last()? {
+        'K' => (&text[..text.len() - 1], 10),
+        'M' => (&text[..text.len() - 1], 20),
+        'G' => (&text[..text.len() - 1], 30),
+        _ => (text, 0),
+    };
+    parse_number(number)?.checked_mul(1 << shift)
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        help: false,
+        filename: String::new(),
+        guest_args: Vec::new(),
+        raw: false,
+        mem_base: DRAM_BASE_ADDR,
+        mem_size: DRAM_SIZE,
+        entry: None,
+        isa: Isa::default(),
+        dtb: None,
+        bootargs: String::new(),
+        max_instructions: None,
+        exit_on_ecall: false,
+        pk: false,
+        semihosting: false,
+        sandbox: PathBuf::from("."),
+        user: false,
+        gdb_port: None,
+        trace: None,
+        commit_log: None,
+        cosim: None,
+    };
+    let mut filename = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or_else(|| format!("missing value for {arg}"));
+        let invalid = |value: &str| format!("invalid value for {arg}: {value:?}");
+        match arg.as_str() {
+            "-h" | "--help" => options.help = true,
+            "--format" => match value()?.as_str() {
+                "elf" => options.raw = false,
+                "raw" => options.raw = true,
+                format => return Err(invalid(format)),
+            },
+            "--raw" => options.raw = true,
+            "--mem-base" => {
+                let text = value()?;
+                options.mem_base = parse_number(&text).ok_or_else(|| invalid(&text))? as usize;
+            },
+            "--mem-size" => {
+                let text = value()?;
+                options.mem_size = parse_size(&text).filter(|size| *size > 0).ok_or_else(|| invalid(&text))? as usize;
+            },
+            "--entry" => {
+                let text = value()?;
+                options.entry = Some(parse_number(&text).ok_or_else(|| invalid(&text))?);
+            },
+            "--isa" => options.isa = Isa::parse(&value()?).map_err(|err| err.to_string())?,
+            "--dtb" => options.dtb = Some(value()?),
+            "--bootargs" => options.bootargs = value()?,
+            "--max-instructions" => {
+                let text = value()?;
+                options.max_instructions = Some(parse_number(&text).ok_or_else(|| invalid(&text))?);
+            },
+            "--exit-on-ecall" => options.exit_on_ecall = true,
+            "--pk" => options.pk = true,
+            "--semihosting" => options.semihosting = true,
+            "--sandbox" => options.sandbox = PathBuf::from(value()?),
+            "--user" => options.user = true,
+            "--gdb" => {
+                let text = value()?;
+                options.gdb_port = Some(text.parse().map_err(|_| invalid(&text))?);
+            },
+            "--trace" => options.trace = Some(value()?),
+            "--log-commits" => options.commit_log = Some(value()?),
+            "--cosim" => options.cosim = Some(value()?),
+            _ if arg.starts_with('-') && filename.is_none() => return Err(format!("unknown option {arg}")),
+            _ if filename.is_none() => {
+                filename = Some(arg.clone());
+                // Everything after the binary belongs to the guest
+                options.guest_args.extend(args.by_ref().cloned());
+            },
+            _ => unreachable!(),
+        }
+    }
+    if options.help {
+        return Ok(options);
+    }
+    options.filename = filename.ok_or("missing binary filename")?;
+    if !options.user && !options.semihosting && !options.guest_args.is_empty() {
+        return Err("guest arguments need --user or --semihosting".to_string());
+    }
+    if [options.exit_on_ecall, options.pk, options.user].iter().filter(|enabled| **enabled).count() > 1 {
+        return Err("only one of --exit-on-ecall, --pk and --user can service ECALL".to_string());
+    }
+    if options.pk && options.raw {
+        return Err("--pk needs an ELF file to place the heap".to_string());
+    }
+    if options.user && (options.raw || options.gdb_port.is_some() || options.cosim.is_some()) {
+        return Err("--user can not be combined with --raw, --gdb or --cosim".to_string());
+    }
+    if options.max_instructions.is_some() && (options.gdb_port.is_some() || options.cosim.is_some()) {
+        return Err("--max-instructions can not be combined with --gdb or --cosim".to_string());
+    }
+    if options.isa.is_embedded() && (options.user || options.pk) {
+        return Err("--user and --pk need the I base ISA".to_string());
+    }
+    // The host services, the debugger and the commit log formats are implemented for RV64 only
+    if options.isa.xlen() == 32
+        && (options.user || options.pk || options.semihosting || options.gdb_port.is_some() || options.cosim.is_some() || options.commit_log.is_some()) {
+        return Err("--user, --pk, --semihosting, --gdb, --cosim and --log-commits need an RV64 ISA".to_string());
+    }
+    if !options.user {
+        check_memory_layout(options.mem_base as u64, options.mem_size as u64, options.isa.xlen())?;
+    }
+    Ok(options)
+}
+
+/// Checks that DRAM fits into the `xlen` bit address space next to the devices, the initial stack pointer is the end of DRAM
+fn check_memory_layout(base: u64, size: u64, xlen: u32) -> Result<(), String> {
+    let end = base.checked_add(size)
+        .filter(|end| xlen == 64 || *end <= 1 << 32)
+        .ok_or_else(|| format!("DRAM at {base:#x} ({size:#x} bytes) does not fit into the address space"))?;
+    match DEVICE_REGIONS.iter().find(|(_, device_base, device_size)| base < device_base + device_size && *device_base < end) {
+        Some((name, device_base, _)) => Err(format!("DRAM at {base:#x}..{end:#x} overlaps the {name} at {device_base:#x}")),
+        None => Ok(()),
+    }
+}
+
+/// Reports a fatal error and exits, the commit log and trace are flushed first
+fn fail(cpu: Option<&mut BasicCpu>, message: &str) -> ! {
+    eprintln!("riscv-emu: {message}");
+    if let Some(cpu) = cpu {
+        cpu.set_commit_log(None);
+        cpu.set_trace(None);
+    }
+    process::exit(EXIT_FAILURE);
+}
+
+fn create_output(filename: &str) -> BufWriter<File> {
+    let file = File::create(filename).unwrap_or_else(|err| fail(None, &format!("failed to create {filename}: {err}")));
+    BufWriter::new(file)
+}
 
-    if args.len() != 2 {
-        panic!("Usage: main <binary_filename>");
+/// ELF32 executables need an RV32 ISA and ELF64 executables an RV64 one
+fn check_elf_xlen(elf: &ElfFile, options: &Options) {
+    if elf.xlen != options.isa.xlen() {
+        fail(None, &format!("ELF{} executable can not run on {}, select the ISA with --isa", elf.xlen, options.isa));
     }
-    let mut file = File::open(&args[1])
-        .expect("Failed to open binary file");
-    let mut binary = Vec::new();
-    file.read_to_end(&mut binary)
-        .expect("Failed to read binary file");
+}
 
-    let mut cpu = basic_cpu::BasicCpu::new();
+/// Sets up a statically linked Linux executable with its system calls serviced by the host
+fn load_user_mode(cpu: &mut BasicCpu, binary: &[u8], options: &Options) {
+    let mut args = options.guest_args.clone();
+    args.insert(0, options.filename.clone());
+    let env: Vec<String> = env::vars().map(|(key, value)| format!("{key}={value}")).collect();
+    let elf = ElfFile::parse(binary).unwrap_or_else(|err| fail(None, &format!("failed to load Linux executable: {err}")));
+    check_elf_xlen(&elf, options);
+    let process = LinuxProcess::load(cpu, &elf, &args, &env)
+        .unwrap_or_else(|err| fail(None, &format!("failed to load Linux executable: {err}")));
+    cpu.set_syscall_handler(Some(Box::new(process)));
+}
 
-    for (i, byte) in binary.iter().enumerate() {
-        cpu.mem.dram_write(dram::DRAM_BASE_ADDR + i, 8, (*byte).into());
+/// Loads a bare-metal program or kernel with the devices of the machine, returns the HTIF of programs defining tohost.
+/// Like on Spike and QEMU, a0 holds the hart ID and a1 the address of the device tree blob.
+fn load_system(cpu: &mut BasicCpu, binary: &[u8], options: &Options) -> Option<Htif> {
+    let devices: [(&'static str, u64, u64, Box<dyn Device>); 3] = [
+        ("clint", CLINT_BASE, CLINT_SIZE, Box::new(Clint::new())),
+        ("plic", PLIC_BASE, PLIC_SIZE, Box::new(Plic::new())),
+        ("uart", UART_BASE, UART_SIZE, Box::new(Uart::new(Box::new(StdioBackend::new())))),
+    ];
+    for (name, base, size, device) in devices {
+        cpu.bus.add_device(name, base, size, device).unwrap_or_else(|err| fail(None, &format!("invalid memory map: {err}")));
     }
+    cpu.bus.add_irq_source("uart", UART_IRQ);
 
+    let (entry, htif) = if options.raw {
+        let dram = &mut cpu.bus.dram;
+        if binary.len() > dram.size() {
+            fail(None, "binary does not fit into DRAM");
+        }
+        dram.mem[..binary.len()].copy_from_slice(binary);
+        (dram.base() as u64, None)
+    } else {
+        let elf = ElfFile::parse(binary).unwrap_or_else(|err| fail(None, &format!("failed to load ELF file: {err}")));
+        check_elf_xlen(&elf, options);
+        elf.load(&mut cpu.bus.dram).unwrap_or_else(|err| fail(None, &format!("failed to load ELF file: {err}")));
+        // Programs defining tohost (e.g. riscv-tests) report their result through HTIF
+        let htif = elf.symbol("tohost").map(|tohost| Htif::new(tohost, elf.symbol("fromhost")));
+        if options.pk {
+            let kernel = ProxyKernel::new(&elf, &cpu.bus.dram, options.sandbox.clone());
+            cpu.set_syscall_handler(Some(Box::new(kernel)));
+        }
+        (elf.entry, htif)
+    };
     info!("Init - Loaded binary into DRAM memory.");
-    
+
+    let dtb = match &options.dtb {
+        Some(filename) => fs::read(filename).unwrap_or_else(|err| fail(None, &format!("failed to read {filename}: {err}"))),
+        None => fdt::machine_fdt(cpu.bus.dram.base() as u64, cpu.bus.dram.size() as u64, &options.isa, &options.bootargs),
+    };
+    if dtb.len() as u64 > FDT_MAX_SIZE {
+        fail(None, &format!("device tree blob of {} bytes does not fit into the {FDT_MAX_SIZE:#x} bytes reserved for it", dtb.len()));
+    }
+    cpu.bus.add_device("dtb", FDT_BASE, dtb.len().next_multiple_of(8) as u64, Box::new(Rom::new(dtb)))
+        .unwrap_or_else(|err| fail(None, &format!("invalid memory map: {err}")));
+
     info!("Init - Initializing CPU...");
     cpu.init();
-    info!("Init - Current registers:");
-    cpu.print_registers();
-    info!("Init - Current PC: {:#x}", cpu.get_pc());
+    cpu.set_pc(options.entry.unwrap_or(entry));
+    cpu.set_register(10, 0); // hart ID
+    cpu.set_register(11, FDT_BASE);
+    htif
+}
+
+/// Steps the CPU until the program finishes, returns the exit status
+fn run(cpu: &mut BasicCpu, mut htif: Option<Htif>, max_instructions: Option<u64>) -> i32 {
     info!("Init - Starting execution...");
+    let mut instret: u64 = 0;
     loop {
-        info!("FETCH");
-        let current_instruction = cpu.fetch_instr();
-        let current_pc = cpu.get_pc();
+        if max_instructions.is_some_and(|max| instret >= max) {
+            eprintln!("Instruction limit of {instret} reached at PC {:#x}.", cpu.get_pc());
+            return EXIT_TIMEOUT;
+        }
+        info!("FETCH + DECODE + EXECUTE - PC: {:#x}", cpu.get_pc());
+        // Exceptions are taken as traps, the run stops when there is no handler to make progress
+        if let Some(trap) = cpu.step()
+            && !trap.handled {
+            eprintln!("Error executing instruction: {}", trap.exception);
+            cpu.print_registers();
+            return EXIT_FAILURE;
+        }
+        instret += 1;
 
-        info!("PC: {:#x}, Instruction: {:#x}", current_pc, current_instruction);
+        if let Some(code) = htif.as_mut().and_then(|htif| htif.poll(&mut cpu.bus.dram)) {
+            info!("Program exited through HTIF with code {code}.");
+            if code != 0 {
+                eprintln!("*** FAILED *** (tohost = {code})");
+            }
+            cpu.print_registers();
+            return code as i32;
+        }
 
-        cpu.set_pc(cpu.get_pc() + 4);
-        info!("DECODE + EXECUTE");
-        match cpu.execute_instr(current_instruction) {
-            Ok(_) => {},
-            Err(err) => {
-                println!("Error executing instruction at PC {:#x}: {}", current_pc, err);
-                break
-            },
-        };
+        if let Some(code) = cpu.exit_code() {
+            info!("Program exited with code {code}.");
+            return code as i32;
+        }
 
-        if cpu.get_pc() >= (dram::DRAM_BASE_ADDR + dram::DRAM_SIZE) as u64 {
+        if cpu.get_pc() >= (cpu.bus.dram.base() + cpu.bus.dram.size()) as u64 {
             warn!("Reached end of DRAM memory.");
-            break;
+            return 0;
         }
 
         if cpu.get_pc() == 0 {
             info!("Program terminated - PC at 0x0.");
-            break;
+            return 0;
         }
     }
+}
+
+fn main() {
+    env_logger::init();
+    info!("Starting RISC-V Emulator...");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = parse_options(&args).unwrap_or_else(|err| {
+        eprintln!("riscv-emu: {err}\n\n{USAGE}");
+        process::exit(EXIT_USAGE);
+    });
+    if options.help {
+        println!("{USAGE}");
+        return;
+    }
+    let binary = fs::read(&options.filename)
+        .unwrap_or_else(|err| fail(None, &format!("failed to read {}: {err}", options.filename)));
+
+    let bus = if options.user {
+        linux::user_bus()
+    } else {
+        Bus::with_dram(DramMemory::with_layout(options.mem_base, options.mem_size))
+    };
+    let mut cpu = basic_cpu::BasicCpu::with_bus(bus);
+    // Before loading, the embedded base ISA replaces the register file
+    cpu.set_isa(options.isa);
+    let htif = if options.user {
+        load_user_mode(&mut cpu, &binary, &options);
+        None
+    } else {
+        load_system(&mut cpu, &binary, &options)
+    };
+    if options.exit_on_ecall {
+        cpu.set_syscall_handler(Some(Box::new(ExitOnEcall)));
+    }
+    if options.semihosting {
+        let cmdline = [options.filename.clone()].into_iter().chain(options.guest_args.iter().cloned()).collect::<Vec<_>>().join(" ");
+        cpu.set_semihosting_handler(Some(Box::new(Semihosting::new(cmdline, options.sandbox.clone()))));
+    }
+    if let Some(filename) = &options.commit_log {
+        cpu.set_commit_log(Some(CommitLog::new(Box::new(create_output(filename)))));
+    }
+    if let Some(filename) = &options.trace {
+        cpu.set_trace(Some(Box::new(create_output(filename))));
+    }
+    info!("Init - Current registers:");
+    cpu.print_registers();
+    info!("Init - Current PC: {:#x}", cpu.get_pc());
+
+    let code = if let Some(port) = options.gdb_port {
+        match gdb::serve(&mut cpu, port, htif) {
+            Ok(code) => code.map_or(0, |code| code as i32),
+            Err(err) => fail(Some(&mut cpu), &format!("GDB connection failed: {err}")),
+        }
+    } else if let Some(filename) = &options.cosim {
+        let trace = File::open(filename)
+            .unwrap_or_else(|err| fail(Some(&mut cpu), &format!("failed to open reference trace {filename}: {err}")));
+        let mut htif = htif;
+        match cosim::run_lockstep(&mut cpu, BufReader::new(trace), htif.as_mut()) {
+            Ok(instret) => {
+                eprintln!("Co-simulation passed: {instret} instructions matched the reference.");
+                0
+            },
+            Err(err) => {
+                eprintln!("Co-simulation failed: {err}");
+                for (idx, value) in cpu.registers().iter() {
+                    eprintln!("  x{idx:<2} {:<4} {value:#018x}", RegisterFile::abi_name(idx).unwrap_or("?"));
+                }
+                eprintln!("  pc        {:#018x}", cpu.get_pc());
+                EXIT_FAILURE
+            },
+        }
+    } else {
+        run(&mut cpu, htif, options.max_instructions)
+    };
     info!("Done - Final CPU state:");
     cpu.print_registers();
     info!("Done - Final PC: {:#x}", cpu.get_pc());
     info!("Done - Emulation finished.");
-
-}
\ No newline at end of file
+    // Dropping the commit log and trace flushes them, process::exit does not run destructors
+    cpu.set_commit_log(None);
+    cpu.set_trace(None);
+    process::exit(code);
+}
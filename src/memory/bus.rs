@@ -0,0 +1,193 @@
+use crate::cpu::basic_cpu::TReg;
+use crate::memory::dram::DramMemory;
+use log::{info, warn};
+use std::fmt;
+
+/// Access which could not be completed: unmapped address, unsupported size or read-only region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessFault;
+
+/// Memory-mapped device. Offsets are relative to the base address the device is mapped at,
+/// sizes are given in bits (8, 16, 32 or 64) like for `DramMemory`.
+pub trait Device {
+    fn read(&mut self, offset: u64, size: usize) -> Result<u64, AccessFault>;
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> Result<(), AccessFault>;
+
+    /// Called once per CPU step, e.g. to poll host input
+    fn tick(&mut self) {}
+
+    /// Level of the device interrupt line
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// mip bits driven directly by the device (e.g. the CLINT timer)
+    fn mip(&self) -> TReg {
+        0
+    }
+
+    /// Levels of the interrupt sources connected to the bus (bit n is source n), used by interrupt controllers
+    fn set_irq_lines(&mut self, _lines: u64) {}
+}
+
+/// Device region which can not be added to the memory map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapError {
+    /// The region is empty or extends beyond the end of the address space
+    InvalidRegion { name: &'static str, base: u64, size: u64 },
+    /// The region overlaps DRAM
+    OverlapsDram { name: &'static str, base: u64 },
+    /// The region overlaps a device mapped before
+    OverlapsDevice { name: &'static str, base: u64, other: &'static str },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::InvalidRegion { name, base, size } => write!(f, "device {name} at {base:#x} ({size:#x} bytes) does not fit into the address space"),
+            MapError::OverlapsDram { name, base } => write!(f, "device {name} at {base:#x} overlaps DRAM"),
+            MapError::OverlapsDevice { name, base, other } => write!(f, "device {name} at {base:#x} overlaps device {other}"),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+struct MappedDevice {
+    name: &'static str,
+    base: u64,
+    size: u64,
+    device: Box<dyn Device>,
+}
+
+impl MappedDevice {
+    fn contains(&self, addr: u64, size: usize) -> bool {
+        addr.checked_sub(self.base)
+            .and_then(|offset| offset.checked_add(size as u64 / 8))
+            .is_some_and(|end| end <= self.size)
+    }
+
+    fn last(&self) -> u64 {
+        self.base + (self.size - 1) // checked when the device is added
+    }
+}
+
+/// Last address of the region [base, base + size), None if it is empty or wraps around
+fn last_addr(base: u64, size: u64) -> Option<u64> {
+    size.checked_sub(1).and_then(|len| base.checked_add(len))
+}
+
+/// System bus routing physical accesses to DRAM or to the devices registered in the memory map.
+pub struct Bus {
+    pub dram: DramMemory,
+    devices: Vec<MappedDevice>,
+    irq_sources: Vec<(&'static str, u32)>, // device name and interrupt source number
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+
+    pub fn new() -> Bus {
+        Bus::with_dram(DramMemory::new())
+    }
+
+    pub fn with_dram(dram: DramMemory) -> Bus {
+        Bus {
+            dram,
+            devices: Vec::new(),
+            irq_sources: Vec::new(),
+        }
+    }
+
+    /// Maps a device at [base, base + size), fails if the region overlaps DRAM or another device.
+    pub fn add_device(&mut self, name: &'static str, base: u64, size: u64, device: Box<dyn Device>) -> Result<(), MapError> {
+        // Regions are compared by their last address, DRAM may end at the top of the address space
+        let last = last_addr(base, size).ok_or(MapError::InvalidRegion { name, base, size })?;
+        if let Some(dram_last) = last_addr(self.dram.base() as u64, self.dram.size() as u64)
+            && base <= dram_last && self.dram.base() as u64 <= last {
+            return Err(MapError::OverlapsDram { name, base });
+        }
+        if let Some(other) = self.devices.iter().find(|other| base <= other.last() && other.base <= last) {
+            return Err(MapError::OverlapsDevice { name, base, other: other.name });
+        }
+        info!("Mapping device {name} at {base:#x}..={last:#x}");
+        self.devices.push(MappedDevice { name, base, size, device });
+        Ok(())
+    }
+
+    /// Connects the interrupt line of the device registered under `name` to interrupt source `source` (1..=63).
+    pub fn add_irq_source(&mut self, name: &'static str, source: u32) {
+        info!("Connecting interrupt of device {name} to source {source}");
+        self.irq_sources.push((name, source));
+    }
+
+    /// Advances all devices by one step and forwards the interrupt lines to the interrupt controllers.
+    pub fn tick(&mut self) {
+        for mapped in self.devices.iter_mut() {
+            mapped.device.tick();
+        }
+        let lines = self.irq_sources.iter()
+            .filter(|(name, _)| self.irq(name))
+            .fold(0u64, |lines, (_, source)| lines | (1 << source));
+        for mapped in self.devices.iter_mut() {
+            mapped.device.set_irq_lines(lines);
+        }
+    }
+
+    /// Interrupt line of the device registered under `name`.
+    pub fn irq(&self, name: &str) -> bool {
+        self.devices.iter().any(|mapped| mapped.name == name && mapped.device.irq())
+    }
+
+    /// mip bits driven by all devices.
+    pub fn mip(&self) -> TReg {
+        self.devices.iter().fold(0, |mip, mapped| mip | mapped.device.mip())
+    }
+
+    fn device_mut(&mut self, addr: u64, size: usize) -> Option<&mut MappedDevice> {
+        self.devices.iter_mut().find(|mapped| mapped.contains(addr, size))
+    }
+
+    /// Whether an access of `size` bits at `addr` reaches DRAM or a device.
+    pub fn is_mapped(&self, addr: u64, size: usize) -> bool {
+        self.dram.contains(addr as usize, size) || self.devices.iter().any(|mapped| mapped.contains(addr, size))
+    }
+
+    pub fn read(&mut self, addr: u64, size: usize) -> Result<u64, AccessFault> {
+        if !matches!(size, 8 | 16 | 32 | 64) {
+            return Err(AccessFault);
+        }
+        if self.dram.contains(addr as usize, size) {
+            return Ok(self.dram.dram_read(addr as usize, size));
+        }
+        match self.device_mut(addr, size) {
+            Some(mapped) => mapped.device.read(addr - mapped.base, size),
+            None => {
+                warn!("Read from unmapped address {addr:#x}");
+                Err(AccessFault)
+            },
+        }
+    }
+
+    pub fn write(&mut self, addr: u64, size: usize, value: u64) -> Result<(), AccessFault> {
+        if !matches!(size, 8 | 16 | 32 | 64) {
+            return Err(AccessFault);
+        }
+        if self.dram.contains(addr as usize, size) {
+            self.dram.dram_write(addr as usize, size, value);
+            return Ok(());
+        }
+        match self.device_mut(addr, size) {
+            Some(mapped) => mapped.device.write(addr - mapped.base, size, value),
+            None => {
+                warn!("Write to unmapped address {addr:#x}");
+                Err(AccessFault)
+            },
+        }
+    }
+}
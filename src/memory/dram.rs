@@ -2,30 +2,87 @@ use log::info;
 
 pub const DRAM_SIZE: usize = 1024*1024*8;
 pub const DRAM_BASE_ADDR: usize = 0x80000000;
+pub const RESERVATION_GRANULE: usize = 8; // size (in bytes) of the reservation set used by LR/SC
 
 pub struct DramMemory {
-    pub mem : Vec<u8>
+    pub mem : Vec<u8>,
+    base : usize, // physical address of the first byte
+    reservation : Option<usize>, // address of the reservation set registered by LR (aligned to RESERVATION_GRANULE)
+}
+
+impl Default for DramMemory {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DramMemory {
 
+    pub fn new() -> DramMemory {
+        DramMemory::with_layout(DRAM_BASE_ADDR, DRAM_SIZE)
+    }
+
+    pub fn with_layout(base: usize, size: usize) -> DramMemory {
+        DramMemory {
+            mem: vec![0; size],
+            base,
+            reservation: None,
+        }
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    pub fn size(&self) -> usize {
+        self.mem.len()
+    }
+
+    /// Checks if an access of `size` bits at `addr` lies entirely within DRAM
+    pub fn contains(&self, addr: usize, size: usize) -> bool {
+        addr.checked_sub(self.base)
+            .and_then(|offset| offset.checked_add(size / 8))
+            .is_some_and(|end| end <= self.mem.len())
+    }
+
     pub fn dram_read(&self, addr: usize, size: usize) -> u64{
         let mut value: u64 = 0;
 
         for shift in 0..size/8 {            
-            let b = u64::from(self.mem[addr-DRAM_BASE_ADDR+shift]) << shift*8;
-            info!("Reading 0x{:02x} from 0x{:08x}", b, addr-DRAM_BASE_ADDR+shift);
-            value |= b as u64;
+            let b = u64::from(self.mem[addr-self.base+shift]) << (shift*8);
+            info!("Reading 0x{:02x} from 0x{:08x}", b, addr-self.base+shift);
+            value |= b;
         }
 
         value
     }
 
     pub fn dram_write(&mut self, addr: usize, size: usize, value: u64){
+        // Any store overlapping the reservation set invalidates it
+        if let Some(reserved) = self.reservation
+            && addr < reserved + RESERVATION_GRANULE && reserved < addr + size/8 {
+            info!("Store to 0x{:08x} invalidates reservation at 0x{:08x}", addr, reserved);
+            self.reservation = None;
+        }
 
         for shift in 0..size/8 {
-            info!("Writing 0x{:02x} to 0x{:08x}", ((value >> shift*8) & 0xFF) as u8, addr-DRAM_BASE_ADDR+shift);
-            self.mem[addr-DRAM_BASE_ADDR+shift] = ((value >> shift*8) & 0xFF) as u8;
+            info!("Writing 0x{:02x} to 0x{:08x}", ((value >> (shift*8)) & 0xFF) as u8, addr-self.base+shift);
+            self.mem[addr-self.base+shift] = ((value >> (shift*8)) & 0xFF) as u8;
         }
     }
-}
\ No newline at end of file
+
+    //
+    // Reservation set (LR/SC)
+    //
+    pub fn reserve(&mut self, addr: usize) {
+        self.reservation = Some(addr & !(RESERVATION_GRANULE - 1));
+    }
+
+    pub fn is_reserved(&self, addr: usize) -> bool {
+        self.reservation == Some(addr & !(RESERVATION_GRANULE - 1))
+    }
+
+    pub fn clear_reservation(&mut self) {
+        self.reservation = None;
+    }
+}
@@ -0,0 +1,24 @@
+use crate::memory::bus::{AccessFault, Device};
+
+/// Read-only memory, e.g. a boot ROM or a device tree blob. Writes fault.
+pub struct Rom {
+    pub data: Vec<u8>,
+}
+
+impl Rom {
+    pub fn new(data: Vec<u8>) -> Rom {
+        Rom { data }
+    }
+}
+
+impl Device for Rom {
+    fn read(&mut self, offset: u64, size: usize) -> Result<u64, AccessFault> {
+        let start = offset as usize;
+        let bytes = self.data.get(start..start + size / 8).ok_or(AccessFault)?;
+        Ok(bytes.iter().rev().fold(0, |value, byte| (value << 8) | u64::from(*byte)))
+    }
+
+    fn write(&mut self, _offset: u64, _size: usize, _value: u64) -> Result<(), AccessFault> {
+        Err(AccessFault)
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::os::syscall::{EACCES, EBADF, EINVAL, EIO, ESPIPE};
+use log::info;
+
+// File types of st_mode
+pub const S_IFCHR: u32 = 0o020000;
+pub const S_IFDIR: u32 = 0o040000;
+pub const S_IFREG: u32 = 0o100000;
+/// Size of the asm-generic struct stat used by Linux and the proxy kernel
+pub const STAT_SIZE: usize = 128;
+const STAT_BLOCK_SIZE: u32 = 4096;
+
+enum OpenFile {
+    Stdin,
+    Stdout,
+    Stderr,
+    Host(File),
+}
+
+/// Negative errno of a failed host call
+pub fn errno(err: &io::Error) -> i64 {
+    -err.raw_os_error().map_or(EIO, i64::from)
+}
+
+/// struct stat of the asm-generic ABI
+pub fn stat_bytes(mode: u32, size: u64, modified: Option<SystemTime>) -> [u8; STAT_SIZE] {
+    let time = modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok()).unwrap_or_default();
+    let mut stat = [0u8; STAT_SIZE];
+    stat[16..20].copy_from_slice(&mode.to_le_bytes()); // st_mode
+    stat[20..24].copy_from_slice(&1u32.to_le_bytes()); // st_nlink
+    stat[48..56].copy_from_slice(&size.to_le_bytes()); // st_size
+    stat[56..60].copy_from_slice(&STAT_BLOCK_SIZE.to_le_bytes()); // st_blksize
+    stat[64..72].copy_from_slice(&size.div_ceil(512).to_le_bytes()); // st_blocks
+    for offset in [72, 88, 104] {
+        // st_atime, st_mtime and st_ctime with their nanoseconds
+        stat[offset..offset + 8].copy_from_slice(&time.as_secs().to_le_bytes());
+        stat[offset + 8..offset + 16].copy_from_slice(&(time.subsec_nanos() as u64).to_le_bytes());
+    }
+    stat
+}
+
+fn metadata_stat(metadata: &Metadata) -> [u8; STAT_SIZE] {
+    let mode = if metadata.is_dir() { S_IFDIR | 0o755 } else { S_IFREG | 0o644 };
+    stat_bytes(mode, metadata.len(), metadata.modified().ok())
+}
+
+/// File descriptor table of a guest: 0-2 are connected to host streams, the others to host files.
+/// With a sandbox root, guest paths are resolved below it and can not leave it, neither with ".." nor through symbolic links.
+pub struct HostFiles {
+    files: BTreeMap<i64, OpenFile>,
+    stdin: Box<dyn Read>,
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+    root: Option<PathBuf>,
+}
+
+impl Default for HostFiles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HostFiles {
+    /// Guest paths are host paths
+    pub fn new() -> HostFiles {
+        HostFiles {
+            files: BTreeMap::from([(0, OpenFile::Stdin), (1, OpenFile::Stdout), (2, OpenFile::Stderr)]),
+            stdin: Box::new(io::stdin()),
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+            root: None,
+        }
+    }
+
+    /// Guest paths, absolute or relative, are resolved below `root`. A relative root like "." is the current directory.
+    pub fn sandboxed(root: PathBuf) -> HostFiles {
+        HostFiles { root: Some(root), ..HostFiles::new() }
+    }
+
+    pub fn set_output(&mut self, stdout: Box<dyn Write>, stderr: Box<dyn Write>) {
+        self.stdout = stdout;
+        self.stderr = stderr;
+    }
+
+    pub fn set_input(&mut self, stdin: Box<dyn Read>) {
+        self.stdin = stdin;
+    }
+
+    /// Host path of a guest path, paths escaping the sandbox are refused with EACCES
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, i64> {
+        let Some(root) = &self.root else {
+            return Ok(PathBuf::from(path));
+        };
+        let mut resolved = root.clone();
+        let mut depth = 0;
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => {
+                    resolved.push(name);
+                    depth += 1;
+                },
+                Component::ParentDir if depth > 0 => {
+                    resolved.pop();
+                    depth -= 1;
+                },
+                Component::ParentDir => return Err(-EACCES),
+                Component::RootDir | Component::CurDir | Component::Prefix(_) => {},
+            }
+        }
+        // Symbolic links may point outside of the sandbox, the target has to stay below the canonical root.
+        // A file which does not exist yet (e.g. for O_CREAT) is checked through its parent directory.
+        let root = root.canonicalize().map_err(|err| errno(&err))?;
+        let canonical = match resolved.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(_) if fs::symlink_metadata(&resolved).is_ok() => return Err(-EACCES), // dangling link
+            Err(err) => match (resolved.parent(), resolved.file_name()) {
+                (Some(parent), Some(name)) => parent.canonicalize().map_err(|_| errno(&err))?.join(name),
+                _ => return Err(errno(&err)),
+            },
+        };
+        if !canonical.starts_with(&root) {
+            info!("Refusing {path:?}, {} is outside of the sandbox", canonical.display());
+            return Err(-EACCES);
+        }
+        Ok(canonical)
+    }
+
+    /// Opens a guest path, returns the new descriptor or a negative errno
+    pub fn open(&mut self, path: &str, options: &OpenOptions) -> i64 {
+        let host_path = match self.resolve(path) {
+            Ok(host_path) => host_path,
+            Err(err) => return err,
+        };
+        info!("Opening {path:?} as {}", host_path.display());
+        match options.open(host_path) {
+            Ok(file) => {
+                let fd = (0..).find(|fd| !self.files.contains_key(fd)).unwrap();
+                self.files.insert(fd, OpenFile::Host(file));
+                fd
+            },
+            Err(err) => errno(&err),
+        }
+    }
+
+    pub fn close(&mut self, fd: i64) -> i64 {
+        match self.files.remove(&fd) {
+            Some(_) => 0,
+            None => -EBADF,
+        }
+    }
+
+    /// Reads up to `len` bytes
+    pub fn read(&mut self, fd: i64, len: usize) -> Result<Vec<u8>, i64> {
+        let mut buffer = vec![0; len];
+        let read = match self.files.get_mut(&fd) {
+            Some(OpenFile::Stdin) => self.stdin.read(&mut buffer),
+            Some(OpenFile::Host(file)) => file.read(&mut buffer),
+            Some(OpenFile::Stdout | OpenFile::Stderr) | None => return Err(-EBADF),
+        };
+        let read = read.map_err(|err| errno(&err))?;
+        buffer.truncate(read);
+        Ok(buffer)
+    }
+
+    pub fn write(&mut self, fd: i64, bytes: &[u8]) -> Result<usize, i64> {
+        let written = match self.files.get_mut(&fd) {
+            Some(OpenFile::Stdout) => self.stdout.write_all(bytes).and_then(|_| self.stdout.flush()),
+            Some(OpenFile::Stderr) => self.stderr.write_all(bytes).and_then(|_| self.stderr.flush()),
+            Some(OpenFile::Host(file)) => file.write_all(bytes),
+            Some(OpenFile::Stdin) | None => return Err(-EBADF),
+        };
+        written.map(|_| bytes.len()).map_err(|err| errno(&err))
+    }
+
+    /// lseek with SEEK_SET (0), SEEK_CUR (1) or SEEK_END (2), returns the new offset
+    pub fn seek(&mut self, fd: i64, offset: i64, whence: u64) -> i64 {
+        let position = match whence {
+            0 if offset >= 0 => SeekFrom::Start(offset as u64),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => return -EINVAL,
+        };
+        match self.files.get_mut(&fd) {
+            Some(OpenFile::Host(file)) => file.seek(position).map_or_else(|err| errno(&err), |offset| offset as i64),
+            Some(_) => -ESPIPE,
+            None => -EBADF,
+        }
+    }
+
+    /// Host file behind a descriptor (None for the standard streams)
+    pub fn host_file(&mut self, fd: i64) -> Result<&mut File, i64> {
+        match self.files.get_mut(&fd) {
+            Some(OpenFile::Host(file)) => Ok(file),
+            _ => Err(-EBADF),
+        }
+    }
+
+    pub fn fstat(&self, fd: i64) -> Result<[u8; STAT_SIZE], i64> {
+        match self.files.get(&fd) {
+            Some(OpenFile::Host(file)) => file.metadata().map(|metadata| metadata_stat(&metadata)).map_err(|err| errno(&err)),
+            Some(_) => Ok(stat_bytes(S_IFCHR | 0o620, 0, None)),
+            None => Err(-EBADF),
+        }
+    }
+
+    pub fn stat(&self, path: &str) -> Result<[u8; STAT_SIZE], i64> {
+        let host_path = self.resolve(path)?;
+        fs::metadata(host_path).map(|metadata| metadata_stat(&metadata)).map_err(|err| errno(&err))
+    }
+}
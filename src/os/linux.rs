@@ -0,0 +1,478 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::cpu::basic_cpu::{BasicCpu, Privilege, TReg};
+use crate::loader::elf::{ElfError, ElfFile};
+use crate::memory::bus::Bus;
+use crate::memory::dram::DramMemory;
+use crate::os::host_files::{errno, HostFiles};
+use crate::os::syscall::*;
+use log::{info, warn};
+
+// Address space of a user-mode process: statically linked executables start at 0x10000,
+// the stack grows down from the end of memory and mmap allocates below the stack.
+pub const USER_MEMORY_BASE: usize = 0x10000;
+pub const USER_MEMORY_SIZE: usize = 256 * 1024 * 1024;
+pub const USER_STACK_SIZE: u64 = 8 * 1024 * 1024;
+pub const USER_PAGE_SIZE: u64 = 4096;
+pub const USER_PID: i64 = 1000;
+
+// Syscall numbers (asm-generic/unistd.h as used by riscv64)
+pub const SYS_GETCWD: u64 = 17;
+pub const SYS_IOCTL: u64 = 29;
+pub const SYS_FACCESSAT: u64 = 48;
+pub const SYS_OPENAT: u64 = 56;
+pub const SYS_CLOSE: u64 = 57;
+pub const SYS_LSEEK: u64 = 62;
+pub const SYS_READ: u64 = 63;
+pub const SYS_WRITE: u64 = 64;
+pub const SYS_READV: u64 = 65;
+pub const SYS_WRITEV: u64 = 66;
+pub const SYS_READLINKAT: u64 = 78;
+pub const SYS_NEWFSTATAT: u64 = 79;
+pub const SYS_FSTAT: u64 = 80;
+pub const SYS_EXIT: u64 = 93;
+pub const SYS_EXIT_GROUP: u64 = 94;
+pub const SYS_SET_TID_ADDRESS: u64 = 96;
+pub const SYS_FUTEX: u64 = 98;
+pub const SYS_SET_ROBUST_LIST: u64 = 99;
+pub const SYS_CLOCK_GETTIME: u64 = 113;
+pub const SYS_TGKILL: u64 = 131;
+pub const SYS_SIGALTSTACK: u64 = 132;
+pub const SYS_RT_SIGACTION: u64 = 134;
+pub const SYS_RT_SIGPROCMASK: u64 = 135;
+pub const SYS_UNAME: u64 = 160;
+pub const SYS_GETTIMEOFDAY: u64 = 169;
+pub const SYS_GETPID: u64 = 172;
+pub const SYS_GETPPID: u64 = 173;
+pub const SYS_GETUID: u64 = 174;
+pub const SYS_GETEUID: u64 = 175;
+pub const SYS_GETGID: u64 = 176;
+pub const SYS_GETEGID: u64 = 177;
+pub const SYS_GETTID: u64 = 178;
+pub const SYS_BRK: u64 = 214;
+pub const SYS_MUNMAP: u64 = 215;
+pub const SYS_MREMAP: u64 = 216;
+pub const SYS_MMAP: u64 = 222;
+pub const SYS_MPROTECT: u64 = 226;
+pub const SYS_MADVISE: u64 = 233;
+pub const SYS_PRLIMIT64: u64 = 261;
+pub const SYS_GETRANDOM: u64 = 278;
+
+// openat flags, file types and auxiliary vector entries
+const AT_FDCWD: i64 = -100;
+const AT_EMPTY_PATH: u64 = 0x1000;
+const O_ACCMODE: u64 = 0o3;
+const O_WRONLY: u64 = 0o1;
+const O_RDWR: u64 = 0o2;
+const O_CREAT: u64 = 0o100;
+const O_EXCL: u64 = 0o200;
+const O_TRUNC: u64 = 0o1000;
+const O_APPEND: u64 = 0o2000;
+const MAP_FIXED: u64 = 0x10;
+const MAP_ANONYMOUS: u64 = 0x20;
+const AT_NULL: u64 = 0;
+const AT_PHDR: u64 = 3;
+const AT_PHENT: u64 = 4;
+const AT_PHNUM: u64 = 5;
+const AT_PAGESZ: u64 = 6;
+const AT_BASE: u64 = 7;
+const AT_ENTRY: u64 = 9;
+const AT_UID: u64 = 11;
+const AT_EUID: u64 = 12;
+const AT_GID: u64 = 13;
+const AT_EGID: u64 = 14;
+const AT_HWCAP: u64 = 16;
+const AT_CLKTCK: u64 = 17;
+const AT_SECURE: u64 = 23;
+const AT_RANDOM: u64 = 25;
+const AT_EXECFN: u64 = 31;
+// One bit per single letter extension (bit 0 is 'a'): IMAFDC
+const HWCAP_RV64GC: u64 = 1 << 8 | 1 << 12 | 1 << 0 | 1 << 5 | 1 << 3 | 1 << 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinuxError {
+    Elf(ElfError),
+    /// Arguments and environment do not fit on the stack
+    StackOverflow,
+}
+
+impl fmt::Display for LinuxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinuxError::Elf(err) => write!(f, "{err}"),
+            LinuxError::StackOverflow => write!(f, "arguments and environment do not fit on the stack"),
+        }
+    }
+}
+
+impl std::error::Error for LinuxError {}
+
+/// Bus of a user-mode process: memory at the addresses statically linked executables use, no devices
+pub fn user_bus() -> Bus {
+    Bus::with_dram(DramMemory::with_layout(USER_MEMORY_BASE, USER_MEMORY_SIZE))
+}
+
+fn page_align_up(addr: u64) -> u64 {
+    addr.div_ceil(USER_PAGE_SIZE) * USER_PAGE_SIZE
+}
+
+/// Linux process emulated at the syscall level (like qemu-user): the program runs in U-mode
+/// and its system calls are mapped onto the host.
+pub struct LinuxProcess {
+    files: HostFiles,
+    executable: String,
+    brk_start: u64,
+    brk: u64,
+    mmap_bottom: u64, // lowest address handed out by mmap, the heap may grow up to it
+    start_time: Instant,
+    random_state: u64,
+}
+
+impl LinuxProcess {
+    /// Loads `elf` into the memory of `cpu` (see `user_bus`) and prepares the CPU to run it in U-mode
+    /// with the Linux initial stack: argc, argv, envp and the auxiliary vector.
+    pub fn load(cpu: &mut BasicCpu, elf: &ElfFile, args: &[String], env: &[String]) -> Result<LinuxProcess, LinuxError> {
+        elf.load(&mut cpu.bus.dram).map_err(LinuxError::Elf)?;
+        let memory_end = (cpu.bus.dram.base() + cpu.bus.dram.size()) as u64;
+        let brk_start = page_align_up(elf.segments.iter().map(|segment| segment.vaddr + segment.memsz).max().unwrap_or(USER_MEMORY_BASE as u64));
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64);
+        let mut process = LinuxProcess {
+            files: HostFiles::new(),
+            executable: args.first().cloned().unwrap_or_default(),
+            brk_start,
+            brk: brk_start,
+            mmap_bottom: memory_end - USER_STACK_SIZE,
+            start_time: Instant::now(),
+            random_state: seed | 1,
+        };
+
+        // Program headers are mapped as part of the segment which contains them
+        let phdr = elf.segments.iter()
+            .find(|segment| segment.offset <= elf.phoff && elf.phoff < segment.offset + segment.filesz)
+            .map_or(0, |segment| segment.vaddr + elf.phoff - segment.offset);
+        let mut random = [0u8; 16];
+        process.fill_random(&mut random);
+        let sp = LinuxProcess::setup_stack(&mut cpu.bus.dram, memory_end, args, env, &random, |execfn, random| vec![
+            (AT_PHDR, phdr),
+            (AT_PHENT, elf.phentsize as u64),
+            (AT_PHNUM, elf.phnum as u64),
+            (AT_PAGESZ, USER_PAGE_SIZE),
+            (AT_BASE, 0),
+            (AT_ENTRY, elf.entry),
+            (AT_UID, 0),
+            (AT_EUID, 0),
+            (AT_GID, 0),
+            (AT_EGID, 0),
+            (AT_HWCAP, HWCAP_RV64GC),
+            (AT_CLKTCK, 100),
+            (AT_SECURE, 0),
+            (AT_RANDOM, random),
+            (AT_EXECFN, execfn),
+            (AT_NULL, 0),
+        ])?;
+        info!("Linux: entry {:#x}, stack pointer {:#x}, program break {:#x}", elf.entry, sp, brk_start);
+
+        cpu.set_register(2, sp);
+        cpu.set_pc(elf.entry);
+        cpu.set_privilege(Privilege::User);
+        Ok(process)
+    }
+
+    /// Replaces the host streams used for the guest's stdout and stderr
+    pub fn set_output(&mut self, stdout: Box<dyn Write>, stderr: Box<dyn Write>) {
+        self.files.set_output(stdout, stderr);
+    }
+
+    pub fn set_input(&mut self, stdin: Box<dyn Read>) {
+        self.files.set_input(stdin);
+    }
+
+    /// Writes the strings and the argc/argv/envp/auxv table below `top`, returns the initial stack pointer
+    fn setup_stack(dram: &mut DramMemory, top: u64, args: &[String], env: &[String], random: &[u8],
+        auxv: impl FnOnce(u64, u64) -> Vec<(u64, u64)>) -> Result<u64, LinuxError> {
+        let stack_bottom = top - USER_STACK_SIZE;
+        let mut sp = top;
+        let mut push = |bytes: &[u8]| -> Result<u64, LinuxError> {
+            sp = sp.checked_sub(bytes.len() as u64).filter(|sp| *sp >= stack_bottom).ok_or(LinuxError::StackOverflow)?;
+            write_guest(dram, sp, bytes);
+            Ok(sp)
+        };
+        let random = push(random)?;
+        let mut push_string = |string: &String| push(&[string.as_bytes(), &[0]].concat());
+        let arg_pointers = args.iter().map(&mut push_string).collect::<Result<Vec<u64>, _>>()?;
+        let env_pointers = env.iter().map(&mut push_string).collect::<Result<Vec<u64>, _>>()?;
+        let execfn = arg_pointers.first().copied().unwrap_or(0);
+
+        let mut table = vec![args.len() as u64];
+        table.extend(&arg_pointers);
+        table.push(0);
+        table.extend(&env_pointers);
+        table.push(0);
+        for (key, value) in auxv(execfn, random) {
+            table.extend([key, value]);
+        }
+        let bytes: Vec<u8> = table.iter().flat_map(|word| word.to_le_bytes()).collect();
+        // The ABI requires a 16-byte aligned stack pointer pointing at argc
+        let sp = sp.checked_sub(bytes.len() as u64).map(|sp| sp & !0xf).filter(|sp| *sp >= stack_bottom).ok_or(LinuxError::StackOverflow)?;
+        write_guest(dram, sp, &bytes);
+        Ok(sp)
+    }
+
+    /// xorshift64* for getrandom and AT_RANDOM, guests only use it to seed stack protectors and hash tables
+    fn fill_random(&mut self, buffer: &mut [u8]) {
+        for byte in buffer {
+            self.random_state ^= self.random_state >> 12;
+            self.random_state ^= self.random_state << 25;
+            self.random_state ^= self.random_state >> 27;
+            *byte = (self.random_state.wrapping_mul(0x2545f4914f6cdd1d) >> 56) as u8;
+        }
+    }
+
+    fn sys_read(&mut self, dram: &mut DramMemory, fd: i64, buf: u64, count: u64) -> i64 {
+        match self.files.read(fd, count.min(1 << 20) as usize) {
+            Ok(bytes) if write_guest(dram, buf, &bytes) => bytes.len() as i64,
+            Ok(_) => -EFAULT,
+            Err(err) => err,
+        }
+    }
+
+    fn sys_write(&mut self, dram: &DramMemory, fd: i64, buf: u64, count: u64) -> i64 {
+        let Some(bytes) = read_guest(dram, buf, count as usize) else {
+            return -EFAULT;
+        };
+        self.files.write(fd, bytes).map_or_else(|err| err, |written| written as i64)
+    }
+
+    /// readv and writev: an array of `iovcnt` (base, len) pairs at `iov`
+    fn sys_vector_io(&mut self, dram: &mut DramMemory, fd: i64, iov: u64, iovcnt: u64, write: bool) -> i64 {
+        let mut total = 0;
+        for i in 0..iovcnt {
+            let Some(entry) = read_guest(dram, iov + 16 * i, 16) else {
+                return -EFAULT;
+            };
+            let base = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+            let len = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+            let done = if write { self.sys_write(dram, fd, base, len) } else { self.sys_read(dram, fd, base, len) };
+            if done < 0 {
+                return if total > 0 { total } else { done };
+            }
+            total += done;
+            if (done as u64) < len {
+                break;
+            }
+        }
+        total
+    }
+
+    fn sys_openat(&mut self, dram: &DramMemory, dirfd: i64, path: u64, flags: u64, mode: u64) -> i64 {
+        let Some(path) = read_guest_string(dram, path) else {
+            return -EFAULT;
+        };
+        if dirfd != AT_FDCWD && !path.starts_with('/') {
+            warn!("Linux: openat relative to directory descriptor {dirfd} is not supported");
+            return -EBADF;
+        }
+        let mut options = OpenOptions::new();
+        match flags & O_ACCMODE {
+            O_WRONLY => options.write(true),
+            O_RDWR => options.read(true).write(true),
+            _ => options.read(true),
+        };
+        options.append(flags & O_APPEND != 0).truncate(flags & O_TRUNC != 0);
+        if flags & O_CREAT != 0 {
+            if flags & O_EXCL != 0 { options.create_new(true) } else { options.create(true) };
+        }
+        info!("Linux: openat {path:?}, flags {flags:#o}, mode {mode:#o}");
+        self.files.open(&path, &options)
+    }
+
+    fn sys_newfstatat(&mut self, dram: &mut DramMemory, dirfd: i64, path: u64, statbuf: u64, flags: u64) -> i64 {
+        let Some(path) = read_guest_string(dram, path) else {
+            return -EFAULT;
+        };
+        let stat = if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+            self.files.fstat(dirfd)
+        } else {
+            self.files.stat(&path)
+        };
+        match stat {
+            Ok(stat) if write_guest(dram, statbuf, &stat) => 0,
+            Ok(_) => -EFAULT,
+            Err(err) => err,
+        }
+    }
+
+    fn sys_brk(&mut self, dram: &mut DramMemory, addr: u64) -> i64 {
+        if addr >= self.brk_start && addr <= self.mmap_bottom {
+            if addr > self.brk {
+                // Memory released by shrinking the heap reads as zero when it is grown again
+                write_guest(dram, self.brk, &vec![0; (addr - self.brk) as usize]);
+            }
+            self.brk = addr;
+        }
+        self.brk as i64
+    }
+
+    fn sys_mmap(&mut self, dram: &mut DramMemory, addr: u64, len: u64, flags: u64, fd: i64, offset: u64) -> i64 {
+        let len = page_align_up(len);
+        if len == 0 {
+            return -EINVAL;
+        }
+        let start = if flags & MAP_FIXED != 0 {
+            if !addr.is_multiple_of(USER_PAGE_SIZE) || read_guest(dram, addr, len as usize).is_none() {
+                return -EINVAL;
+            }
+            addr
+        } else {
+            match self.mmap_bottom.checked_sub(len).filter(|start| *start >= self.brk) {
+                Some(start) => {
+                    self.mmap_bottom = start;
+                    start
+                },
+                None => return -ENOMEM,
+            }
+        };
+        write_guest(dram, start, &vec![0; len as usize]);
+        if flags & MAP_ANONYMOUS == 0 {
+            // Private file mapping: a copy of the file contents
+            let contents = match self.files.host_file(fd) {
+                Ok(file) => {
+                    let mut contents = Vec::new();
+                    let read = file.stream_position().and_then(|position| {
+                        file.seek(SeekFrom::Start(offset))?;
+                        Read::by_ref(file).take(len).read_to_end(&mut contents)?;
+                        file.seek(SeekFrom::Start(position))
+                    });
+                    if let Err(err) = read {
+                        return errno(&err);
+                    }
+                    contents
+                },
+                Err(err) => return err,
+            };
+            write_guest(dram, start, &contents);
+        }
+        info!("Linux: mmap {len:#x} bytes at {start:#x}");
+        start as i64
+    }
+
+    fn sys_clock_gettime(&self, dram: &mut DramMemory, clock: u64, tp: u64) -> i64 {
+        let time = match clock {
+            0 | 5 => SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default(), // CLOCK_REALTIME(_COARSE)
+            _ => self.start_time.elapsed(), // monotonic and CPU time clocks count from the start of the process
+        };
+        let timespec = [time.as_secs().to_le_bytes(), (time.subsec_nanos() as u64).to_le_bytes()].concat();
+        if write_guest(dram, tp, &timespec) { 0 } else { -EFAULT }
+    }
+
+    fn sys_gettimeofday(&self, dram: &mut DramMemory, tv: u64) -> i64 {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let timeval = [time.as_secs().to_le_bytes(), (time.subsec_micros() as u64).to_le_bytes()].concat();
+        if tv == 0 || write_guest(dram, tv, &timeval) { 0 } else { -EFAULT }
+    }
+
+    fn sys_uname(&self, dram: &mut DramMemory, buf: u64) -> i64 {
+        // struct utsname: sysname, nodename, release, version, machine and domainname, 65 bytes each
+        let mut utsname = [0u8; 6 * 65];
+        for (i, field) in ["Linux", "riscv-emu", "6.1.0", "#1", "riscv64", ""].iter().enumerate() {
+            utsname[i * 65..i * 65 + field.len()].copy_from_slice(field.as_bytes());
+        }
+        if write_guest(dram, buf, &utsname) { 0 } else { -EFAULT }
+    }
+
+    fn sys_readlinkat(&self, dram: &mut DramMemory, path: u64, buf: u64, size: u64) -> i64 {
+        match read_guest_string(dram, path) {
+            Some(path) if path == "/proc/self/exe" => {
+                let target = fs::canonicalize(&self.executable).map_or_else(|_| self.executable.clone(), |path| path.to_string_lossy().into_owned());
+                let bytes = &target.as_bytes()[..target.len().min(size as usize)];
+                if write_guest(dram, buf, bytes) { bytes.len() as i64 } else { -EFAULT }
+            },
+            Some(path) => fs::read_link(&path).map_or_else(|err| errno(&err), |target| {
+                let target = target.to_string_lossy().into_owned();
+                let bytes = &target.as_bytes()[..target.len().min(size as usize)];
+                if write_guest(dram, buf, bytes) { bytes.len() as i64 } else { -EFAULT }
+            }),
+            None => -EFAULT,
+        }
+    }
+
+    fn sys_prlimit64(&self, dram: &mut DramMemory, resource: u64, old_limit: u64) -> i64 {
+        const RLIMIT_STACK: u64 = 3;
+        let limit = if resource == RLIMIT_STACK { USER_STACK_SIZE } else { u64::MAX };
+        let rlimit = [limit.to_le_bytes(), limit.to_le_bytes()].concat(); // soft and hard limit
+        if old_limit == 0 || write_guest(dram, old_limit, &rlimit) { 0 } else { -EFAULT }
+    }
+
+    fn sys_getrandom(&mut self, dram: &mut DramMemory, buf: u64, len: u64) -> i64 {
+        let mut bytes = vec![0; len.min(1 << 20) as usize];
+        self.fill_random(&mut bytes);
+        if write_guest(dram, buf, &bytes) { bytes.len() as i64 } else { -EFAULT }
+    }
+}
+
+impl SyscallHandler for LinuxProcess {
+    fn syscall(&mut self, cpu: &mut BasicCpu) -> SyscallOutcome {
+        let (number, [a0, a1, a2, a3, a4, a5]) = syscall_args(cpu);
+        info!("Linux: syscall {number} ({a0:#x}, {a1:#x}, {a2:#x}, {a3:#x}, {a4:#x}, {a5:#x})");
+        let dram = &mut cpu.bus.dram;
+        let result: i64 = match number {
+            SYS_READ => self.sys_read(dram, a0 as i64, a1, a2),
+            SYS_WRITE => self.sys_write(dram, a0 as i64, a1, a2),
+            SYS_READV => self.sys_vector_io(dram, a0 as i64, a1, a2, false),
+            SYS_WRITEV => self.sys_vector_io(dram, a0 as i64, a1, a2, true),
+            SYS_OPENAT => self.sys_openat(dram, a0 as i64, a1, a2, a3),
+            SYS_CLOSE => self.files.close(a0 as i64),
+            SYS_LSEEK => self.files.seek(a0 as i64, a1 as i64, a2),
+            SYS_FSTAT => match self.files.fstat(a0 as i64) {
+                Ok(stat) if write_guest(dram, a1, &stat) => 0,
+                Ok(_) => -EFAULT,
+                Err(err) => err,
+            },
+            SYS_NEWFSTATAT => self.sys_newfstatat(dram, a0 as i64, a1, a2, a3),
+            SYS_FACCESSAT => match read_guest_string(dram, a1) {
+                Some(path) => self.files.stat(&path).map_or_else(|err| err, |_| 0),
+                None => -EFAULT,
+            },
+            SYS_READLINKAT => self.sys_readlinkat(dram, a1, a2, a3),
+            SYS_GETCWD => match std::env::current_dir() {
+                Ok(dir) => {
+                    let bytes = [dir.to_string_lossy().as_bytes(), &[0]].concat();
+                    if bytes.len() as u64 > a1 { -EINVAL } else if write_guest(dram, a0, &bytes) { bytes.len() as i64 } else { -EFAULT }
+                },
+                Err(err) => errno(&err),
+            },
+            SYS_IOCTL => -ENOTTY, // no terminal, stdio is treated as a pipe
+            SYS_BRK => self.sys_brk(dram, a0),
+            SYS_MMAP => self.sys_mmap(dram, a0, a1, a3, a4 as i64, a5),
+            SYS_MUNMAP | SYS_MPROTECT | SYS_MADVISE => 0, // mappings are never reused, permissions are not enforced
+            SYS_MREMAP => -ENOMEM, // callers fall back to mmap and copy
+            SYS_EXIT | SYS_EXIT_GROUP => {
+                info!("Linux: exit with code {}", a0 as i32);
+                return SyscallOutcome::Exit(a0 as i32 as i64);
+            },
+            SYS_TGKILL => {
+                // Signals can not be delivered, the default action of the signals raise() sends is to terminate
+                info!("Linux: terminated by signal {a2}");
+                return SyscallOutcome::Exit(128 + a2 as i64);
+            },
+            SYS_CLOCK_GETTIME => self.sys_clock_gettime(dram, a0, a1),
+            SYS_GETTIMEOFDAY => self.sys_gettimeofday(dram, a0),
+            SYS_UNAME => self.sys_uname(dram, a0),
+            SYS_PRLIMIT64 => self.sys_prlimit64(dram, a1, a3),
+            SYS_GETRANDOM => self.sys_getrandom(dram, a0, a1),
+            SYS_SET_TID_ADDRESS | SYS_GETPID | SYS_GETTID => USER_PID,
+            SYS_GETPPID => USER_PID - 1,
+            SYS_GETUID | SYS_GETEUID | SYS_GETGID | SYS_GETEGID => 0,
+            SYS_SET_ROBUST_LIST | SYS_RT_SIGACTION | SYS_RT_SIGPROCMASK | SYS_SIGALTSTACK | SYS_FUTEX => 0, // single threaded, no signals
+            _ => {
+                warn!("Linux: unimplemented syscall {number}");
+                -ENOSYS
+            },
+        };
+        cpu.set_register(REG_A0, result as TReg);
+        SyscallOutcome::Return
+    }
+}
@@ -0,0 +1,157 @@
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cpu::basic_cpu::{BasicCpu, TReg};
+use crate::loader::elf::ElfFile;
+use crate::memory::dram::DramMemory;
+use crate::os::host_files::HostFiles;
+use crate::os::syscall::*;
+use log::{info, warn};
+
+/// Top of DRAM left to the stack, the heap (brk) may grow up to it
+pub const PK_STACK_SIZE: u64 = 1024 * 1024;
+const PK_PAGE_SIZE: u64 = 4096;
+
+// Syscall numbers of the riscv-pk ABI used by newlib's libgloss
+pub const SYS_OPENAT: u64 = 56;
+pub const SYS_CLOSE: u64 = 57;
+pub const SYS_LSEEK: u64 = 62;
+pub const SYS_READ: u64 = 63;
+pub const SYS_WRITE: u64 = 64;
+pub const SYS_FSTAT: u64 = 80;
+pub const SYS_EXIT: u64 = 93;
+pub const SYS_EXIT_GROUP: u64 = 94;
+pub const SYS_GETTIMEOFDAY: u64 = 169;
+pub const SYS_BRK: u64 = 214;
+pub const SYS_OPEN: u64 = 1024;
+
+// open flags of newlib (sys/_default_fcntl.h), they differ from the Linux values
+const AT_FDCWD: i64 = -100;
+const O_ACCMODE: u64 = 0x3;
+const O_WRONLY: u64 = 0x1;
+const O_RDWR: u64 = 0x2;
+const O_APPEND: u64 = 0x0008;
+const O_CREAT: u64 = 0x0200;
+const O_TRUNC: u64 = 0x0400;
+const O_EXCL: u64 = 0x0800;
+
+/// Proxy kernel (riscv-pk) emulation for bare-metal newlib programs: ECALLs are serviced on the host,
+/// files are opened below a sandbox root directory.
+pub struct ProxyKernel {
+    files: HostFiles,
+    brk_start: u64,
+    brk: u64,
+    brk_end: u64,
+}
+
+impl ProxyKernel {
+    /// The heap starts after the highest segment of `elf` and ends `PK_STACK_SIZE` below the end of `dram`
+    pub fn new(elf: &ElfFile, dram: &DramMemory, sandbox_root: PathBuf) -> ProxyKernel {
+        let image_end = elf.segments.iter().map(|segment| segment.vaddr + segment.memsz).max().unwrap_or(dram.base() as u64);
+        let brk_start = image_end.div_ceil(PK_PAGE_SIZE) * PK_PAGE_SIZE;
+        let brk_end = (dram.base() + dram.size()) as u64 - PK_STACK_SIZE;
+        info!("pk: program break {brk_start:#x}, heap limit {brk_end:#x}, sandbox {}", sandbox_root.display());
+        ProxyKernel {
+            files: HostFiles::sandboxed(sandbox_root),
+            brk_start,
+            brk: brk_start,
+            brk_end,
+        }
+    }
+
+    /// Replaces the host streams used for the guest's stdout and stderr
+    pub fn set_output(&mut self, stdout: Box<dyn Write>, stderr: Box<dyn Write>) {
+        self.files.set_output(stdout, stderr);
+    }
+
+    /// Replaces the host stream read as the guest's stdin
+    pub fn set_input(&mut self, stdin: Box<dyn Read>) {
+        self.files.set_input(stdin);
+    }
+
+    fn sys_read(&mut self, dram: &mut DramMemory, fd: i64, buf: u64, count: u64) -> i64 {
+        match self.files.read(fd, count.min(1 << 20) as usize) {
+            Ok(bytes) if write_guest(dram, buf, &bytes) => bytes.len() as i64,
+            Ok(_) => -EFAULT,
+            Err(err) => err,
+        }
+    }
+
+    fn sys_write(&mut self, dram: &DramMemory, fd: i64, buf: u64, count: u64) -> i64 {
+        let Some(bytes) = read_guest(dram, buf, count as usize) else {
+            return -EFAULT;
+        };
+        self.files.write(fd, bytes).map_or_else(|err| err, |written| written as i64)
+    }
+
+    fn sys_open(&mut self, dram: &DramMemory, path: u64, flags: u64, mode: u64) -> i64 {
+        let Some(path) = read_guest_string(dram, path) else {
+            return -EFAULT;
+        };
+        let mut options = OpenOptions::new();
+        match flags & O_ACCMODE {
+            O_WRONLY => options.write(true),
+            O_RDWR => options.read(true).write(true),
+            _ => options.read(true),
+        };
+        options.append(flags & O_APPEND != 0).truncate(flags & O_TRUNC != 0);
+        if flags & O_CREAT != 0 {
+            if flags & O_EXCL != 0 { options.create_new(true) } else { options.create(true) };
+        }
+        info!("pk: open {path:?}, flags {flags:#x}, mode {mode:#o}");
+        self.files.open(&path, &options)
+    }
+
+    fn sys_brk(&mut self, dram: &mut DramMemory, addr: u64) -> i64 {
+        if addr >= self.brk_start && addr <= self.brk_end {
+            if addr > self.brk {
+                // Memory released by shrinking the heap reads as zero when it is grown again
+                write_guest(dram, self.brk, &vec![0; (addr - self.brk) as usize]);
+            }
+            self.brk = addr;
+        }
+        self.brk as i64
+    }
+
+    fn sys_gettimeofday(&self, dram: &mut DramMemory, tv: u64) -> i64 {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let timeval = [time.as_secs().to_le_bytes(), (time.subsec_micros() as u64).to_le_bytes()].concat();
+        if tv == 0 || write_guest(dram, tv, &timeval) { 0 } else { -EFAULT }
+    }
+}
+
+impl SyscallHandler for ProxyKernel {
+    fn syscall(&mut self, cpu: &mut BasicCpu) -> SyscallOutcome {
+        let (number, [a0, a1, a2, a3, ..]) = syscall_args(cpu);
+        info!("pk: syscall {number} ({a0:#x}, {a1:#x}, {a2:#x}, {a3:#x})");
+        let dram = &mut cpu.bus.dram;
+        let result: i64 = match number {
+            SYS_READ => self.sys_read(dram, a0 as i64, a1, a2),
+            SYS_WRITE => self.sys_write(dram, a0 as i64, a1, a2),
+            SYS_OPEN => self.sys_open(dram, a0, a1, a2),
+            SYS_OPENAT if a0 as i64 == AT_FDCWD => self.sys_open(dram, a1, a2, a3),
+            SYS_OPENAT => -EBADF, // no directory descriptors
+            SYS_CLOSE => self.files.close(a0 as i64),
+            SYS_LSEEK => self.files.seek(a0 as i64, a1 as i64, a2),
+            SYS_FSTAT => match self.files.fstat(a0 as i64) {
+                Ok(stat) if write_guest(dram, a1, &stat) => 0,
+                Ok(_) => -EFAULT,
+                Err(err) => err,
+            },
+            SYS_BRK => self.sys_brk(dram, a0),
+            SYS_GETTIMEOFDAY => self.sys_gettimeofday(dram, a0),
+            SYS_EXIT | SYS_EXIT_GROUP => {
+                info!("pk: exit with code {}", a0 as i32);
+                return SyscallOutcome::Exit(a0 as i32 as i64);
+            },
+            _ => {
+                warn!("pk: unimplemented syscall {number}");
+                -ENOSYS
+            },
+        };
+        cpu.set_register(REG_A0, result as TReg);
+        SyscallOutcome::Return
+    }
+}
@@ -0,0 +1,168 @@
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::cpu::basic_cpu::{BasicCpu, TReg};
+use crate::memory::dram::DramMemory;
+use crate::os::host_files::HostFiles;
+use crate::os::syscall::{read_guest, read_guest_string, write_guest, SyscallHandler, SyscallOutcome, REG_A0};
+use log::{info, warn};
+
+// Semihosting operation numbers (ARM semihosting, shared by the RISC-V semihosting spec)
+pub const SYS_OPEN: TReg = 0x01;
+pub const SYS_CLOSE: TReg = 0x02;
+pub const SYS_WRITE0: TReg = 0x04;
+pub const SYS_WRITE: TReg = 0x05;
+pub const SYS_READ: TReg = 0x06;
+pub const SYS_CLOCK: TReg = 0x10;
+pub const SYS_GET_CMDLINE: TReg = 0x15;
+pub const SYS_EXIT: TReg = 0x18;
+
+/// SYS_EXIT reason of a program that finished normally, the exit code is the subcode
+pub const ADP_STOPPED_APPLICATION_EXIT: TReg = 0x20026;
+/// Name which opens the debug console instead of a host file
+const CONSOLE_NAME: &[u8] = b":tt";
+const REG_A1: usize = 11;
+
+/// Semihosting for bare-metal programs: the operation is in a0, a1 points to its parameter block
+/// (XLEN sized fields) and the result is returned in a0. Files are opened below a sandbox root directory.
+pub struct Semihosting {
+    files: HostFiles,
+    cmdline: String,
+    start_time: Instant,
+}
+
+impl Semihosting {
+    /// `cmdline` is returned by SYS_GET_CMDLINE
+    pub fn new(cmdline: String, sandbox_root: PathBuf) -> Semihosting {
+        Semihosting {
+            files: HostFiles::sandboxed(sandbox_root),
+            cmdline,
+            start_time: Instant::now(),
+        }
+    }
+
+    /// Replaces the host streams used for the guest's stdout and stderr
+    pub fn set_output(&mut self, stdout: Box<dyn Write>, stderr: Box<dyn Write>) {
+        self.files.set_output(stdout, stderr);
+    }
+
+    /// Replaces the host stream read as the guest's stdin
+    pub fn set_input(&mut self, stdin: Box<dyn Read>) {
+        self.files.set_input(stdin);
+    }
+
+    /// The first `N` fields of the parameter block at `block`
+    fn parameters<const N: usize>(dram: &DramMemory, block: TReg) -> Option<[TReg; N]> {
+        let bytes = read_guest(dram, block, 8 * N)?;
+        Some(std::array::from_fn(|i| TReg::from_le_bytes(bytes[8 * i..8 * i + 8].try_into().unwrap())))
+    }
+
+    /// Parameters: name, mode (fopen mode 0-11: r, rb, r+, r+b, w, wb, w+, w+b, a, ab, a+, a+b), name length
+    fn sys_open(&mut self, dram: &DramMemory, block: TReg) -> i64 {
+        let Some([name, mode, len]) = Semihosting::parameters(dram, block) else {
+            return -1;
+        };
+        let Some(name) = read_guest(dram, name, len as usize) else {
+            return -1;
+        };
+        if name == CONSOLE_NAME {
+            return match mode {
+                0..=3 => 0, // stdin
+                4..=7 => 1, // stdout
+                _ => 2, // stderr
+            };
+        }
+        let mut options = OpenOptions::new();
+        match mode {
+            0 | 1 => options.read(true),
+            2 | 3 => options.read(true).write(true),
+            4 | 5 => options.write(true).create(true).truncate(true),
+            6 | 7 => options.read(true).write(true).create(true).truncate(true),
+            8 | 9 => options.append(true).create(true),
+            10 | 11 => options.read(true).append(true).create(true),
+            _ => return -1,
+        };
+        let name = String::from_utf8_lossy(name).into_owned();
+        info!("Semihosting: open {name:?}, mode {mode}");
+        self.files.open(&name, &options).max(-1)
+    }
+
+    /// Parameters: handle, buffer, length; returns the number of bytes not written
+    fn sys_write(&mut self, dram: &DramMemory, block: TReg) -> i64 {
+        let Some([handle, buf, len]) = Semihosting::parameters(dram, block) else {
+            return -1;
+        };
+        let Some(bytes) = read_guest(dram, buf, len as usize) else {
+            return -1;
+        };
+        match self.files.write(handle as i64, bytes) {
+            Ok(written) => (len - written as TReg) as i64,
+            Err(_) => len as i64,
+        }
+    }
+
+    /// Parameters: handle, buffer, length; returns the number of bytes not read (length at end of file)
+    fn sys_read(&mut self, dram: &mut DramMemory, block: TReg) -> i64 {
+        let Some([handle, buf, len]) = Semihosting::parameters(dram, block) else {
+            return -1;
+        };
+        match self.files.read(handle as i64, len.min(1 << 20) as usize) {
+            Ok(bytes) if write_guest(dram, buf, &bytes) => (len - bytes.len() as TReg) as i64,
+            _ => -1,
+        }
+    }
+
+    /// Parameters: buffer, size; the command line is copied NUL terminated and the size field set to its length
+    fn sys_get_cmdline(&self, dram: &mut DramMemory, block: TReg) -> i64 {
+        let Some([buf, size]) = Semihosting::parameters(dram, block) else {
+            return -1;
+        };
+        let cmdline = [self.cmdline.as_bytes(), &[0]].concat();
+        if cmdline.len() as TReg > size || !write_guest(dram, buf, &cmdline) {
+            return -1;
+        }
+        write_guest(dram, block + 8, &(self.cmdline.len() as TReg).to_le_bytes());
+        0
+    }
+}
+
+impl SyscallHandler for Semihosting {
+    fn syscall(&mut self, cpu: &mut BasicCpu) -> SyscallOutcome {
+        let (operation, parameter) = (cpu.get_register(REG_A0), cpu.get_register(REG_A1));
+        info!("Semihosting: operation {operation:#x} ({parameter:#x})");
+        let dram = &mut cpu.bus.dram;
+        let result: i64 = match operation {
+            SYS_OPEN => self.sys_open(dram, parameter),
+            SYS_CLOSE => match Semihosting::parameters(dram, parameter) {
+                Some([handle]) if handle < 3 => 0, // the console stays open
+                Some([handle]) => self.files.close(handle as i64).max(-1),
+                None => -1,
+            },
+            SYS_WRITE0 => match read_guest_string(dram, parameter) {
+                Some(text) => self.files.write(1, text.as_bytes()).map_or(-1, |_| 0),
+                None => -1,
+            },
+            SYS_WRITE => self.sys_write(dram, parameter),
+            SYS_READ => self.sys_read(dram, parameter),
+            SYS_CLOCK => (self.start_time.elapsed().as_millis() / 10) as i64, // centiseconds
+            SYS_GET_CMDLINE => self.sys_get_cmdline(dram, parameter),
+            SYS_EXIT => {
+                // RV64 passes the reason and the exit code in a parameter block
+                let code = match Semihosting::parameters(dram, parameter) {
+                    Some([ADP_STOPPED_APPLICATION_EXIT, subcode]) => subcode as i32 as i64,
+                    _ => 1,
+                };
+                info!("Semihosting: exit with code {code}");
+                return SyscallOutcome::Exit(code);
+            },
+            _ => {
+                warn!("Semihosting: unimplemented operation {operation:#x}");
+                -1
+            },
+        };
+        cpu.set_register(REG_A0, result as TReg);
+        SyscallOutcome::Return
+    }
+}
@@ -0,0 +1,80 @@
+use crate::cpu::basic_cpu::{BasicCpu, TReg};
+use crate::memory::dram::DramMemory;
+
+// Registers of the syscall ABI: number in a7, arguments in a0-a5, result in a0
+pub const REG_A0: usize = 10;
+pub const REG_A7: usize = 17;
+
+// errno values shared by the Linux and proxy kernel ABIs, syscalls return them negated
+pub const ENOENT: i64 = 2;
+pub const EIO: i64 = 5;
+pub const EBADF: i64 = 9;
+pub const ENOMEM: i64 = 12;
+pub const EACCES: i64 = 13;
+pub const EFAULT: i64 = 14;
+pub const EINVAL: i64 = 22;
+pub const ENOTTY: i64 = 25;
+pub const ESPIPE: i64 = 29;
+pub const ENOSYS: i64 = 38;
+
+/// What the CPU does after a system call was serviced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallOutcome {
+    /// Continue after the ECALL, results have been written to the guest registers
+    Return,
+    /// The guest terminated with the given exit code
+    Exit(i64),
+}
+
+/// Host side implementation of the system calls of a guest (e.g. Linux user-mode emulation).
+/// Installed with `BasicCpu::set_syscall_handler`, ECALL then calls the handler instead of trapping.
+pub trait SyscallHandler {
+    fn syscall(&mut self, cpu: &mut BasicCpu) -> SyscallOutcome;
+}
+
+/// Syscall number and the six arguments
+pub fn syscall_args(cpu: &BasicCpu) -> (TReg, [TReg; 6]) {
+    let args = std::array::from_fn(|i| cpu.get_register(REG_A0 + i));
+    (cpu.get_register(REG_A7), args)
+}
+
+fn guest_range(dram: &DramMemory, addr: TReg, len: usize) -> Option<std::ops::Range<usize>> {
+    let addr = usize::try_from(addr).ok()?;
+    if !dram.contains(addr, len.checked_mul(8)?) {
+        return None;
+    }
+    let start = addr - dram.base();
+    Some(start..start + len)
+}
+
+/// Guest memory at `addr`, None if it is not backed by DRAM
+pub fn read_guest(dram: &DramMemory, addr: TReg, len: usize) -> Option<&[u8]> {
+    guest_range(dram, addr, len).map(|range| &dram.mem[range])
+}
+
+/// Copies `bytes` to guest memory, false if it is not backed by DRAM
+pub fn write_guest(dram: &mut DramMemory, addr: TReg, bytes: &[u8]) -> bool {
+    match guest_range(dram, addr, bytes.len()) {
+        Some(range) => {
+            dram.mem[range].copy_from_slice(bytes);
+            true
+        },
+        None => false,
+    }
+}
+
+/// NUL terminated string at `addr`
+pub fn read_guest_string(dram: &DramMemory, addr: TReg) -> Option<String> {
+    let start = guest_range(dram, addr, 0)?.start;
+    let len = dram.mem[start..].iter().position(|byte| *byte == 0)?;
+    Some(String::from_utf8_lossy(&dram.mem[start..start + len]).into_owned())
+}
+
+/// Ends the program at the first ECALL with the exit code in a0, for bare-metal tests without an exit mechanism
+pub struct ExitOnEcall;
+
+impl SyscallHandler for ExitOnEcall {
+    fn syscall(&mut self, cpu: &mut BasicCpu) -> SyscallOutcome {
+        SyscallOutcome::Exit(cpu.get_register(REG_A0) as i32 as i64)
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+use std::io;
+
+use crate::cpu::basic_cpu::BasicCpu;
+use crate::cpu::error::ExecError;
+use crate::cpu::isa::Isa;
+use crate::devices::htif::Htif;
+use crate::loader::elf::{ElfError, ElfFile};
+use crate::memory::dram::DramMemory;
+use log::info;
+
+pub const DEFAULT_MAX_STEPS: u64 = 10_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComplianceError {
+    Elf(ElfError),
+    /// Test binary does not define a symbol the harness needs
+    MissingSymbol(&'static str),
+    /// Error which was not handled by a trap handler of the test
+    Exec(ExecError),
+    /// The test did not write to tohost within the step limit
+    Timeout { steps: u64 },
+}
+
+impl fmt::Display for ComplianceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComplianceError::Elf(err) => write!(f, "{err}"),
+            ComplianceError::MissingSymbol(name) => write!(f, "missing symbol {name}"),
+            ComplianceError::Exec(err) => write!(f, "{err}"),
+            ComplianceError::Timeout { steps } => write!(f, "no exit after {steps} steps"),
+        }
+    }
+}
+
+impl std::error::Error for ComplianceError {}
+
+/// First line where a signature differs from its reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureMismatch {
+    pub line: usize,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl fmt::Display for SignatureMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |word: &Option<String>| word.clone().unwrap_or_else(|| "<missing>".to_string());
+        write!(f, "line {}: expected {}, got {}", self.line, show(&self.expected), show(&self.actual))
+    }
+}
+
+/// Result of a test which halted through HTIF.
+pub struct TestRun {
+    pub exit_code: u64,
+    pub signature: Vec<u32>,
+}
+
+/// Steps the CPU until the guest exits through HTIF. Unhandled errors end the run.
+pub fn run_to_exit(cpu: &mut BasicCpu, htif: &mut Htif, max_steps: u64) -> Result<u64, ComplianceError> {
+    for _ in 0..max_steps {
+        if let Some(trap) = cpu.step()
+            && !trap.handled {
+            return Err(ComplianceError::Exec(trap.exception));
+        }
+        if let Some(code) = htif.poll(&mut cpu.bus.dram) {
+            return Ok(code);
+        }
+    }
+    Err(ComplianceError::Timeout { steps: max_steps })
+}
+
+/// Words between `begin` (inclusive) and `end` (exclusive).
+pub fn read_signature(dram: &DramMemory, begin: u64, end: u64) -> Vec<u32> {
+    (begin..end)
+        .step_by(4)
+        .filter(|addr| dram.contains(*addr as usize, 32))
+        .map(|addr| dram.dram_read(addr as usize, 32) as u32)
+        .collect()
+}
+
+/// Standard riscv-arch-test signature format: one word per line, 8 lowercase hex digits.
+pub fn format_signature(signature: &[u32]) -> String {
+    signature.iter().map(|word| format!("{word:08x}\n")).collect()
+}
+
+/// Compares a signature with a reference file (case and surrounding whitespace are ignored).
+pub fn compare_signature(actual: &str, reference: &str) -> Result<(), SignatureMismatch> {
+    let words = |text: &str| -> Vec<String> {
+        text.lines().map(|line| line.trim().to_lowercase()).filter(|line| !line.is_empty()).collect()
+    };
+    let (actual, reference) = (words(actual), words(reference));
+    for line in 0..actual.len().max(reference.len()) {
+        if actual.get(line) != reference.get(line) {
+            return Err(SignatureMismatch {
+                line: line + 1,
+                expected: reference.get(line).cloned(),
+                actual: actual.get(line).cloned(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Loads and runs a riscv-arch-test ELF, then reads its signature.
+pub fn run_test(binary: &[u8], max_steps: u64) -> Result<TestRun, ComplianceError> {
+    let elf = ElfFile::parse(binary).map_err(ComplianceError::Elf)?;
+    let symbol = |name: &'static str| elf.symbol(name).ok_or(ComplianceError::MissingSymbol(name));
+    let tohost = symbol("tohost")?;
+    let (begin, end) = (symbol("begin_signature")?, symbol("end_signature")?);
+
+    let mut cpu = BasicCpu::new();
+    if elf.xlen == 32 {
+        cpu.set_isa(Isa::parse("rv32imafdc").unwrap());
+    }
+    elf.load(&mut cpu.bus.dram).map_err(ComplianceError::Elf)?;
+    cpu.init();
+    cpu.set_pc(elf.entry);
+    let mut htif = Htif::with_output(tohost, elf.symbol("fromhost"), Box::new(io::stdout()));
+
+    let exit_code = run_to_exit(&mut cpu, &mut htif, max_steps)?;
+    info!("Test exited with code {exit_code}, signature {begin:#x}..{end:#x}");
+    Ok(TestRun { exit_code, signature: read_signature(&cpu.bus.dram, begin, end) })
+}
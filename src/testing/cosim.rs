@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use crate::cpu::basic_cpu::BasicCpu;
+use crate::cpu::commit_log::{Commit, CommitEntry, CommitLog};
+use crate::cpu::error::ExecError;
+use crate::devices::htif::Htif;
+use log::info;
+
+/// Steps allowed without a retired instruction (traps and interrupts) before giving up.
+pub const MAX_STEPS_WITHOUT_COMMIT: usize = 16;
+
+/// First retired instruction where the emulator and the reference disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Number of instructions which matched before this one
+    pub instret: u64,
+    pub reason: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "divergence in {} after {} instructions", self.reason, self.instret)?;
+        writeln!(f, "  reference: {}", self.expected)?;
+        write!(f, "  emulator:  {}", self.actual)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CosimError {
+    /// The reference trace could not be read
+    Io(String),
+    /// Error which was not handled by a trap handler of the program
+    Exec(ExecError),
+    Diverged(Divergence),
+}
+
+impl fmt::Display for CosimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CosimError::Io(err) => write!(f, "failed to read reference trace: {err}"),
+            CosimError::Exec(err) => write!(f, "{err}"),
+            CosimError::Diverged(divergence) => write!(f, "{divergence}"),
+        }
+    }
+}
+
+impl std::error::Error for CosimError {}
+
+/// Commit log output the lockstep loop reads back after every step
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Compares two retired instructions. CSR writes are not compared, the reference may update CSRs we do not model.
+pub fn compare_commits(expected: &Commit, actual: &Commit) -> Result<(), &'static str> {
+    let registers = |commit: &Commit| -> Vec<CommitEntry> {
+        let mut entries: Vec<CommitEntry> = commit.entries.iter()
+            .filter(|entry| matches!(entry, CommitEntry::Register { .. } | CommitEntry::FRegister { .. }))
+            .copied()
+            .collect();
+        entries.sort_by_key(|entry| match *entry {
+            CommitEntry::FRegister { idx, .. } => 32 + idx,
+            CommitEntry::Register { idx, .. } => idx,
+            _ => 0,
+        });
+        entries
+    };
+    let memory = |commit: &Commit| -> Vec<CommitEntry> {
+        commit.entries.iter().filter(|entry| matches!(entry, CommitEntry::Load { .. } | CommitEntry::Store { .. })).copied().collect()
+    };
+    if actual.pc != expected.pc {
+        Err("pc")
+    } else if actual.instr != expected.instr || actual.instr_len != expected.instr_len {
+        Err("instruction")
+    } else if actual.privilege != expected.privilege {
+        Err("privilege")
+    } else if registers(actual) != registers(expected) {
+        Err("register write")
+    } else if memory(actual) != memory(expected) {
+        Err("memory access")
+    } else {
+        Ok(())
+    }
+}
+
+/// Steps the CPU in lockstep with a reference commit trace (Spike `--log-commits` format) and stops at the first divergence.
+/// Reference instructions before the CPU's current PC (e.g. Spike's boot ROM) are skipped. Returns the number of matched instructions.
+pub fn run_lockstep(cpu: &mut BasicCpu, reference: impl BufRead, htif: Option<&mut Htif>) -> Result<u64, CosimError> {
+    let buffer = SharedBuffer::default();
+    cpu.set_commit_log(Some(CommitLog::new(Box::new(buffer.clone()))));
+    let result = lockstep(cpu, reference, htif, &buffer);
+    cpu.set_commit_log(None);
+    result
+}
+
+fn lockstep(cpu: &mut BasicCpu, reference: impl BufRead, mut htif: Option<&mut Htif>, buffer: &SharedBuffer) -> Result<u64, CosimError> {
+    let mut instret = 0;
+    let mut started = false;
+    for line in reference.lines() {
+        let line = line.map_err(|err| CosimError::Io(err.to_string()))?;
+        let Some(expected) = Commit::parse(&line) else {
+            continue;
+        };
+        if !started && expected.pc != cpu.get_pc() {
+            continue;
+        }
+        started = true;
+
+        let mut steps = 0;
+        while buffer.0.borrow().is_empty() && steps < MAX_STEPS_WITHOUT_COMMIT {
+            if let Some(trap) = cpu.step()
+                && !trap.handled {
+                return Err(CosimError::Exec(trap.exception));
+            }
+            steps += 1;
+        }
+        let output = String::from_utf8_lossy(&buffer.0.borrow_mut().split_off(0)).into_owned();
+        let actual_line = output.trim_end().to_string();
+        let actual = match Commit::parse(&actual_line) {
+            Some(actual) => actual,
+            None => {
+                let actual_line = format!("<no instruction retired in {steps} steps, pc {:#x}>", cpu.get_pc());
+                return Err(CosimError::Diverged(Divergence { instret, reason: "pc", expected: line.trim().to_string(), actual: actual_line }));
+            },
+        };
+        if let Err(reason) = compare_commits(&expected, &actual) {
+            return Err(CosimError::Diverged(Divergence {
+                instret,
+                reason,
+                expected: line.trim().to_string(),
+                actual: actual_line,
+            }));
+        }
+        instret += 1;
+
+        if let Some(code) = htif.as_deref_mut().and_then(|htif| htif.poll(&mut cpu.bus.dram)) {
+            info!("Program exited through HTIF with code {code} during co-simulation");
+            break;
+        }
+    }
+    Ok(instret)
+}
@@ -0,0 +1,198 @@
+//! Helpers shared by the integration tests
+#![allow(dead_code)] // every test crate compiles this module, but not every test crate uses every helper
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use riscv_emu::loader::elf::{ELFCLASS32, ELFCLASS64, ELFDATA2LSB, ELF_MAGIC, EM_RISCV, ET_EXEC, PT_LOAD, SHT_SYMTAB};
+
+/// Size of the ELF header and one program header, the code of `build_executable` starts here
+pub const ELF_HEADERS_SIZE: u64 = 64 + 56;
+
+/// In-memory output (console, commit log) shared between a test and the code writing to it
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Builds a minimal ELF64 executable with one PT_LOAD segment per (paddr, contents, memsz) entry
+/// and a symbol table if `symbols` is not empty
+pub fn build_elf(machine: u16, entry: u64, segments: &[(u64, &[u8], u64)], symbols: &[(&str, u64)]) -> Vec<u8> {
+    let phoff = 64;
+    let mut data_offset = phoff + 56 * segments.len();
+    let mut elf = Vec::new();
+    elf.extend_from_slice(&ELF_MAGIC);
+    elf.extend_from_slice(&[ELFCLASS64, ELFDATA2LSB, 1, 0]);
+    elf.extend_from_slice(&[0; 8]);
+    elf.extend_from_slice(&ET_EXEC.to_le_bytes());
+    elf.extend_from_slice(&machine.to_le_bytes());
+    elf.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    elf.extend_from_slice(&entry.to_le_bytes());
+    elf.extend_from_slice(&(phoff as u64).to_le_bytes());
+    elf.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+    elf.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    elf.extend_from_slice(&64u16.to_le_bytes()); // e_ehsize
+    elf.extend_from_slice(&56u16.to_le_bytes()); // e_phentsize
+    elf.extend_from_slice(&(segments.len() as u16).to_le_bytes());
+    elf.extend_from_slice(&[0; 6]); // e_shentsize, e_shnum, e_shstrndx
+    for (paddr, contents, memsz) in segments {
+        elf.extend_from_slice(&PT_LOAD.to_le_bytes());
+        elf.extend_from_slice(&7u32.to_le_bytes()); // RWX
+        elf.extend_from_slice(&(data_offset as u64).to_le_bytes());
+        elf.extend_from_slice(&paddr.to_le_bytes()); // vaddr
+        elf.extend_from_slice(&paddr.to_le_bytes());
+        elf.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+        elf.extend_from_slice(&memsz.to_le_bytes());
+        elf.extend_from_slice(&0x1000u64.to_le_bytes());
+        data_offset += contents.len();
+    }
+    for (_, contents, _) in segments {
+        elf.extend_from_slice(contents);
+    }
+    if symbols.is_empty() {
+        return elf;
+    }
+
+    let strtab = elf.len();
+    elf.push(0);
+    let mut names = Vec::new();
+    for (name, _) in symbols {
+        names.push(elf.len() - strtab);
+        elf.extend_from_slice(name.as_bytes());
+        elf.push(0);
+    }
+    let strtab_size = elf.len() - strtab;
+    elf.resize(elf.len().next_multiple_of(8), 0);
+    let symtab = elf.len();
+    elf.extend_from_slice(&[0; 24]); // null symbol
+    for ((_, value), name) in symbols.iter().zip(names) {
+        elf.extend_from_slice(&(name as u32).to_le_bytes());
+        elf.extend_from_slice(&[0x10, 0, 1, 0]); // global, section 1
+        elf.extend_from_slice(&value.to_le_bytes());
+        elf.extend_from_slice(&0u64.to_le_bytes());
+    }
+    let symtab_size = elf.len() - symtab;
+
+    let shoff = elf.len();
+    elf.extend_from_slice(&[0; 64]); // null section
+    for (sh_type, offset, size, link) in [(SHT_SYMTAB, symtab, symtab_size, 2u32), (3, strtab, strtab_size, 0)] {
+        elf.extend_from_slice(&0u32.to_le_bytes()); // sh_name
+        elf.extend_from_slice(&sh_type.to_le_bytes());
+        elf.extend_from_slice(&[0; 16]); // sh_flags, sh_addr
+        elf.extend_from_slice(&(offset as u64).to_le_bytes());
+        elf.extend_from_slice(&(size as u64).to_le_bytes());
+        elf.extend_from_slice(&link.to_le_bytes());
+        elf.extend_from_slice(&[0; 20]); // sh_info, sh_addralign, sh_entsize
+    }
+    elf[40..48].copy_from_slice(&(shoff as u64).to_le_bytes());
+    elf[58..60].copy_from_slice(&64u16.to_le_bytes());
+    elf[60..62].copy_from_slice(&3u16.to_le_bytes());
+    elf
+}
+
+/// ELF32 variant of `build_elf` for RV32 programs
+pub fn build_elf32(machine: u16, entry: u32, segments: &[(u32, &[u8], u32)], symbols: &[(&str, u32)]) -> Vec<u8> {
+    let phoff = 52;
+    let mut data_offset = phoff + 32 * segments.len();
+    let mut elf = Vec::new();
+    elf.extend_from_slice(&ELF_MAGIC);
+    elf.extend_from_slice(&[ELFCLASS32, ELFDATA2LSB, 1, 0]);
+    elf.extend_from_slice(&[0; 8]);
+    elf.extend_from_slice(&ET_EXEC.to_le_bytes());
+    elf.extend_from_slice(&machine.to_le_bytes());
+    elf.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    elf.extend_from_slice(&entry.to_le_bytes());
+    elf.extend_from_slice(&(phoff as u32).to_le_bytes());
+    elf.extend_from_slice(&0u32.to_le_bytes()); // e_shoff
+    elf.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    elf.extend_from_slice(&52u16.to_le_bytes()); // e_ehsize
+    elf.extend_from_slice(&32u16.to_le_bytes()); // e_phentsize
+    elf.extend_from_slice(&(segments.len() as u16).to_le_bytes());
+    elf.extend_from_slice(&[0; 6]); // e_shentsize, e_shnum, e_shstrndx
+    for (paddr, contents, memsz) in segments {
+        elf.extend_from_slice(&PT_LOAD.to_le_bytes());
+        elf.extend_from_slice(&(data_offset as u32).to_le_bytes());
+        elf.extend_from_slice(&paddr.to_le_bytes()); // vaddr
+        elf.extend_from_slice(&paddr.to_le_bytes());
+        elf.extend_from_slice(&(contents.len() as u32).to_le_bytes());
+        elf.extend_from_slice(&memsz.to_le_bytes());
+        elf.extend_from_slice(&7u32.to_le_bytes()); // RWX
+        elf.extend_from_slice(&0x1000u32.to_le_bytes());
+        data_offset += contents.len();
+    }
+    for (_, contents, _) in segments {
+        elf.extend_from_slice(contents);
+    }
+    if symbols.is_empty() {
+        return elf;
+    }
+
+    let strtab = elf.len();
+    elf.push(0);
+    let mut names = Vec::new();
+    for (name, _) in symbols {
+        names.push(elf.len() - strtab);
+        elf.extend_from_slice(name.as_bytes());
+        elf.push(0);
+    }
+    let strtab_size = elf.len() - strtab;
+    elf.resize(elf.len().next_multiple_of(4), 0);
+    let symtab = elf.len();
+    elf.extend_from_slice(&[0; 16]); // null symbol
+    for ((_, value), name) in symbols.iter().zip(names) {
+        elf.extend_from_slice(&(name as u32).to_le_bytes());
+        elf.extend_from_slice(&value.to_le_bytes());
+        elf.extend_from_slice(&0u32.to_le_bytes()); // st_size
+        elf.extend_from_slice(&[0x10, 0, 1, 0]); // global, section 1
+    }
+    let symtab_size = elf.len() - symtab;
+
+    let shoff = elf.len();
+    elf.extend_from_slice(&[0; 40]); // null section
+    for (sh_type, offset, size, link) in [(SHT_SYMTAB, symtab, symtab_size, 2u32), (3, strtab, strtab_size, 0)] {
+        elf.extend_from_slice(&0u32.to_le_bytes()); // sh_name
+        elf.extend_from_slice(&sh_type.to_le_bytes());
+        elf.extend_from_slice(&[0; 8]); // sh_flags, sh_addr
+        elf.extend_from_slice(&(offset as u32).to_le_bytes());
+        elf.extend_from_slice(&(size as u32).to_le_bytes());
+        elf.extend_from_slice(&link.to_le_bytes());
+        elf.extend_from_slice(&[0; 12]); // sh_info, sh_addralign, sh_entsize
+    }
+    elf[32..36].copy_from_slice(&(shoff as u32).to_le_bytes());
+    elf[46..48].copy_from_slice(&40u16.to_le_bytes());
+    elf[48..50].copy_from_slice(&3u16.to_le_bytes());
+    elf
+}
+
+/// Executable with a single PT_LOAD segment mapping the whole file (incl. headers) at `base` with some BSS,
+/// the code follows the headers and is the entry point
+pub fn build_executable(base: u64, code: &[u32], data: &[u8]) -> Vec<u8> {
+    let mut body: Vec<u8> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+    body.extend_from_slice(data);
+    let entry = base + ELF_HEADERS_SIZE;
+    let mut elf = build_elf(EM_RISCV, entry, &[(entry, &body, body.len() as u64)], &[]);
+    // Extend the segment down to the start of the file like the first segment of a linked program
+    let file_size = elf.len() as u64;
+    let phdr = &mut elf[64..64 + 56];
+    phdr[8..16].copy_from_slice(&0u64.to_le_bytes()); // p_offset
+    phdr[16..24].copy_from_slice(&base.to_le_bytes()); // p_vaddr
+    phdr[24..32].copy_from_slice(&base.to_le_bytes()); // p_paddr
+    phdr[32..40].copy_from_slice(&file_size.to_le_bytes()); // p_filesz
+    phdr[40..48].copy_from_slice(&(file_size + 0x100).to_le_bytes()); // p_memsz, with BSS
+    elf
+}
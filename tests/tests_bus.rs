@@ -0,0 +1,123 @@
+use riscv_emu::cpu::basic_cpu::BasicCpu;
+use riscv_emu::cpu::error::{ExecError, MemoryAccess};
+use riscv_emu::memory::bus::{AccessFault, Bus, Device, MapError};
+use riscv_emu::memory::dram::{DramMemory, DRAM_BASE_ADDR};
+use riscv_emu::memory::rom::Rom;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    /// Single 32-bit register which only supports word accesses
+    struct ScratchRegister {
+        value: u64,
+    }
+
+    impl Device for ScratchRegister {
+        fn read(&mut self, offset: u64, size: usize) -> Result<u64, AccessFault> {
+            if offset != 0 || size != 32 {
+                return Err(AccessFault);
+            }
+            Ok(self.value)
+        }
+
+        fn write(&mut self, offset: u64, size: usize, value: u64) -> Result<(), AccessFault> {
+            if offset != 0 || size != 32 {
+                return Err(AccessFault);
+            }
+            self.value = value;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_bus_dram_and_unmapped() {
+        test_init();
+        let mut bus = Bus::new();
+
+        assert_eq!(bus.write(DRAM_BASE_ADDR as u64, 32, 0x12345678), Ok(()));
+        assert_eq!(bus.read(DRAM_BASE_ADDR as u64, 16), Ok(0x5678));
+        // Below DRAM, past the end of DRAM and straddling the end are unmapped
+        assert_eq!(bus.read(0x0, 8), Err(AccessFault));
+        assert_eq!(bus.read((DRAM_BASE_ADDR + bus.dram.size()) as u64, 8), Err(AccessFault));
+        assert_eq!(bus.write((DRAM_BASE_ADDR + bus.dram.size() - 2) as u64, 32, 0), Err(AccessFault));
+        // Unsupported access size
+        assert_eq!(bus.read(DRAM_BASE_ADDR as u64, 24), Err(AccessFault));
+    }
+
+    #[test]
+    fn test_bus_devices() {
+        test_init();
+        let mut bus = Bus::new();
+        bus.add_device("rom", 0x1000, 0x10, Box::new(Rom::new(vec![0x11, 0x22, 0x33, 0x44]))).unwrap();
+        bus.add_device("scratch", 0x2000, 0x4, Box::new(ScratchRegister { value: 0 })).unwrap();
+
+        assert_eq!(bus.read(0x1000, 32), Ok(0x44332211));
+        assert_eq!(bus.read(0x1002, 8), Ok(0x33));
+        assert_eq!(bus.write(0x1000, 8, 0xFF), Err(AccessFault)); // ROM is read-only
+        assert_eq!(bus.read(0x1004, 8), Err(AccessFault)); // beyond the ROM contents
+
+        assert_eq!(bus.write(0x2000, 32, 0xABCD), Ok(()));
+        assert_eq!(bus.read(0x2000, 32), Ok(0xABCD));
+        assert_eq!(bus.read(0x2000, 8), Err(AccessFault)); // the device rejects byte accesses
+    }
+
+    #[test]
+    fn test_bus_overlapping_devices() {
+        test_init();
+        let mut bus = Bus::new();
+        bus.add_device("rom", 0x1000, 0x100, Box::new(Rom::new(vec![0; 0x100]))).unwrap();
+        assert_eq!(bus.add_device("scratch", 0x10FC, 0x4, Box::new(ScratchRegister { value: 0 })),
+            Err(MapError::OverlapsDevice { name: "scratch", base: 0x10FC, other: "rom" }));
+        assert_eq!(bus.add_device("scratch", DRAM_BASE_ADDR as u64 - 2, 0x4, Box::new(ScratchRegister { value: 0 })),
+            Err(MapError::OverlapsDram { name: "scratch", base: DRAM_BASE_ADDR as u64 - 2 }));
+        // Regions wrapping around the address space are rejected instead of overflowing
+        assert_eq!(bus.add_device("scratch", 0xFFFF_FFFF_FFFF_FFFE, 0x4, Box::new(ScratchRegister { value: 0 })),
+            Err(MapError::InvalidRegion { name: "scratch", base: 0xFFFF_FFFF_FFFF_FFFE, size: 0x4 }));
+        bus.add_device("scratch", 0xFFFF_FFFF_FFFF_FFFC, 0x4, Box::new(ScratchRegister { value: 0 })).unwrap();
+        assert_eq!(bus.read(0xFFFF_FFFF_FFFF_FFFC, 32), Ok(0));
+        assert_eq!(bus.read(0xFFFF_FFFF_FFFF_FFFE, 32), Err(AccessFault));
+
+        // DRAM at the top of the address space
+        let mut bus = Bus::with_dram(DramMemory::with_layout(0xFFFF_FFFF_FFFF_0000, 0x10000));
+        assert!(bus.add_device("rom", 0xFFFF_FFFF_FFFF_FF00, 0x100, Box::new(Rom::new(vec![0; 0x100]))).is_err());
+        assert_eq!(bus.read(0xFFFF_FFFF_FFFF_FFF8, 64), Ok(0));
+        assert_eq!(bus.read(0xFFFF_FFFF_FFFF_FFFC, 64), Err(AccessFault));
+    }
+
+    #[test]
+    fn test_cpu_with_custom_dram_base() {
+        test_init();
+        let mut cpu = BasicCpu::with_bus(Bus::with_dram(DramMemory::with_layout(0x4000_0000, 0x10000)));
+        cpu.init();
+        assert_eq!(cpu.get_pc(), 0x4000_0000);
+        assert_eq!(cpu.get_register(2), 0x4001_0000);
+
+        // lw a0, 0(a1) from DRAM, and from the unmapped default DRAM base
+        cpu.bus.dram.dram_write(0x4000_0100, 32, 0x55);
+        cpu.set_register(11, 0x4000_0100);
+        assert!(cpu.execute_instr(0x0005A503).is_ok());
+        assert_eq!(cpu.get_register(10), 0x55);
+        cpu.set_register(11, DRAM_BASE_ADDR as u64);
+        assert_eq!(cpu.execute_instr(0x0005A503), Err(ExecError::MemoryFault { addr: DRAM_BASE_ADDR as u64, access: MemoryAccess::Load, pc: 0x4000_0004 }));
+    }
+
+    #[test]
+    fn test_cpu_store_to_rom_faults() {
+        test_init();
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        cpu.bus.add_device("rom", 0x1000, 0x1000, Box::new(Rom::new(vec![0xAA; 0x1000]))).unwrap();
+        let pc = cpu.get_pc();
+
+        // lbu a0, 0(a1) / sb a0, 0(a1)
+        cpu.set_register(11, 0x1000);
+        assert!(cpu.execute_instr(0x0005C503).is_ok());
+        assert_eq!(cpu.get_register(10), 0xAA);
+        assert_eq!(cpu.execute_instr(0x00A58023), Err(ExecError::MemoryFault { addr: 0x1000, access: MemoryAccess::Store, pc: pc + 4 }));
+    }
+}
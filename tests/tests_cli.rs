@@ -0,0 +1,163 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use riscv_emu::loader::elf::EM_RISCV;
+use riscv_emu::memory::dram::DRAM_BASE_ADDR;
+
+use common::{build_elf, build_elf32};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // li a0, 42; ecall
+    const EXIT_42: [u32; 2] = [0x02a00513, 0x00000073];
+    // lbu a0, 0(a1); ecall
+    const EXIT_DTB_BYTE: [u32; 2] = [0x0005c503, 0x00000073];
+    // j .
+    const LOOP: [u32; 1] = [0x0000006f];
+    // mul a0, a0, a0; ecall
+    const EXIT_MUL: [u32; 2] = [0x02a50533, 0x00000073];
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    /// Writes a raw binary to a temporary file
+    fn write_program(name: &str, code: &[u32]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("riscv-emu-cli-{}-{name}", std::process::id()));
+        fs::write(&path, code.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<u8>>()).unwrap();
+        path
+    }
+
+    fn emulator(args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_riscv-emu")).args(args).env_remove("RUST_LOG").output().unwrap()
+    }
+
+    fn status(args: &[&str]) -> i32 {
+        emulator(args).status.code().unwrap()
+    }
+
+    #[test]
+    fn test_cli_usage() {
+        test_init();
+        let help = emulator(&["--help"]);
+        assert_eq!(help.status.code(), Some(0));
+        assert!(String::from_utf8_lossy(&help.stdout).starts_with("Usage: riscv-emu"));
+
+        for args in [
+            &[][..],
+            &["--bogus", "prog"],
+            &["--mem-size"],
+            &["--mem-size", "12Q", "prog"],
+            &["--format", "hex", "prog"],
+            &["--isa", "rv128i", "prog"],
+            &["--isa", "rv32imac", "--pk", "prog"],
+            &["--isa", "rv32imac", "--gdb", "1234", "prog"],
+            &["--gdb", "port", "prog"],
+            &["--exit-on-ecall", "--pk", "prog"],
+            &["--raw", "--pk", "prog"],
+            &["--max-instructions", "10", "--gdb", "1234", "prog"],
+            &["--max-instructions", "10", "--cosim", "trace", "prog"],
+            &["prog", "arg"], // guest arguments without --user or --semihosting
+            // DRAM overlapping the devices or wrapping around the address space
+            &["--raw", "--mem-base", "0", "--mem-size", "64M", "prog"],
+            &["--mem-base", "0x10000000", "prog"],
+            &["--mem-base", "0xffffffffffffff00", "prog"],
+            &["--isa", "rv32i", "--mem-base", "0xfff00000", "--mem-size", "2M", "prog"],
+        ] {
+            let output = emulator(args);
+            assert_eq!(output.status.code(), Some(2), "{args:?}");
+            assert!(String::from_utf8_lossy(&output.stderr).contains("Usage: riscv-emu"), "{args:?}");
+        }
+        // Options after the binary belong to the guest
+        let program = write_program("usage", &EXIT_42);
+        assert_eq!(status(&["--raw", "--exit-on-ecall", "--semihosting", program.to_str().unwrap(), "--help"]), 42);
+        assert_eq!(status(&["--raw", "missing-binary"]), 1);
+        fs::remove_file(program).unwrap();
+    }
+
+    #[test]
+    fn test_cli_exit_status() {
+        test_init();
+        let exit = write_program("exit", &EXIT_42);
+        let dtb_byte = write_program("dtb-byte", &EXIT_DTB_BYTE);
+        let endless = write_program("loop", &LOOP);
+        let mul = write_program("mul", &EXIT_MUL);
+        let dtb = std::env::temp_dir().join(format!("riscv-emu-cli-{}.dtb", std::process::id()));
+        fs::write(&dtb, [0x11u8; 8]).unwrap();
+
+        assert_eq!(status(&["--raw", "--exit-on-ecall", exit.to_str().unwrap()]), 42);
+        // a1 points to the generated device tree (magic 0xd00dfeed) or the one given with --dtb
+        assert_eq!(status(&["--raw", "--exit-on-ecall", "--bootargs", "console=ttyS0", dtb_byte.to_str().unwrap()]), 0xd0);
+        assert_eq!(status(&["--raw", "--exit-on-ecall", "--dtb", dtb.to_str().unwrap(), dtb_byte.to_str().unwrap()]), 0x11);
+        let timeout = emulator(&["--raw", "--max-instructions", "1000", endless.to_str().unwrap()]);
+        assert_eq!(timeout.status.code(), Some(124));
+        // Status messages go to stderr, stdout belongs to the guest
+        assert!(timeout.stdout.is_empty());
+        assert!(String::from_utf8_lossy(&timeout.stderr).contains("Instruction limit of 1000 reached"));
+        // Without a trap handler the ECALL is an unhandled exception
+        assert_eq!(status(&["--raw", exit.to_str().unwrap()]), 1);
+        // a0 is 0 at the start: 0 * 0
+        assert_eq!(status(&["--raw", "--exit-on-ecall", mul.to_str().unwrap()]), 0);
+        assert_eq!(status(&["--raw", "--exit-on-ecall", "--isa", "rv64iac", mul.to_str().unwrap()]), 1);
+
+        for path in [exit, dtb_byte, endless, mul, dtb] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_cli_memory_layout_and_trace() {
+        test_init();
+        let program = write_program("layout", &[0x00000013, EXIT_42[0], EXIT_42[1]]); // nop; li a0, 42; ecall
+        let trace = std::env::temp_dir().join(format!("riscv-emu-cli-{}.trace", std::process::id()));
+        let args = [
+            "--format", "raw", "--mem-base", "0x40000000", "--mem-size", "64K", "--entry", "0x40000004",
+            "--exit-on-ecall", "--trace", trace.to_str().unwrap(), program.to_str().unwrap(),
+        ];
+        assert_eq!(status(&args), 42);
+        assert_eq!(fs::read_to_string(&trace).unwrap(), "\
+core   0: 0x0000000040000004 (0x02a00513) li a0, 42
+core   0: 0x0000000040000008 (0x00000073) ecall
+");
+        // RV32 traces print 32-bit addresses
+        let args = ["--raw", "--isa", "rv32i", "--exit-on-ecall", "--trace", trace.to_str().unwrap(), program.to_str().unwrap()];
+        assert_eq!(status(&args), 42);
+        assert_eq!(fs::read_to_string(&trace).unwrap(), "\
+core   0: 0x80000000 (0x00000013) nop
+core   0: 0x80000004 (0x02a00513) li a0, 42
+core   0: 0x80000008 (0x00000073) ecall
+");
+        // The program has to fit into DRAM
+        assert_eq!(status(&["--raw", "--mem-size", "8", program.to_str().unwrap()]), 1);
+        fs::remove_file(program).unwrap();
+        fs::remove_file(trace).unwrap();
+    }
+
+    #[test]
+    fn test_cli_elf_class() {
+        test_init();
+        let code: Vec<u8> = EXIT_42.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let base = DRAM_BASE_ADDR as u32;
+        let elf32 = std::env::temp_dir().join(format!("riscv-emu-cli-{}-elf32", std::process::id()));
+        fs::write(&elf32, build_elf32(EM_RISCV, base, &[(base, &code, code.len() as u32)], &[])).unwrap();
+        let elf64 = std::env::temp_dir().join(format!("riscv-emu-cli-{}-elf64", std::process::id()));
+        fs::write(&elf64, build_elf(EM_RISCV, base as u64, &[(base as u64, &code, code.len() as u64)], &[])).unwrap();
+
+        assert_eq!(status(&["--isa", "rv32i", "--exit-on-ecall", elf32.to_str().unwrap()]), 42);
+        assert_eq!(status(&["--exit-on-ecall", elf64.to_str().unwrap()]), 42);
+        // The ELF class has to match the base ISA
+        let output = emulator(&["--exit-on-ecall", elf32.to_str().unwrap()]);
+        assert_eq!(output.status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("ELF32 executable can not run on rv64imafdc"));
+        assert_eq!(status(&["--isa", "rv32i", "--exit-on-ecall", elf64.to_str().unwrap()]), 1);
+        assert_eq!(status(&["--user", elf32.to_str().unwrap()]), 1);
+
+        fs::remove_file(elf32).unwrap();
+        fs::remove_file(elf64).unwrap();
+    }
+}
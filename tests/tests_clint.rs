@@ -0,0 +1,104 @@
+use riscv_emu::cpu::basic_cpu::{
+    BasicCpu, Privilege, CSR_MCAUSE, CSR_MEPC, CSR_MIE, CSR_MIP, CSR_MSTATUS, CSR_MTVEC, IRQ_M_SOFT, IRQ_M_TIMER,
+    MCAUSE_INTERRUPT, MIP_MSIP, MIP_MTIP, MSTATUS_MIE,
+};
+use riscv_emu::devices::clint::*;
+use riscv_emu::memory::bus::{AccessFault, Bus, Device};
+use riscv_emu::memory::dram::DRAM_BASE_ADDR;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HANDLER: u64 = DRAM_BASE_ADDR as u64 + 0x1000;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    /// CPU with a CLINT, a trap handler and a program of NOPs
+    fn setup_cpu() -> BasicCpu {
+        let mut bus = Bus::new();
+        bus.add_device("clint", CLINT_BASE, CLINT_SIZE, Box::new(Clint::new())).unwrap();
+        let mut cpu = BasicCpu::with_bus(bus);
+        cpu.init();
+        for i in 0..0x100 {
+            cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 4 * i, 32, 0x00000013); // nop
+        }
+        cpu.set_csr(CSR_MTVEC, HANDLER);
+        cpu
+    }
+
+    #[test]
+    fn test_clint_registers() {
+        test_init();
+        let mut clint = Clint::new();
+
+        // mtime as a whole and as 32-bit halves
+        assert_eq!(clint.write(CLINT_MTIME, 64, 0x1_0000_0002), Ok(()));
+        clint.tick();
+        assert_eq!(clint.read(CLINT_MTIME, 32), Ok(3));
+        assert_eq!(clint.read(CLINT_MTIME + 4, 32), Ok(1));
+        assert_eq!(clint.write(CLINT_MTIMECMP + 4, 32, 0), Ok(()));
+        assert_eq!(clint.write(CLINT_MTIMECMP, 32, 0x10), Ok(()));
+        assert_eq!(clint.read(CLINT_MTIMECMP, 64), Ok(0x10));
+        assert_eq!(clint.mip(), MIP_MTIP);
+
+        // msip only implements bit 0
+        assert_eq!(clint.write(CLINT_MSIP, 32, 0xFF), Ok(()));
+        assert_eq!(clint.read(CLINT_MSIP, 32), Ok(1));
+        assert_eq!(clint.mip(), MIP_MSIP | MIP_MTIP);
+        assert_eq!(clint.read(CLINT_MSIP, 8), Err(AccessFault));
+    }
+
+    #[test]
+    fn test_timer_interrupt() {
+        test_init();
+        let mut cpu = setup_cpu();
+        cpu.bus.write(CLINT_BASE + CLINT_MTIMECMP, 64, 5).unwrap();
+        cpu.set_csr(CSR_MIE, MIP_MTIP);
+
+        // Globally disabled in M-mode: the timer is pending but not taken
+        for _ in 0..10 {
+            assert_eq!(cpu.step(), None);
+        }
+        assert_eq!(cpu.get_csr(CSR_MIP) & MIP_MTIP, MIP_MTIP);
+        assert_eq!(cpu.get_pc(), DRAM_BASE_ADDR as u64 + 40);
+
+        // Enabled: taken before the next instruction
+        cpu.set_csr(CSR_MSTATUS, MSTATUS_MIE);
+        assert_eq!(cpu.step(), None);
+        assert_eq!(cpu.get_pc(), HANDLER);
+        assert_eq!(cpu.get_csr(CSR_MCAUSE), MCAUSE_INTERRUPT | IRQ_M_TIMER);
+        assert_eq!(cpu.get_csr(CSR_MEPC), DRAM_BASE_ADDR as u64 + 40);
+        assert_eq!(cpu.get_csr(CSR_MSTATUS) & MSTATUS_MIE, 0);
+
+        // Writing mtimecmp clears the pending timer
+        cpu.bus.write(CLINT_BASE + CLINT_MTIMECMP, 64, u64::MAX).unwrap();
+        assert!(cpu.step().is_some()); // the empty handler raises an illegal instruction
+        assert_eq!(cpu.get_csr(CSR_MIP) & MIP_MTIP, 0);
+    }
+
+    #[test]
+    fn test_software_interrupt_from_user_mode() {
+        test_init();
+        let mut cpu = setup_cpu();
+        cpu.set_csr(CSR_MIE, MIP_MSIP);
+        cpu.set_privilege(Privilege::User);
+        cpu.bus.write(CLINT_BASE + CLINT_MSIP, 32, 1).unwrap();
+
+        // Machine interrupts are taken in lower privilege modes regardless of mstatus.MIE
+        assert_eq!(cpu.step(), None);
+        assert_eq!(cpu.get_privilege(), Privilege::Machine);
+        assert_eq!(cpu.get_pc(), HANDLER);
+        assert_eq!(cpu.get_csr(CSR_MCAUSE), MCAUSE_INTERRUPT | IRQ_M_SOFT);
+    }
+
+    #[test]
+    fn test_mip_device_bits_read_only() {
+        test_init();
+        let mut cpu = setup_cpu();
+        cpu.set_csr(CSR_MIP, MIP_MTIP | MIP_MSIP);
+        assert_eq!(cpu.get_csr(CSR_MIP), 0);
+    }
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use riscv_emu::cpu::basic_cpu::{BasicCpu, Privilege};
+use riscv_emu::cpu::commit_log::*;
+use riscv_emu::cpu::error::ExecError;
+use riscv_emu::memory::dram::DRAM_BASE_ADDR;
+
+use common::SharedBuffer;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u64 = DRAM_BASE_ADDR as u64;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[test]
+    fn test_format_commit() {
+        test_init();
+        let entries = [
+            CommitEntry::Load { addr: 0x80001000 },
+            CommitEntry::Register { idx: 0, value: 1 },
+            CommitEntry::FRegister { idx: 10, value: 0x3ff0000000000000 },
+            CommitEntry::Csr { idx: 0x001, value: 0x10 },
+        ];
+        assert_eq!(
+            format_commit(Privilege::Supervisor, 0x80000010, 0x00053507, 4, &entries),
+            "core   0: 1 0x0000000080000010 (0x00053507) f10 0x3ff0000000000000 c1_fflags 0x0000000000000010 mem 0x0000000080001000"
+        );
+        assert_eq!(
+            format_commit(Privilege::User, 0x10, 0x0000c10c, 2, &[CommitEntry::Store { addr: 0x20, size: 32, value: 0xab }]),
+            "core   0: 0 0x0000000000000010 (0xc10c) mem 0x0000000000000020 0x000000ab"
+        );
+
+        // A second write to the same register replaces the first one
+        let buffer = SharedBuffer::default();
+        let mut log = CommitLog::new(Box::new(buffer.clone()));
+        log.record(CommitEntry::Register { idx: 5, value: 1 });
+        log.record(CommitEntry::Register { idx: 5, value: 2 });
+        log.commit(Privilege::Machine, BASE, 0x00128293, 4).unwrap();
+        log.record(CommitEntry::Register { idx: 6, value: 3 });
+        log.discard();
+        log.commit(Privilege::Machine, BASE + 4, 0x00000013, 4).unwrap();
+        assert_eq!(
+            buffer.contents(),
+            "core   0: 3 0x0000000080000000 (0x00128293) x5  0x0000000000000002\n\
+             core   0: 3 0x0000000080000004 (0x00000013)\n"
+        );
+    }
+
+    #[test]
+    fn test_cpu_commit_log() {
+        test_init();
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        let buffer = SharedBuffer::default();
+        cpu.set_commit_log(Some(CommitLog::new(Box::new(buffer.clone()))));
+
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR, 32, 0x00500293); // addi t0, zero, 5
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 4, 32, 0x00000317); // auipc t1, 0
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 8, 32, 0x10533023); // sd t0, 256(t1)
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 12, 32, 0x10033383); // ld t2, 256(t1)
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 16, 16, 0x0285); // c.addi t0, 1
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 18, 32, 0x30531073); // csrw mtvec, t1
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 22, 32, 0x00000013); // nop
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 26, 16, 0x0000); // illegal, traps to mtvec
+        for _ in 0..7 {
+            assert_eq!(cpu.step(), None);
+        }
+        assert!(matches!(cpu.step().map(|trap| trap.exception), Some(ExecError::IllegalInstruction { .. })));
+        assert_eq!(cpu.get_pc(), BASE + 4);
+
+        let log = buffer.contents();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines, [
+            "core   0: 3 0x0000000080000000 (0x00500293) x5  0x0000000000000005",
+            "core   0: 3 0x0000000080000004 (0x00000317) x6  0x0000000080000004",
+            "core   0: 3 0x0000000080000008 (0x10533023) mem 0x0000000080000104 0x0000000000000005",
+            "core   0: 3 0x000000008000000c (0x10033383) x7  0x0000000000000005 mem 0x0000000080000104",
+            "core   0: 3 0x0000000080000010 (0x0285) x5  0x0000000000000006",
+            "core   0: 3 0x0000000080000012 (0x30531073) c773_mtvec 0x0000000080000004",
+            "core   0: 3 0x0000000080000016 (0x00000013)",
+        ]);
+    }
+}
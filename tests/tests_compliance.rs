@@ -0,0 +1,80 @@
+use std::io;
+
+use riscv_emu::cpu::basic_cpu::BasicCpu;
+use riscv_emu::devices::htif::Htif;
+use riscv_emu::memory::dram::DRAM_BASE_ADDR;
+use riscv_emu::testing::compliance::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOHOST: usize = DRAM_BASE_ADDR + 0x1000;
+    const SIGNATURE: usize = DRAM_BASE_ADDR + 0x2000;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[test]
+    fn test_signature_format_and_compare() {
+        test_init();
+        let signature = format_signature(&[0x1, 0xDEADBEEF]);
+        assert_eq!(signature, "00000001\ndeadbeef\n");
+
+        assert_eq!(compare_signature(&signature, "00000001\r\nDEADBEEF\n\n"), Ok(()));
+        assert_eq!(compare_signature(&signature, "00000001\ndeadbeee\n"), Err(SignatureMismatch {
+            line: 2,
+            expected: Some("deadbeee".to_string()),
+            actual: Some("deadbeef".to_string()),
+        }));
+        assert_eq!(compare_signature(&signature, "00000001\ndeadbeef\n00000000\n"), Err(SignatureMismatch {
+            line: 3,
+            expected: Some("00000000".to_string()),
+            actual: None,
+        }));
+    }
+
+    #[test]
+    fn test_run_to_exit_and_read_signature() {
+        test_init();
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        let mut htif = Htif::with_output(TOHOST as u64, None, Box::new(io::sink()));
+        let program = [
+            0x00A62023, // sw a0, 0(a2)
+            0x00B62223, // sw a1, 4(a2)
+            0x00100293, // li t0, 1
+            0x0056B023, // sd t0, 0(a3)
+        ];
+        for (i, instr) in program.iter().enumerate() {
+            cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 4 * i, 32, *instr);
+        }
+        cpu.set_register(10, 0x12345678);
+        cpu.set_register(11, 0xCAFE);
+        cpu.set_register(12, SIGNATURE as u64);
+        cpu.set_register(13, TOHOST as u64);
+
+        assert_eq!(run_to_exit(&mut cpu, &mut htif, 100), Ok(0));
+        let signature = read_signature(&cpu.bus.dram, SIGNATURE as u64, SIGNATURE as u64 + 12);
+        assert_eq!(signature, vec![0x12345678, 0xCAFE, 0]);
+    }
+
+    #[test]
+    fn test_run_to_exit_errors() {
+        test_init();
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        let mut htif = Htif::with_output(TOHOST as u64, None, Box::new(io::sink()));
+
+        // Loops forever without writing tohost
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR, 32, 0x0000006F); // j .
+        assert_eq!(run_to_exit(&mut cpu, &mut htif, 10), Err(ComplianceError::Timeout { steps: 10 }));
+
+        // Illegal instruction without trap handler
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR, 32, 0);
+        assert!(matches!(run_to_exit(&mut cpu, &mut htif, 10), Err(ComplianceError::Exec(_))));
+
+        assert_eq!(run_test(b"not an elf", 10).err().map(|err| err.to_string()), Some("not an ELF file".to_string()));
+    }
+}
@@ -0,0 +1,101 @@
+use riscv_emu::cpu::compressed::{is_compressed, expand_compressed, expand_compressed_xlen};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[test]
+    fn test_is_compressed() {
+        test_init();
+        assert!(is_compressed(0x0001));     // c.nop
+        assert!(is_compressed(0x8082));     // c.jr ra
+        assert!(!is_compressed(0x00A00093)); // addi x1, x0, 10
+    }
+
+    #[test]
+    fn test_expand_compressed() {
+        test_init();
+        // (compressed instruction, expected 32-bit expansion)
+        let cases: [(u16, u32, &str); 37] = [
+            (0x1fe0, 0x3fc10413, "c.addi4spn s0, sp, 1020"),
+            (0x3de8, 0x0f85b507, "c.fld fa0, 248(a1)"),
+            (0x5ef0, 0x07c6a603, "c.lw a2, 124(a3)"),
+            (0x7ff8, 0x0f87b703, "c.ld a4, 248(a5)"),
+            (0xa588, 0x00a5b427, "c.fsd fa0, 8(a1)"),
+            (0xc2b0, 0x04c6a023, "c.sw a2, 64(a3)"),
+            (0xe3d8, 0x08e7b023, "c.sd a4, 128(a5)"),
+            (0x0001, 0x00000013, "c.nop"),
+            (0x1501, 0xfe050513, "c.addi a0, -32"),
+            (0x25fd, 0x01f5859b, "c.addiw a1, 31"),
+            (0x52fd, 0xfff00293, "c.li t0, -1"),
+            (0x7101, 0xe0010113, "c.addi16sp sp, -512"),
+            (0x7481, 0xfffe04b7, "c.lui s1, 0xfffe0"),
+            (0x907d, 0x03f45413, "c.srli s0, 63"),
+            (0x8485, 0x4014d493, "c.srai s1, 1"),
+            (0x9979, 0xffe57513, "c.andi a0, -2"),
+            (0x8d0d, 0x40b50533, "c.sub a0, a1"),
+            (0x8e35, 0x00d64633, "c.xor a2, a3"),
+            (0x8f5d, 0x00f76733, "c.or a4, a5"),
+            (0x8c65, 0x00947433, "c.and s0, s1"),
+            (0x9d0d, 0x40b5053b, "c.subw a0, a1"),
+            (0x9e35, 0x00d6063b, "c.addw a2, a3"),
+            (0xb001, 0x801ff06f, "c.j -2048"),
+            (0xd101, 0xf00500e3, "c.beqz a0, -256"),
+            (0xecfd, 0x0e049f63, "c.bnez s1, 254"),
+            (0x1306, 0x02131313, "c.slli t1, 33"),
+            (0x35fe, 0x1f813587, "c.fldsp fa1, 504(sp)"),
+            (0x50fe, 0x0fc12083, "c.lwsp ra, 252(sp)"),
+            (0x797e, 0x1f813903, "c.ldsp s2, 504(sp)"),
+            (0x8082, 0x00008067, "c.jr ra"),
+            (0x851e, 0x00700533, "c.mv a0, t2"),
+            (0x9002, 0x00100073, "c.ebreak"),
+            (0x9282, 0x000280e7, "c.jalr t0"),
+            (0x952e, 0x00b50533, "c.add a0, a1"),
+            (0xbfae, 0x1eb13c27, "c.fsdsp fa1, 504(sp)"),
+            (0xdf86, 0x0e112e23, "c.swsp ra, 252(sp)"),
+            (0xffca, 0x1f213c23, "c.sdsp s2, 504(sp)"),
+        ];
+        for (compressed, expected, asm) in cases {
+            assert_eq!(expand_compressed(compressed), Some(expected), "{asm}");
+        }
+    }
+
+    #[test]
+    fn test_expand_compressed_rv32() {
+        test_init();
+        // RV32C encodings which differ from RV64C
+        let cases: [(u16, u32, &str); 7] = [
+            (0x2021, 0x008000ef, "c.jal 8"),
+            (0x3ff5, 0xffdff0ef, "c.jal -4"),
+            (0x2ffd, 0x7fe000ef, "c.jal 2046"),
+            (0x61c8, 0x0045a507, "c.flw fa0, 4(a1)"),
+            (0xe1c8, 0x00a5a227, "c.fsw fa0, 4(a1)"),
+            (0x6522, 0x00812507, "c.flwsp fa0, 8(sp)"),
+            (0xe42a, 0x00a12427, "c.fswsp fa0, 8(sp)"),
+        ];
+        for (compressed, expected, asm) in cases {
+            assert_eq!(expand_compressed_xlen(compressed, 32), Some(expected), "{asm}");
+        }
+        // The other encodings are shared, RV64 keeps C.ADDIW and C.LD
+        assert_eq!(expand_compressed_xlen(0x5ef0, 32), Some(0x07c6a603)); // c.lw a2, 124(a3)
+        assert_eq!(expand_compressed_xlen(0x7ff8, 64), Some(0x0f87b703)); // c.ld a4, 248(a5)
+        assert_eq!(expand_compressed_xlen(0x2001, 64), None); // c.addiw with rd = x0
+        assert_eq!(expand_compressed_xlen(0x2001, 32), Some(0x000000ef)); // c.jal 0
+    }
+
+    #[test]
+    fn test_expand_illegal_compressed() {
+        test_init();
+        assert_eq!(expand_compressed(0x0000), None); // all zeros, c.addi4spn with nzuimm = 0
+        assert_eq!(expand_compressed(0x2001), None); // c.addiw with rd = x0
+        assert_eq!(expand_compressed(0x6081), None); // c.lui with nzimm = 0
+        assert_eq!(expand_compressed(0x6101), None); // c.addi16sp with nzimm = 0
+        assert_eq!(expand_compressed(0x9c41), None); // reserved arithmetic encoding
+        assert_eq!(expand_compressed(0x4002), None); // c.lwsp with rd = x0
+        assert_eq!(expand_compressed(0x8002), None); // c.jr with rs1 = x0
+    }
+}
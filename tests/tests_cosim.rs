@@ -0,0 +1,102 @@
+use riscv_emu::cpu::basic_cpu::{BasicCpu, Privilege};
+use riscv_emu::cpu::commit_log::{Commit, CommitEntry};
+use riscv_emu::memory::dram::DRAM_BASE_ADDR;
+use riscv_emu::testing::cosim::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Spike output for the program in setup_cpu, including boot ROM and disassembly lines
+    const REFERENCE: &str = "\
+core   0: 3 0x0000000000001000 (0x00000297) x5  0x0000000000001000
+core   0: 3 0x0000000000001004 (0x02028593) x11 0x0000000000001020
+core   0: 0x0000000080000000 (0x00500293) li      t0, 5
+core   0: 3 0x0000000080000000 (0x00500293) x5  0x0000000000000005
+core   0: 3 0x0000000080000004 (0x00000317) x6  0x0000000080000004
+core   0: 3 0x0000000080000008 (0x10533023) mem 0x0000000080000104 0x0000000000000005
+core   0: 3 0x000000008000000c (0x10033383) x7  0x0000000000000005 mem 0x0000000080000104
+core   0: exception trap_illegal_instruction, epc 0x0000000080000010
+";
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn setup_cpu() -> BasicCpu {
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR, 32, 0x00500293); // addi t0, zero, 5
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 4, 32, 0x00000317); // auipc t1, 0
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 8, 32, 0x10533023); // sd t0, 256(t1)
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 12, 32, 0x10033383); // ld t2, 256(t1)
+        cpu
+    }
+
+    #[test]
+    fn test_parse_commit() {
+        test_init();
+        let commit = Commit::parse("core   0: 1 0x0000000080000010 (0xc10c) f10 0x3ff0000000000000 c1_fflags 0x0000000000000001 mem 0x0000000080001000 0x00ab").unwrap();
+        assert_eq!(commit, Commit {
+            privilege: Privilege::Supervisor,
+            pc: 0x80000010,
+            instr: 0xc10c,
+            instr_len: 2,
+            entries: vec![
+                CommitEntry::FRegister { idx: 10, value: 0x3ff0000000000000 },
+                CommitEntry::Csr { idx: 1, value: 1 },
+                CommitEntry::Store { addr: 0x80001000, size: 16, value: 0xab },
+            ],
+        });
+        assert_eq!(Commit::parse("core   0: 3 0x0000000080000000 (0x00000013)").unwrap().entries, vec![]);
+        assert_eq!(Commit::parse("core   0: 0x0000000080000000 (0x00500293) li      t0, 5"), None);
+        assert_eq!(Commit::parse("core   0: exception trap_illegal_instruction, epc 0x0000000080000010"), None);
+        assert_eq!(Commit::parse(""), None);
+    }
+
+    #[test]
+    fn test_lockstep_matches() {
+        test_init();
+        let mut cpu = setup_cpu();
+        assert_eq!(run_lockstep(&mut cpu, REFERENCE.as_bytes(), None), Ok(4));
+        assert_eq!(cpu.get_pc(), DRAM_BASE_ADDR as u64 + 16);
+        assert_eq!(cpu.get_register(7), 5);
+    }
+
+    #[test]
+    fn test_lockstep_divergence() {
+        test_init();
+        // Wrong register value in the reference for the store's address base
+        let reference = REFERENCE.replace("x6  0x0000000080000004", "x6  0x0000000080000008");
+        let mut cpu = setup_cpu();
+        let Err(CosimError::Diverged(divergence)) = run_lockstep(&mut cpu, reference.as_bytes(), None) else {
+            panic!("expected a divergence");
+        };
+        assert_eq!(divergence.instret, 1);
+        assert_eq!(divergence.reason, "register write");
+        assert_eq!(divergence.expected, "core   0: 3 0x0000000080000004 (0x00000317) x6  0x0000000080000008");
+        assert_eq!(divergence.actual, "core   0: 3 0x0000000080000004 (0x00000317) x6  0x0000000080000004");
+
+        // A store to a different address
+        let reference = REFERENCE.replace("mem 0x0000000080000104 0x", "mem 0x0000000080000100 0x");
+        let mut cpu = setup_cpu();
+        let Err(CosimError::Diverged(divergence)) = run_lockstep(&mut cpu, reference.as_bytes(), None) else {
+            panic!("expected a divergence");
+        };
+        assert_eq!((divergence.instret, divergence.reason), (2, "memory access"));
+
+        // Reference takes a different path
+        let reference = REFERENCE.replace("0x000000008000000c (0x10033383)", "0x0000000080000010 (0x10033383)");
+        let mut cpu = setup_cpu();
+        let Err(CosimError::Diverged(divergence)) = run_lockstep(&mut cpu, reference.as_bytes(), None) else {
+            panic!("expected a divergence");
+        };
+        assert_eq!((divergence.instret, divergence.reason), (3, "pc"));
+        assert!(divergence.to_string().starts_with("divergence in pc after 3 instructions"));
+
+        // Unhandled errors of the emulator end the run
+        let reference = format!("{REFERENCE}core   0: 3 0x0000000080000010 (0x00000013)\n");
+        let mut cpu = setup_cpu();
+        assert!(matches!(run_lockstep(&mut cpu, reference.as_bytes(), None), Err(CosimError::Exec(_))));
+    }
+}
@@ -0,0 +1,102 @@
+use riscv_emu::cpu::disasm::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[test]
+    fn test_disassemble() {
+        test_init();
+        // Encodings from llvm-mc
+        let cases = [
+            (0x00000013, "nop"),
+            (0xFFB00513, "li a0, -5"),
+            (0x00050593, "mv a1, a0"),
+            (0xFF010113, "addi sp, sp, -16"),
+            (0x00008067, "ret"),
+            (0x00028067, "jr t0"),
+            (0x008780E7, "jalr ra, 8(a5)"),
+            (0x12345537, "lui a0, 0x12345"),
+            (0x00000297, "auipc t0, 0x0"),
+            (0xFEB50CE3, "beq a0, a1, -8"),
+            (0x00737863, "bgeu t1, t2, 16"),
+            (0x00813083, "ld ra, 8(sp)"),
+            (0xFE813823, "sd s0, -16(sp)"),
+            (0x0035C503, "lbu a0, 3(a1)"),
+            (0x02151513, "slli a0, a0, 33"),
+            (0x43F5D593, "srai a1, a1, 63"),
+            (0x4056561B, "sraiw a2, a2, 5"),
+            (0x0015051B, "addiw a0, a0, 1"),
+            (0x0005851B, "sext.w a0, a1"),
+            (0x40C5853B, "subw a0, a1, a2"),
+            (0x02C5B533, "mulhu a0, a1, a2"),
+            (0x02C5F53B, "remuw a0, a1, a2"),
+            (0x1405B52F, "lr.d.aq a0, (a1)"),
+            (0x1AD7262F, "sc.w.rl a2, a3, (a4)"),
+            (0x06B6252F, "amoadd.w.aqrl a0, a1, (a2)"),
+            (0x30002573, "csrr a0, mstatus"),
+            (0x30529073, "csrw mtvec, t0"),
+            (0x30446073, "csrrsi zero, mie, 8"),
+            (0x7C059573, "csrrw a0, 0x7c0, a1"),
+            (0x0310000F, "fence rw, w"),
+            (0x0000100F, "fence.i"),
+            (0x00000073, "ecall"),
+            (0x00100073, "ebreak"),
+            (0x30200073, "mret"),
+            (0x12050073, "sfence.vma a0"),
+            (0x00C5F553, "fadd.s fa0, fa1, fa2"),
+            (0x02C59553, "fadd.d fa0, fa1, fa2, rtz"),
+            (0x5A00F053, "fsqrt.d ft0, ft1"),
+            (0x22B58553, "fmv.d fa0, fa1"),
+            (0x20C59553, "fsgnjn.s fa0, fa1, fa2"),
+            (0xC0051553, "fcvt.w.s a0, fa0, rtz"),
+            (0xD2357553, "fcvt.d.lu fa0, a0"),
+            (0x4015F553, "fcvt.s.d fa0, fa1"),
+            (0xE2050553, "fmv.x.d a0, fa0"),
+            (0xF0050553, "fmv.w.x fa0, a0"),
+            (0xA2B52553, "feq.d a0, fa0, fa1"),
+            (0xE0051553, "fclass.s a0, fa0"),
+            (0x00452507, "flw fa0, 4(a0)"),
+            (0xFEB13C27, "fsd fa1, -8(sp)"),
+            (0x68C5F543, "fmadd.s fa0, fa1, fa2, fa3"),
+            (0x6AC5854B, "fnmsub.d fa0, fa1, fa2, fa3, rne"),
+            (0x10500073, "wfi"),
+        ];
+        for (instr, text) in cases {
+            assert_eq!(disassemble(instr), text, "instruction {instr:#010x}");
+        }
+    }
+
+    #[test]
+    fn test_disassemble_compressed() {
+        test_init();
+        let cases = [
+            (0x0001, "nop"), // c.nop
+            (0x4515, "li a0, 5"), // c.li
+            (0x852E, "mv a0, a1"), // c.mv
+            (0x8082, "ret"), // c.jr ra
+            (0xBFF5, "j -4"), // c.j
+            (0x0505, "addi a0, a0, 1"), // c.addi
+            (0x60A2, "ld ra, 8(sp)"), // c.ldsp
+            (0xE988, "sd a0, 16(a1)"), // c.sd
+            (0xC501, "beq a0, zero, 8"), // c.beqz
+            (0x952E, "add a0, a0, a1"), // c.add
+        ];
+        for (instr, text) in cases {
+            assert_eq!(disassemble(instr), text, "instruction {instr:#06x}");
+        }
+    }
+
+    #[test]
+    fn test_disassemble_invalid() {
+        test_init();
+        assert_eq!(disassemble(0xFFFFFFFF), "unknown 0xffffffff");
+        assert_eq!(disassemble(0x0000), "unknown 0x0000"); // all-zero compressed encoding is reserved
+        assert_eq!(csr_name(0x7c0), "0x7c0");
+        assert_eq!(csr_name(0x342), "mcause");
+    }
+}
@@ -1,6 +1,4 @@
-use riscv_emu::memory::dram::{DramMemory, DRAM_SIZE, DRAM_BASE_ADDR};
-use log::info;
-use env_logger;
+use riscv_emu::memory::dram::{DramMemory, DRAM_BASE_ADDR};
 
 #[cfg(test)]
 mod tests {
@@ -13,9 +11,7 @@ mod tests {
     #[test]
     fn test_dram_write_read_byte() {
         test_init();
-        let mut dram = DramMemory {
-            mem: vec![0; DRAM_SIZE]
-        };
+        let mut dram = DramMemory::new();
 
         // Write and read a single byte
         dram.dram_write(DRAM_BASE_ADDR, 8, 0xAB);
@@ -26,9 +22,7 @@ mod tests {
     #[test]
     fn test_dram_write_read_word() {
         test_init();
-        let mut dram = DramMemory {
-            mem: vec![0; DRAM_SIZE]
-        };
+        let mut dram = DramMemory::new();
 
         // Write and read a 32-bit word
         dram.dram_write(DRAM_BASE_ADDR, 32, 0x12345678);
@@ -39,9 +33,7 @@ mod tests {
     #[test]
     fn test_dram_multiple_addresses() {
         test_init();
-        let mut dram = DramMemory {
-            mem: vec![0; DRAM_SIZE]
-        };
+        let mut dram = DramMemory::new();
 
         // Write to multiple addresses
         dram.dram_write(DRAM_BASE_ADDR, 8, 0xAB);
@@ -58,9 +50,7 @@ mod tests {
     #[test]
     fn test_dram_byte_alignment() {
         test_init();
-        let mut dram = DramMemory {
-            mem: vec![0; DRAM_SIZE]
-        };
+        let mut dram = DramMemory::new();
 
         // Write 32-bit value
         dram.dram_write(DRAM_BASE_ADDR, 32, 0xAABBCCDD);
@@ -81,11 +71,27 @@ mod tests {
     #[should_panic]
     fn test_invalid_address() {
         test_init();
-        let dram = DramMemory {
-            mem: vec![0; DRAM_SIZE]
-        };
+        let dram = DramMemory::new();
 
         // Try to read from invalid address
         dram.dram_read(0x0, 8);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_reservation_invalidated_by_store() {
+        test_init();
+        let mut dram = DramMemory::new();
+
+        dram.reserve(DRAM_BASE_ADDR + 0x10);
+        assert!(dram.is_reserved(DRAM_BASE_ADDR + 0x10));
+        assert!(!dram.is_reserved(DRAM_BASE_ADDR + 0x18));
+
+        // Store next to the reservation set keeps it
+        dram.dram_write(DRAM_BASE_ADDR + 0x18, 64, 0x1);
+        assert!(dram.is_reserved(DRAM_BASE_ADDR + 0x10));
+
+        // Overlapping store invalidates it
+        dram.dram_write(DRAM_BASE_ADDR + 0xF, 16, 0x1);
+        assert!(!dram.is_reserved(DRAM_BASE_ADDR + 0x10));
+    }
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use riscv_emu::loader::elf::*;
+use riscv_emu::memory::dram::{DramMemory, DRAM_BASE_ADDR};
+
+use common::{build_elf, build_elf32};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[test]
+    fn test_elf_load_segments_and_bss() {
+        test_init();
+        let base = DRAM_BASE_ADDR as u64;
+        let text = [0x93, 0x0e, 0x50, 0x00]; // addi x29, x0, 5
+        let data = [0xAA, 0xBB];
+        let elf = build_elf(EM_RISCV, base + 0x100, &[(base + 0x100, &text, 4), (base + 0x2000, &data, 0x10)], &[]);
+
+        let mut dram = DramMemory::new();
+        dram.dram_write(DRAM_BASE_ADDR + 0x2008, 64, u64::MAX); // stale contents in the BSS
+        let file = ElfFile::parse(&elf).unwrap();
+        assert_eq!(file.entry, base + 0x100);
+        assert_eq!(file.segments.len(), 2);
+        assert_eq!(file.load(&mut dram), Ok(()));
+
+        assert_eq!(dram.dram_read(DRAM_BASE_ADDR + 0x100, 32), 0x00500e93);
+        assert_eq!(dram.dram_read(DRAM_BASE_ADDR + 0x2000, 16), 0xBBAA);
+        assert_eq!(dram.dram_read(DRAM_BASE_ADDR + 0x2008, 64), 0);
+    }
+
+    #[test]
+    fn test_elf32_load_and_symbols() {
+        test_init();
+        let base = DRAM_BASE_ADDR as u32;
+        let text = [0x93, 0x0e, 0x50, 0x00]; // addi x29, x0, 5
+        let elf = build_elf32(EM_RISCV, base + 0x100, &[(base + 0x100, &text, 8)], &[("tohost", base + 0x1000)]);
+
+        let mut dram = DramMemory::new();
+        dram.dram_write(DRAM_BASE_ADDR + 0x104, 32, u32::MAX as u64);
+        let file = ElfFile::parse(&elf).unwrap();
+        assert_eq!(file.xlen, 32);
+        assert_eq!(file.entry, base as u64 + 0x100);
+        assert_eq!(file.symbol("tohost"), Some(base as u64 + 0x1000));
+        assert_eq!(file.symbol("fromhost"), None);
+        assert_eq!(file.load(&mut dram), Ok(()));
+        assert_eq!(dram.dram_read(DRAM_BASE_ADDR + 0x100, 64), 0x00500e93);
+
+        let elf = build_elf(EM_RISCV, base as u64, &[(base as u64, &text, 4)], &[]);
+        assert_eq!(ElfFile::parse(&elf).unwrap().xlen, 64);
+    }
+
+    #[test]
+    fn test_elf_rejects_invalid_files() {
+        test_init();
+        let base = DRAM_BASE_ADDR as u64;
+        let text = [0x13, 0x00, 0x00, 0x00];
+
+        assert_eq!(ElfFile::parse(b"not an elf").err(), Some(ElfError::BadMagic));
+        let elf = build_elf(EM_RISCV, base, &[(base, &text, 4)], &[]);
+        assert_eq!(ElfFile::parse(&elf[..40]).err(), Some(ElfError::Truncated));
+        assert_eq!(ElfFile::parse(&elf[..elf.len() - 1]).err(), Some(ElfError::Truncated));
+
+        // x86-64
+        let elf = build_elf(62, base, &[(base, &text, 4)], &[]);
+        assert_eq!(ElfFile::parse(&elf).err(), Some(ElfError::WrongMachine { e_machine: 62 }));
+
+        // Segment linked at 0x0 instead of the DRAM base
+        let elf = build_elf(EM_RISCV, 0, &[(0, &text, 4)], &[]);
+        let mut dram = DramMemory::new();
+        assert_eq!(ElfFile::parse(&elf).unwrap().load(&mut dram), Err(ElfError::SegmentOutOfMemory { paddr: 0, memsz: 4 }));
+    }
+
+    #[test]
+    fn test_elf_symbols() {
+        test_init();
+        let base = DRAM_BASE_ADDR as u64;
+        let text = [0x13, 0x00, 0x00, 0x00];
+        let elf = build_elf(EM_RISCV, base, &[(base, &text, 4)], &[("tohost", base + 0x1000), ("fromhost", base + 0x1040)]);
+
+        let file = ElfFile::parse(&elf).unwrap();
+        assert_eq!(file.symbol("tohost"), Some(base + 0x1000));
+        assert_eq!(file.symbol("fromhost"), Some(base + 0x1040));
+        assert_eq!(file.symbol("tohos"), None);
+
+        // Crafted offsets near the end of the address space are treated as missing instead of overflowing
+        let mut crafted = elf.clone();
+        crafted[40..48].copy_from_slice(&u64::MAX.to_le_bytes()); // e_shoff
+        assert_eq!(ElfFile::parse(&crafted).unwrap().symbol("tohost"), None);
+        let symtab_shdr = u64::from_le_bytes(elf[40..48].try_into().unwrap()) as usize + 64;
+        let mut crafted = elf.clone();
+        crafted[symtab_shdr + 24..symtab_shdr + 40].copy_from_slice(&[0xff; 16]); // sh_offset, sh_size
+        assert_eq!(ElfFile::parse(&crafted).unwrap().symbol("tohost"), None);
+        let mut crafted = elf.clone();
+        crafted[symtab_shdr + 40..symtab_shdr + 44].copy_from_slice(&u32::MAX.to_le_bytes()); // sh_link
+        assert_eq!(ElfFile::parse(&crafted).unwrap().symbol("tohost"), None);
+
+        // Stripped file
+        let elf = build_elf(EM_RISCV, base, &[(base, &text, 4)], &[]);
+        assert_eq!(ElfFile::parse(&elf).unwrap().symbol("tohost"), None);
+    }
+}
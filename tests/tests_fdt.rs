@@ -0,0 +1,127 @@
+use riscv_emu::cpu::isa::Isa;
+use riscv_emu::loader::fdt::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn be32(blob: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(blob[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn c_string(bytes: &[u8]) -> String {
+        let len = bytes.iter().position(|byte| *byte == 0).unwrap();
+        String::from_utf8(bytes[..len].to_vec()).unwrap()
+    }
+
+    /// (node path, property name, value) of every property in the blob
+    fn properties(blob: &[u8]) -> Vec<(String, String, Vec<u8>)> {
+        let (structure, strings) = (be32(blob, 8) as usize, be32(blob, 12) as usize);
+        let mut path: Vec<String> = Vec::new();
+        let mut properties = Vec::new();
+        let mut offset = structure;
+        loop {
+            let token = be32(blob, offset);
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    let name = c_string(&blob[offset..]);
+                    offset += (name.len() + 1).next_multiple_of(4);
+                    path.push(name);
+                },
+                FDT_END_NODE => {
+                    path.pop();
+                },
+                FDT_PROP => {
+                    let (len, name_offset) = (be32(blob, offset) as usize, be32(blob, offset + 4) as usize);
+                    let value = blob[offset + 8..offset + 8 + len].to_vec();
+                    let node = if path.len() <= 1 { "/".to_string() } else { path.join("/") };
+                    properties.push((node, c_string(&blob[strings + name_offset..]), value));
+                    offset += 8 + len.next_multiple_of(4);
+                },
+                FDT_NOP => {},
+                FDT_END => break,
+                _ => panic!("invalid token {token} at {offset:#x}"),
+            }
+        }
+        assert!(path.is_empty());
+        properties
+    }
+
+    fn property<'a>(properties: &'a [(String, String, Vec<u8>)], node: &str, name: &str) -> &'a [u8] {
+        &properties.iter().find(|(path, property, _)| path == node && property == name)
+            .unwrap_or_else(|| panic!("{node} {name} not found")).2
+    }
+
+    #[test]
+    fn test_fdt_builder_layout() {
+        test_init();
+        let mut fdt = FdtBuilder::new();
+        fdt.begin_node("");
+        fdt.property_string("model", "abc");
+        fdt.begin_node("node@1");
+        fdt.property_u32("model", 7);
+        fdt.property_empty("flag");
+        fdt.end_node();
+        fdt.end_node();
+        let blob = fdt.finish();
+
+        assert_eq!(be32(&blob, 0), 0xd00dfeed);
+        assert_eq!(be32(&blob, 4) as usize, blob.len());
+        assert_eq!(be32(&blob, 20), 17); // version
+        assert_eq!(be32(&blob, 24), 16); // last compatible version
+        let (structure, strings) = (be32(&blob, 8) as usize, be32(&blob, 12) as usize);
+        assert_eq!(structure % 8, 0);
+        assert_eq!(be32(&blob, 16), 40); // empty reservation map right after the header
+        assert!(blob[40..56].iter().all(|byte| *byte == 0));
+        assert_eq!(structure + be32(&blob, 36) as usize, strings);
+        // Property names are stored once
+        assert_eq!(&blob[strings..], b"model\0flag\0");
+        assert_eq!(be32(&blob, 32), 11);
+
+        let properties = properties(&blob);
+        assert_eq!(properties.len(), 3);
+        assert_eq!(property(&properties, "/", "model"), b"abc\0");
+        assert_eq!(property(&properties, "/node@1", "model"), 7u32.to_be_bytes());
+        assert!(property(&properties, "/node@1", "flag").is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "unclosed device tree node")]
+    fn test_fdt_builder_unclosed_node() {
+        test_init();
+        let mut fdt = FdtBuilder::new();
+        fdt.begin_node("");
+        fdt.finish();
+    }
+
+    #[test]
+    fn test_machine_fdt() {
+        test_init();
+        let blob = machine_fdt(0x8000_0000, 64 << 20, &Isa::parse("rv64imac").unwrap(), "console=ttyS0 earlycon");
+        let properties = properties(&blob);
+        assert_eq!(property(&properties, "/chosen", "bootargs"), b"console=ttyS0 earlycon\0");
+        assert_eq!(property(&properties, "/chosen", "stdout-path"), b"/soc/serial@10000000\0");
+        assert_eq!(property(&properties, "/cpus/cpu@0", "riscv,isa"), b"rv64imac\0");
+        assert_eq!(property(&properties, "/cpus/cpu@0", "mmu-type"), b"riscv,sv57\0");
+        assert_eq!(property(&properties, "/cpus", "timebase-frequency"), TIMEBASE_FREQUENCY.to_be_bytes());
+        assert_eq!(property(&properties, "/memory@80000000", "reg"), [0x8000_0000u64.to_be_bytes(), (64u64 << 20).to_be_bytes()].concat());
+        assert_eq!(property(&properties, "/soc/clint@2000000", "compatible"), b"riscv,clint0\0");
+        assert_eq!(property(&properties, "/soc/plic@c000000", "riscv,ndev"), 31u32.to_be_bytes());
+        assert_eq!(property(&properties, "/soc/serial@10000000", "interrupts"), 10u32.to_be_bytes());
+        assert_eq!(property(&properties, "/soc/serial@10000000", "interrupt-parent"), property(&properties, "/soc/plic@c000000", "phandle"));
+    }
+
+    #[test]
+    fn test_machine_fdt_rv32() {
+        test_init();
+        let blob = machine_fdt(0x8000_0000, 64 << 20, &Isa::parse("rv32imac").unwrap(), "");
+        let properties = properties(&blob);
+        assert_eq!(property(&properties, "/cpus/cpu@0", "riscv,isa"), b"rv32imac\0");
+        assert_eq!(property(&properties, "/cpus/cpu@0", "mmu-type"), b"riscv,none\0");
+    }
+}
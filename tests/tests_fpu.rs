@@ -0,0 +1,193 @@
+use riscv_emu::cpu::fpu::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn s(value: f32) -> u64 {
+        nan_box(value.to_bits())
+    }
+
+    fn d(value: f64) -> u64 {
+        value.to_bits()
+    }
+
+    #[test]
+    fn test_nan_boxing() {
+        test_init();
+        assert_eq!(nan_box(0x3f80_0000), 0xFFFF_FFFF_3F80_0000);
+        assert_eq!(unbox(0xFFFF_FFFF_3F80_0000), 0x3f80_0000);
+        // Improperly boxed singles read as the canonical NaN
+        assert_eq!(unbox(0x0000_0000_3F80_0000), CANONICAL_NAN_F32);
+        assert!(fp_value(0x3FF0_0000_0000_0000, FpFormat::Single).is_nan());
+    }
+
+    #[test]
+    fn test_add_rounding_modes() {
+        test_init();
+        // 1 + 2^-30 is not representable as a single
+        let a = s(1.0);
+        let b = s(2f32.powi(-30));
+        assert_eq!(fp_add(FpFormat::Single, a, b, RoundingMode::Rne), (s(1.0), FFLAGS_NX));
+        assert_eq!(fp_add(FpFormat::Single, a, b, RoundingMode::Rtz), (s(1.0), FFLAGS_NX));
+        assert_eq!(fp_add(FpFormat::Single, a, b, RoundingMode::Rdn), (s(1.0), FFLAGS_NX));
+        assert_eq!(fp_add(FpFormat::Single, a, b, RoundingMode::Rup), (s(1.0f32.next_up()), FFLAGS_NX));
+
+        // Same for doubles with 1 + 2^-60
+        let a = d(1.0);
+        let b = d(2f64.powi(-60));
+        assert_eq!(fp_add(FpFormat::Double, a, b, RoundingMode::Rne), (d(1.0), FFLAGS_NX));
+        assert_eq!(fp_add(FpFormat::Double, a, b, RoundingMode::Rup), (d(1.0f64.next_up()), FFLAGS_NX));
+        assert_eq!(fp_sub(FpFormat::Double, a, b, RoundingMode::Rtz), (d(1.0f64.next_down()), FFLAGS_NX));
+        assert_eq!(fp_sub(FpFormat::Double, a, b, RoundingMode::Rne), (d(1.0), FFLAGS_NX));
+
+        // Exact results do not raise any flag
+        assert_eq!(fp_add(FpFormat::Double, d(1.5), d(2.25), RoundingMode::Rup), (d(3.75), 0));
+    }
+
+    #[test]
+    fn test_zero_sign_of_exact_cancellation() {
+        test_init();
+        assert_eq!(fp_sub(FpFormat::Double, d(1.0), d(1.0), RoundingMode::Rne), (d(0.0), 0));
+        assert_eq!(fp_sub(FpFormat::Double, d(1.0), d(1.0), RoundingMode::Rdn), (d(-0.0), 0));
+        assert_eq!(fp_add(FpFormat::Single, s(-0.0), s(-0.0), RoundingMode::Rne), (s(-0.0), 0));
+    }
+
+    #[test]
+    fn test_single_rounding_of_double_results() {
+        test_init();
+        // 1/3 in single precision, rounding up and down
+        let (down, flags) = fp_div(FpFormat::Single, s(1.0), s(3.0), RoundingMode::Rdn);
+        assert_eq!(flags, FFLAGS_NX);
+        let (up, _) = fp_div(FpFormat::Single, s(1.0), s(3.0), RoundingMode::Rup);
+        assert_eq!(f32::from_bits(unbox(up)), f32::from_bits(unbox(down)).next_up());
+        let (nearest, _) = fp_div(FpFormat::Single, s(1.0), s(3.0), RoundingMode::Rne);
+        assert_eq!(nearest, s(1.0 / 3.0));
+    }
+
+    #[test]
+    fn test_overflow_and_underflow() {
+        test_init();
+        let max = d(f64::MAX);
+        assert_eq!(fp_mul(FpFormat::Double, max, d(2.0), RoundingMode::Rne), (d(f64::INFINITY), FFLAGS_OF | FFLAGS_NX));
+        assert_eq!(fp_mul(FpFormat::Double, max, d(2.0), RoundingMode::Rtz), (d(f64::MAX), FFLAGS_OF | FFLAGS_NX));
+        assert_eq!(fp_mul(FpFormat::Double, max, d(-2.0), RoundingMode::Rup), (d(-f64::MAX), FFLAGS_OF | FFLAGS_NX));
+        assert_eq!(fp_mul(FpFormat::Single, s(f32::MAX), s(2.0), RoundingMode::Rdn), (s(f32::MAX), FFLAGS_OF | FFLAGS_NX));
+
+        let tiny = s(f32::from_bits(1)); // smallest subnormal
+        assert_eq!(fp_mul(FpFormat::Single, tiny, s(0.5), RoundingMode::Rne), (s(0.0), FFLAGS_UF | FFLAGS_NX));
+        assert_eq!(fp_mul(FpFormat::Single, tiny, s(0.5), RoundingMode::Rup), (tiny, FFLAGS_UF | FFLAGS_NX));
+    }
+
+    #[test]
+    fn test_invalid_and_divide_by_zero() {
+        test_init();
+        let inf = d(f64::INFINITY);
+        assert_eq!(fp_sub(FpFormat::Double, inf, inf, RoundingMode::Rne), (CANONICAL_NAN_F64, FFLAGS_NV));
+        assert_eq!(fp_mul(FpFormat::Double, inf, d(0.0), RoundingMode::Rne), (CANONICAL_NAN_F64, FFLAGS_NV));
+        assert_eq!(fp_sqrt(FpFormat::Single, s(-1.0), RoundingMode::Rne), (s(f32::from_bits(CANONICAL_NAN_F32)), FFLAGS_NV));
+        assert_eq!(fp_div(FpFormat::Double, d(0.0), d(0.0), RoundingMode::Rne), (CANONICAL_NAN_F64, FFLAGS_NV));
+        assert_eq!(fp_div(FpFormat::Double, d(-1.0), d(0.0), RoundingMode::Rne), (d(f64::NEG_INFINITY), FFLAGS_DZ));
+
+        // Quiet NaN operands propagate as canonical NaN without flags, signaling NaNs raise NV
+        let qnan = 0x7FF8_0000_0000_1234;
+        let snan = 0x7FF0_0000_0000_0001;
+        assert_eq!(fp_add(FpFormat::Double, qnan, d(1.0), RoundingMode::Rne), (CANONICAL_NAN_F64, 0));
+        assert_eq!(fp_add(FpFormat::Double, snan, d(1.0), RoundingMode::Rne), (CANONICAL_NAN_F64, FFLAGS_NV));
+        // inf * 0 + qNaN is invalid
+        assert_eq!(fp_fma(FpFormat::Double, inf, d(0.0), qnan, false, false, RoundingMode::Rne), (CANONICAL_NAN_F64, FFLAGS_NV));
+    }
+
+    #[test]
+    fn test_fused_multiply_add() {
+        test_init();
+        // (1 + 2^-30)^2 - 1 requires the unrounded product
+        let x = d(1.0 + 2f64.powi(-30));
+        assert_eq!(fp_fma(FpFormat::Double, x, x, d(1.0), false, true, RoundingMode::Rne), (d(2f64.powi(-29) + 2f64.powi(-60)), 0));
+        assert_eq!(fp_fma(FpFormat::Single, s(2.0), s(3.0), s(1.0), true, false, RoundingMode::Rne), (s(-5.0), 0)); // fnmsub
+        assert_eq!(fp_fma(FpFormat::Single, s(2.0), s(3.0), s(1.0), true, true, RoundingMode::Rne), (s(-7.0), 0)); // fnmadd
+    }
+
+    #[test]
+    fn test_min_max_and_compare() {
+        test_init();
+        let qnan = s(f32::NAN);
+        assert_eq!(fp_min(FpFormat::Single, s(-0.0), s(0.0)), (s(-0.0), 0));
+        assert_eq!(fp_max(FpFormat::Single, s(-0.0), s(0.0)), (s(0.0), 0));
+        assert_eq!(fp_min(FpFormat::Single, qnan, s(2.0)), (s(2.0), 0));
+        assert_eq!(fp_max(FpFormat::Single, qnan, qnan), (nan_box(CANONICAL_NAN_F32), 0));
+
+        assert_eq!(fp_eq(FpFormat::Double, d(1.0), d(1.0)), (1, 0));
+        assert_eq!(fp_eq(FpFormat::Double, d(f64::NAN), d(1.0)), (0, 0)); // quiet comparison
+        assert_eq!(fp_lt(FpFormat::Double, d(f64::NAN), d(1.0)), (0, FFLAGS_NV)); // signaling comparison
+        assert_eq!(fp_le(FpFormat::Double, d(-0.0), d(0.0)), (1, 0));
+    }
+
+    #[test]
+    fn test_classify() {
+        test_init();
+        assert_eq!(fp_classify(FpFormat::Double, d(f64::NEG_INFINITY)), 1 << 0);
+        assert_eq!(fp_classify(FpFormat::Double, d(-1.0)), 1 << 1);
+        assert_eq!(fp_classify(FpFormat::Double, d(-f64::from_bits(1))), 1 << 2);
+        assert_eq!(fp_classify(FpFormat::Double, d(-0.0)), 1 << 3);
+        assert_eq!(fp_classify(FpFormat::Single, s(0.0)), 1 << 4);
+        assert_eq!(fp_classify(FpFormat::Single, s(f32::from_bits(1))), 1 << 5);
+        assert_eq!(fp_classify(FpFormat::Single, s(1.0)), 1 << 6);
+        assert_eq!(fp_classify(FpFormat::Single, s(f32::INFINITY)), 1 << 7);
+        assert_eq!(fp_classify(FpFormat::Single, nan_box(0x7f80_0001)), 1 << 8);
+        assert_eq!(fp_classify(FpFormat::Single, nan_box(CANONICAL_NAN_F32)), 1 << 9);
+    }
+
+    #[test]
+    fn test_sign_injection() {
+        test_init();
+        assert_eq!(fp_sign_inject(FpFormat::Single, s(1.5), s(-2.0), SignInjection::Copy), s(-1.5));
+        assert_eq!(fp_sign_inject(FpFormat::Single, s(1.5), s(-2.0), SignInjection::Negate), s(1.5));
+        assert_eq!(fp_sign_inject(FpFormat::Double, d(-1.5), d(-2.0), SignInjection::Xor), d(1.5));
+    }
+
+    #[test]
+    fn test_float_to_int_conversion() {
+        test_init();
+        assert_eq!(fp_to_int(FpFormat::Double, d(-2.5), true, true, RoundingMode::Rne), (-2i64 as u64, FFLAGS_NX));
+        assert_eq!(fp_to_int(FpFormat::Double, d(-2.5), true, true, RoundingMode::Rmm), (-3i64 as u64, FFLAGS_NX));
+        assert_eq!(fp_to_int(FpFormat::Double, d(-2.5), true, true, RoundingMode::Rup), (-2i64 as u64, FFLAGS_NX));
+        assert_eq!(fp_to_int(FpFormat::Double, d(2.5), true, false, RoundingMode::Rdn), (2, FFLAGS_NX));
+        assert_eq!(fp_to_int(FpFormat::Double, d(3.0), false, false, RoundingMode::Rtz), (3, 0));
+
+        // Saturation and NaN
+        assert_eq!(fp_to_int(FpFormat::Single, s(3e9), true, true, RoundingMode::Rtz), (i32::MAX as u64, FFLAGS_NV));
+        assert_eq!(fp_to_int(FpFormat::Single, s(-1.0), false, true, RoundingMode::Rtz), (0, FFLAGS_NV));
+        assert_eq!(fp_to_int(FpFormat::Single, s(-0.5), false, true, RoundingMode::Rtz), (0, FFLAGS_NX));
+        assert_eq!(fp_to_int(FpFormat::Double, d(f64::NAN), true, false, RoundingMode::Rtz), (i64::MAX as u64, FFLAGS_NV));
+        assert_eq!(fp_to_int(FpFormat::Double, d(f64::NEG_INFINITY), true, false, RoundingMode::Rtz), (i64::MIN as u64, FFLAGS_NV));
+        // 32-bit unsigned results are sign-extended
+        assert_eq!(fp_to_int(FpFormat::Double, d(4294967295.0), false, true, RoundingMode::Rtz), (u64::MAX, 0));
+    }
+
+    #[test]
+    fn test_int_to_float_conversion() {
+        test_init();
+        assert_eq!(int_to_fp(FpFormat::Double, -7i64 as u64, true, false, RoundingMode::Rne), (d(-7.0), 0));
+        assert_eq!(int_to_fp(FpFormat::Double, 0xFFFF_FFFF, true, true, RoundingMode::Rne), (d(-1.0), 0));
+        assert_eq!(int_to_fp(FpFormat::Double, 0xFFFF_FFFF, false, true, RoundingMode::Rne), (d(4294967295.0), 0));
+        // 2^24 + 1 is not representable as a single
+        assert_eq!(int_to_fp(FpFormat::Single, 16_777_217, true, false, RoundingMode::Rne), (s(16_777_216.0), FFLAGS_NX));
+        assert_eq!(int_to_fp(FpFormat::Single, 16_777_217, true, false, RoundingMode::Rup), (s(16_777_218.0), FFLAGS_NX));
+        assert_eq!(int_to_fp(FpFormat::Double, u64::MAX, false, false, RoundingMode::Rtz), (d(18446744073709549568.0), FFLAGS_NX));
+    }
+
+    #[test]
+    fn test_precision_conversion() {
+        test_init();
+        assert_eq!(fp_convert(FpFormat::Single, FpFormat::Double, s(1.5), RoundingMode::Rne), (d(1.5), 0));
+        assert_eq!(fp_convert(FpFormat::Double, FpFormat::Single, d(0.1), RoundingMode::Rne), (s(0.1), FFLAGS_NX));
+        assert_eq!(fp_convert(FpFormat::Double, FpFormat::Single, d(0.1), RoundingMode::Rdn), (s(0.1f32.next_down()), FFLAGS_NX));
+        assert_eq!(fp_convert(FpFormat::Double, FpFormat::Single, d(1e300), RoundingMode::Rne), (s(f32::INFINITY), FFLAGS_OF | FFLAGS_NX));
+        assert_eq!(fp_convert(FpFormat::Single, FpFormat::Double, nan_box(0x7f80_0001), RoundingMode::Rne), (CANONICAL_NAN_F64, FFLAGS_NV));
+    }
+}
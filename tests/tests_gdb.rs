@@ -0,0 +1,179 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use riscv_emu::cpu::basic_cpu::{BasicCpu, CSR_MEPC, CSR_MSTATUS};
+use riscv_emu::debug::gdb::*;
+use riscv_emu::memory::dram::DRAM_BASE_ADDR;
+use riscv_emu::os::syscall::ExitOnEcall;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: u64 = DRAM_BASE_ADDR as u64;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn setup_cpu() -> BasicCpu {
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        for i in 0..16 {
+            cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 4 * i, 32, 0x00128293); // addi t0, t0, 1
+        }
+        cpu
+    }
+
+    fn command(stub: &mut GdbStub, cpu: &mut BasicCpu, packet: &str) -> String {
+        stub.handle_command(cpu, packet, &mut || false)
+    }
+
+    #[test]
+    fn test_gdb_packets() {
+        test_init();
+        assert_eq!(checksum("OK"), 0x9a);
+        assert_eq!(encode_packet("OK"), "$OK#9a");
+        assert_eq!(encode_packet(""), "$#00");
+    }
+
+    #[test]
+    fn test_gdb_registers() {
+        test_init();
+        let mut cpu = setup_cpu();
+        let mut stub = GdbStub::new();
+        cpu.set_register(10, 0x1122334455667788);
+
+        let regs = command(&mut stub, &mut cpu, "g");
+        assert_eq!(regs.len(), 33 * 16);
+        assert_eq!(&regs[10 * 16..11 * 16], "8877665544332211");
+        assert_eq!(&regs[32 * 16..], "0000008000000000"); // pc
+
+        assert_eq!(command(&mut stub, &mut cpu, "P5=efbeadde00000000"), "OK");
+        assert_eq!(cpu.get_register(5), 0xdeadbeef);
+        assert_eq!(command(&mut stub, &mut cpu, "P0=0100000000000000"), "OK");
+        assert_eq!(cpu.get_register(0), 0);
+        assert_eq!(command(&mut stub, &mut cpu, &format!("P{:x}=0800000000000000", GDB_REG_CSR0 + CSR_MSTATUS)), "OK");
+        assert_eq!(cpu.get_csr(CSR_MSTATUS), 0x8);
+        assert_eq!(command(&mut stub, &mut cpu, &format!("p{:x}", GDB_REG_PRIV)), "0300000000000000");
+        assert_eq!(command(&mut stub, &mut cpu, "p20"), "0000008000000000");
+        assert_eq!(command(&mut stub, &mut cpu, "p100000"), "E01");
+    }
+
+    #[test]
+    fn test_gdb_memory() {
+        test_init();
+        let mut cpu = setup_cpu();
+        let mut stub = GdbStub::new();
+
+        assert_eq!(command(&mut stub, &mut cpu, &format!("m{BASE:x},4")), "93821200");
+        assert_eq!(command(&mut stub, &mut cpu, &format!("M{:x},2:abcd", BASE + 0x100)), "OK");
+        assert_eq!(cpu.bus.dram.dram_read(DRAM_BASE_ADDR + 0x100, 16), 0xcdab);
+        // Reads stop at the end of memory, nothing readable is an error
+        let end = BASE + cpu.bus.dram.size() as u64;
+        assert_eq!(command(&mut stub, &mut cpu, &format!("m{:x},4", end - 2)), "0000");
+        assert_eq!(command(&mut stub, &mut cpu, &format!("m{end:x},4")), "E14");
+        assert_eq!(command(&mut stub, &mut cpu, "m0,4"), "E14");
+        // Non-ASCII hex digits (invalid UTF-8 is received as U+FFFD) and commands are rejected instead of panicking
+        assert_eq!(command(&mut stub, &mut cpu, &format!("M{BASE:x},2:\u{fffd}a")), "E01");
+        assert_eq!(command(&mut stub, &mut cpu, &format!("M{BASE:x},1:\u{e9}")), "E01");
+        assert_eq!(command(&mut stub, &mut cpu, "\u{fffd}"), "");
+        assert_eq!(command(&mut stub, &mut cpu, "\u{fffd}m0,4"), "");
+    }
+
+    #[test]
+    fn test_gdb_breakpoints_and_stepping() {
+        test_init();
+        let mut cpu = setup_cpu();
+        let mut stub = GdbStub::new();
+
+        assert_eq!(command(&mut stub, &mut cpu, &format!("Z0,{:x},4", BASE + 8)), "OK");
+        assert_eq!(command(&mut stub, &mut cpu, "c"), "T05swbreak:;");
+        assert_eq!(cpu.get_pc(), BASE + 8);
+        assert_eq!(cpu.get_register(5), 2);
+
+        // Single step off the breakpoint
+        assert_eq!(command(&mut stub, &mut cpu, "s"), "S05");
+        assert_eq!(cpu.get_pc(), BASE + 12);
+
+        // Without breakpoints execution continues until the illegal instruction after the program traps without a handler
+        assert_eq!(command(&mut stub, &mut cpu, &format!("z0,{:x},4", BASE + 8)), "OK");
+        assert_eq!(command(&mut stub, &mut cpu, &format!("c{BASE:x}")), "S04");
+        assert_eq!(cpu.get_pc(), BASE + 64);
+        assert_eq!(cpu.get_csr(CSR_MEPC), 0);
+        assert_eq!(cpu.get_register(5), 2 + 1 + 16);
+
+        // Continue is interrupted by the debugger
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 64, 32, 0x0000006F); // j .
+        assert_eq!(stub.handle_command(&mut cpu, &format!("c{:x}", BASE + 64), &mut || true), "S02");
+
+        // The exit of the program is reported and kept for the exit status
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 68, 32, 0x02a00513); // li a0, 42
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 72, 32, 0x00000073); // ecall
+        cpu.set_syscall_handler(Some(Box::new(ExitOnEcall)));
+        assert_eq!(command(&mut stub, &mut cpu, &format!("c{:x}", BASE + 68)), "W2a");
+        assert_eq!(stub.exit_code, Some(42));
+    }
+
+    #[test]
+    fn test_gdb_queries() {
+        test_init();
+        let mut cpu = setup_cpu();
+        let mut stub = GdbStub::new();
+
+        assert!(command(&mut stub, &mut cpu, "qSupported:swbreak+").contains("qXfer:features:read+"));
+        assert_eq!(command(&mut stub, &mut cpu, "?"), "S05");
+        assert_eq!(command(&mut stub, &mut cpu, "vMustReplyEmpty"), "");
+
+        let xml = target_xml();
+        assert!(xml.contains("<reg name=\"pc\" bitsize=\"64\" regnum=\"32\" type=\"code_ptr\"/>"));
+        assert!(xml.contains("<reg name=\"mstatus\" bitsize=\"64\" regnum=\"833\" type=\"int\"/>"));
+        let first = command(&mut stub, &mut cpu, "qXfer:features:read:target.xml:0,10");
+        assert_eq!(first, format!("m{}", &xml[..0x10]));
+        let last = command(&mut stub, &mut cpu, &format!("qXfer:features:read:target.xml:10,{:x}", xml.len()));
+        assert_eq!(last, format!("l{}", &xml[0x10..]));
+    }
+
+    #[test]
+    fn test_gdb_tcp_session() {
+        test_init();
+        let mut cpu = setup_cpu();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            // Packets with a wrong checksum are rejected
+            stream.write_all(b"$?#00").unwrap();
+            let mut nack = [0u8];
+            stream.read_exact(&mut nack).unwrap();
+
+            let mut exchange = |packet: &str| -> String {
+                stream.write_all(encode_packet(packet).as_bytes()).unwrap();
+                let mut reply = Vec::new();
+                let mut byte = [0u8];
+                // "+" acknowledgement followed by "$data#cs"
+                while reply.len() < 3 || reply[reply.len() - 3] != b'#' {
+                    stream.read_exact(&mut byte).unwrap();
+                    reply.push(byte[0]);
+                }
+                stream.write_all(b"+").unwrap();
+                String::from_utf8(reply).unwrap()
+            };
+            let stop = exchange("?");
+            let step = exchange("s");
+            let detach = exchange("D");
+            (nack[0], stop, step, detach)
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        GdbStub::new().run(&mut cpu, stream).unwrap();
+        let (nack, stop, step, detach) = client.join().unwrap();
+        assert_eq!(nack, b'-');
+        assert_eq!(stop, "+$S05#b8");
+        assert_eq!(step, "+$S05#b8");
+        assert_eq!(detach, "+$OK#9a");
+        assert_eq!(cpu.get_pc(), BASE + 4);
+    }
+}
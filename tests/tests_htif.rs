@@ -0,0 +1,94 @@
+mod common;
+
+use riscv_emu::cpu::basic_cpu::BasicCpu;
+use riscv_emu::devices::htif::*;
+use riscv_emu::memory::dram::{DramMemory, DRAM_BASE_ADDR};
+
+use common::SharedBuffer;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOHOST: usize = DRAM_BASE_ADDR + 0x1000;
+    const FROMHOST: usize = DRAM_BASE_ADDR + 0x1040;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn new_htif(output: &SharedBuffer) -> Htif {
+        Htif::with_output(TOHOST as u64, Some(FROMHOST as u64), Box::new(output.clone()))
+    }
+
+    #[test]
+    fn test_htif_exit_code() {
+        test_init();
+        let mut dram = DramMemory::new();
+        let mut htif = new_htif(&SharedBuffer::default());
+
+        assert_eq!(htif.poll(&mut dram), None);
+        // riscv-tests report a pass as 1 and a failure of test N as (N << 1) | 1
+        dram.dram_write(TOHOST, 64, (3 << 1) | 1);
+        assert_eq!(htif.poll(&mut dram), Some(3));
+        assert_eq!(htif.exit_code(), Some(3));
+
+        let mut htif = new_htif(&SharedBuffer::default());
+        dram.dram_write(TOHOST, 64, 1);
+        assert_eq!(htif.poll(&mut dram), Some(0));
+    }
+
+    #[test]
+    fn test_htif_syscall_proxy_and_console() {
+        test_init();
+        let mut dram = DramMemory::new();
+        let output = SharedBuffer::default();
+        let mut htif = new_htif(&output);
+
+        // magic_mem = [SYS_write, fd 1, buf, len]
+        let magic_mem = DRAM_BASE_ADDR + 0x2000;
+        let buf = DRAM_BASE_ADDR + 0x3000;
+        for (i, byte) in b"hello".iter().enumerate() {
+            dram.dram_write(buf + i, 8, *byte as u64);
+        }
+        for (i, value) in [HTIF_SYS_WRITE, 1, buf as u64, 5].iter().enumerate() {
+            dram.dram_write(magic_mem + 8 * i, 64, *value);
+        }
+        dram.dram_write(TOHOST, 64, magic_mem as u64);
+        assert_eq!(htif.poll(&mut dram), None);
+        assert_eq!(output.contents(), "hello");
+        assert_eq!(dram.dram_read(magic_mem, 64), 5); // return value
+        assert_eq!(dram.dram_read(TOHOST, 64), 0);
+        assert_eq!(dram.dram_read(FROMHOST, 64), 1);
+
+        // Console putchar
+        dram.dram_write(FROMHOST, 64, 0);
+        dram.dram_write(TOHOST, 64, (HTIF_DEV_CONSOLE << 56) | (HTIF_CONSOLE_PUTCHAR << 48) | b'!' as u64);
+        assert_eq!(htif.poll(&mut dram), None);
+        assert_eq!(output.contents(), "hello!");
+        assert_eq!(dram.dram_read(FROMHOST, 64), (HTIF_DEV_CONSOLE << 56) | (HTIF_CONSOLE_PUTCHAR << 48));
+
+        // SYS_exit through the proxy
+        for (i, value) in [HTIF_SYS_EXIT, 7].iter().enumerate() {
+            dram.dram_write(magic_mem + 8 * i, 64, *value);
+        }
+        dram.dram_write(TOHOST, 64, magic_mem as u64);
+        assert_eq!(htif.poll(&mut dram), Some(7));
+    }
+
+    #[test]
+    fn test_htif_guest_store() {
+        test_init();
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        let mut htif = new_htif(&SharedBuffer::default());
+
+        // sd a0, 0(a1) with a0 = 1 (pass) and a1 = tohost
+        cpu.bus.dram.dram_write(DRAM_BASE_ADDR, 32, 0x00A5B023);
+        cpu.set_register(10, 1);
+        cpu.set_register(11, TOHOST as u64);
+        assert_eq!(htif.poll(&mut cpu.bus.dram), None);
+        assert_eq!(cpu.step(), None);
+        assert_eq!(htif.poll(&mut cpu.bus.dram), Some(0));
+    }
+}
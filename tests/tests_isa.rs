@@ -0,0 +1,135 @@
+use riscv_emu::cpu::basic_cpu::{BasicCpu, CSR_MISA};
+use riscv_emu::cpu::error::ExecError;
+use riscv_emu::cpu::isa::*;
+use riscv_emu::cpu::registers::EMBEDDED_REGISTERS_COUNT;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MUL: u32 = 0x02c58533; // mul a0, a1, a2
+    const AMOADD_W: u32 = 0x00b6252f; // amoadd.w a0, a1, (a2)
+    const FLW: u32 = 0x0005a507; // flw fa0, 0(a1)
+    const FLD: u32 = 0x0005b507; // fld fa0, 0(a1)
+    const FADD_S: u32 = 0x00c5f553; // fadd.s fa0, fa1, fa2
+    const FADD_D: u32 = 0x02c5f553; // fadd.d fa0, fa1, fa2
+    const FCVT_S_D: u32 = 0x4015f553; // fcvt.s.d fa0, fa1
+    const FMADD_D: u32 = 0x6ac5f543; // fmadd.d fa0, fa1, fa2, fa3
+    const ADDI: u32 = 0x00150513; // addi a0, a0, 1
+    const SRAI_31: u32 = 0x41f55513; // srai a0, a0, 31
+    const SRAI_32: u32 = 0x42055513; // srai a0, a0, 32
+    const ADDIW: u32 = 0x0015051b; // addiw a0, a0, 1
+    const LD: u32 = 0x0005b503; // ld a0, 0(a1)
+    const SD: u32 = 0x00a5b023; // sd a0, 0(a1)
+    const AMOADD_D: u32 = 0x00b6352f; // amoadd.d a0, a1, (a2)
+    const FMV_X_D: u32 = 0xe2050553; // fmv.x.d a0, fa0
+    const FCVT_L_S: u32 = 0xc0257553; // fcvt.l.s a0, fa0
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[test]
+    fn test_isa_parse() {
+        test_init();
+        for (isa, canonical) in [
+            ("rv64imafdc", "rv64imafdc"),
+            ("rv64gc", "rv64imafdc"),
+            ("RV64IMAC", "rv64imac"),
+            ("rv64i", "rv64i"),
+            ("rv64icm", "rv64imc"),
+            ("rv64e", "rv64e"),
+            ("rv64emc", "rv64emc"),
+            ("rv64ima_zicsr_zifencei", "rv64ima"),
+            ("rv32imac", "rv32imac"),
+            ("RV32GC", "rv32imafdc"),
+            ("rv32e", "rv32e"),
+            ("rv32emc", "rv32emc"),
+        ] {
+            assert_eq!(Isa::parse(isa).unwrap().to_string(), canonical, "{isa}");
+        }
+        assert_eq!(Isa::default().to_string(), "rv64imafdc");
+
+        for (isa, reason) in [
+            ("imac", "expected rv32 or rv64 prefix"),
+            ("rv128i", "expected rv32 or rv64 prefix"),
+            ("rv32", "expected base ISA i, e or g"),
+            ("rv64", "expected base ISA i, e or g"),
+            ("rv64mac", "expected base ISA i, e or g"),
+            ("rv64imv", "unsupported extension"),
+            ("rv64ii", "unsupported extension"),
+            ("rv64imm", "duplicate extension"),
+            ("rv64gf", "duplicate extension"),
+            ("rv64id", "D requires F"),
+            ("rv64i_zba", "unsupported extension"),
+        ] {
+            assert_eq!(Isa::parse(isa), Err(IsaError { isa: isa.to_string(), reason }), "{isa}");
+        }
+    }
+
+    #[test]
+    fn test_isa_accepts() {
+        test_init();
+        let full = Isa::default();
+        for instr in [MUL, AMOADD_W, FLW, FLD, FADD_S, FADD_D, FCVT_S_D, FMADD_D, ADDI] {
+            assert!(full.accepts(instr, false), "{instr:#010x}");
+        }
+        assert!(full.accepts(ADDI, true));
+
+        let base = Isa::parse("rv64i").unwrap();
+        for instr in [MUL, AMOADD_W, FLW, FLD, FADD_S, FADD_D, FCVT_S_D, FMADD_D] {
+            assert!(!base.accepts(instr, false), "{instr:#010x}");
+        }
+        assert!(base.accepts(ADDI, false));
+        assert!(!base.accepts(ADDI, true)); // c.addi without C
+
+        let single = Isa::parse("rv64imaf").unwrap();
+        for instr in [MUL, AMOADD_W, FLW, FADD_S] {
+            assert!(single.accepts(instr, false), "{instr:#010x}");
+        }
+        for instr in [FLD, FADD_D, FCVT_S_D, FMADD_D] {
+            assert!(!single.accepts(instr, false), "{instr:#010x}");
+        }
+
+        // RV32 has the same extensions without the RV64-only instructions
+        let rv32 = Isa::parse("rv32imafdc").unwrap();
+        for instr in [MUL, AMOADD_W, FLW, FLD, FADD_S, FADD_D, FCVT_S_D, FMADD_D, ADDI, SRAI_31] {
+            assert!(rv32.accepts(instr, false), "{instr:#010x}");
+        }
+        for instr in [ADDIW, LD, SD, AMOADD_D, SRAI_32, FMV_X_D, FCVT_L_S] {
+            assert!(!rv32.accepts(instr, false), "{instr:#010x}");
+            assert!(full.accepts(instr, false), "{instr:#010x}");
+        }
+    }
+
+    #[test]
+    fn test_cpu_isa() {
+        test_init();
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        // MXL = 64, A C D F I M S U
+        assert_eq!(cpu.get_csr(CSR_MISA), (2 << 62) | 0x14112d);
+        cpu.set_csr(CSR_MISA, 0);
+        assert_eq!(cpu.get_csr(CSR_MISA), (2 << 62) | 0x14112d);
+
+        cpu.set_isa(Isa::parse("rv64ic").unwrap());
+        assert_eq!(cpu.get_csr(CSR_MISA), (2 << 62) | 0x140104);
+        assert!(matches!(cpu.execute_instr(MUL), Err(ExecError::IllegalInstruction { .. })));
+        cpu.execute_instr(0x0505).unwrap(); // c.addi a0, 1
+        assert_eq!(cpu.get_register(10), 1);
+
+        cpu.set_isa(Isa::parse("rv64e").unwrap());
+        assert_eq!(cpu.registers().count(), EMBEDDED_REGISTERS_COUNT);
+        assert_eq!(cpu.isa().to_string(), "rv64e");
+        assert!(matches!(cpu.execute_instr(0x0505), Err(ExecError::IllegalInstruction { .. })));
+
+        // RV32E: 16 registers with 32-bit values, MXL = 32 and the E bit in misa
+        cpu.set_isa(Isa::parse("rv32ec").unwrap());
+        assert_eq!(cpu.registers().count(), EMBEDDED_REGISTERS_COUNT);
+        assert_eq!(cpu.get_csr(CSR_MISA), (1 << 30) | 0x140014);
+        cpu.set_register(10, 0x7fff_ffff);
+        cpu.execute_instr(0x0505).unwrap(); // c.addi a0, 1
+        assert_eq!(cpu.get_register(10), 0xffff_ffff_8000_0000);
+        assert!(matches!(cpu.execute_instr(0x00180813), Err(ExecError::IllegalInstruction { .. }))); // addi a6, a6, 1
+    }
+}
@@ -0,0 +1,181 @@
+mod common;
+
+use std::io;
+
+use riscv_emu::cpu::basic_cpu::{BasicCpu, Privilege, TReg};
+use riscv_emu::loader::elf::ElfFile;
+use riscv_emu::os::linux::*;
+use riscv_emu::os::syscall::{read_guest, read_guest_string, SyscallHandler, SyscallOutcome};
+
+use common::{build_executable, SharedBuffer, ELF_HEADERS_SIZE};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT_BASE: u64 = 0x10000;
+
+    // write(1, "hello\n", 6); exit_group(argc)
+    const HELLO: [u32; 10] = [
+        0x00013403, // ld s0, 0(sp)
+        0x00100513, // li a0, 1
+        0x00000597, // auipc a1, 0
+        0x02058593, // addi a1, a1, 32
+        0x00600613, // li a2, 6
+        0x04000893, // li a7, 64
+        0x00000073, // ecall
+        0x00040513, // mv a0, s0
+        0x05e00893, // li a7, 94
+        0x00000073, // ecall
+    ];
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn load(binary: &[u8], args: &[&str], env: &[&str]) -> (BasicCpu, LinuxProcess, SharedBuffer) {
+        let mut cpu = BasicCpu::with_bus(user_bus());
+        let elf = ElfFile::parse(binary).unwrap();
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let env: Vec<String> = env.iter().map(|var| var.to_string()).collect();
+        let mut process = LinuxProcess::load(&mut cpu, &elf, &args, &env).unwrap();
+        let stdout = SharedBuffer::default();
+        process.set_output(Box::new(stdout.clone()), Box::new(io::sink()));
+        (cpu, process, stdout)
+    }
+
+    fn read_u64(cpu: &BasicCpu, addr: TReg) -> u64 {
+        u64::from_le_bytes(read_guest(&cpu.bus.dram, addr, 8).unwrap().try_into().unwrap())
+    }
+
+    /// Runs the syscall in a7 with the arguments in a0.. and returns a0
+    fn syscall(cpu: &mut BasicCpu, process: &mut LinuxProcess, number: u64, args: &[u64]) -> i64 {
+        cpu.set_register(17, number);
+        for (i, arg) in args.iter().enumerate() {
+            cpu.set_register(10 + i, *arg);
+        }
+        assert_eq!(process.syscall(cpu), SyscallOutcome::Return);
+        cpu.get_register(10) as i64
+    }
+
+    #[test]
+    fn test_linux_hello_world() {
+        test_init();
+        let binary = build_executable(TEXT_BASE, &HELLO, b"hello\n");
+        let (mut cpu, process, stdout) = load(&binary, &["hello", "world"], &[]);
+        cpu.set_syscall_handler(Some(Box::new(process)));
+        for _ in 0..HELLO.len() {
+            assert_eq!(cpu.step(), None);
+        }
+        assert_eq!(stdout.contents(), "hello\n");
+        assert_eq!(cpu.exit_code(), Some(2)); // argc
+        assert_eq!(cpu.get_privilege(), Privilege::User);
+    }
+
+    #[test]
+    fn test_linux_initial_stack() {
+        test_init();
+        let binary = build_executable(TEXT_BASE, &HELLO, b"hello\n");
+        let (cpu, _, _) = load(&binary, &["prog", "-v"], &["HOME=/root"]);
+        let sp = cpu.get_register(2);
+        assert_eq!(sp % 16, 0);
+        assert_eq!(cpu.get_pc(), TEXT_BASE + ELF_HEADERS_SIZE);
+        let dram = &cpu.bus.dram;
+
+        assert_eq!(read_u64(&cpu, sp), 2); // argc
+        assert_eq!(read_guest_string(dram, read_u64(&cpu, sp + 8)).unwrap(), "prog");
+        assert_eq!(read_guest_string(dram, read_u64(&cpu, sp + 16)).unwrap(), "-v");
+        assert_eq!(read_u64(&cpu, sp + 24), 0);
+        assert_eq!(read_guest_string(dram, read_u64(&cpu, sp + 32)).unwrap(), "HOME=/root");
+        assert_eq!(read_u64(&cpu, sp + 40), 0);
+
+        let mut auxv = Vec::new();
+        let mut entry = sp + 48;
+        loop {
+            let (key, value) = (read_u64(&cpu, entry), read_u64(&cpu, entry + 8));
+            if key == 0 {
+                break;
+            }
+            auxv.push((key, value));
+            entry += 16;
+        }
+        let aux = |key: u64| auxv.iter().find(|(k, _)| *k == key).map(|(_, value)| *value);
+        assert_eq!(aux(3), Some(TEXT_BASE + 64)); // AT_PHDR
+        assert_eq!(aux(4), Some(56)); // AT_PHENT
+        assert_eq!(aux(5), Some(1)); // AT_PHNUM
+        assert_eq!(aux(6), Some(4096)); // AT_PAGESZ
+        assert_eq!(aux(9), Some(TEXT_BASE + ELF_HEADERS_SIZE)); // AT_ENTRY
+        let random = aux(25).unwrap(); // AT_RANDOM
+        assert!(random > sp && read_guest(dram, random, 16).is_some());
+        assert_eq!(read_guest_string(dram, aux(31).unwrap()).unwrap(), "prog"); // AT_EXECFN
+    }
+
+    #[test]
+    fn test_linux_memory_syscalls() {
+        test_init();
+        let binary = build_executable(TEXT_BASE, &HELLO, b"hello\n");
+        let (mut cpu, mut process, _) = load(&binary, &["prog"], &[]);
+
+        // The program break starts after the BSS, page aligned
+        let brk = syscall(&mut cpu, &mut process, SYS_BRK, &[0]) as u64;
+        assert_eq!(brk, TEXT_BASE + 0x1000);
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_BRK, &[brk + 0x2000]) as u64, brk + 0x2000);
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_BRK, &[brk - 0x1000]) as u64, brk + 0x2000); // below the start
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_BRK, &[u64::MAX]) as u64, brk + 0x2000);
+
+        // Anonymous mappings are page aligned, zeroed and do not overlap
+        let first = syscall(&mut cpu, &mut process, SYS_MMAP, &[0, 100, 3, 0x22, u64::MAX, 0]) as u64;
+        let second = syscall(&mut cpu, &mut process, SYS_MMAP, &[0, 0x3000, 3, 0x22, u64::MAX, 0]) as u64;
+        assert_eq!(first % USER_PAGE_SIZE, 0);
+        assert_eq!(second + 0x3000, first);
+        assert!(read_guest(&cpu.bus.dram, second, 0x4000).unwrap().iter().all(|byte| *byte == 0));
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_MMAP, &[0, USER_MEMORY_SIZE as u64, 3, 0x22, u64::MAX, 0]), -12); // ENOMEM
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_MUNMAP, &[first, 100]), 0);
+    }
+
+    #[test]
+    fn test_linux_file_syscalls() {
+        test_init();
+        let binary = build_executable(TEXT_BASE, &HELLO, b"hello\n");
+        let (mut cpu, mut process, stdout) = load(&binary, &["prog"], &[]);
+        let scratch = cpu.get_register(2) - 0x1000;
+        let path = std::env::temp_dir().join(format!("riscv-emu-linux-{}.txt", std::process::id()));
+        let path_string = format!("{}\0", path.display());
+        cpu.bus.dram.mem[(scratch - USER_MEMORY_BASE as u64) as usize..][..path_string.len()].copy_from_slice(path_string.as_bytes());
+        let buffer = scratch + 0x200;
+        let statbuf = scratch + 0x400;
+        cpu.bus.dram.mem[(buffer - USER_MEMORY_BASE as u64) as usize..][..5].copy_from_slice(b"12345");
+
+        // openat(AT_FDCWD, path, O_RDWR | O_CREAT | O_TRUNC), write, seek back and read
+        let fd = syscall(&mut cpu, &mut process, SYS_OPENAT, &[-100i64 as u64, scratch, 0o1102, 0o644]);
+        assert_eq!(fd, 3);
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_WRITE, &[fd as u64, buffer, 5]), 5);
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_LSEEK, &[fd as u64, 1, 0]), 1);
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_READ, &[fd as u64, buffer + 0x10, 16]), 4);
+        assert_eq!(read_guest(&cpu.bus.dram, buffer + 0x10, 4).unwrap(), b"2345");
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_FSTAT, &[fd as u64, statbuf]), 0);
+        assert_eq!(read_u64(&cpu, statbuf + 48), 5); // st_size
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_CLOSE, &[fd as u64]), 0);
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_CLOSE, &[fd as u64]), -9); // EBADF
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_OPENAT, &[-100i64 as u64, scratch, 0, 0]), -2); // ENOENT
+
+        // writev to stdout, fstat of a terminal-less stdout
+        for (i, (base, len)) in [(buffer, 2u64), (buffer + 0x10, 2)].iter().enumerate() {
+            let iov = scratch + 0x100 + 16 * i as u64;
+            cpu.bus.dram.mem[(iov - USER_MEMORY_BASE as u64) as usize..][..16].copy_from_slice(&[base.to_le_bytes(), len.to_le_bytes()].concat());
+        }
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_WRITEV, &[1, scratch + 0x100, 2]), 4);
+        assert_eq!(stdout.contents(), "1223");
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_FSTAT, &[1, statbuf]), 0);
+        assert_eq!(read_u64(&cpu, statbuf + 16) as u32 & 0o170000, 0o020000); // S_IFCHR
+
+        // uname, clock_gettime and unknown syscalls
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_UNAME, &[buffer]), 0);
+        assert_eq!(read_guest_string(&cpu.bus.dram, buffer).unwrap(), "Linux");
+        assert_eq!(read_guest_string(&cpu.bus.dram, buffer + 4 * 65).unwrap(), "riscv64");
+        assert_eq!(syscall(&mut cpu, &mut process, SYS_CLOCK_GETTIME, &[0, buffer]), 0);
+        assert!(read_u64(&cpu, buffer) > 1_600_000_000);
+        assert_eq!(syscall(&mut cpu, &mut process, 1234, &[]), -38); // ENOSYS
+    }
+}
@@ -0,0 +1,209 @@
+use riscv_emu::cpu::basic_cpu::{BasicCpu, Privilege, CSR_MSTATUS, CSR_SATP, MSTATUS_MPRV, MSTATUS_MXR, MSTATUS_SUM};
+use riscv_emu::cpu::error::{ExecError, MemoryAccess};
+use riscv_emu::cpu::mmu::{PTE_A, PTE_D, PTE_R, PTE_U, PTE_V, PTE_W, PTE_X, SATP_MODE_SV39, SATP_MODE_SV48};
+use riscv_emu::memory::dram::DRAM_BASE_ADDR;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: u64 = DRAM_BASE_ADDR as u64 + 0x10000;
+    const L1: u64 = DRAM_BASE_ADDR as u64 + 0x11000;
+    const L0: u64 = DRAM_BASE_ADDR as u64 + 0x12000;
+    const L3: u64 = DRAM_BASE_ADDR as u64 + 0x13000;
+    const DATA: u64 = DRAM_BASE_ADDR as u64 + 0x20000;
+    const VADDR: u64 = 0x4000_1000; // vpn[2] = 1, vpn[1] = 0, vpn[0] = 1
+
+    const LD_A0_A1: u32 = 0x0005B503; // ld a0, 0(a1)
+    const SD_A0_A1: u32 = 0x00A5B023; // sd a0, 0(a1)
+    const SFENCE_VMA: u32 = 0x12000073; // sfence.vma zero, zero
+    const SFENCE_VMA_A1: u32 = 0x12058073; // sfence.vma a1, zero
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn pte(paddr: u64, flags: u64) -> u64 {
+        ((paddr >> 12) << 10) | flags
+    }
+
+    fn satp(mode: u64, root: u64) -> u64 {
+        (mode << 60) | (root >> 12)
+    }
+
+    /// Sv39 mapping of VADDR to DATA through a three level walk, running in S-mode
+    fn setup_sv39(leaf_flags: u64) -> BasicCpu {
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        cpu.bus.dram.dram_write(ROOT as usize + 8, 64, pte(L1, PTE_V));
+        cpu.bus.dram.dram_write(L1 as usize, 64, pte(L0, PTE_V));
+        cpu.bus.dram.dram_write(L0 as usize + 8, 64, pte(DATA, leaf_flags));
+        cpu.bus.dram.dram_write(DATA as usize + 0x10, 64, 0x1122_3344_5566_7788);
+        cpu.set_csr(CSR_SATP, satp(SATP_MODE_SV39, ROOT));
+        cpu.set_privilege(Privilege::Supervisor);
+        cpu
+    }
+
+    fn load(cpu: &mut BasicCpu, vaddr: u64) -> Result<u64, ExecError> {
+        cpu.set_register(11, vaddr);
+        cpu.execute_instr(LD_A0_A1).map(|_| cpu.get_register(10))
+    }
+
+    #[test]
+    fn test_sv39_translation_and_accessed_dirty() {
+        test_init();
+        let mut cpu = setup_sv39(PTE_V | PTE_R | PTE_W);
+
+        assert_eq!(load(&mut cpu, VADDR + 0x10), Ok(0x1122_3344_5566_7788));
+        let leaf = cpu.bus.dram.dram_read(L0 as usize + 8, 64);
+        assert_eq!(leaf & (PTE_A | PTE_D), PTE_A);
+
+        // Store sets the dirty bit and writes the physical page
+        cpu.set_register(10, 0xCAFE);
+        cpu.set_register(11, VADDR + 0x18);
+        assert!(cpu.execute_instr(SD_A0_A1).is_ok());
+        assert_eq!(cpu.bus.dram.dram_read(DATA as usize + 0x18, 64), 0xCAFE);
+        let leaf = cpu.bus.dram.dram_read(L0 as usize + 8, 64);
+        assert_eq!(leaf & (PTE_A | PTE_D), PTE_A | PTE_D);
+    }
+
+    #[test]
+    fn test_page_faults() {
+        test_init();
+        let mut cpu = setup_sv39(PTE_V | PTE_R);
+        let pc = cpu.get_pc();
+
+        // Unmapped page
+        assert_eq!(load(&mut cpu, VADDR + 0x1000), Err(ExecError::PageFault { addr: VADDR + 0x1000, access: MemoryAccess::Load, pc }));
+        // Non-canonical address
+        assert!(matches!(load(&mut cpu, 0x0000_0040_0000_0000), Err(ExecError::PageFault { .. })));
+        // Store to a read-only page
+        cpu.set_register(11, VADDR);
+        assert_eq!(cpu.execute_instr(SD_A0_A1), Err(ExecError::PageFault { addr: VADDR, access: MemoryAccess::Store, pc }));
+        // Fetch from a non-executable page
+        cpu.set_pc(VADDR);
+        assert_eq!(cpu.fetch_instr(), Err(ExecError::PageFault { addr: VADDR, access: MemoryAccess::Fetch, pc: VADDR }));
+        // Load crossing into the unmapped page
+        cpu.set_pc(pc);
+        assert!(matches!(load(&mut cpu, VADDR + 0xFFC), Err(ExecError::Misaligned { .. })));
+    }
+
+    #[test]
+    fn test_user_pages_sum_and_mxr() {
+        test_init();
+        let mut cpu = setup_sv39(PTE_V | PTE_R | PTE_U);
+
+        // S-mode needs SUM to access U pages
+        assert!(matches!(load(&mut cpu, VADDR), Err(ExecError::PageFault { .. })));
+        cpu.set_csr(CSR_MSTATUS, MSTATUS_SUM);
+        assert!(load(&mut cpu, VADDR).is_ok());
+        cpu.set_privilege(Privilege::User);
+        assert!(load(&mut cpu, VADDR).is_ok());
+
+        // Execute-only page is readable with MXR
+        let mut cpu = setup_sv39(PTE_V | PTE_X);
+        assert!(matches!(load(&mut cpu, VADDR), Err(ExecError::PageFault { .. })));
+        cpu.set_csr(CSR_MSTATUS, MSTATUS_MXR);
+        assert!(load(&mut cpu, VADDR).is_ok());
+    }
+
+    #[test]
+    fn test_superpages() {
+        test_init();
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        // 1 GiB gigapage mapping 0xC000_0000 to the start of DRAM
+        cpu.bus.dram.dram_write(ROOT as usize + 3 * 8, 64, pte(DRAM_BASE_ADDR as u64, PTE_V | PTE_R | PTE_W | PTE_A | PTE_D));
+        // Misaligned gigapage (ppn[0] != 0)
+        cpu.bus.dram.dram_write(ROOT as usize + 4 * 8, 64, pte(DATA, PTE_V | PTE_R));
+        cpu.bus.dram.dram_write(DATA as usize, 64, 0x55);
+        cpu.set_csr(CSR_SATP, satp(SATP_MODE_SV39, ROOT));
+        cpu.set_privilege(Privilege::Supervisor);
+
+        assert_eq!(load(&mut cpu, 0xC000_0000 + 0x20000), Ok(0x55));
+        assert!(matches!(load(&mut cpu, 0x1_0000_0000), Err(ExecError::PageFault { .. })));
+    }
+
+    #[test]
+    fn test_sv48_translation() {
+        test_init();
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        // Four level walk for a virtual address above the Sv39 range
+        let vaddr: u64 = 0x0000_0080_4000_1000; // vpn[3] = 1, vpn[2] = 1, vpn[1] = 0, vpn[0] = 1
+        cpu.bus.dram.dram_write(ROOT as usize + 8, 64, pte(L3, PTE_V));
+        cpu.bus.dram.dram_write(L3 as usize + 8, 64, pte(L1, PTE_V));
+        cpu.bus.dram.dram_write(L1 as usize, 64, pte(L0, PTE_V));
+        cpu.bus.dram.dram_write(L0 as usize + 8, 64, pte(DATA, PTE_V | PTE_R));
+        cpu.bus.dram.dram_write(DATA as usize, 64, 0x48);
+        cpu.set_csr(CSR_SATP, satp(SATP_MODE_SV48, ROOT));
+        cpu.set_privilege(Privilege::Supervisor);
+
+        assert_eq!(load(&mut cpu, vaddr), Ok(0x48));
+    }
+
+    #[test]
+    fn test_tlb_and_sfence_vma() {
+        test_init();
+        let mut cpu = setup_sv39(PTE_V | PTE_R);
+        cpu.bus.dram.dram_write(DATA as usize + 0x1000, 64, 0x2222);
+        assert_eq!(load(&mut cpu, VADDR + 0x10), Ok(0x1122_3344_5566_7788));
+
+        // Remap the page: the cached translation is used until SFENCE.VMA
+        cpu.bus.dram.dram_write(L0 as usize + 8, 64, pte(DATA + 0x1000, PTE_V | PTE_R));
+        assert_eq!(load(&mut cpu, VADDR), Ok(0));
+        assert!(cpu.execute_instr(SFENCE_VMA).is_ok());
+        assert_eq!(load(&mut cpu, VADDR), Ok(0x2222));
+
+        // SFENCE.VMA is illegal in U-mode
+        cpu.set_privilege(Privilege::User);
+        assert!(matches!(cpu.execute_instr(SFENCE_VMA), Err(ExecError::IllegalInstruction { .. })));
+    }
+
+    #[test]
+    fn test_sfence_vma_high_half_address() {
+        test_init();
+        let mut cpu = setup_sv39(PTE_V | PTE_R);
+        // Kernel style mapping of the sign-extended address 0xffff_ffc0_0000_0000 (vpn[2] = 256)
+        let vaddr: u64 = 0xffff_ffc0_0000_0000;
+        cpu.bus.dram.dram_write(ROOT as usize + 256 * 8, 64, pte(L3, PTE_V));
+        cpu.bus.dram.dram_write(L3 as usize, 64, pte(L0, PTE_V));
+        cpu.bus.dram.dram_write(L0 as usize, 64, pte(DATA + 0x1000, PTE_V | PTE_R));
+        cpu.bus.dram.dram_write(DATA as usize + 0x1000, 64, 0x2222);
+        cpu.bus.dram.dram_write(DATA as usize + 0x2000, 64, 0x3333);
+        assert_eq!(load(&mut cpu, vaddr), Ok(0x2222));
+
+        // Flushing another page keeps the cached translation, flushing the address itself drops it
+        cpu.bus.dram.dram_write(L0 as usize, 64, pte(DATA + 0x2000, PTE_V | PTE_R));
+        cpu.set_register(11, VADDR);
+        assert!(cpu.execute_instr(SFENCE_VMA_A1).is_ok());
+        assert_eq!(load(&mut cpu, vaddr), Ok(0x2222));
+        cpu.set_register(11, vaddr);
+        assert!(cpu.execute_instr(SFENCE_VMA_A1).is_ok());
+        assert_eq!(load(&mut cpu, vaddr), Ok(0x3333));
+    }
+
+    #[test]
+    fn test_machine_mode_and_mprv() {
+        test_init();
+        let mut cpu = setup_sv39(PTE_V | PTE_R);
+
+        // M-mode accesses are not translated
+        cpu.set_privilege(Privilege::Machine);
+        assert_eq!(load(&mut cpu, DATA + 0x10), Ok(0x1122_3344_5566_7788));
+
+        // MPRV with MPP = S translates loads and stores
+        cpu.set_csr(CSR_MSTATUS, MSTATUS_MPRV | (0b01 << 11));
+        assert_eq!(load(&mut cpu, VADDR + 0x10), Ok(0x1122_3344_5566_7788));
+    }
+
+    #[test]
+    fn test_satp_unsupported_mode_ignored() {
+        test_init();
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        cpu.set_csr(CSR_SATP, satp(SATP_MODE_SV39, ROOT));
+        cpu.set_csr(CSR_SATP, satp(1, ROOT)); // reserved mode
+        assert_eq!(cpu.get_csr(CSR_SATP), satp(SATP_MODE_SV39, ROOT));
+    }
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use std::io;
+use std::path::PathBuf;
+
+use riscv_emu::cpu::basic_cpu::{BasicCpu, TReg};
+use riscv_emu::loader::elf::ElfFile;
+use riscv_emu::memory::dram::{DRAM_BASE_ADDR, DRAM_SIZE};
+use riscv_emu::os::pk::*;
+use riscv_emu::os::syscall::{read_guest, write_guest, SyscallHandler, SyscallOutcome};
+
+use common::{build_executable, SharedBuffer};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT_BASE: u64 = DRAM_BASE_ADDR as u64;
+
+    // write(1, "hi\n", 3); exit(3)
+    const HELLO: [u32; 9] = [
+        0x00100513, // li a0, 1
+        0x00000597, // auipc a1, 0
+        0x02058593, // addi a1, a1, 32
+        0x00300613, // li a2, 3
+        0x04000893, // li a7, 64
+        0x00000073, // ecall
+        0x00300513, // li a0, 3
+        0x05d00893, // li a7, 93
+        0x00000073, // ecall
+    ];
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    fn load(binary: &[u8], sandbox: PathBuf) -> (BasicCpu, ProxyKernel, SharedBuffer) {
+        let mut cpu = BasicCpu::new();
+        let elf = ElfFile::parse(binary).unwrap();
+        elf.load(&mut cpu.bus.dram).unwrap();
+        cpu.init();
+        cpu.set_pc(elf.entry);
+        let mut kernel = ProxyKernel::new(&elf, &cpu.bus.dram, sandbox);
+        let stdout = SharedBuffer::default();
+        kernel.set_output(Box::new(stdout.clone()), Box::new(io::sink()));
+        (cpu, kernel, stdout)
+    }
+
+    fn read_u64(cpu: &BasicCpu, addr: TReg) -> u64 {
+        u64::from_le_bytes(read_guest(&cpu.bus.dram, addr, 8).unwrap().try_into().unwrap())
+    }
+
+    /// Runs the syscall in a7 with the arguments in a0.. and returns a0
+    fn syscall(cpu: &mut BasicCpu, kernel: &mut ProxyKernel, number: u64, args: &[u64]) -> i64 {
+        cpu.set_register(17, number);
+        for (i, arg) in args.iter().enumerate() {
+            cpu.set_register(10 + i, *arg);
+        }
+        assert_eq!(kernel.syscall(cpu), SyscallOutcome::Return);
+        cpu.get_register(10) as i64
+    }
+
+    #[test]
+    fn test_pk_hello_world() {
+        test_init();
+        let binary = build_executable(TEXT_BASE, &HELLO, b"hi\n");
+        let (mut cpu, kernel, stdout) = load(&binary, PathBuf::from("."));
+        cpu.set_syscall_handler(Some(Box::new(kernel)));
+        for _ in 0..HELLO.len() {
+            assert_eq!(cpu.step(), None);
+        }
+        assert_eq!(stdout.contents(), "hi\n");
+        assert_eq!(cpu.exit_code(), Some(3));
+    }
+
+    #[test]
+    fn test_pk_sandboxed_files() {
+        test_init();
+        let sandbox = std::env::temp_dir().join(format!("riscv-emu-pk-{}", std::process::id()));
+        std::fs::create_dir_all(&sandbox).unwrap();
+        let binary = build_executable(TEXT_BASE, &HELLO, b"hi\n");
+        let (mut cpu, mut kernel, _) = load(&binary, sandbox.clone());
+        let scratch = cpu.get_register(2) - 0x1000;
+        let (name, escape, buffer, statbuf) = (scratch, scratch + 0x40, scratch + 0x200, scratch + 0x400);
+        assert!(write_guest(&mut cpu.bus.dram, name, b"/out.txt\0"));
+        assert!(write_guest(&mut cpu.bus.dram, escape, b"sub/../../out.txt\0"));
+        assert!(write_guest(&mut cpu.bus.dram, buffer, b"12345"));
+
+        // open("/out.txt", O_RDWR | O_CREAT | O_TRUNC) with newlib flags lands inside the sandbox
+        let fd = syscall(&mut cpu, &mut kernel, SYS_OPEN, &[name, 0x602, 0o644]);
+        assert_eq!(fd, 3);
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_WRITE, &[fd as u64, buffer, 5]), 5);
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_LSEEK, &[fd as u64, 2, 0]), 2);
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_READ, &[fd as u64, buffer + 0x10, 16]), 3);
+        assert_eq!(read_guest(&cpu.bus.dram, buffer + 0x10, 3).unwrap(), b"345");
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_FSTAT, &[fd as u64, statbuf]), 0);
+        assert_eq!(read_u64(&cpu, statbuf + 48), 5); // st_size
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_CLOSE, &[fd as u64]), 0);
+        assert_eq!(std::fs::read(sandbox.join("out.txt")).unwrap(), b"12345");
+
+        // openat(AT_FDCWD) reads it back, paths leaving the sandbox are refused
+        let fd = syscall(&mut cpu, &mut kernel, SYS_OPENAT, &[-100i64 as u64, name, 0, 0]);
+        assert_eq!(fd, 3);
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_CLOSE, &[fd as u64]), 0);
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_OPEN, &[escape, 0, 0]), -13); // EACCES
+
+        // Symbolic links are followed only as long as the target stays inside the sandbox
+        let outside = std::env::temp_dir().join(format!("riscv-emu-pk-{}-outside", std::process::id()));
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("secret.txt"), b"secret").unwrap();
+        std::os::unix::fs::symlink(&outside, sandbox.join("outside")).unwrap();
+        std::os::unix::fs::symlink(outside.join("new.txt"), sandbox.join("dangling")).unwrap();
+        std::os::unix::fs::symlink(sandbox.join("out.txt"), sandbox.join("inside")).unwrap();
+        for (path, result) in [("/outside/secret.txt\0", -13), ("/outside/new.txt\0", -13), ("/dangling\0", -13), ("/inside\0", 3)] {
+            assert!(write_guest(&mut cpu.bus.dram, name + 0x100, path.as_bytes()));
+            assert_eq!(syscall(&mut cpu, &mut kernel, SYS_OPEN, &[name + 0x100, 0x602, 0o644]), result, "{path:?}");
+        }
+        assert!(!outside.join("new.txt").exists());
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_CLOSE, &[3]), 0);
+        std::fs::remove_dir_all(&outside).unwrap();
+        std::fs::remove_dir_all(&sandbox).unwrap();
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_OPEN, &[name, 0, 0]), -2); // ENOENT
+    }
+
+    #[test]
+    fn test_pk_brk_and_time() {
+        test_init();
+        let binary = build_executable(TEXT_BASE, &HELLO, b"hi\n");
+        let (mut cpu, mut kernel, _) = load(&binary, PathBuf::from("."));
+
+        // The heap starts after the BSS, page aligned, and stops below the stack
+        let brk = syscall(&mut cpu, &mut kernel, SYS_BRK, &[0]) as u64;
+        assert_eq!(brk, TEXT_BASE + 0x1000);
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_BRK, &[brk + 0x2000]) as u64, brk + 0x2000);
+        let heap_end = TEXT_BASE + DRAM_SIZE as u64 - PK_STACK_SIZE;
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_BRK, &[heap_end + 1]) as u64, brk + 0x2000);
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_BRK, &[heap_end]) as u64, heap_end);
+
+        assert_eq!(syscall(&mut cpu, &mut kernel, SYS_GETTIMEOFDAY, &[brk]), 0);
+        assert!(read_u64(&cpu, brk) > 1_600_000_000);
+        assert!(read_u64(&cpu, brk + 8) < 1_000_000);
+        assert_eq!(syscall(&mut cpu, &mut kernel, 1234, &[]), -38); // ENOSYS
+    }
+}
@@ -0,0 +1,139 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use riscv_emu::cpu::basic_cpu::{
+    BasicCpu, Privilege, CSR_MCAUSE, CSR_MIDELEG, CSR_MIE, CSR_MIP, CSR_MSTATUS, CSR_MTVEC, CSR_SCAUSE, CSR_STVEC,
+    IRQ_M_EXT, IRQ_S_EXT, MCAUSE_INTERRUPT, MIP_MEIP, MIP_SEIP, MSTATUS_MIE,
+};
+use riscv_emu::devices::plic::*;
+use riscv_emu::memory::bus::{AccessFault, Bus, Device};
+use riscv_emu::memory::dram::DRAM_BASE_ADDR;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: u32 = 5;
+    const M_CLAIM: u64 = PLIC_BASE + PLIC_CONTEXT + 4;
+    const S_CLAIM: u64 = PLIC_BASE + PLIC_CONTEXT + PLIC_CONTEXT_STRIDE + 4;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    /// Test-only interrupt source whose line is driven by the test
+    struct TestIrqSource {
+        line: Rc<Cell<bool>>,
+    }
+
+    impl Device for TestIrqSource {
+        fn read(&mut self, _offset: u64, _size: usize) -> Result<u64, AccessFault> {
+            Ok(0)
+        }
+
+        fn write(&mut self, _offset: u64, _size: usize, _value: u64) -> Result<(), AccessFault> {
+            Ok(())
+        }
+
+        fn irq(&self) -> bool {
+            self.line.get()
+        }
+    }
+
+    fn setup_cpu(line: &Rc<Cell<bool>>) -> BasicCpu {
+        let mut bus = Bus::new();
+        bus.add_device("plic", PLIC_BASE, PLIC_SIZE, Box::new(Plic::new())).unwrap();
+        bus.add_device("test", 0x1000, 0x100, Box::new(TestIrqSource { line: line.clone() })).unwrap();
+        bus.add_irq_source("test", SOURCE);
+        let mut cpu = BasicCpu::with_bus(bus);
+        cpu.init();
+        for i in 0..0x40 {
+            cpu.bus.dram.dram_write(DRAM_BASE_ADDR + 4 * i, 32, 0x00000013); // nop
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_plic_priority_threshold_claim_complete() {
+        test_init();
+        let mut plic = Plic::new();
+        plic.write(PLIC_PRIORITY + 4 * 3, 32, 1).unwrap();
+        plic.write(PLIC_PRIORITY + 4 * 7, 32, 6).unwrap();
+        plic.write(PLIC_PRIORITY + 4 * 9, 32, 6).unwrap();
+        plic.write(PLIC_ENABLE, 32, (1 << 3) | (1 << 7) | (1 << 9)).unwrap();
+        assert_eq!(plic.read(PLIC_PRIORITY + 4 * 7, 32), Ok(6));
+
+        plic.set_irq_lines((1 << 3) | (1 << 7) | (1 << 9));
+        assert_eq!(plic.read(PLIC_PENDING, 32), Ok((1 << 3) | (1 << 7) | (1 << 9)));
+        assert_eq!(plic.mip(), MIP_MEIP);
+
+        // Highest priority first, ties go to the lowest source id
+        assert_eq!(plic.read(PLIC_CONTEXT + 4, 32), Ok(7));
+        assert_eq!(plic.read(PLIC_CONTEXT + 4, 32), Ok(9));
+        // Threshold masks priority 1
+        plic.write(PLIC_CONTEXT, 32, 1).unwrap();
+        assert_eq!(plic.mip(), 0);
+        assert_eq!(plic.read(PLIC_CONTEXT + 4, 32), Ok(0));
+        plic.write(PLIC_CONTEXT, 32, 0).unwrap();
+        assert_eq!(plic.read(PLIC_CONTEXT + 4, 32), Ok(3));
+
+        // Claimed sources are not pending again before completion, even with the line still high
+        plic.set_irq_lines(1 << 7);
+        assert_eq!(plic.read(PLIC_PENDING, 32), Ok(0));
+        plic.write(PLIC_CONTEXT + 4, 32, 7).unwrap();
+        assert_eq!(plic.read(PLIC_PENDING, 32), Ok(1 << 7));
+
+        // Only word accesses are supported
+        assert_eq!(plic.read(PLIC_PENDING, 8), Err(AccessFault));
+    }
+
+    #[test]
+    fn test_plic_machine_external_interrupt() {
+        test_init();
+        let line = Rc::new(Cell::new(false));
+        let mut cpu = setup_cpu(&line);
+        let handler = DRAM_BASE_ADDR as u64 + 0x800;
+        cpu.set_csr(CSR_MTVEC, handler);
+        cpu.set_csr(CSR_MIE, MIP_MEIP);
+        cpu.set_csr(CSR_MSTATUS, MSTATUS_MIE);
+        cpu.bus.write(PLIC_BASE + PLIC_PRIORITY + 4 * SOURCE as u64, 32, 1).unwrap();
+        cpu.bus.write(PLIC_BASE + PLIC_ENABLE, 32, 1 << SOURCE).unwrap();
+
+        assert_eq!(cpu.step(), None);
+        assert_eq!(cpu.get_pc(), DRAM_BASE_ADDR as u64 + 4);
+
+        // The device raises its line: external interrupt is taken on the next step
+        line.set(true);
+        assert_eq!(cpu.step(), None);
+        assert_eq!(cpu.get_pc(), handler);
+        assert_eq!(cpu.get_csr(CSR_MCAUSE), MCAUSE_INTERRUPT | IRQ_M_EXT);
+
+        // Handler claims, the device lowers its line, handler completes
+        assert_eq!(cpu.bus.read(M_CLAIM, 32), Ok(SOURCE as u64));
+        line.set(false);
+        cpu.bus.write(M_CLAIM, 32, SOURCE as u64).unwrap();
+        cpu.bus.tick();
+        assert_eq!(cpu.bus.mip() & MIP_MEIP, 0);
+    }
+
+    #[test]
+    fn test_plic_supervisor_context() {
+        test_init();
+        let line = Rc::new(Cell::new(true));
+        let mut cpu = setup_cpu(&line);
+        let handler = DRAM_BASE_ADDR as u64 + 0x800;
+        cpu.set_csr(CSR_STVEC, handler);
+        cpu.set_csr(CSR_MIDELEG, MIP_SEIP);
+        cpu.set_csr(CSR_MIE, MIP_SEIP);
+        cpu.set_privilege(Privilege::User);
+        cpu.bus.write(PLIC_BASE + PLIC_PRIORITY + 4 * SOURCE as u64, 32, 2).unwrap();
+        cpu.bus.write(PLIC_BASE + PLIC_ENABLE + PLIC_ENABLE_STRIDE, 32, 1 << SOURCE).unwrap();
+
+        assert_eq!(cpu.step(), None);
+        assert_eq!(cpu.get_csr(CSR_MIP) & (MIP_SEIP | MIP_MEIP), MIP_SEIP);
+        assert_eq!(cpu.get_privilege(), Privilege::Supervisor);
+        assert_eq!(cpu.get_pc(), handler);
+        assert_eq!(cpu.get_csr(CSR_SCAUSE), MCAUSE_INTERRUPT | IRQ_S_EXT);
+        assert_eq!(cpu.bus.read(S_CLAIM, 32), Ok(SOURCE as u64));
+    }
+}
@@ -0,0 +1,92 @@
+use riscv_emu::cpu::basic_cpu::{BasicCpu, REGISTERS_COUNT};
+use riscv_emu::cpu::error::ExecError;
+use riscv_emu::cpu::registers::*;
+use riscv_emu::memory::bus::Bus;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[test]
+    fn test_register_file_invariants() {
+        test_init();
+        let mut registers = RegisterFile::new();
+        assert_eq!(registers.count(), REGISTERS_COUNT);
+        assert!(registers.set(0, 0xdead));
+        assert_eq!(registers.get(0), Some(0));
+        assert!(registers.set(31, 0xbeef));
+        assert_eq!(registers.get(31), Some(0xbeef));
+        assert!(!registers.set(32, 1));
+        assert_eq!(registers.get(32), None);
+        assert_eq!(registers.iter().count(), 32);
+
+        let mut registers = RegisterFile::embedded();
+        assert_eq!(registers.count(), EMBEDDED_REGISTERS_COUNT);
+        assert!(registers.set(15, 1));
+        assert!(!registers.set(16, 1));
+        assert_eq!(registers.get(16), None);
+        assert_eq!(registers.iter().map(|(idx, _)| idx).max(), Some(15));
+    }
+
+    #[test]
+    fn test_register_abi_names() {
+        test_init();
+        assert_eq!(RegisterFile::abi_name(0), Some("zero"));
+        assert_eq!(RegisterFile::abi_name(1), Some("ra"));
+        assert_eq!(RegisterFile::abi_name(2), Some("sp"));
+        assert_eq!(RegisterFile::abi_name(10), Some("a0"));
+        assert_eq!(RegisterFile::abi_name(31), Some("t6"));
+        assert_eq!(RegisterFile::abi_name(32), None);
+        assert_eq!(RegisterFile::lookup("a0"), Some(10));
+        assert_eq!(RegisterFile::lookup("fp"), Some(8));
+        assert_eq!(RegisterFile::lookup("s0"), Some(8));
+        assert_eq!(RegisterFile::lookup("x0"), Some(0));
+        assert_eq!(RegisterFile::lookup("x31"), Some(31));
+        assert_eq!(RegisterFile::lookup("x32"), None);
+        assert_eq!(RegisterFile::lookup("x05"), None);
+        assert_eq!(RegisterFile::lookup("a8"), None);
+    }
+
+    #[test]
+    fn test_cpu_register_file() {
+        test_init();
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        // x0 stays zero for instruction results and direct writes
+        cpu.execute_instr(0x00500013).unwrap(); // addi zero, zero, 5
+        assert_eq!(cpu.get_register(0), 0);
+        cpu.set_register(0, 7);
+        assert_eq!(cpu.get_register(0), 0);
+        // Out of range indexes are ignored instead of panicking
+        cpu.set_register(32, 7);
+        assert_eq!(cpu.get_register(32), 0);
+        assert_eq!(cpu.registers().get(2), Some(cpu.get_register(2)));
+
+        let mut cpu = BasicCpu::with_register_file(Bus::new(), RegisterFile::embedded());
+        cpu.init();
+        cpu.execute_instr(0x00500793).unwrap(); // addi a5, zero, 5
+        assert_eq!(cpu.get_register(15), 5);
+        assert!(matches!(cpu.execute_instr(0x00500813), Err(ExecError::IllegalInstruction { .. }))); // addi a6, zero, 5
+        assert!(matches!(cpu.execute_instr(0x01078733), Err(ExecError::IllegalInstruction { .. }))); // add a4, a5, a6
+        assert!(matches!(cpu.execute_instr(0x0107b023), Err(ExecError::IllegalInstruction { .. }))); // sd a6, 0(a5)
+        assert_eq!(cpu.get_register(16), 0);
+
+        // Integer register fields of floating-point moves, comparisons and conversions and of SFENCE.VMA
+        cpu.execute_instr(0xe00507d3).unwrap(); // fmv.x.w a5, fa0
+        for instr in [
+            0xe0050853, // fmv.x.w a6, fa0
+            0xe2051853, // fclass.d a6, fa0
+            0xc0057853, // fcvt.w.s a6, fa0
+            0xa0b52853, // feq.s a6, fa0, fa1
+            0xf0080553, // fmv.w.x fa0, a6
+            0xd0087553, // fcvt.s.w fa0, a6
+            0x13078073, // sfence.vma a5, a6
+        ] {
+            assert!(matches!(cpu.execute_instr(instr), Err(ExecError::IllegalInstruction { .. })), "{instr:#010x}");
+        }
+    }
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use std::io;
+use std::path::PathBuf;
+
+use riscv_emu::cpu::basic_cpu::{BasicCpu, TReg};
+use riscv_emu::cpu::error::ExecError;
+use riscv_emu::os::semihosting::*;
+use riscv_emu::os::syscall::{read_guest, read_guest_string, write_guest, SyscallHandler, SyscallOutcome};
+
+use common::SharedBuffer;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SYS_WRITE0("hi\n"); SYS_EXIT(ADP_Stopped_ApplicationExit, 7)
+    const PROGRAM: [u32; 12] = [
+        0x00400513, // li a0, 4
+        0x00000597, // auipc a1, 0
+        0x0fc58593, // addi a1, a1, 0xfc
+        0x01f01013, // slli zero, zero, 31
+        0x00100073, // ebreak
+        0x40705013, // srai zero, zero, 7
+        0x01800513, // li a0, 0x18
+        0x00000597, // auipc a1, 0
+        0x0f458593, // addi a1, a1, 0xf4
+        0x01f01013, // slli zero, zero, 31
+        0x00100073, // ebreak
+        0x40705013, // srai zero, zero, 7
+    ];
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    /// CPU with `code` at the start of DRAM, "hi\n" at +0x100 and an exit parameter block at +0x110
+    fn load(code: &[u32]) -> BasicCpu {
+        let mut cpu = BasicCpu::new();
+        cpu.init();
+        let base = cpu.get_pc();
+        let code: Vec<u8> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+        assert!(write_guest(&mut cpu.bus.dram, base, &code));
+        assert!(write_guest(&mut cpu.bus.dram, base + 0x100, b"hi\n\0"));
+        let exit_block = [ADP_STOPPED_APPLICATION_EXIT.to_le_bytes(), 7u64.to_le_bytes()].concat();
+        assert!(write_guest(&mut cpu.bus.dram, base + 0x110, &exit_block));
+        cpu
+    }
+
+    fn semihosting(cmdline: &str, sandbox: PathBuf) -> (Semihosting, SharedBuffer) {
+        let mut semihosting = Semihosting::new(cmdline.to_string(), sandbox);
+        let stdout = SharedBuffer::default();
+        semihosting.set_output(Box::new(stdout.clone()), Box::new(io::sink()));
+        (semihosting, stdout)
+    }
+
+    /// Writes the parameter block to scratch memory, runs the operation and returns a0
+    fn call(cpu: &mut BasicCpu, semihosting: &mut Semihosting, operation: TReg, block: &[TReg]) -> i64 {
+        let scratch = cpu.get_register(2) - 0x1000;
+        let bytes: Vec<u8> = block.iter().flat_map(|field| field.to_le_bytes()).collect();
+        assert!(write_guest(&mut cpu.bus.dram, scratch, &bytes));
+        cpu.set_register(10, operation);
+        cpu.set_register(11, scratch);
+        assert_eq!(semihosting.syscall(cpu), SyscallOutcome::Return);
+        cpu.get_register(10) as i64
+    }
+
+    #[test]
+    fn test_semihosting_write_and_exit() {
+        test_init();
+        let mut cpu = load(&PROGRAM);
+        let (semihosting, stdout) = semihosting("prog", PathBuf::from("."));
+        cpu.set_semihosting_handler(Some(Box::new(semihosting)));
+        for _ in 0..6 {
+            assert_eq!(cpu.step(), None);
+        }
+        assert_eq!(stdout.contents(), "hi\n");
+        assert_eq!(cpu.exit_code(), None);
+        for _ in 0..6 {
+            assert_eq!(cpu.step(), None);
+        }
+        assert_eq!(cpu.exit_code(), Some(7));
+    }
+
+    #[test]
+    fn test_semihosting_requires_marker_sequence() {
+        test_init();
+        // Without a handler the sequence is an ordinary breakpoint
+        let mut cpu = load(&PROGRAM);
+        for _ in 0..4 {
+            assert_eq!(cpu.step(), None);
+        }
+        assert!(matches!(cpu.execute_instr(0x00100073), Err(ExecError::Breakpoint { .. })));
+
+        // A lone EBREAK or a compressed C.EBREAK inside the sequence is not a semihosting call
+        let mut cpu = load(&[0x00400513, 0x00100073, 0x40705013]); // li a0, 4; ebreak; srai zero, zero, 7
+        let (semihosting, stdout) = semihosting("prog", PathBuf::from("."));
+        cpu.set_semihosting_handler(Some(Box::new(semihosting)));
+        assert_eq!(cpu.step(), None);
+        assert!(matches!(cpu.execute_instr(0x00100073), Err(ExecError::Breakpoint { .. })));
+        let mut cpu = load(&[0x01f01013, 0x00019002, 0x40705013]); // c.ebreak padded with c.nop
+        cpu.set_semihosting_handler(Some(Box::new(Semihosting::new(String::new(), PathBuf::from(".")))));
+        assert_eq!(cpu.step(), None);
+        assert!(matches!(cpu.execute_instr(0x9002), Err(ExecError::Breakpoint { .. })));
+        assert!(stdout.contents().is_empty());
+    }
+
+    #[test]
+    fn test_semihosting_operations() {
+        test_init();
+        let sandbox = std::env::temp_dir().join(format!("riscv-emu-semihosting-{}", std::process::id()));
+        std::fs::create_dir_all(&sandbox).unwrap();
+        let mut cpu = load(&PROGRAM);
+        let (mut semihosting, stdout) = semihosting("prog --fast", sandbox.clone());
+        let base = cpu.get_pc();
+        let (name, console, buffer) = (base + 0x200, base + 0x220, base + 0x300);
+        assert!(write_guest(&mut cpu.bus.dram, name, b"log.txt"));
+        assert!(write_guest(&mut cpu.bus.dram, console, b":tt"));
+        assert!(write_guest(&mut cpu.bus.dram, buffer, b"abcdef"));
+
+        // The console opened for writing is stdout, SYS_WRITE returns the number of bytes not written
+        assert_eq!(call(&mut cpu, &mut semihosting, SYS_OPEN, &[console, 4, 3]), 1);
+        assert_eq!(call(&mut cpu, &mut semihosting, SYS_WRITE, &[1, buffer, 3]), 0);
+        assert_eq!(stdout.contents(), "abc");
+
+        // Files are created below the sandbox, SYS_READ returns the number of bytes not read
+        let handle = call(&mut cpu, &mut semihosting, SYS_OPEN, &[name, 4, 7]) as u64;
+        assert_eq!(call(&mut cpu, &mut semihosting, SYS_WRITE, &[handle, buffer, 6]), 0);
+        assert_eq!(call(&mut cpu, &mut semihosting, SYS_CLOSE, &[handle]), 0);
+        assert_eq!(std::fs::read(sandbox.join("log.txt")).unwrap(), b"abcdef");
+        let handle = call(&mut cpu, &mut semihosting, SYS_OPEN, &[name, 0, 7]) as u64;
+        assert_eq!(call(&mut cpu, &mut semihosting, SYS_READ, &[handle, buffer + 0x10, 16]), 10);
+        assert_eq!(read_guest(&cpu.bus.dram, buffer + 0x10, 6).unwrap(), b"abcdef");
+        assert_eq!(call(&mut cpu, &mut semihosting, SYS_CLOSE, &[handle]), 0);
+        assert_eq!(call(&mut cpu, &mut semihosting, SYS_CLOSE, &[handle]), -1);
+        std::fs::remove_dir_all(&sandbox).unwrap();
+        assert_eq!(call(&mut cpu, &mut semihosting, SYS_OPEN, &[name, 0, 7]), -1);
+
+        // The command line is copied NUL terminated if it fits
+        assert_eq!(call(&mut cpu, &mut semihosting, SYS_GET_CMDLINE, &[buffer, 8]), -1);
+        assert_eq!(call(&mut cpu, &mut semihosting, SYS_GET_CMDLINE, &[buffer, 64]), 0);
+        assert_eq!(read_guest_string(&cpu.bus.dram, buffer).unwrap(), "prog --fast");
+        let size = read_guest(&cpu.bus.dram, cpu.get_register(11) + 8, 8).unwrap();
+        assert_eq!(u64::from_le_bytes(size.try_into().unwrap()), 11);
+
+        assert!(call(&mut cpu, &mut semihosting, SYS_CLOCK, &[]) >= 0);
+        assert_eq!(call(&mut cpu, &mut semihosting, 0x99, &[]), -1);
+        cpu.set_register(10, SYS_EXIT);
+        cpu.set_register(11, cpu.get_pc() + 0x300); // not ADP_Stopped_ApplicationExit
+        assert_eq!(semihosting.syscall(&mut cpu), SyscallOutcome::Exit(1));
+    }
+}
@@ -0,0 +1,122 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use riscv_emu::cpu::basic_cpu::BasicCpu;
+use riscv_emu::devices::uart::*;
+use riscv_emu::memory::bus::{AccessFault, Bus, Device};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_init() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    /// In-memory serial line shared between the test and the UART
+    #[derive(Clone, Default)]
+    struct TestBackend {
+        output: Rc<RefCell<Vec<u8>>>,
+        input: Rc<RefCell<VecDeque<u8>>>,
+    }
+
+    impl UartBackend for TestBackend {
+        fn write_byte(&mut self, byte: u8) {
+            self.output.borrow_mut().push(byte);
+        }
+
+        fn read_byte(&mut self) -> Option<u8> {
+            self.input.borrow_mut().pop_front()
+        }
+    }
+
+    #[test]
+    fn test_uart_transmit() {
+        test_init();
+        let backend = TestBackend::default();
+        let mut uart = Uart::new(Box::new(backend.clone()));
+
+        for byte in b"Hi\n" {
+            assert_eq!(uart.write(UART_THR, 8, *byte as u64), Ok(()));
+        }
+        assert_eq!(backend.output.borrow().as_slice(), b"Hi\n");
+        // Transmitter is always ready
+        assert_eq!(uart.read(UART_LSR, 8), Ok((LSR_THRE | LSR_TEMT) as u64));
+        // Only byte accesses are supported
+        assert_eq!(uart.read(UART_LSR, 32), Err(AccessFault));
+    }
+
+    #[test]
+    fn test_uart_receive_and_interrupts() {
+        test_init();
+        let backend = TestBackend::default();
+        let mut uart = Uart::new(Box::new(backend.clone()));
+        // The backend is not read before the guest uses the receiver
+        backend.input.borrow_mut().push_back(b'-');
+        uart.tick();
+        assert_eq!(backend.input.borrow().len(), 1);
+        assert_eq!(uart.read(UART_LSR, 8).unwrap() as u8 & LSR_DR, LSR_DR);
+        assert_eq!(uart.read(UART_RBR, 8), Ok(b'-' as u64));
+
+        uart.write(UART_IER, 8, IER_RDI as u64).unwrap();
+        assert!(!uart.irq());
+        assert_eq!(uart.read(UART_IIR, 8), Ok(IIR_NO_INT as u64));
+
+        // Input becomes visible after polling the backend
+        backend.input.borrow_mut().extend(b"ok");
+        uart.tick();
+        assert!(uart.irq());
+        assert_eq!(uart.read(UART_IIR, 8), Ok(IIR_RDI as u64));
+        assert_eq!(uart.read(UART_LSR, 8).unwrap() as u8 & LSR_DR, LSR_DR);
+        assert_eq!(uart.read(UART_RBR, 8), Ok(b'o' as u64));
+        assert_eq!(uart.read(UART_RBR, 8), Ok(b'k' as u64));
+        assert_eq!(uart.read(UART_LSR, 8).unwrap() as u8 & LSR_DR, 0);
+        assert!(!uart.irq());
+
+        // THR empty interrupt is raised when enabled and cleared by reading IIR
+        uart.write(UART_IER, 8, IER_THRI as u64).unwrap();
+        assert!(uart.irq());
+        assert_eq!(uart.read(UART_IIR, 8), Ok(IIR_THRI as u64));
+        assert!(!uart.irq());
+        uart.write(UART_THR, 8, b'x' as u64).unwrap();
+        assert!(uart.irq());
+    }
+
+    #[test]
+    fn test_uart_divisor_latch_and_scratch() {
+        test_init();
+        let mut uart = Uart::new(Box::new(TestBackend::default()));
+
+        uart.write(UART_LCR, 8, LCR_DLAB as u64).unwrap();
+        uart.write(UART_THR, 8, 0x03).unwrap(); // DLL
+        uart.write(UART_IER, 8, 0x00).unwrap(); // DLM
+        assert_eq!(uart.read(UART_RBR, 8), Ok(0x03));
+        uart.write(UART_LCR, 8, 0x03).unwrap(); // 8N1, DLAB cleared
+        assert_eq!(uart.read(UART_IER, 8), Ok(0));
+        uart.write(UART_SCR, 8, 0x5A).unwrap();
+        assert_eq!(uart.read(UART_SCR, 8), Ok(0x5A));
+    }
+
+    #[test]
+    fn test_uart_on_the_bus() {
+        test_init();
+        let backend = TestBackend::default();
+        let mut bus = Bus::new();
+        bus.add_device("uart", UART_BASE, UART_SIZE, Box::new(Uart::new(Box::new(backend.clone())))).unwrap();
+        let mut cpu = BasicCpu::with_bus(bus);
+        cpu.init();
+
+        // sb a0, 0(a1) with a1 = UART base
+        cpu.set_register(10, b'A' as u64);
+        cpu.set_register(11, UART_BASE);
+        assert!(cpu.execute_instr(0x00A58023).is_ok());
+        assert_eq!(backend.output.borrow().as_slice(), b"A");
+
+        // Interrupt line is visible through the bus
+        backend.input.borrow_mut().push_back(b'z');
+        cpu.bus.write(UART_BASE + UART_IER, 8, IER_RDI as u64).unwrap();
+        cpu.bus.tick();
+        assert!(cpu.bus.irq("uart"));
+    }
+}